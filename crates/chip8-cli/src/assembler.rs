@@ -0,0 +1,997 @@
+//! Assembler for Octo (`.8o`) source, the syntax most modern CHIP-8 programs are written in:
+//!
+//! ```text
+//! : main
+//!   i := digit
+//!   v0 := 0
+//!   loop
+//!     sprite v0 v0 5
+//!     v0 += 6
+//!     if v0 == 60 then v0 := 0
+//!   again
+//! : digit
+//!   0xF0 0x90 0x90 0x90 0xF0
+//! ```
+//!
+//! Programs start at the `main` label, reached through a jump emitted at 0x200.
+//!
+//! Constants and macros keep magic numbers out of the code. `:calc` evaluates an expression
+//! over numbers, constants, labels defined earlier, and `HERE` (the current address):
+//!
+//! ```text
+//! :const SPEED 2
+//! :calc PADDLE_END { paddle + 6 }
+//! :macro move reg amount { reg += amount }
+//! : main
+//!   move v0 SPEED
+//! ```
+//!
+//! Data can be written as bare bytes, with `db`/`dw` (which take the rest of the line), as
+//! sprite rows drawn with `X` and `.`, or pulled in from a binary file:
+//!
+//! ```text
+//! : ball   pixels .XX. XXXX XXXX .XX.
+//! : scores dw 0x1234 ball
+//! : level  incbin "level1.bin"
+//! ```
+//!
+//! `saveflags vx` and `loadflags vx` move V0 through vx to and from SCHIP's flag registers,
+//! which `--persist-flags` keeps in a file per ROM, for high scores that outlast the session:
+//!
+//! ```text
+//! :macro keep-best reg { v0 := reg  saveflags v0 }
+//! : main
+//!   loadflags v0
+//! ```
+use crate::source_map::SourceMap;
+use crate::symbols::Symbols;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Address programs are loaded at.
+pub const BASE_ADDRESS: usize = 0x200;
+
+/// An assembled program, along with the labels it defines and the source lines its bytes
+/// came from.
+#[derive(Debug, Default)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    pub symbols: Symbols,
+    pub source_map: SourceMap,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for AssembleError {}
+
+type Result<T> = std::result::Result<T, AssembleError>;
+
+/// Assembles a program. Files named by `incbin` are read relative to `dir`.
+pub fn assemble(source: &str, dir: &Path) -> Result<Program> {
+    let mut asm = Assembler::new(source, dir);
+    // Reserve room for the jump to main
+    asm.emit(0x1000);
+    asm.fixups.push(Fixup {
+        at: 0,
+        label: "main".to_string(),
+        line: 1,
+    });
+    while asm.pos < asm.tokens.len() {
+        let start = asm.here();
+        let line = asm.tokens[asm.pos].line;
+        asm.statement()?;
+        if asm.here() > start {
+            asm.source_map.insert(start, line);
+        }
+    }
+    asm.finish()
+}
+
+/// Depth at which a macro that keeps invoking itself is reported instead of expanded.
+const MAX_MACRO_EXPANSIONS: usize = 1000;
+
+#[derive(Copy, Clone)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+/// A `:macro`, expanded by substituting its arguments for its parameters in the body.
+struct Macro<'a> {
+    params: Vec<&'a str>,
+    body: Vec<Token<'a>>,
+}
+
+/// A reference to a label that must be patched into the low 12 bits of an instruction once
+/// every label is known.
+struct Fixup {
+    at: usize,
+    label: String,
+    line: usize,
+}
+
+/// Open `if ... begin` block, waiting for its `else` or `end`.
+struct Block {
+    /// Offset of the jump that must be pointed past the branch being assembled.
+    jump: usize,
+    line: usize,
+}
+
+/// Open `loop`, waiting for its `again`.
+struct Loop {
+    start: usize,
+    /// Offsets of jumps emitted by `while` that exit the loop.
+    exits: Vec<usize>,
+    line: usize,
+}
+
+/// A condition from `if`/`while`, stored as the skip instruction that skips the next
+/// instruction when the condition is *false*.
+struct Condition(u16);
+
+impl Condition {
+    /// The skip instruction that skips the next instruction when the condition is *true*.
+    fn inverse(&self) -> u16 {
+        match self.0 & 0xF000 {
+            0x3000 => self.0 + 0x1000,
+            0x4000 => self.0 - 0x1000,
+            0x5000 => self.0 + 0x4000,
+            0x9000 => self.0 - 0x4000,
+            _ if self.0 & 0xFF == 0x9E => (self.0 & 0xFF00) | 0xA1,
+            _ => (self.0 & 0xFF00) | 0x9E,
+        }
+    }
+}
+
+struct Assembler<'a> {
+    dir: &'a Path,
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    out: Vec<u8>,
+    labels: HashMap<String, usize>,
+    aliases: HashMap<String, u8>,
+    consts: HashMap<String, i32>,
+    macros: HashMap<String, Macro<'a>>,
+    expansions: usize,
+    fixups: Vec<Fixup>,
+    source_map: SourceMap,
+    blocks: Vec<Block>,
+    loops: Vec<Loop>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(source: &'a str, dir: &'a Path) -> Assembler<'a> {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                let code = line.split('#').next().unwrap_or("");
+                code.split_whitespace()
+                    .map(move |text| Token { text, line: i + 1 })
+            })
+            .collect();
+        Assembler {
+            dir,
+            tokens,
+            pos: 0,
+            out: Vec::new(),
+            labels: HashMap::new(),
+            aliases: HashMap::new(),
+            consts: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            fixups: Vec::new(),
+            source_map: SourceMap::default(),
+            blocks: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    fn finish(self) -> Result<Program> {
+        if let Some(block) = self.blocks.last() {
+            return Err(error(block.line, "`begin` without matching `end`"));
+        }
+        if let Some(l) = self.loops.last() {
+            return Err(error(l.line, "`loop` without matching `again`"));
+        }
+
+        let mut bytes = self.out;
+        for fixup in self.fixups.iter() {
+            let addr = *self
+                .labels
+                .get(&fixup.label)
+                .ok_or_else(|| error(fixup.line, &format!("Undefined label: {}", fixup.label)))?;
+            bytes[fixup.at] |= (addr >> 8) as u8 & 0x0F;
+            bytes[fixup.at + 1] = addr as u8;
+        }
+
+        let mut symbols = Symbols::default();
+        let mut labels: Vec<(&String, &usize)> = self.labels.iter().collect();
+        labels.sort_by_key(|(name, addr)| (**addr, (*name).clone()));
+        for (name, addr) in labels {
+            symbols.insert(name, *addr);
+        }
+        Ok(Program {
+            bytes,
+            symbols,
+            source_map: self.source_map,
+        })
+    }
+
+    /// Line of the most recently consumed token.
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos.saturating_sub(1))
+            .map_or(1, |t| t.line)
+    }
+
+    fn fail<T>(&self, message: &str) -> Result<T> {
+        Err(error(self.line(), message))
+    }
+
+    fn next(&mut self) -> Result<&'a str> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.text)
+            }
+            None => self.fail("Unexpected end of file"),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<()> {
+        let token = self.next()?;
+        if token != text {
+            return self.fail(&format!("Expected `{}` but found `{}`", text, token));
+        }
+        Ok(())
+    }
+
+    fn here(&self) -> usize {
+        BASE_ADDRESS + self.out.len()
+    }
+
+    fn emit(&mut self, op: u16) {
+        self.out.push((op >> 8) as u8);
+        self.out.push(op as u8);
+    }
+
+    /// Points the jump at offset `at` to the current address.
+    fn patch_jump(&mut self, at: usize) {
+        let addr = self.here();
+        self.out[at] = 0x10 | ((addr >> 8) as u8 & 0x0F);
+        self.out[at + 1] = addr as u8;
+    }
+
+    fn register(&mut self) -> Result<u8> {
+        let token = self.next()?;
+        match parse_register(token).or_else(|| self.aliases.get(token).copied()) {
+            Some(r) => Ok(r),
+            None => self.fail(&format!("Expected a register but found `{}`", token)),
+        }
+    }
+
+    fn is_register(&self, token: &str) -> bool {
+        parse_register(token).is_some() || self.aliases.contains_key(token)
+    }
+
+    /// A number literal or a constant.
+    fn number(&self, token: &str) -> Option<i32> {
+        parse_number(token).or_else(|| self.consts.get(token).copied())
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let token = self.next()?;
+        match self.number(token) {
+            Some(n) if (-128..=255).contains(&n) => Ok(n as u8),
+            Some(_) => self.fail(&format!("Value does not fit in a byte: {}", token)),
+            None => self.fail(&format!("Expected a number but found `{}`", token)),
+        }
+    }
+
+    fn nibble(&mut self) -> Result<u8> {
+        let token = self.next()?;
+        match self.number(token) {
+            Some(n) if (0..=15).contains(&n) => Ok(n as u8),
+            _ => self.fail(&format!(
+                "Expected a number from 0 to 15 but found `{}`",
+                token
+            )),
+        }
+    }
+
+    /// Number of tokens left on the line of the most recently consumed token.
+    fn rest_of_line(&self) -> usize {
+        let line = self.line();
+        self.tokens[self.pos..]
+            .iter()
+            .take_while(|t| t.line == line)
+            .count()
+    }
+
+    /// Emits a 16-bit big-endian value, which may be a label defined later.
+    fn word(&mut self) -> Result<()> {
+        let token = self.next()?;
+        match self.number(token) {
+            Some(n) if (-0x8000..=0xFFFF).contains(&n) => self.emit(n as u16),
+            Some(_) => return self.fail(&format!("Value does not fit in a word: {}", token)),
+            None => {
+                self.fixups.push(Fixup {
+                    at: self.out.len(),
+                    label: token.to_string(),
+                    line: self.line(),
+                });
+                self.emit(0);
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits an instruction taking a 12-bit address, which may be a label defined later.
+    fn emit_address(&mut self, op: u16) -> Result<()> {
+        let token = self.next()?;
+        match self.number(token) {
+            Some(n) if (0..=0xFFF).contains(&n) => self.emit(op | n as u16),
+            Some(_) => return self.fail(&format!("Address out of range: {}", token)),
+            None => {
+                self.fixups.push(Fixup {
+                    at: self.out.len(),
+                    label: token.to_string(),
+                    line: self.line(),
+                });
+                self.emit(op);
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<()> {
+        let token = self.next()?;
+        if self.macros.contains_key(token) {
+            return self.expand_macro(token);
+        }
+        match token {
+            ":" => {
+                let name = self.next()?;
+                if self.labels.insert(name.to_string(), self.here()).is_some() {
+                    return self.fail(&format!("Label defined twice: {}", name));
+                }
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let r = self.register()?;
+                self.aliases.insert(name.to_string(), r);
+            }
+            ":const" => {
+                let name = self.next()?;
+                let token = self.next()?;
+                let value = match self.number(token) {
+                    Some(n) => n,
+                    None => match self.labels.get(token) {
+                        Some(&addr) => addr as i32,
+                        None => {
+                            return self.fail(&format!("Expected a value but found `{}`", token))
+                        }
+                    },
+                };
+                self.define(name, value)?;
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let mut expr = Vec::new();
+                loop {
+                    match self.next()? {
+                        "}" => break,
+                        token => expr.push(token),
+                    }
+                }
+                let value = self.evaluate(&expr)?;
+                self.define(name, value)?;
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let b = self.byte()?;
+                self.out.push(b);
+            }
+            "db" => {
+                for _ in 0..self.rest_of_line() {
+                    let b = self.byte()?;
+                    self.out.push(b);
+                }
+            }
+            "dw" => {
+                for _ in 0..self.rest_of_line() {
+                    self.word()?;
+                }
+            }
+            "pixels" => {
+                for _ in 0..self.rest_of_line() {
+                    let row = self.next()?;
+                    let b = parse_pixels(row).ok_or_else(|| {
+                        error(
+                            self.line(),
+                            &format!("Expected up to 8 pixels of `X` and `.` but found `{}`", row),
+                        )
+                    })?;
+                    self.out.push(b);
+                }
+            }
+            "incbin" => {
+                let name = self.next()?.trim_matches('"');
+                let data = fs::read(self.dir.join(name))
+                    .map_err(|e| error(self.line(), &format!("Cannot read {}: {}", name, e)))?;
+                self.out.extend(data);
+            }
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "exit" => self.emit(0x00FD),
+            "jump" => self.emit_address(0x1000)?,
+            "jump0" => self.emit_address(0xB000)?,
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(0xF033 | u16::from(x) << 8);
+            }
+            "save" => {
+                let x = self.register()?;
+                self.emit(0xF055 | u16::from(x) << 8);
+            }
+            "load" => {
+                let x = self.register()?;
+                self.emit(0xF065 | u16::from(x) << 8);
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(0xF075 | u16::from(x) << 8);
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(0xF085 | u16::from(x) << 8);
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | u16::from(x) << 8 | u16::from(y) << 4 | u16::from(n));
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let op = match token {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit(op | u16::from(x) << 8);
+            }
+            "audio" => self.emit(0xF002),
+            "i" => self.index_statement()?,
+            "if" => {
+                let condition = self.condition()?;
+                match self.next()? {
+                    "then" => {
+                        self.emit(condition.0);
+                        self.statement()?;
+                    }
+                    "begin" => {
+                        self.emit(condition.inverse());
+                        self.blocks.push(Block {
+                            jump: self.out.len(),
+                            line: self.line(),
+                        });
+                        self.emit(0x1000);
+                    }
+                    other => {
+                        return self
+                            .fail(&format!("Expected `then` or `begin` but found `{}`", other));
+                    }
+                }
+            }
+            "else" => {
+                let block = self
+                    .blocks
+                    .pop()
+                    .ok_or_else(|| error(self.line(), "`else` without `begin`"))?;
+                let jump = self.out.len();
+                self.emit(0x1000);
+                self.patch_jump(block.jump);
+                self.blocks.push(Block {
+                    jump,
+                    line: block.line,
+                });
+            }
+            "end" => {
+                let block = self
+                    .blocks
+                    .pop()
+                    .ok_or_else(|| error(self.line(), "`end` without `begin`"))?;
+                self.patch_jump(block.jump);
+            }
+            "loop" => self.loops.push(Loop {
+                start: self.here(),
+                exits: Vec::new(),
+                line: self.line(),
+            }),
+            "while" => {
+                let condition = self.condition()?;
+                if self.loops.is_empty() {
+                    return self.fail("`while` outside of a loop");
+                }
+                // Leave the loop unless the condition holds
+                self.emit(condition.inverse());
+                let exit = self.out.len();
+                self.loops.last_mut().unwrap().exits.push(exit);
+                self.emit(0x1000);
+            }
+            "again" => {
+                let l = self
+                    .loops
+                    .pop()
+                    .ok_or_else(|| error(self.line(), "`again` without `loop`"))?;
+                self.emit(0x1000 | l.start as u16);
+                for exit in l.exits {
+                    self.patch_jump(exit);
+                }
+            }
+            _ if self.is_register(token) => {
+                self.pos -= 1;
+                self.register_statement()?;
+            }
+            _ => match self.number(token) {
+                // Bare numbers are raw data bytes
+                Some(n) if (-128..=255).contains(&n) => self.out.push(n as u8),
+                Some(_) => return self.fail(&format!("Value does not fit in a byte: {}", token)),
+                // Anything else is a call to a subroutine label
+                None => {
+                    self.pos -= 1;
+                    self.emit_address(0x2000)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, value: i32) -> Result<()> {
+        if self.consts.insert(name.to_string(), value).is_some() {
+            return self.fail(&format!("Constant defined twice: {}", name));
+        }
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<()> {
+        let name = self.next()?;
+        let line = self.line();
+        let mut params = Vec::new();
+        loop {
+            match self.next()? {
+                "{" => break,
+                param => params.push(param),
+            }
+        }
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = *self
+                .tokens
+                .get(self.pos)
+                .ok_or_else(|| error(line, "`:macro` without closing `}`"))?;
+            self.pos += 1;
+            match token.text {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name.to_string(), Macro { params, body });
+        Ok(())
+    }
+
+    /// Replaces a macro invocation with its body, arguments substituted, so the expansion is
+    /// assembled next.
+    fn expand_macro(&mut self, name: &str) -> Result<()> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return self.fail(&format!("Macro expands without end: {}", name));
+        }
+        let count = self.macros[name].params.len();
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            args.push(self.next()?);
+        }
+        let m = &self.macros[name];
+        let expansion: Vec<Token<'a>> = m
+            .body
+            .iter()
+            .map(
+                |token| match m.params.iter().position(|p| *p == token.text) {
+                    Some(i) => Token {
+                        text: args[i],
+                        line: token.line,
+                    },
+                    None => *token,
+                },
+            )
+            .collect();
+        self.tokens.splice(self.pos..self.pos, expansion);
+        Ok(())
+    }
+
+    /// Evaluates a `:calc` expression. Operators bind as in C, from `*` `/` `%` (tightest)
+    /// through `+` `-`, `<<` `>>`, `&`, `^`, to `|`.
+    fn evaluate(&self, expr: &[&str]) -> Result<i32> {
+        let mut pos = 0;
+        let value = self.binary(expr, &mut pos, 0)?;
+        match expr.get(pos) {
+            None => Ok(value),
+            Some(token) => self.fail(&format!("Unexpected `{}` in expression", token)),
+        }
+    }
+
+    fn binary(&self, expr: &[&str], pos: &mut usize, level: usize) -> Result<i32> {
+        const LEVELS: [&[&str]; 6] = [
+            &["|"],
+            &["^"],
+            &["&"],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.operand(expr, pos);
+        }
+        let mut lhs = self.binary(expr, pos, level + 1)?;
+        while let Some(&op) = expr.get(*pos).filter(|op| LEVELS[level].contains(op)) {
+            *pos += 1;
+            let rhs = self.binary(expr, pos, level + 1)?;
+            lhs = match op {
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                _ if rhs == 0 => return self.fail("Division by zero in expression"),
+                "/" => lhs / rhs,
+                _ => lhs % rhs,
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn operand(&self, expr: &[&str], pos: &mut usize) -> Result<i32> {
+        let token = match expr.get(*pos) {
+            Some(token) => *token,
+            None => return self.fail("Incomplete expression"),
+        };
+        *pos += 1;
+        match token {
+            "(" => {
+                let value = self.binary(expr, pos, 0)?;
+                if expr.get(*pos) != Some(&")") {
+                    return self.fail("Expected `)` in expression");
+                }
+                *pos += 1;
+                Ok(value)
+            }
+            "-" => Ok(-self.operand(expr, pos)?),
+            "HERE" => Ok(self.here() as i32),
+            _ => match self
+                .number(token)
+                .or_else(|| self.labels.get(token).map(|&a| a as i32))
+            {
+                Some(value) => Ok(value),
+                None => self.fail(&format!("Unknown name in expression: {}", token)),
+            },
+        }
+    }
+
+    fn index_statement(&mut self) -> Result<()> {
+        match self.next()? {
+            ":=" => {
+                if self.tokens.get(self.pos).map(|t| t.text) == Some("hex") {
+                    self.pos += 1;
+                    let x = self.register()?;
+                    self.emit(0xF029 | u16::from(x) << 8);
+                } else {
+                    self.emit_address(0xA000)?;
+                }
+            }
+            "+=" => {
+                let x = self.register()?;
+                self.emit(0xF01E | u16::from(x) << 8);
+            }
+            other => return self.fail(&format!("Unsupported operation on i: `{}`", other)),
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self) -> Result<()> {
+        let x = u16::from(self.register()?) << 8;
+        let op = self.next()?;
+        let next = self.tokens.get(self.pos).map(|t| t.text);
+        let rhs_is_register = next.is_some_and(|t| self.is_register(t));
+        match op {
+            ":=" if next == Some("random") => {
+                self.pos += 1;
+                let kk = self.byte()?;
+                self.emit(0xC000 | x | u16::from(kk));
+            }
+            ":=" if next == Some("delay") => {
+                self.pos += 1;
+                self.emit(0xF007 | x);
+            }
+            ":=" if next == Some("key") => {
+                self.pos += 1;
+                self.emit(0xF00A | x);
+            }
+            ":=" | "+=" | "-=" if !rhs_is_register => {
+                let kk = self.byte()?;
+                match op {
+                    ":=" => self.emit(0x6000 | x | u16::from(kk)),
+                    "+=" => self.emit(0x7000 | x | u16::from(kk)),
+                    _ => self.emit(0x7000 | x | u16::from(kk.wrapping_neg())),
+                }
+            }
+            _ => {
+                let n = match op {
+                    ":=" => 0x0,
+                    "|=" => 0x1,
+                    "&=" => 0x2,
+                    "^=" => 0x3,
+                    "+=" => 0x4,
+                    "-=" => 0x5,
+                    ">>=" => 0x6,
+                    "=-" => 0x7,
+                    "<<=" => 0xE,
+                    _ => return self.fail(&format!("Unsupported register operation: `{}`", op)),
+                };
+                let y = u16::from(self.register()?) << 4;
+                self.emit(0x8000 | x | y | n);
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition> {
+        let x = u16::from(self.register()?) << 8;
+        let op = self.next()?;
+        let condition = match op {
+            "key" => Condition(0xE0A1 | x),
+            "-key" => Condition(0xE09E | x),
+            "==" | "!=" => {
+                let next = self.tokens.get(self.pos).map(|t| t.text);
+                let equal = op == "==";
+                if next.is_some_and(|t| self.is_register(t)) {
+                    let y = u16::from(self.register()?) << 4;
+                    Condition(if equal { 0x9000 } else { 0x5000 } | x | y)
+                } else {
+                    let kk = u16::from(self.byte()?);
+                    Condition(if equal { 0x4000 } else { 0x3000 } | x | kk)
+                }
+            }
+            _ => return self.fail(&format!("Unsupported comparison: `{}`", op)),
+        };
+        Ok(condition)
+    }
+}
+
+fn error(line: usize, message: &str) -> AssembleError {
+    AssembleError {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn parse_register(token: &str) -> Option<u8> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(c), None) | (Some('V'), Some(c), None) => c.to_digit(16).map(|d| d as u8),
+        _ => None,
+    }
+}
+
+/// Turns a sprite row like `X..XX` into a byte, filling from the most significant bit.
+fn parse_pixels(row: &str) -> Option<u8> {
+    if row.len() > 8 {
+        return None;
+    }
+    row.chars().enumerate().try_fold(0u8, |b, (i, c)| match c {
+        'X' | 'x' => Some(b | 0x80 >> i),
+        '.' => Some(b),
+        _ => None,
+    })
+}
+
+fn parse_number(token: &str) -> Option<i32> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i32::from_str_radix(bin, 2).ok()?
+    } else if digits.chars().all(|c| c.is_ascii_digit()) && !digits.is_empty() {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn assemble(source: &str) -> Result<Program> {
+        super::assemble(source, Path::new("."))
+    }
+
+    fn words(program: &Program) -> Vec<u16> {
+        program
+            .bytes
+            .chunks(2)
+            .map(|w| u16::from(w[0]) << 8 | u16::from(*w.get(1).unwrap_or(&0)))
+            .collect()
+    }
+
+    #[test]
+    fn assembles_basic_statements() {
+        let program = assemble(
+            ": main\n\
+             clear\n\
+             v0 := 5  v1 += 1  v2 -= 1  v3 := v4  v5 += v6  v7 =- v8  v9 <<= va\n\
+             i := glyph  i += v0  i := hex v1\n\
+             vb := random 0x3F  vc := key  vd := delay  delay := v0  buzzer := v1\n\
+             sprite v0 v1 8  bcd v2  save v3  load v4\n\
+             draw  jump main\n\
+             : draw return\n\
+             : glyph 0b11110000 -1 :byte 7",
+        )
+        .unwrap();
+        assert_eq!(
+            words(&program),
+            vec![
+                0x1202, 0x00E0, 0x6005, 0x7101, 0x72FF, 0x8340, 0x8564, 0x8787, 0x89AE, 0xA230,
+                0xF01E, 0xF129, 0xCB3F, 0xFC0A, 0xFD07, 0xF015, 0xF118, 0xD018, 0xF233, 0xF355,
+                0xF465, 0x222E, 0x1202, 0x00EE, 0xF0FF, 0x0700
+            ]
+        );
+        assert_eq!(program.symbols.addr_of("glyph"), Some(0x230));
+        assert_eq!(program.source_map.line_of(0x201), None);
+        assert_eq!(program.source_map.line_of(0x202), Some(2));
+        assert_eq!(program.source_map.line_of(0x22C), Some(7));
+        assert_eq!(program.source_map.line_of(0x232), Some(9));
+        assert_eq!(program.bytes.len(), 51);
+    }
+
+    #[test]
+    fn assembles_control_flow() {
+        let program = assemble(
+            ": main\n\
+             :alias x v3\n\
+             if x == 2 then x := 0\n\
+             if x != v1 begin x += 1 else x += 2 end\n\
+             loop\n\
+               while x key\n\
+               x += 1\n\
+             again",
+        )
+        .unwrap();
+        assert_eq!(
+            words(&program),
+            vec![
+                0x1202, 0x4302, 0x6300, 0x9310, 0x120E, 0x7301, 0x1210, 0x7302, 0xE39E, 0x1218,
+                0x7301, 0x1210
+            ]
+        );
+        assert_eq!(
+            words(&assemble(": main exit").unwrap()),
+            vec![0x1202, 0x00FD]
+        );
+    }
+
+    #[test]
+    fn assembles_xo_chip_audio() {
+        let program = assemble(": main i := tone audio pitch := v3\n: tone").unwrap();
+        assert_eq!(words(&program), vec![0x1202, 0xA208, 0xF002, 0xF33A]);
+    }
+
+    #[test]
+    fn assembles_flag_registers() {
+        let program = assemble(
+            ":macro keep-best reg { v0 := reg  saveflags v0 }
+             : main loadflags v1 keep-best v5",
+        )
+        .unwrap();
+        assert_eq!(words(&program), vec![0x1202, 0xF185, 0x8050, 0xF075]);
+    }
+
+    #[test]
+    fn expands_constants_and_macros() {
+        let program = assemble(
+            ":const SPEED 3\n\
+             :macro move reg amount { reg += amount }\n\
+             :macro twice stmt { stmt stmt }\n\
+             : main\n\
+             v0 := SPEED\n\
+             move v1 SPEED\n\
+             twice clear\n\
+             :calc SHIFTED { ( SPEED + 1 ) << 2 | 1 }\n\
+             :calc NEXT { HERE + 2 * 2 }\n\
+             v2 := SHIFTED  jump NEXT\n\
+             : table\n\
+             :calc TABLE_END { table + 8 - SPEED % 2 }\n\
+             i := TABLE_END",
+        )
+        .unwrap();
+        assert_eq!(
+            words(&program),
+            vec![0x1202, 0x6003, 0x7103, 0x00E0, 0x00E0, 0x6211, 0x120E, 0xA215]
+        );
+        assert!(assemble(":macro loop_forever { loop_forever }\n: main loop_forever").is_err());
+        assert!(assemble(":const A 1\n:const A 2").is_err());
+        assert_eq!(assemble(": main\n:calc X { 1 / 0 }").unwrap_err().line, 2);
+        assert!(assemble(":calc X { later }\n: later").is_err());
+    }
+
+    #[test]
+    fn assembles_data_directives() {
+        let dir = env::temp_dir().join("chip8-assembler-test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("level.bin"), [0xAB, 0xCD, 0xEF]).unwrap();
+        let program = super::assemble(
+            ": main\n\
+             db 1 2 0xFF\n\
+             clear\n\
+             : words dw 0x1234 -1 words\n\
+             : ball pixels .XX. XXXXXXXX ........\n\
+             incbin \"level.bin\"",
+            &dir,
+        )
+        .unwrap();
+        assert_eq!(
+            program.bytes,
+            vec![
+                0x12, 0x02, 0x01, 0x02, 0xFF, 0x00, 0xE0, 0x12, 0x34, 0xFF, 0xFF, 0x02, 0x07, 0x60,
+                0xFF, 0x00, 0xAB, 0xCD, 0xEF
+            ]
+        );
+        assert!(assemble(": main pixels XX..XX..X").is_err());
+        assert!(assemble(": main pixels XO").is_err());
+        assert_eq!(
+            super::assemble(": main\nincbin missing.bin", &dir)
+                .unwrap_err()
+                .line,
+            2
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let err = assemble(": main\n  v0 := 5\n  jump nowhere\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("nowhere"));
+        assert_eq!(assemble(": main\nv0 := 300").unwrap_err().line, 2);
+        assert_eq!(assemble(": main\nloop\nv0 += 1").unwrap_err().line, 2);
+        assert!(assemble("v0 := 1").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! The buzzer, played through the default output device (`--features audio`).
+//!
+//! Samples are generated on their own thread and handed to the device through a lock-free
+//! ring buffer, a few frames ahead of playback. The emulator only reports what the buzzer
+//! should be playing, so a stall in the main loop leaves the tone running rather than
+//! starving the device, and fast-forwarding shortens tones without changing their pitch.
+use crate::tone::{Beep, Synth, Tone};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How far ahead of playback samples are generated. This is also how long it takes a new tone
+/// to be heard.
+const BUFFER_AHEAD: Duration = Duration::from_millis(40);
+/// How often the generator tops up the ring buffer.
+const FILL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A single-producer, single-consumer queue of samples that neither side ever waits on.
+/// Samples are stored as the bits of an f32, and the read and write positions only ever
+/// grow, so comparing them tells a full buffer from an empty one.
+struct RingBuffer {
+    samples: Box<[AtomicU32]>,
+    read: AtomicUsize,
+    write: AtomicUsize,
+}
+
+impl RingBuffer {
+    fn new(capacity: usize) -> RingBuffer {
+        RingBuffer {
+            samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.write.load(Ordering::Acquire) - self.read.load(Ordering::Acquire)
+    }
+
+    /// Adds a sample, returning false if the buffer is full. Only the generator pushes.
+    fn push(&self, sample: f32) -> bool {
+        let write = self.write.load(Ordering::Relaxed);
+        if write - self.read.load(Ordering::Acquire) == self.samples.len() {
+            return false;
+        }
+        self.samples[write % self.samples.len()].store(sample.to_bits(), Ordering::Relaxed);
+        self.write.store(write + 1, Ordering::Release);
+        true
+    }
+
+    /// Takes the oldest sample. Only the device callback pops.
+    fn pop(&self) -> Option<f32> {
+        let read = self.read.load(Ordering::Relaxed);
+        if read == self.write.load(Ordering::Acquire) {
+            return None;
+        }
+        let sample =
+            f32::from_bits(self.samples[read % self.samples.len()].load(Ordering::Relaxed));
+        self.read.store(read + 1, Ordering::Release);
+        Some(sample)
+    }
+}
+
+/// Plays the buzzer until dropped.
+pub struct Audio {
+    /// Closing this channel ends the generator thread.
+    tones: Sender<Tone>,
+    tone: Tone,
+    muted: bool,
+    _stream: cpal::Stream,
+}
+
+impl Audio {
+    /// Opens the default output device and starts the generator thread, playing the beep as
+    /// configured.
+    pub fn start(beep: Beep) -> Result<Audio, Box<dyn std::error::Error>> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("No audio output device")?;
+        let supported = device.default_output_config()?;
+        let config = supported.config();
+        let capacity = (f64::from(config.sample_rate) * BUFFER_AHEAD.as_secs_f64()) as usize;
+        let ring = Arc::new(RingBuffer::new(capacity));
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::F32 => open_stream::<f32>(&device, &config, ring.clone())?,
+            cpal::SampleFormat::I16 => open_stream::<i16>(&device, &config, ring.clone())?,
+            cpal::SampleFormat::U16 => open_stream::<u16>(&device, &config, ring.clone())?,
+            format => return Err(format!("Unsupported sample format: {}", format).into()),
+        };
+        stream.play()?;
+
+        let (tones, rx) = mpsc::channel();
+        let synth = Synth::new(config.sample_rate, beep);
+        thread::spawn(move || generate(synth, &rx, &ring));
+        Ok(Audio {
+            tones,
+            tone: Tone::SILENT,
+            muted: false,
+            _stream: stream,
+        })
+    }
+
+    /// Switches to a new tone, once the samples already generated have played. While muted,
+    /// every tone is silent.
+    pub fn set_tone(&mut self, tone: Tone) {
+        if tone != self.tone {
+            self.tone = tone;
+            let on = tone.on && !self.muted;
+            let _ = self.tones.send(Tone { on, ..tone });
+        }
+    }
+
+    /// Silences the buzzer, or lets it be heard again. Returns whether it's now muted.
+    pub fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        let tone = std::mem::replace(&mut self.tone, Tone::SILENT);
+        self.set_tone(tone);
+        self.muted
+    }
+}
+
+/// Keeps the ring buffer topped up with samples of the latest tone, until the tone channel
+/// closes.
+fn generate(mut synth: Synth, tones: &Receiver<Tone>, ring: &RingBuffer) {
+    loop {
+        loop {
+            match tones.try_recv() {
+                Ok(tone) => synth.tone = tone,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        while ring.len() < ring.samples.len() {
+            ring.push(synth.sample());
+        }
+        thread::sleep(FILL_INTERVAL);
+    }
+}
+
+/// Opens an output stream that plays samples from the ring buffer on every channel, and
+/// silence if the generator falls behind.
+fn open_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    ring: Arc<RingBuffer>,
+) -> Result<cpal::Stream, Box<dyn std::error::Error>>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = usize::from(config.channels);
+    let stream = device.build_output_stream(
+        *config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            for frame in data.chunks_mut(channels) {
+                let sample = T::from_sample(ring.pop().unwrap_or(0.0));
+                frame.iter_mut().for_each(|s| *s = sample);
+            }
+        },
+        |e| tracing::warn!("Audio output: {}", e),
+        None,
+    )?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_wraps_around() {
+        let ring = RingBuffer::new(3);
+        assert_eq!(ring.pop(), None);
+        for round in 0..4 {
+            let base = round as f32 * 10.0;
+            assert!(ring.push(base) && ring.push(base + 1.0) && ring.push(base + 2.0));
+            assert!(!ring.push(base + 3.0));
+            assert_eq!(ring.len(), 3);
+            assert_eq!(ring.pop(), Some(base));
+            assert_eq!(ring.pop(), Some(base + 1.0));
+            assert_eq!(ring.pop(), Some(base + 2.0));
+            assert_eq!(ring.pop(), None);
+        }
+    }
+}
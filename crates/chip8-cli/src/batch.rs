@@ -0,0 +1,160 @@
+//! Regression runs over a whole ROM collection (`chip8 batch`). Every ROM runs headless for
+//! a fixed number of frames, and the hash of the screen it ends on goes into a manifest.
+//! Later runs compare against the manifest to report which ROMs changed behavior.
+//!
+//! Manifests are plain text, one ROM per line, with `error` in place of the hash for ROMs
+//! that faulted:
+//!
+//! ```text
+//! 035d51ba17427bf3 PONG
+//! error BROKEN
+//! ```
+use crate::chip8::{Chip8, Fault};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Frames each ROM runs for when `--frames` is left out, ten seconds of play.
+pub const DEFAULT_FRAMES: u64 = 600;
+/// Where the manifest goes when `-o` is left out, inside the ROM directory.
+pub const MANIFEST_NAME: &str = "chip8-batch.txt";
+
+/// How a ROM's run ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RunResult {
+    Screen(u64),
+    Error,
+}
+
+impl fmt::Display for RunResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunResult::Screen(hash) => write!(f, "{:016x}", hash),
+            RunResult::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Results by ROM name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub results: BTreeMap<String, RunResult>,
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Manifest, Box<dyn std::error::Error>> {
+        let mut manifest = Manifest::default();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let (result, name) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("Invalid manifest line: {}", line))?;
+            let result = match result {
+                "error" => RunResult::Error,
+                hash => RunResult::Screen(u64::from_str_radix(hash, 16)?),
+            };
+            manifest.results.insert(name.to_string(), result);
+        }
+        Ok(manifest)
+    }
+
+    /// Describes every ROM whose result differs from `baseline`, including ROMs that were
+    /// added or removed since.
+    pub fn changes_since(&self, baseline: &Manifest) -> Vec<String> {
+        let mut changes = Vec::new();
+        for (name, result) in self.results.iter() {
+            match baseline.results.get(name) {
+                None => changes.push(format!("{}: new ({})", name, result)),
+                Some(old) if old != result => {
+                    changes.push(format!("{}: changed from {} to {}", name, old, result))
+                }
+                _ => {}
+            }
+        }
+        for name in baseline.results.keys() {
+            if !self.results.contains_key(name) {
+                changes.push(format!("{}: removed", name));
+            }
+        }
+        changes
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, result) in self.results.iter() {
+            writeln!(f, "{} {}", result, name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a machine with a ROM loaded for `frames` frames with no keys held, returning the
+/// hash of the final screen.
+pub fn run(chip8: &mut Chip8, frames: u64) -> Result<u64, Fault> {
+    for _ in 0..frames {
+        chip8.run_frame()?;
+    }
+    Ok(chip8.screen_hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(results: &[(&str, RunResult)]) -> Manifest {
+        Manifest {
+            results: results
+                .iter()
+                .map(|(name, result)| (name.to_string(), *result))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn manifest_round_trips_through_text() {
+        let m = manifest(&[
+            ("PONG", RunResult::Screen(0x035d_51ba_1742_7bf3)),
+            ("SPACE INVADERS", RunResult::Error),
+        ]);
+        assert_eq!(
+            m.to_string(),
+            "035d51ba17427bf3 PONG\nerror SPACE INVADERS\n"
+        );
+        assert_eq!(Manifest::parse(&m.to_string()).unwrap(), m);
+        assert!(Manifest::parse("PONG").is_err());
+    }
+
+    #[test]
+    fn reports_changed_roms() {
+        let baseline = manifest(&[
+            ("BLINKY", RunResult::Screen(1)),
+            ("PONG", RunResult::Screen(2)),
+            ("UFO", RunResult::Screen(3)),
+        ]);
+        let current = manifest(&[
+            ("BLINKY", RunResult::Screen(1)),
+            ("PONG", RunResult::Error),
+            ("TETRIS", RunResult::Screen(4)),
+        ]);
+        assert_eq!(
+            current.changes_since(&baseline),
+            vec![
+                "PONG: changed from 0000000000000002 to error",
+                "TETRIS: new (0000000000000004)",
+                "UFO: removed",
+            ]
+        );
+        assert!(baseline.changes_since(&baseline).is_empty());
+    }
+
+    #[test]
+    fn hashes_final_screen() {
+        // Draws the "0" glyph, then loops
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&[0x00, 0xE0, 0xD0, 0x05, 0x12, 0x04]);
+        let hash = run(&mut chip8, 10).unwrap();
+        let mut again = Chip8::with_seed(0);
+        again.load_program(&[0x00, 0xE0, 0xD0, 0x05, 0x12, 0x04]);
+        assert_eq!(run(&mut again, 3), Ok(hash));
+        assert_ne!(hash, Chip8::with_seed(0).screen_hash());
+    }
+}
@@ -0,0 +1,227 @@
+//! A ROM picker drawn on the CHIP-8 display and driven entirely by the hex keypad, so the
+//! emulator can be used without a keyboard (`--romdir`).
+use crate::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Keypad keys for moving the selection and starting the selected ROM.
+pub const UP_KEY: u8 = 0x5;
+pub const DOWN_KEY: u8 = 0x8;
+pub const SELECT_KEY: u8 = 0x6;
+/// Glyphs are 3x5 pixels plus a pixel of spacing, which leaves room for 5 lines of 15
+/// characters on the 64x32 display.
+const CHAR_WIDTH: usize = 4;
+const LINE_HEIGHT: usize = 6;
+const LINES: usize = SCREEN_HEIGHT / LINE_HEIGHT;
+const LINE_CHARS: usize = (SCREEN_WIDTH - 1) / CHAR_WIDTH;
+
+pub struct Browser {
+    roms: Vec<PathBuf>,
+    selected: usize,
+    /// Keys held on the previous update, so holding a key moves the selection only once.
+    held: [bool; 16],
+}
+
+impl Browser {
+    pub fn new(roms: Vec<PathBuf>) -> Browser {
+        Browser {
+            roms,
+            selected: 0,
+            held: [false; 16],
+        }
+    }
+
+    pub fn scan(dir: &Path) -> Result<Browser, Box<dyn std::error::Error>> {
+        Ok(Browser::new(scan_roms(dir)?))
+    }
+
+    pub fn selected(&self) -> &Path {
+        &self.roms[self.selected]
+    }
+
+    /// Moves the selection for newly pressed keys, returning the ROM to run once one is chosen.
+    /// The selection wraps around at either end of the list.
+    pub fn press(&mut self, keys: &[bool; 16]) -> Option<&Path> {
+        let pressed = |key: u8| keys[key as usize] && !self.held[key as usize];
+        let (up, down, select) = (pressed(UP_KEY), pressed(DOWN_KEY), pressed(SELECT_KEY));
+        self.held = *keys;
+        if up {
+            self.selected = (self.selected + self.roms.len() - 1) % self.roms.len();
+        } else if down {
+            self.selected = (self.selected + 1) % self.roms.len();
+        } else if select {
+            return Some(self.selected());
+        }
+        None
+    }
+
+    /// Draws the page of ROM names around the selection, with the selected one inverted.
+    pub fn render(&self) -> Vec<u8> {
+        let mut screen = vec![0u8; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let top = (self.selected + 1).saturating_sub(LINES);
+        for (line, rom) in self.roms.iter().enumerate().skip(top).take(LINES) {
+            let inverted = line == self.selected;
+            let y = (line - top) * LINE_HEIGHT;
+            if inverted {
+                let band = y * SCREEN_WIDTH..(y + LINE_HEIGHT) * SCREEN_WIDTH;
+                screen[band].iter_mut().for_each(|pixel| *pixel = 1);
+            }
+            let name = rom
+                .file_name()
+                .map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+            for (i, c) in name.chars().take(LINE_CHARS).enumerate() {
+                draw_glyph(&mut screen, 1 + i * CHAR_WIDTH, y + 1, glyph(c), inverted);
+            }
+        }
+        screen
+    }
+}
+
+/// Lists the ROMs in a directory by name. Besides `.ch8` files this takes Octo source and
+/// files without an extension, which is how the bundled games are named.
+pub fn scan_roms(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut roms = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_rom(&path) {
+            roms.push(path);
+        }
+    }
+    if roms.is_empty() {
+        return Err(format!("No ROMs found in {}", dir.display()).into());
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+fn is_rom(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => ["ch8", "c8", "8o"].contains(&ext.to_ascii_lowercase().as_str()),
+        None => !path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.')),
+    }
+}
+
+fn draw_glyph(screen: &mut [u8], x: usize, y: usize, rows: [u8; 5], inverted: bool) {
+    for (dy, bits) in rows.iter().enumerate() {
+        for dx in 0..3 {
+            if bits & (0b100 >> dx) != 0 {
+                screen[(y + dy) * SCREEN_WIDTH + x + dx] = if inverted { 0 } else { 1 };
+            }
+        }
+    }
+}
+
+/// A 3x5 font covering what shows up in file names and error messages. Lowercase letters are
+/// drawn as uppercase and anything else as a question mark.
+#[rustfmt::skip]
+pub fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '[' => [0b011, 0b010, 0b010, 0b010, 0b011],
+        ']' => [0b110, 0b010, 0b010, 0b010, 0b110],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(held: &[u8]) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for key in held {
+            keys[*key as usize] = true;
+        }
+        keys
+    }
+
+    #[test]
+    fn navigates_with_keypad() {
+        let roms = ["PONG", "TETRIS", "UFO"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let mut browser = Browser::new(roms);
+        assert_eq!(browser.press(&keys(&[DOWN_KEY])), None);
+        // Holding the key doesn't keep moving
+        assert_eq!(browser.press(&keys(&[DOWN_KEY])), None);
+        assert_eq!(browser.selected(), Path::new("TETRIS"));
+        browser.press(&keys(&[]));
+        browser.press(&keys(&[UP_KEY]));
+        browser.press(&keys(&[]));
+        browser.press(&keys(&[UP_KEY]));
+        assert_eq!(browser.selected(), Path::new("UFO"));
+        assert_eq!(browser.press(&keys(&[SELECT_KEY])), Some(Path::new("UFO")));
+    }
+
+    #[test]
+    fn renders_selection_inverted() {
+        let roms = (0..8).map(|i| PathBuf::from(format!("ROM{}", i))).collect();
+        let mut browser = Browser::new(roms);
+        assert_eq!(browser.render()[0], 1);
+        assert_eq!(browser.render()[LINE_HEIGHT * SCREEN_WIDTH], 0);
+        // Scrolls to keep the selection on the last line
+        for _ in 0..6 {
+            browser.press(&keys(&[DOWN_KEY]));
+            browser.press(&keys(&[]));
+        }
+        let screen = browser.render();
+        assert_eq!(screen[0], 0);
+        assert_eq!(screen[(LINES - 1) * LINE_HEIGHT * SCREEN_WIDTH], 1);
+    }
+
+    #[test]
+    fn recognizes_rom_files() {
+        assert!(is_rom(Path::new("games/chip/PONG")));
+        assert!(is_rom(Path::new("games/pong.CH8")));
+        assert!(is_rom(Path::new("games/pong.8o")));
+        assert!(!is_rom(Path::new("games/pong.txt")));
+        assert!(!is_rom(Path::new("games/.hidden")));
+    }
+}
@@ -0,0 +1,443 @@
+use crate::chip8::Chip8;
+use crate::disasm;
+use crate::opcode::Opcode;
+use crate::source_map::SourceMap;
+use crate::symbols::Symbols;
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::io;
+
+/// How many frames can be stepped back through: five minutes at 60 frames per second.
+pub const HISTORY_LEN: usize = 5 * 60 * 60;
+/// Favors speed, since a delta is compressed every frame. Deltas are mostly zeros, which
+/// compress well at any level.
+const COMPRESSION_LEVEL: i32 = 1;
+
+/// Undo information for a single step: the save state from before it, XORed with the one
+/// after it and compressed with zstd. Everything the step left alone XORs to zero, so a
+/// delta that changed a few registers takes a few dozen bytes.
+struct Delta {
+    /// Length of the save state from before, which is different after calls and returns.
+    len: usize,
+    xor: Vec<u8>,
+    frame: Frame,
+}
+
+/// A frame that was stepped, with the input it was fed, so that it can be run again.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub number: u64,
+    pub input: [bool; 16],
+}
+
+/// Journal of recently run steps, allowing execution to be rewound one step at a time.
+/// Each entry only stores what changed, compressed, so minutes of history fit in a few
+/// megabytes.
+pub struct History {
+    deltas: VecDeque<Delta>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            deltas: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+    }
+
+    /// Records a step, given the save state from before it ran and the frame it was.
+    pub fn record(&mut self, before: Vec<u8>, chip8: &Chip8, frame: Frame) -> io::Result<()> {
+        let after = chip8.save_state();
+        let xor = xor(&before, &after);
+        let delta = Delta {
+            len: before.len(),
+            xor: zstd::bulk::compress(&xor, COMPRESSION_LEVEL)?,
+            frame,
+        };
+
+        if self.deltas.len() == self.capacity {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+        Ok(())
+    }
+
+    /// Restores the state from before the most recently recorded step, returning the frame it
+    /// was. Returns None if there is nothing left to step back through.
+    pub fn step_back(
+        &mut self,
+        chip8: &mut Chip8,
+    ) -> Result<Option<Frame>, Box<dyn std::error::Error>> {
+        let delta = match self.deltas.pop_back() {
+            Some(delta) => delta,
+            None => return Ok(None),
+        };
+        let xor_bytes = zstd::bulk::decompress(&delta.xor, delta.len)?;
+        chip8.load_state(&xor(&xor_bytes, &chip8.save_state()))?;
+        Ok(Some(delta.frame))
+    }
+}
+
+/// XORs `b` into `a`, as far as `a` goes, reading past the end of `b` as zeros.
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter()
+        .enumerate()
+        .map(|(i, x)| x ^ b.get(i).copied().unwrap_or(0))
+        .collect()
+}
+
+/// Why the debugger paused on its own.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint,
+    SteppedOver,
+    SteppedOut,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Breakpoint => write!(f, "Breakpoint"),
+            Stop::SteppedOver => write!(f, "Stepped over"),
+            Stop::SteppedOut => write!(f, "Stepped out"),
+        }
+    }
+}
+
+/// Interactive debugging session: pausing, breakpoints, single-stepping, stepping over and
+/// out of subroutines, and stepping backwards.
+pub struct Debugger {
+    paused: bool,
+    history: History,
+    breakpoints: BTreeSet<usize>,
+    /// A temporary breakpoint for stepping over or out of a subroutine: pause as soon as the
+    /// stack is back down to this depth.
+    return_depth: Option<(usize, Stop)>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger {
+            paused: false,
+            history: History::new(HISTORY_LEN),
+            breakpoints: BTreeSet::new(),
+            return_depth: None,
+        }
+    }
+}
+
+impl Debugger {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pausing or resuming by hand drops any step over or out still under way.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.return_depth = None;
+    }
+
+    /// Resumes until the subroutine called by the instruction at PC returns. Returns false,
+    /// leaving things as they are, if that instruction isn't a call.
+    pub fn step_over(&mut self, chip8: &Chip8) -> bool {
+        if !matches!(chip8.opcode_at(chip8.pc()), Some(Opcode::CallSubroutine(_))) {
+            return false;
+        }
+        self.paused = false;
+        self.return_depth = Some((chip8.stack_depth(), Stop::SteppedOver));
+        true
+    }
+
+    /// Resumes until the current subroutine returns. Returns false if there isn't one.
+    pub fn step_out(&mut self, chip8: &Chip8) -> bool {
+        if chip8.stack_depth() == 0 {
+            return false;
+        }
+        self.paused = false;
+        self.return_depth = Some((chip8.stack_depth() - 1, Stop::SteppedOut));
+        true
+    }
+
+    pub fn history(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Adds a breakpoint, or removes it if one is already set. Returns whether it is now set.
+    pub fn toggle_breakpoint(&mut self, addr: usize) -> bool {
+        if self.breakpoints.remove(&addr) {
+            false
+        } else {
+            self.breakpoints.insert(addr)
+        }
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// The breakpoints as text to save, one hex address per line.
+    pub fn saved_breakpoints(&self) -> String {
+        self.breakpoints
+            .iter()
+            .map(|addr| format!("0x{:03X}\n", addr))
+            .collect()
+    }
+
+    /// Sets the breakpoints saved by `saved_breakpoints`, adding to any already set.
+    pub fn restore_breakpoints(&mut self, text: &str) -> Result<(), String> {
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let addr = usize::from_str_radix(line.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("Invalid breakpoint: {}", line))?;
+            self.breakpoints.insert(addr);
+        }
+        Ok(())
+    }
+
+    /// Pauses if execution has reached a breakpoint, or the end of a step over or out.
+    /// Returns why it did.
+    pub fn check_breakpoint(&mut self, chip8: &Chip8) -> Option<Stop> {
+        if self.paused {
+            return None;
+        }
+        let stop = match self.return_depth {
+            Some((depth, stop)) if chip8.stack_depth() <= depth => stop,
+            _ if self.breakpoints.contains(&chip8.pc()) => Stop::Breakpoint,
+            _ => return None,
+        };
+        self.set_paused(true);
+        Some(stop)
+    }
+}
+
+/// Describes the machine state and the instruction about to execute, for display while paused.
+pub fn describe(chip8: &Chip8, symbols: &Symbols, source_map: &SourceMap) -> String {
+    let reg = chip8.registers();
+    let mut regs = String::new();
+    for (i, v) in reg.iter().enumerate() {
+        regs += &format!("V{:X}={:02X} ", i, v);
+    }
+    let location = match symbols.locate(chip8.pc()) {
+        Some(label) => format!(" <{}>", label),
+        None => String::new(),
+    };
+    let source = match source_map.locate(chip8.pc()) {
+        Some(line) => format!(" ({})", line),
+        None => String::new(),
+    };
+    let mut text = format!(
+        "{:03X}{}{}: {:04X}  {}\n{}\nI={:03X} DT={:02X} ST={:02X} SP={}",
+        chip8.pc(),
+        location,
+        source,
+        chip8.instruction_at(chip8.pc()),
+        match chip8.opcode_at(chip8.pc()) {
+            Some(op) => disasm::format_opcode(op, symbols),
+            None => "(invalid)".to_string(),
+        },
+        regs.trim_end(),
+        chip8.i_addr(),
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        chip8.stack().len()
+    );
+    let stack = call_stack(chip8, symbols);
+    if !stack.is_empty() {
+        text += "\n";
+        text += &stack;
+    }
+    text
+}
+
+/// Lists the subroutines being run, innermost first, each with where it returns to. They
+/// are named by the CALL just before each return address, by label when there is one.
+pub fn call_stack(chip8: &Chip8, symbols: &Symbols) -> String {
+    let name = |addr: usize| match symbols.locate(addr) {
+        Some(label) => format!("{:03X} <{}>", addr, label),
+        None => format!("{:03X}", addr),
+    };
+    chip8
+        .stack()
+        .iter()
+        .rev()
+        .map(|&ret| {
+            let routine = match chip8.opcode_at(ret.wrapping_sub(2)) {
+                Some(Opcode::CallSubroutine(nnn)) => name(nnn),
+                _ => "???".to_string(),
+            };
+            format!("  in {}, returning to {}", routine, name(ret))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_back_through_history() {
+        let mut chip8 = Chip8::with_seed(0);
+        // LD V0, 0x11; CALL 0x206; (unused); LD V0, 0x22; JP 0x206
+        chip8.load_program(&[0x60, 0x11, 0x22, 0x06, 0x00, 0x00, 0x60, 0x22, 0x12, 0x06]);
+        let start = chip8.save_state();
+        let mut history = History::new(16);
+        let mut states = vec![];
+        for number in 0..4 {
+            let before = chip8.save_state();
+            states.push(before.clone());
+            chip8.tick().unwrap();
+            let input = [number == 2; 16];
+            history
+                .record(before, &chip8, Frame { number, input })
+                .unwrap();
+        }
+
+        while let Some(expected) = states.pop() {
+            let frame = history.step_back(&mut chip8).unwrap().unwrap();
+            assert_eq!(frame.number, states.len() as u64);
+            assert_eq!(frame.input, [frame.number == 2; 16]);
+            assert_eq!(chip8.save_state(), expected);
+        }
+        assert_eq!(chip8.save_state(), start);
+        assert_eq!(history.step_back(&mut chip8).unwrap(), None);
+    }
+
+    #[test]
+    fn pauses_at_breakpoints() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&[0x60, 0x01, 0x61, 0x02, 0x12, 0x00]);
+        let mut debugger = Debugger::default();
+        assert!(debugger.toggle_breakpoint(0x204));
+        chip8.tick().unwrap();
+        assert_eq!(debugger.check_breakpoint(&chip8), None);
+        chip8.tick().unwrap();
+        assert_eq!(debugger.check_breakpoint(&chip8), Some(Stop::Breakpoint));
+        assert!(debugger.is_paused());
+        assert!(!debugger.toggle_breakpoint(0x204));
+        assert_eq!(debugger.breakpoints().count(), 0);
+
+        debugger.toggle_breakpoint(0x2A0);
+        debugger.toggle_breakpoint(0x204);
+        let saved = debugger.saved_breakpoints();
+        assert_eq!(saved, "0x204\n0x2A0\n");
+        let mut restored = Debugger::default();
+        restored.restore_breakpoints(&saved).unwrap();
+        assert_eq!(
+            restored.breakpoints().collect::<Vec<_>>(),
+            vec![0x204, 0x2A0]
+        );
+        assert!(restored.restore_breakpoints("0x2G0").is_err());
+    }
+
+    #[test]
+    fn steps_over_and_out_of_calls() {
+        let mut chip8 = Chip8::with_seed(0);
+        // CALL 0x206; JP 0x202; (unused); CALL 0x20A; RET; RET
+        chip8.load_program(&[
+            0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x22, 0x0A, 0x00, 0xEE, 0x00, 0xEE,
+        ]);
+        let mut debugger = Debugger::default();
+        let run = |debugger: &mut Debugger, chip8: &mut Chip8| loop {
+            chip8.tick().unwrap();
+            if let Some(stop) = debugger.check_breakpoint(chip8) {
+                return stop;
+            }
+        };
+        assert!(debugger.step_over(&chip8));
+        assert_eq!(run(&mut debugger, &mut chip8), Stop::SteppedOver);
+        assert_eq!(chip8.pc(), 0x202);
+        assert!(!debugger.step_over(&chip8));
+        assert!(!debugger.step_out(&chip8));
+
+        // Into the outer call, then out of it from inside the inner one
+        chip8.set_pc(0x200);
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.stack_depth(), 2);
+        assert!(debugger.step_out(&chip8));
+        assert_eq!(run(&mut debugger, &mut chip8), Stop::SteppedOut);
+        assert_eq!((chip8.pc(), chip8.stack_depth()), (0x208, 1));
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&[0x70, 0x01, 0x12, 0x00]);
+        let mut history = History::new(3);
+        for number in 0..10 {
+            let before = chip8.save_state();
+            chip8.tick().unwrap();
+            let frame = Frame {
+                number,
+                input: [false; 16],
+            };
+            history.record(before, &chip8, frame).unwrap();
+        }
+        assert_eq!(history.len(), 3);
+    }
+
+    #[test]
+    fn keeps_minutes_of_history_small() {
+        let mut chip8 = Chip8::with_seed(0);
+        // Count in V0 and flip a sprite on and off forever
+        chip8.load_program(&[0x70, 0x01, 0xD1, 0x15, 0x12, 0x00]);
+        chip8.set_instructions_per_frame(Some(8));
+        // A minute of frames
+        let mut history = History::new(HISTORY_LEN);
+        for number in 0..60 * 60 {
+            let before = chip8.save_state();
+            chip8.run_frame().unwrap();
+            let frame = Frame {
+                number,
+                input: [false; 16],
+            };
+            history.record(before, &chip8, frame).unwrap();
+        }
+        let size: usize = history.deltas.iter().map(|d| d.xor.len()).sum();
+        assert!(size < 1024 * 1024, "{} bytes of history", size);
+        let now = chip8.save_state();
+        assert!(history.step_back(&mut chip8).unwrap().is_some());
+        assert_ne!(chip8.save_state(), now);
+    }
+
+    #[test]
+    fn describes_source_locations() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&[0x60, 0x01, 0xFF, 0xFF]);
+        let symbols = Symbols::parse("main 0x200").unwrap();
+        let source_map = SourceMap::parse("file game.8o\n0x200 3\n0x202 4").unwrap();
+        let text = describe(&chip8, &symbols, &source_map);
+        assert!(text.starts_with("200 <main> (game.8o:3): 6001  LD V0, 0x01\n"));
+        chip8.tick().unwrap();
+        let text = describe(&chip8, &symbols, &source_map);
+        assert!(text.starts_with("202 <main+2> (game.8o:4): FFFF  (invalid)\n"));
+        assert!(chip8.tick().is_err());
+    }
+
+    #[test]
+    fn lists_call_stack() {
+        let mut chip8 = Chip8::with_seed(0);
+        // CALL draw; (unused); draw: CALL wait; (unused); wait: JP wait
+        chip8.load_program(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00, 0x12, 0x08]);
+        let symbols = Symbols::parse("main 0x200\ndraw 0x204\nwait 0x208").unwrap();
+        assert_eq!(call_stack(&chip8, &symbols), "");
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(
+            call_stack(&chip8, &symbols),
+            "  in 208 <wait>, returning to 206 <draw+2>\n  \
+             in 204 <draw>, returning to 202 <main+2>"
+        );
+        let text = describe(&chip8, &symbols, &SourceMap::default());
+        assert!(text.ends_with(&format!("SP=2\n{}", call_stack(&chip8, &symbols))));
+    }
+}
@@ -0,0 +1,273 @@
+//! Reconstructs Octo source from a ROM. Code is found by following every path execution can
+//! take from the entry point, so whatever is never reached (sprites, tables, padding) is
+//! emitted as data. Call targets become subroutines, jump targets and `i` targets get labels,
+//! and single skipped instructions are written as `if ... then`.
+use crate::opcode::Opcode;
+use std::collections::{BTreeMap, BTreeSet};
+
+const BASE_ADDRESS: usize = 0x200;
+const DATA_BYTES_PER_LINE: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Subroutine,
+    Code,
+    Data,
+}
+
+struct Analysis {
+    end: usize,
+    /// Addresses of instructions reached by execution.
+    code: BTreeSet<usize>,
+    labels: BTreeMap<usize, LabelKind>,
+}
+
+impl Analysis {
+    fn label(&mut self, addr: usize, kind: LabelKind) {
+        // Addresses outside the ROM (the font, say) stay as numbers
+        if addr < BASE_ADDRESS || addr >= self.end {
+            return;
+        }
+        let entry = self.labels.entry(addr).or_insert(kind);
+        // A subroutine is also a code label, and code is also data; keep the most specific
+        *entry = (*entry).min(kind);
+    }
+
+    fn name(&self, addr: usize) -> Option<String> {
+        self.labels.get(&addr).map(|kind| match kind {
+            _ if addr == BASE_ADDRESS => "main".to_string(),
+            LabelKind::Subroutine => format!("sub_{:03X}", addr),
+            LabelKind::Code => format!("label_{:03X}", addr),
+            LabelKind::Data => format!("data_{:03X}", addr),
+        })
+    }
+}
+
+pub fn decompile(rom: &[u8]) -> String {
+    let end = BASE_ADDRESS + rom.len();
+    let word_at = |addr: usize| -> Option<u16> {
+        if addr >= BASE_ADDRESS && addr + 1 < end {
+            let i = addr - BASE_ADDRESS;
+            Some(u16::from(rom[i]) << 8 | u16::from(rom[i + 1]))
+        } else {
+            None
+        }
+    };
+
+    let mut analysis = Analysis {
+        end,
+        code: BTreeSet::new(),
+        labels: BTreeMap::new(),
+    };
+    analysis.label(BASE_ADDRESS, LabelKind::Code);
+    let mut pending = vec![BASE_ADDRESS];
+    while let Some(addr) = pending.pop() {
+        if analysis.code.contains(&addr) {
+            continue;
+        }
+        let op = match word_at(addr).and_then(Opcode::decode) {
+            Some(op) => op,
+            None => continue,
+        };
+        analysis.code.insert(addr);
+        match op {
+            Opcode::Return | Opcode::Exit => {}
+            Opcode::Jump(nnn) => {
+                analysis.label(nnn, LabelKind::Code);
+                pending.push(nnn);
+            }
+            // The offset is only known at runtime, but the base is usually a jump table
+            Opcode::JumpPlus(nnn) => {
+                analysis.label(nnn, LabelKind::Code);
+                pending.push(nnn);
+            }
+            Opcode::CallSubroutine(nnn) => {
+                analysis.label(nnn, LabelKind::Subroutine);
+                pending.push(nnn);
+                pending.push(addr + 2);
+            }
+            Opcode::SkipIfConstantEqual(..)
+            | Opcode::SkipIfConstantNotEqual(..)
+            | Opcode::SkipIfRegistersEqual(..)
+            | Opcode::SkipIfRegistersNotEqual(..)
+            | Opcode::SkipIfPressed(..)
+            | Opcode::SkipIfNotPressed(..) => {
+                pending.push(addr + 2);
+                pending.push(addr + 4);
+            }
+            Opcode::LoadAddress(nnn) => {
+                analysis.label(nnn, LabelKind::Data);
+                pending.push(addr + 2);
+            }
+            _ => pending.push(addr + 2),
+        }
+    }
+
+    let mut out = String::new();
+    let mut data: Vec<u8> = Vec::new();
+    let mut addr = BASE_ADDRESS;
+    while addr < end {
+        let is_instruction = analysis.code.contains(&addr)
+            // An instruction can only be written as such if nothing points inside it
+            && !analysis.labels.contains_key(&(addr + 1));
+        if (is_instruction || analysis.labels.contains_key(&addr)) && !data.is_empty() {
+            out += &format_data(&data);
+            data.clear();
+        }
+        if let Some(name) = analysis.name(addr) {
+            if analysis.labels[&addr] == LabelKind::Subroutine {
+                out += "\n";
+            }
+            out += &format!(": {}\n", name);
+        }
+
+        if is_instruction {
+            let raw = word_at(addr).unwrap();
+            let next = addr + 2;
+            // A skip guarding a single instruction reads naturally as `if ... then`
+            let guarded = analysis.code.contains(&next)
+                && !analysis.labels.contains_key(&next)
+                && !analysis.labels.contains_key(&(next + 1))
+                && word_at(next).is_some_and(|w| condition(w).is_none());
+            match condition(raw) {
+                Some(cond) if guarded => {
+                    let text = format_instruction(word_at(next).unwrap(), &analysis);
+                    out += &format!("  if {} then {}\n", cond, text);
+                    addr += 4;
+                }
+                _ => {
+                    out += &format!("  {}\n", format_instruction(raw, &analysis));
+                    addr += 2;
+                }
+            }
+        } else {
+            data.push(rom[addr - BASE_ADDRESS]);
+            if data.len() == DATA_BYTES_PER_LINE {
+                out += &format_data(&data);
+                data.clear();
+            }
+            addr += 1;
+        }
+    }
+    if !data.is_empty() {
+        out += &format_data(&data);
+    }
+    out
+}
+
+fn format_data(data: &[u8]) -> String {
+    let bytes: Vec<String> = data.iter().map(|b| format!("0x{:02X}", b)).collect();
+    format!("  {}\n", bytes.join(" "))
+}
+
+/// The Octo condition under which a skip instruction does *not* skip, i.e. the condition that
+/// guards the following instruction.
+fn condition(raw: u16) -> Option<String> {
+    let x = (raw >> 8) & 0xF;
+    let y = (raw >> 4) & 0xF;
+    let kk = raw & 0xFF;
+    let cond = match Opcode::decode(raw)? {
+        Opcode::SkipIfConstantEqual(..) => format!("v{:x} != 0x{:02X}", x, kk),
+        Opcode::SkipIfConstantNotEqual(..) => format!("v{:x} == 0x{:02X}", x, kk),
+        Opcode::SkipIfRegistersEqual(..) => format!("v{:x} != v{:x}", x, y),
+        Opcode::SkipIfRegistersNotEqual(..) => format!("v{:x} == v{:x}", x, y),
+        Opcode::SkipIfPressed(..) => format!("v{:x} -key", x),
+        Opcode::SkipIfNotPressed(..) => format!("v{:x} key", x),
+        _ => return None,
+    };
+    Some(cond)
+}
+
+fn format_instruction(raw: u16, analysis: &Analysis) -> String {
+    let x = (raw >> 8) & 0xF;
+    let y = (raw >> 4) & 0xF;
+    let kk = raw & 0xFF;
+    let addr = |nnn: usize| {
+        analysis
+            .name(nnn)
+            .unwrap_or_else(|| format!("0x{:03X}", nnn))
+    };
+    match Opcode::decode(raw).unwrap() {
+        Opcode::ClearDisplay => "clear".to_string(),
+        Opcode::Return => "return".to_string(),
+        Opcode::Exit => "exit".to_string(),
+        // SYS calls have no Octo equivalent
+        Opcode::Noop => format!("0x{:02X} 0x{:02X}", raw >> 8, raw & 0xFF),
+        Opcode::Jump(nnn) => format!("jump {}", addr(nnn)),
+        Opcode::CallSubroutine(nnn) => addr(nnn),
+        Opcode::LoadConstant(..) => format!("v{:x} := 0x{:02X}", x, kk),
+        Opcode::AddConstant(..) => format!("v{:x} += 0x{:02X}", x, kk),
+        Opcode::LoadRegister(..) => format!("v{:x} := v{:x}", x, y),
+        Opcode::Or(..) => format!("v{:x} |= v{:x}", x, y),
+        Opcode::And(..) => format!("v{:x} &= v{:x}", x, y),
+        Opcode::Xor(..) => format!("v{:x} ^= v{:x}", x, y),
+        Opcode::AddRegister(..) => format!("v{:x} += v{:x}", x, y),
+        Opcode::SubtractRightRegister(..) => format!("v{:x} -= v{:x}", x, y),
+        Opcode::ShiftRight(..) => format!("v{:x} >>= v{:x}", x, y),
+        Opcode::SubtractLeftRegister(..) => format!("v{:x} =- v{:x}", x, y),
+        Opcode::ShiftLeft(..) => format!("v{:x} <<= v{:x}", x, y),
+        Opcode::LoadAddress(nnn) => format!("i := {}", addr(nnn)),
+        Opcode::JumpPlus(nnn) => format!("jump0 {}", addr(nnn)),
+        Opcode::Random(..) => format!("v{:x} := random 0x{:02X}", x, kk),
+        Opcode::DisplaySprite(..) => format!("sprite v{:x} v{:x} {}", x, y, raw & 0xF),
+        Opcode::LoadDelayTimer(..) => format!("v{:x} := delay", x),
+        Opcode::WaitForPress(..) => format!("v{:x} := key", x),
+        Opcode::SetDelayTimer(..) => format!("delay := v{:x}", x),
+        Opcode::SetSoundTimer(..) => format!("buzzer := v{:x}", x),
+        Opcode::AddAddress(..) => format!("i += v{:x}", x),
+        Opcode::LoadAddressOfSprite(..) => format!("i := hex v{:x}", x),
+        Opcode::LoadDigits(..) => format!("bcd v{:x}", x),
+        Opcode::StoreRegisters(..) => format!("save v{:x}", x),
+        Opcode::LoadRegisters(..) => format!("load v{:x}", x),
+        Opcode::SaveFlags(..) => format!("saveflags v{:x}", x),
+        Opcode::LoadFlags(..) => format!("loadflags v{:x}", x),
+        Opcode::LoadAudioPattern => "audio".to_string(),
+        Opcode::SetPitch(..) => format!("pitch := v{:x}", x),
+        // Skips that don't guard a single instruction are left as raw bytes
+        _ => format!("0x{:02X} 0x{:02X}", raw >> 8, raw & 0xFF),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use std::path::Path;
+
+    #[test]
+    fn separates_code_from_data() {
+        let rom = [
+            0x22, 0x08, // call sub
+            0xA2, 0x0C, // i := sprite
+            0x30, 0x01, // skip unless v0 == 1
+            0x12, 0x02, // jump back
+            0xD0, 0x11, // sub: sprite
+            0x00, 0xEE, // return
+            0xFF, 0x81, // sprite data
+        ];
+        assert_eq!(
+            decompile(&rom),
+            ": main\n  \
+             sub_208\n\
+             : label_202\n  \
+             i := data_20C\n  \
+             if v0 != 0x01 then jump label_202\n\
+             \n: sub_208\n  \
+             sprite v0 v1 1\n  \
+             return\n\
+             : data_20C\n  \
+             0xFF 0x81\n"
+        );
+    }
+
+    #[test]
+    fn decompiled_roms_reassemble() {
+        let rom = include_bytes!("../../../games/chip/PONG");
+        let source = decompile(rom);
+        let program = assembler::assemble(&source, Path::new(".")).unwrap();
+        // Everything moves up behind the assembler's entry jump, and the labels follow it
+        assert_eq!(program.bytes.len(), rom.len() + 2);
+        assert_eq!(program.bytes[2..6], rom[0..4]);
+        assert_eq!(program.symbols.addr_of("main"), Some(0x202));
+    }
+}
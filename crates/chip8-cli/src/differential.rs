@@ -0,0 +1,236 @@
+//! Differential testing (`chip8 compare`): runs this emulator in lockstep with a reference,
+//! comparing the machine state after every instruction and stopping at the first divergence.
+//! The reference can be a recorded JSON Lines trace (`--trace-format jsonl`, from this or any
+//! other emulator that writes the same format), or this emulator with other quirks, which
+//! shows exactly where a quirk changes a ROM's behavior.
+use crate::chip8::Chip8;
+use crate::trace::Entry;
+use std::error::Error;
+use std::fmt;
+use std::io::BufRead;
+
+/// How many instructions to compare when no limit is given.
+pub const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+/// What an instruction left behind: everything traces record, which is what gets compared.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct State {
+    /// Address of the instruction that ran.
+    pub pc: usize,
+    pub registers: [u8; 16],
+    pub i_addr: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X}:", self.pc)?;
+        for (r, value) in self.registers.iter().enumerate() {
+            write!(f, " V{:X}={:02X}", r, value)?;
+        }
+        write!(
+            f,
+            " I={:03X} DT={:02X} ST={:02X}",
+            self.i_addr, self.delay_timer, self.sound_timer
+        )
+    }
+}
+
+/// An implementation to check this emulator against.
+pub trait ReferenceCore {
+    /// Runs the next instruction and returns the state it left, or None once there is
+    /// nothing more to compare.
+    fn step(&mut self) -> Result<Option<State>, Box<dyn Error>>;
+}
+
+/// This emulator as a core to compare, running frames the same way a session does: the
+/// timers count down once per frame of one or `instructions_per_frame` instructions. No keys
+/// are ever pressed, so it stops at the first Fx0A.
+pub struct Core {
+    pub chip8: Chip8,
+    steps: u64,
+}
+
+impl Core {
+    pub fn new(chip8: Chip8) -> Core {
+        Core { chip8, steps: 0 }
+    }
+}
+
+impl ReferenceCore for Core {
+    fn step(&mut self) -> Result<Option<State>, Box<dyn Error>> {
+        if self.chip8.is_waiting_for_key() {
+            return Ok(None);
+        }
+        let instructions_per_frame = u64::from(self.chip8.instructions_per_frame().unwrap_or(1));
+        if self.steps.is_multiple_of(instructions_per_frame) {
+            self.chip8.tick_timers();
+        }
+        let pc = self.chip8.pc();
+        self.chip8.step()?;
+        self.steps += 1;
+        Ok(Some(State {
+            pc,
+            registers: *self.chip8.registers(),
+            i_addr: self.chip8.i_addr(),
+            delay_timer: self.chip8.delay_timer(),
+            sound_timer: self.chip8.sound_timer(),
+        }))
+    }
+}
+
+/// A recorded JSON Lines trace. Traces only list the registers each instruction changed, so
+/// the rest are carried over, starting from all zeros.
+pub struct TraceReference<R> {
+    lines: std::io::Lines<R>,
+    registers: [u8; 16],
+}
+
+impl<R: BufRead> TraceReference<R> {
+    pub fn new(trace: R) -> TraceReference<R> {
+        TraceReference {
+            lines: trace.lines(),
+            registers: [0; 16],
+        }
+    }
+}
+
+impl<R: BufRead> ReferenceCore for TraceReference<R> {
+    fn step(&mut self) -> Result<Option<State>, Box<dyn Error>> {
+        let line = loop {
+            match self.lines.next() {
+                Some(line) if line.as_ref().is_ok_and(|l| l.trim().is_empty()) => continue,
+                Some(line) => break line?,
+                None => return Ok(None),
+            }
+        };
+        let entry = Entry::parse_json(&line)?;
+        for (r, value) in entry.changed {
+            self.registers[r] = value;
+        }
+        Ok(Some(State {
+            pc: entry.pc,
+            registers: self.registers,
+            i_addr: entry.i_addr,
+            delay_timer: entry.delay_timer,
+            sound_timer: entry.sound_timer,
+        }))
+    }
+}
+
+/// How a comparison ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Both agreed for this many instructions, until one of them ran out or the limit hit.
+    Matched(u64),
+    /// The states after instruction number `step` (counting from 1) differ.
+    Diverged {
+        step: u64,
+        ours: State,
+        reference: State,
+    },
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Matched(steps) => {
+                write!(f, "Matched the reference for {} instructions", steps)
+            }
+            Outcome::Diverged {
+                step,
+                ours,
+                reference,
+            } => write!(
+                f,
+                "Diverged at instruction {}\n  ours:      {}\n  reference: {}",
+                step, ours, reference
+            ),
+        }
+    }
+}
+
+/// Steps both cores together for at most `max_steps` instructions, returning where they first
+/// differ.
+pub fn compare(
+    ours: &mut dyn ReferenceCore,
+    reference: &mut dyn ReferenceCore,
+    max_steps: u64,
+) -> Result<Outcome, Box<dyn Error>> {
+    for step in 1..=max_steps {
+        let (ours, reference) = match (ours.step()?, reference.step()?) {
+            (Some(ours), Some(reference)) => (ours, reference),
+            _ => return Ok(Outcome::Matched(step - 1)),
+        };
+        if ours != reference {
+            return Ok(Outcome::Diverged {
+                step,
+                ours,
+                reference,
+            });
+        }
+    }
+    Ok(Outcome::Matched(max_steps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+    use crate::symbols::Symbols;
+
+    // V1 := 0x81; V2 := 0x03; V1 >>= V2; V1 += 1; loop
+    const PROGRAM: [u8; 10] = [0x61, 0x81, 0x62, 0x03, 0x81, 0x26, 0x71, 0x01, 0x12, 0x08];
+
+    fn core(quirks: &str) -> Core {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_quirks(Quirks::parse(quirks).unwrap());
+        chip8.load_program(&PROGRAM);
+        Core::new(chip8)
+    }
+
+    #[test]
+    fn finds_first_divergence_between_quirks() {
+        let outcome = compare(&mut core(""), &mut core("shift-vy"), 100).unwrap();
+        match outcome {
+            Outcome::Diverged {
+                step,
+                ours,
+                reference,
+            } => {
+                assert_eq!(step, 3);
+                assert_eq!(ours.pc, 0x204);
+                assert_eq!((ours.registers[1], reference.registers[1]), (0x40, 0x01));
+            }
+            _ => panic!("Expected a divergence, got {:?}", outcome),
+        }
+        assert_eq!(
+            compare(&mut core(""), &mut core("jump-vx"), 100).unwrap(),
+            Outcome::Matched(100)
+        );
+    }
+
+    #[test]
+    fn compares_against_recorded_trace() {
+        // Record a trace of the first few instructions, the same way --trace does
+        let mut recording = core("");
+        let mut trace = String::new();
+        for _ in 0..4 {
+            let executed = recording.chip8.tick().unwrap().unwrap();
+            let entry = Entry::new(&executed, &recording.chip8, &Symbols::default());
+            trace += &entry.to_json();
+            trace += "\n";
+        }
+        let mut reference = TraceReference::new(trace.as_bytes());
+        assert_eq!(
+            compare(&mut core(""), &mut reference, 100).unwrap(),
+            Outcome::Matched(4)
+        );
+
+        let tampered = trace.replacen("\"V1\":64", "\"V1\":65", 1);
+        let mut reference = TraceReference::new(tampered.as_bytes());
+        let outcome = compare(&mut core(""), &mut reference, 100).unwrap();
+        assert!(matches!(outcome, Outcome::Diverged { step: 3, .. }));
+    }
+}
@@ -0,0 +1,56 @@
+use crate::opcode::Opcode;
+use crate::symbols::Symbols;
+
+/// Formats an opcode as assembly, naming address operands after their labels when known.
+pub fn format_opcode(op: Opcode, symbols: &Symbols) -> String {
+    let addr = |nnn: usize| match symbols.name_of(nnn) {
+        Some(name) => name.to_string(),
+        None => format!("0x{:03X}", nnn),
+    };
+    match op {
+        Opcode::Jump(nnn) => format!("JP {}", addr(nnn)),
+        Opcode::CallSubroutine(nnn) => format!("CALL {}", addr(nnn)),
+        Opcode::LoadAddress(nnn) => format!("LD I, {}", addr(nnn)),
+        Opcode::JumpPlus(nnn) => format!("JP V0, {}", addr(nnn)),
+        _ => op.to_string(),
+    }
+}
+
+/// Disassembles a program loaded at `base`, one instruction per line. Words that don't decode
+/// (typically sprite data) are emitted as `DW` directives.
+pub fn disassemble(program: &[u8], base: usize, symbols: &Symbols) -> String {
+    let mut out = String::new();
+    for (i, word) in program.chunks(2).enumerate() {
+        let addr = base + i * 2;
+        if let Some(name) = symbols.name_of(addr) {
+            out += &format!("{}:\n", name);
+        }
+        let raw = u16::from(word[0]) << 8 | u16::from(*word.get(1).unwrap_or(&0));
+        let text = match Opcode::decode(raw) {
+            Some(op) => format_opcode(op, symbols),
+            None => format!("DW 0x{:04X}", raw),
+        };
+        out += &format!("{:03X}: {:04X}  {}\n", addr, raw, text);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembles_with_labels() {
+        let symbols = Symbols::parse("main 0x200\ndraw_score 0x206").unwrap();
+        let program = [0x22, 0x06, 0x12, 0x00, 0x80, 0x08, 0x00, 0xEE];
+        assert_eq!(
+            disassemble(&program, 0x200, &symbols),
+            "main:\n\
+             200: 2206  CALL draw_score\n\
+             202: 1200  JP main\n\
+             204: 8008  DW 0x8008\n\
+             draw_score:\n\
+             206: 00EE  RET\n"
+        );
+    }
+}
@@ -0,0 +1,476 @@
+//! Plain-English explanations of instructions as they execute (`--explain`), saying what each
+//! one did with the values it saw, for people writing their first ROM or emulator. `chip8
+//! explain` describes a single instruction word instead, without running anything.
+use crate::chip8::{Chip8, Executed, Register};
+use crate::disasm;
+use crate::opcode::Opcode;
+use crate::symbols::Symbols;
+
+/// The parts of the machine instructions read from, taken before one runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Before {
+    pub registers: [u8; 16],
+    pub i_addr: usize,
+}
+
+impl Before {
+    pub fn of(chip8: &Chip8) -> Before {
+        Before {
+            registers: *chip8.registers(),
+            i_addr: chip8.i_addr(),
+        }
+    }
+}
+
+/// Formats an instruction that just ran on `chip8` like a disassembly line, followed by what
+/// it did.
+pub fn explain_line(
+    executed: &Executed,
+    before: &Before,
+    chip8: &Chip8,
+    symbols: &Symbols,
+) -> String {
+    format!(
+        "{:03X}: {:<20} {}",
+        executed.pc,
+        disasm::format_opcode(executed.opcode, symbols),
+        explain(executed, before, chip8, symbols)
+    )
+}
+
+/// Says what an instruction did, given the machine before and after it ran.
+pub fn explain(executed: &Executed, before: &Before, chip8: &Chip8, symbols: &Symbols) -> String {
+    let addr = |nnn: usize| match symbols.name_of(nnn) {
+        Some(name) => format!("{} (0x{:03X})", name, nnn),
+        None => format!("0x{:03X}", nnn),
+    };
+    // A register with the value it held before the instruction
+    let reg = |r: Register| format!("{} (0x{:02X})", r, before.registers[r as usize]);
+    let after = |r: Register| chip8.registers()[r as usize];
+    let vf = after(Register::VF);
+    let skip = |skipped: bool, reason: String| match skipped {
+        true => format!("Skip the next instruction because {}", reason),
+        false => format!("Don't skip the next instruction, since {}", reason),
+    };
+    let skipped = executed.branched;
+    match executed.opcode {
+        Opcode::ClearDisplay => "Clear the screen".to_string(),
+        Opcode::Return => format!(
+            "Return from the subroutine to 0x{:03X}, just after the CALL",
+            chip8.pc()
+        ),
+        Opcode::Exit => "Stop the program".to_string(),
+        Opcode::Noop => "Do nothing: SYS called machine code on the COSMAC VIP".to_string(),
+        Opcode::Jump(nnn) => format!("Jump to {}", addr(nnn)),
+        Opcode::CallSubroutine(nnn) => format!(
+            "Call the subroutine at {}, to come back to 0x{:03X} when it returns",
+            addr(nnn),
+            executed.pc + 2
+        ),
+        Opcode::SkipIfConstantEqual(vx, kk) => skip(
+            skipped,
+            format!(
+                "{} {} 0x{:02X}",
+                reg(vx),
+                if skipped { "equals" } else { "doesn't equal" },
+                kk
+            ),
+        ),
+        Opcode::SkipIfConstantNotEqual(vx, kk) => skip(
+            skipped,
+            format!(
+                "{} {} 0x{:02X}",
+                reg(vx),
+                if skipped { "doesn't equal" } else { "equals" },
+                kk
+            ),
+        ),
+        Opcode::SkipIfRegistersEqual(vx, vy) => skip(
+            skipped,
+            format!(
+                "{} {} {}",
+                reg(vx),
+                if skipped { "equals" } else { "doesn't equal" },
+                reg(vy)
+            ),
+        ),
+        Opcode::SkipIfRegistersNotEqual(vx, vy) => skip(
+            skipped,
+            format!(
+                "{} {} {}",
+                reg(vx),
+                if skipped { "doesn't equal" } else { "equals" },
+                reg(vy)
+            ),
+        ),
+        Opcode::SkipIfPressed(vx) => skip(
+            skipped,
+            format!(
+                "key {:X} (from {}) {} held",
+                before.registers[vx as usize] & 0x0F,
+                vx,
+                if skipped { "is" } else { "isn't" }
+            ),
+        ),
+        Opcode::SkipIfNotPressed(vx) => skip(
+            skipped,
+            format!(
+                "key {:X} (from {}) {} held",
+                before.registers[vx as usize] & 0x0F,
+                vx,
+                if skipped { "isn't" } else { "is" }
+            ),
+        ),
+        Opcode::LoadConstant(vx, kk) => format!("Set {} to 0x{:02X}", vx, kk),
+        Opcode::AddConstant(vx, kk) => {
+            let wrapped = before.registers[vx as usize].checked_add(kk).is_none();
+            format!(
+                "Add 0x{:02X} to {}, making 0x{:02X}{}",
+                kk,
+                reg(vx),
+                after(vx),
+                if wrapped {
+                    " after wrapping past 0xFF (VF isn't touched)"
+                } else {
+                    ""
+                }
+            )
+        }
+        Opcode::LoadRegister(vx, vy) => format!("Copy {} into {}", reg(vy), vx),
+        Opcode::Or(vx, vy) => logic("OR", vx, vy, &reg, after(vx)),
+        Opcode::And(vx, vy) => logic("AND", vx, vy, &reg, after(vx)),
+        Opcode::Xor(vx, vy) => logic("XOR", vx, vy, &reg, after(vx)),
+        Opcode::AddRegister(vx, vy) => {
+            let (x, y) = (before.registers[vx as usize], before.registers[vy as usize]);
+            format!(
+                "Add {} to {}, making 0x{:02X} {} a carry past 0xFF, so VF = {}",
+                reg(vy),
+                reg(vx),
+                x.wrapping_add(y),
+                if x.checked_add(y).is_none() {
+                    "with"
+                } else {
+                    "without"
+                },
+                vf
+            )
+        }
+        Opcode::SubtractRightRegister(vx, vy) => subtract(vx, vy, vx, before, &reg, vf),
+        Opcode::SubtractLeftRegister(vx, vy) => subtract(vy, vx, vx, before, &reg, vf),
+        Opcode::ShiftRight(vx, vy) | Opcode::ShiftLeft(vx, vy) => {
+            let source = if chip8.quirks().shift_vy { vy } else { vx };
+            let right = matches!(executed.opcode, Opcode::ShiftRight(..));
+            let value = before.registers[source as usize];
+            format!(
+                "Shift {} {} by one bit into {}, making 0x{:02X}, with the bit shifted out in \
+                 VF = {}",
+                reg(source),
+                if right { "right" } else { "left" },
+                vx,
+                if right { value >> 1 } else { value << 1 },
+                if right { value & 1 } else { value >> 7 }
+            )
+        }
+        Opcode::LoadAddress(nnn) => format!("Point I at {}", addr(nnn)),
+        Opcode::JumpPlus(nnn) => {
+            // With the quirk, the top nibble of the address picks the register too
+            let offset = if chip8.quirks().jump_vx { nnn >> 8 } else { 0 };
+            format!(
+                "Jump to {} plus V{:X} (0x{:02X}), landing on 0x{:03X}",
+                addr(nnn),
+                offset,
+                before.registers[offset],
+                chip8.pc()
+            )
+        }
+        Opcode::Random(vx, kk) => format!(
+            "Set {} to a random number masked with AND 0x{:02X}, which came out 0x{:02X}",
+            vx,
+            kk,
+            after(vx)
+        ),
+        Opcode::DisplaySprite(vx, vy, n) => format!(
+            "Draw the {}-row sprite at I (0x{:03X}) at ({}, {}) from {} and {}, XORing it onto \
+             the screen. {}",
+            n,
+            before.i_addr,
+            before.registers[vx as usize],
+            before.registers[vy as usize],
+            vx,
+            vy,
+            match vf {
+                1 => "It turned off a lit pixel, so VF = 1",
+                _ => "It turned no lit pixels off, so VF = 0",
+            }
+        ),
+        Opcode::LoadDelayTimer(vx) => {
+            format!("Copy the delay timer (0x{:02X}) into {}", after(vx), vx)
+        }
+        Opcode::WaitForPress(vx) if executed.waits_for_key => {
+            format!("Wait for a key to be pressed, then put it in {}", vx)
+        }
+        Opcode::WaitForPress(vx) => {
+            format!("Put key {:X}, already held, in {}", after(vx), vx)
+        }
+        Opcode::SetDelayTimer(vx) => format!(
+            "Set the delay timer to {}, to count down to 0 at 60Hz",
+            reg(vx)
+        ),
+        Opcode::SetSoundTimer(vx) => format!(
+            "Set the sound timer to {}, to beep until it counts down to 0 at 60Hz",
+            reg(vx)
+        ),
+        Opcode::AddAddress(vx) => format!(
+            "Add {} to I (0x{:03X}), making 0x{:03X}",
+            reg(vx),
+            before.i_addr,
+            chip8.i_addr()
+        ),
+        Opcode::LoadAddressOfSprite(vx) => format!(
+            "Point I at the font sprite for the digit {:X} in {}, at 0x{:03X}",
+            before.registers[vx as usize] & 0x0F,
+            vx,
+            chip8.i_addr()
+        ),
+        Opcode::LoadDigits(vx) => {
+            let value = before.registers[vx as usize];
+            format!(
+                "Store the decimal digits of {} ({}) as {}, {}, and {} at I (0x{:03X}) onward",
+                reg(vx),
+                value,
+                value / 100,
+                value / 10 % 10,
+                value % 10,
+                before.i_addr
+            )
+        }
+        Opcode::StoreRegisters(vx) => transfer("Store", vx, "in memory at", before, chip8),
+        Opcode::LoadRegisters(vx) => transfer("Load", vx, "from memory at", before, chip8),
+        Opcode::SaveFlags(vx) => format!("Save V0 through {} in the flag registers", vx),
+        Opcode::LoadFlags(vx) => format!("Load V0 through {} from the flag registers", vx),
+        Opcode::LoadAudioPattern => format!(
+            "Load the 16-byte audio pattern from I (0x{:03X}) onward",
+            before.i_addr
+        ),
+        Opcode::SetPitch(vx) => format!("Set the audio pattern's pitch to {}", reg(vx)),
+        Opcode::Custom(word) => format!("Run the handler registered for 0x{:04X}", word),
+    }
+}
+
+/// Describes the instruction word in `text`, given in hex like `0x8AC7`. Letters from the
+/// instruction patterns (x, y, n, and k) can stand in for any value, as in `DXY5`.
+pub fn explain_word(text: &str) -> Result<String, String> {
+    let invalid = || format!("Not an instruction word: {} (expected 4 hex digits)", text);
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    if digits.chars().count() != 4 {
+        return Err(invalid());
+    }
+    let (mut word, mut open) = (0, [false; 4]);
+    for (i, c) in digits.chars().enumerate() {
+        let digit = match c.to_digit(16) {
+            Some(digit) => digit as u16,
+            None if "xynk".contains(c.to_ascii_lowercase()) => {
+                open[i] = true;
+                0
+            }
+            None => return Err(invalid()),
+        };
+        word = word << 4 | digit;
+    }
+    let opcode = Opcode::decode(word).ok_or_else(|| format!("{} isn't an instruction", text))?;
+    // The description starts with the pattern, like *Dxyn - DRW Vx, Vy, nibble*
+    let description = opcode.description().replace('*', "");
+    let (pattern, rest) = description.split_once(" - ").unwrap_or_default();
+    let pattern: Vec<char> = pattern.chars().collect();
+    if (0..4).any(|i| open[i] && !pattern[i].is_ascii_lowercase()) {
+        return Err(format!(
+            "{} has a fixed digit where {} has a letter",
+            pattern.iter().collect::<String>(),
+            text
+        ));
+    }
+
+    let mut out = match open.contains(&true) {
+        true => format!(
+            "{}  {}
+",
+            digits.to_ascii_uppercase(),
+            rest.split('.').next().unwrap_or_default()
+        ),
+        false => format!(
+            "{:04X}  {}
+",
+            word, opcode
+        ),
+    };
+    out += &description;
+    out.push('\n');
+    let field = |i: usize| (word >> (12 - 4 * i)) as usize & 0xF;
+    if pattern[1] == 'x' {
+        out += &match open[1] {
+            true => "  x = any register\n".to_string(),
+            false => format!("  x = {:X} (V{:X})\n", field(1), field(1)),
+        };
+    }
+    if pattern[2] == 'y' {
+        out += &match open[2] {
+            true => "  y = any register\n".to_string(),
+            false => format!("  y = {:X} (V{:X})\n", field(2), field(2)),
+        };
+    }
+    if pattern[1..] == ['n', 'n', 'n'] {
+        out += &match open[1..].contains(&true) {
+            true => "  nnn = any address\n".to_string(),
+            false => format!("  nnn = 0x{:03X}\n", word & 0xFFF),
+        };
+    } else if pattern[3] == 'n' {
+        out += &match open[3] {
+            true => "  n = any number of rows\n".to_string(),
+            false => format!("  n = {} rows\n", field(3)),
+        };
+    } else if pattern[2..] == ['k', 'k'] {
+        out += &match open[2..].contains(&true) {
+            true => "  kk = any byte\n".to_string(),
+            false => format!("  kk = 0x{:02X} ({})\n", word & 0xFF, word & 0xFF),
+        };
+    }
+    Ok(out)
+}
+
+/// Explains OR, AND, and XOR, which combine Vx and Vy bit by bit.
+fn logic(
+    op: &str,
+    vx: Register,
+    vy: Register,
+    reg: &dyn Fn(Register) -> String,
+    result: u8,
+) -> String {
+    format!(
+        "Set {} to {} {} {}, bit by bit, making 0x{:02X}",
+        vx,
+        reg(vx),
+        op,
+        reg(vy),
+        result
+    )
+}
+
+/// Explains SUB and SUBN, which subtract `from - by` into `into`.
+fn subtract(
+    from: Register,
+    by: Register,
+    into: Register,
+    before: &Before,
+    reg: &dyn Fn(Register) -> String,
+    vf: u8,
+) -> String {
+    let (a, b) = (
+        before.registers[from as usize],
+        before.registers[by as usize],
+    );
+    format!(
+        "Subtract {} from {} into {}, making 0x{:02X} {} a borrow, so VF = {}",
+        reg(by),
+        reg(from),
+        into,
+        a.wrapping_sub(b),
+        if a < b { "with" } else { "without" },
+        vf
+    )
+}
+
+/// Explains Fx55 and Fx65, which move V0 up to Vx between the registers and memory.
+fn transfer(verb: &str, vx: Register, place: &str, before: &Before, chip8: &Chip8) -> String {
+    let last = match chip8.quirks().exclusive_range {
+        true => (vx as usize).checked_sub(1),
+        false => Some(vx as usize),
+    };
+    let registers = match last {
+        None => return format!("{} no registers, since the range stops short of V0", verb),
+        Some(0) => "V0".to_string(),
+        Some(last) => format!("V0 through V{:X}", last),
+    };
+    let mut text = format!(
+        "{} {} {} I (0x{:03X}) onward",
+        verb, registers, place, before.i_addr
+    );
+    if chip8.i_addr() != before.i_addr {
+        text += &format!(", moving I past them to 0x{:03X}", chip8.i_addr());
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `program` up to its last instruction, then explains that one.
+    fn explain_last(program: &[u8]) -> String {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(program);
+        loop {
+            let before = Before::of(&chip8);
+            let executed = chip8.step().unwrap().unwrap();
+            if executed.pc + 2 == 0x200 + program.len() {
+                return explain_line(&executed, &before, &chip8, &Symbols::default());
+            }
+        }
+    }
+
+    #[test]
+    fn explains_skips() {
+        // LD V3, 0x1F; SE V3, 0x1F
+        assert_eq!(
+            explain_last(&[0x63, 0x1F, 0x33, 0x1F]),
+            "202: SE V3, 0x1F          Skip the next instruction because V3 (0x1F) equals 0x1F"
+        );
+        // SNE V3, V4
+        assert_eq!(
+            explain_last(&[0x93, 0x40]),
+            "200: SNE V3, V4           Don't skip the next instruction, since V3 (0x00) equals \
+             V4 (0x00)"
+        );
+    }
+
+    #[test]
+    fn explains_words() {
+        assert_eq!(
+            explain_word("0x8AC7").unwrap(),
+            "8AC7  SUBN VA, VC\n\
+             8xy7 - SUBN Vx, Vy. Subtracts the value of register Vx from register Vy, then \
+             stores result in Vx.\n  \
+             x = A (VA)\n  \
+             y = C (VC)\n"
+        );
+        assert_eq!(
+            explain_word("DXY5").unwrap(),
+            "DXY5  DRW Vx, Vy, nibble\n\
+             Dxyn - DRW Vx, Vy, nibble. Displays n-byte sprite starting at memory location I at \
+             (Vx, Vy).\n  \
+             x = any register\n  \
+             y = any register\n  \
+             n = 5 rows\n"
+        );
+        assert!(explain_word("1234").unwrap().ends_with("nnn = 0x234\n"));
+        assert!(explain_word("6xkk").unwrap().ends_with("kk = any byte\n"));
+        assert!(explain_word("8XY8").is_err());
+        assert!(explain_word("00EX").is_err());
+        assert!(explain_word("12345").is_err());
+    }
+
+    #[test]
+    fn explains_arithmetic_with_flags() {
+        // LD V0, 0xF0; LD V1, 0x20; ADD V0, V1
+        assert_eq!(
+            explain_last(&[0x60, 0xF0, 0x61, 0x20, 0x80, 0x14]),
+            "204: ADD V0, V1           Add V1 (0x20) to V0 (0xF0), making 0x10 with a carry past \
+             0xFF, so VF = 1"
+        );
+        // LD V0, 0x05; LD V1, 0x07; SUB V0, V1
+        assert_eq!(
+            explain_last(&[0x60, 0x05, 0x61, 0x07, 0x80, 0x15]),
+            "204: SUB V0, V1           Subtract V1 (0x07) from V0 (0x05) into V0, making 0xFE \
+             with a borrow, so VF = 0"
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Running without a window (`--headless`), for automated runs. No keys are ever held, and
+//! frames run back to back rather than in real time, until one of the `Limits` ends the run.
+//!
+//! Test ROMs report their result through the exit code: they end with the SCHIP exit
+//! instruction (00FD) with V0 set to 0 on success or anything else on failure, and
+//! `--expect-hash` can also check what they left on the screen.
+//!
+//! `--dump-frames` saves the screen along the way, as numbered PNGs.
+use crate::screenshot;
+use crate::session::Session;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// Process exit codes for the end of a run. Errors exit with 1, as they do for any command.
+pub const EXIT_PASSED: i32 = 0;
+pub const EXIT_LIMIT: i32 = 2;
+pub const EXIT_FAILED: i32 = 3;
+
+/// When a headless run stops. At least one of these has to be set, so the run always ends.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Stop after this many instructions. They are counted in whole frames, so with
+    /// `--ipf` the run can go past the limit by up to a frame.
+    pub max_cycles: Option<u64>,
+    /// Stop after this many frames.
+    pub max_frames: Option<u64>,
+    /// Stop once the program halts by jumping to itself. Programs that exit with 00FD always
+    /// stop the run.
+    pub exit_on_halt: bool,
+}
+
+impl Limits {
+    pub fn is_unbounded(&self) -> bool {
+        self.max_cycles.is_none() && self.max_frames.is_none() && !self.exit_on_halt
+    }
+}
+
+/// Where and how often to save the screen during a run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameDump {
+    pub dir: PathBuf,
+    /// Save every this many frames, named by the frame count once they've run.
+    pub every: u64,
+}
+
+impl FrameDump {
+    fn frame(&self, session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+        if !session.frame.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        let path = self.dir.join(format!("{:06}.png", session.frame));
+        let mut file = BufWriter::new(File::create(path)?);
+        screenshot::write_screen(&mut file, &session.chip8, &[])?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// Why a headless run stopped. Errors end the run too, with the usual exit code of 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// The program ran 00FD, with this value in V0.
+    Exited(u8),
+    Halted,
+    CycleLimit,
+    FrameLimit,
+}
+
+impl Stop {
+    /// The process exit code reporting this stop.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Stop::Exited(0) | Stop::Halted => EXIT_PASSED,
+            Stop::Exited(_) => EXIT_FAILED,
+            Stop::CycleLimit | Stop::FrameLimit => EXIT_LIMIT,
+        }
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Exited(0) => write!(f, "Passed"),
+            Stop::Exited(v0) => write!(f, "Failed with V0 = 0x{:02X}", v0),
+            Stop::Halted => write!(f, "Halted"),
+            Stop::CycleLimit => write!(f, "Reached the cycle limit"),
+            Stop::FrameLimit => write!(f, "Reached the frame limit"),
+        }
+    }
+}
+
+/// Runs frames until one of the limits is reached, saving the screen as it goes if asked.
+pub fn run(
+    session: &mut Session,
+    limits: &Limits,
+    dump: Option<&FrameDump>,
+) -> Result<Stop, Box<dyn std::error::Error>> {
+    if let Some(dump) = dump {
+        fs::create_dir_all(&dump.dir)?;
+    }
+    let instructions_per_frame = u64::from(session.chip8.instructions_per_frame().unwrap_or(1));
+    loop {
+        if session.chip8.has_exited() {
+            return Ok(Stop::Exited(session.chip8.registers()[0]));
+        }
+        if limits.exit_on_halt && session.chip8.is_halted() {
+            return Ok(Stop::Halted);
+        }
+        if limits.max_frames.is_some_and(|max| session.frame >= max) {
+            return Ok(Stop::FrameLimit);
+        }
+        if limits
+            .max_cycles
+            .is_some_and(|max| session.frame * instructions_per_frame >= max)
+        {
+            return Ok(Stop::CycleLimit);
+        }
+        session.run_frame(&[false; 16])?;
+        if let Some(dump) = dump {
+            dump.frame(session)?;
+        }
+    }
+}
+
+/// Reports how the run ended along with the final screen hash, checking it against the
+/// expected one if given, and returns the exit code for the process.
+pub fn finish(session: &Session, stop: Stop, expect_hash: Option<u64>) -> i32 {
+    let screen_hash = session.chip8.screen_hash();
+    println!("{} at frame {}", stop, session.frame);
+    println!("Screen hash {:016x}", screen_hash);
+    match expect_hash {
+        Some(expected) if expected != screen_hash => {
+            println!("Expected screen hash {:016x}", expected);
+            EXIT_FAILED
+        }
+        _ => stop.exit_code(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    fn session(program: &[u8], instructions_per_frame: Option<u32>) -> Session {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_instructions_per_frame(instructions_per_frame);
+        chip8.load_program(program);
+        Session::new(chip8)
+    }
+
+    // Counts up in V0 forever.
+    const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    #[test]
+    fn stops_at_halt() {
+        // Adds to V0 three times, then jumps to itself
+        let program = [0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x12, 0x06];
+        let mut session = session(&program, None);
+        let limits = Limits {
+            exit_on_halt: true,
+            ..Limits::default()
+        };
+        assert_eq!(run(&mut session, &limits, None).unwrap(), Stop::Halted);
+        assert_eq!(session.frame, 3);
+        assert_eq!(session.chip8.registers()[0], 3);
+    }
+
+    #[test]
+    fn stops_at_limits() {
+        let mut frames = session(&COUNTER, None);
+        let limits = Limits {
+            max_frames: Some(10),
+            exit_on_halt: true,
+            ..Limits::default()
+        };
+        assert_eq!(run(&mut frames, &limits, None).unwrap(), Stop::FrameLimit);
+        assert_eq!(frames.frame, 10);
+
+        let mut cycles = session(&COUNTER, Some(4));
+        let limits = Limits {
+            max_cycles: Some(20),
+            ..Limits::default()
+        };
+        assert_eq!(run(&mut cycles, &limits, None).unwrap(), Stop::CycleLimit);
+        assert_eq!(cycles.frame, 5);
+        assert_eq!(cycles.chip8.registers()[0], 10);
+    }
+
+    #[test]
+    fn dumps_every_nth_frame() {
+        let dir = std::env::temp_dir().join("chip8-dump-test");
+        let _ = fs::remove_dir_all(&dir);
+        let dump = FrameDump {
+            dir: dir.clone(),
+            every: 2,
+        };
+        let limits = Limits {
+            max_frames: Some(5),
+            ..Limits::default()
+        };
+        run(&mut session(&COUNTER, None), &limits, Some(&dump)).unwrap();
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names, vec!["000002.png", "000004.png"]);
+    }
+
+    #[test]
+    fn reports_exit_status() {
+        let limits = Limits {
+            max_frames: Some(100),
+            ..Limits::default()
+        };
+        // V0 := 1, exit
+        let mut failed = session(&[0x60, 0x01, 0x00, 0xFD], None);
+        let stop = run(&mut failed, &limits, None).unwrap();
+        assert_eq!(stop, Stop::Exited(1));
+        assert_eq!(finish(&failed, stop, None), EXIT_FAILED);
+
+        let mut passed = session(&[0x00, 0xE0, 0x00, 0xFD], None);
+        let stop = run(&mut passed, &limits, None).unwrap();
+        assert_eq!(stop, Stop::Exited(0));
+        let blank = passed.chip8.screen_hash();
+        assert_eq!(finish(&passed, stop, Some(blank)), EXIT_PASSED);
+        assert_eq!(finish(&passed, stop, Some(blank ^ 1)), EXIT_FAILED);
+    }
+}
@@ -0,0 +1,155 @@
+//! Which keyboard keys play the hex keypad. By default the keypad sits on the left of the
+//! keyboard, on the keys a QWERTY keyboard labels 1 2 3 4, Q W E R, A S D F, and Z X C V, and
+//! stays in that spot whatever the layout. `--keys-by-character` instead uses the keys with
+//! those labels, wherever the layout puts them.
+//!
+//! minifb names keys differently by platform. On Windows and macOS it reads scancodes, so its
+//! keys are positions, named for the QWERTY key in that spot. On X11 it reads keysyms, so its
+//! keys are the characters the active layout prints. Either way, getting the other kind of
+//! mapping takes knowing the layout, from `--keyboard`.
+#[cfg(feature = "gui")]
+use minifb::Key;
+
+/// Whether minifb's keys are characters rather than positions on this platform.
+#[cfg(feature = "gui")]
+const READS_CHARACTERS: bool = cfg!(not(any(windows, target_os = "macos")));
+
+/// The keypad keys, from 0 to F, as the QWERTY labels of their default spots:
+/// 1 2 3 C, 4 5 6 D, 7 8 9 E, and A 0 B F on 1 2 3 4, Q W E R, A S D F, and Z X C V.
+const KEYPAD: [char; 16] = [
+    'X', '1', '2', '3', 'Q', 'W', 'E', 'A', 'S', 'D', 'Z', 'C', '4', 'R', 'F', 'V',
+];
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+    Dvorak,
+}
+
+impl KeyboardLayout {
+    pub fn parse(text: &str) -> Result<KeyboardLayout, String> {
+        match text {
+            "qwerty" => Ok(KeyboardLayout::Qwerty),
+            "azerty" => Ok(KeyboardLayout::Azerty),
+            "qwertz" => Ok(KeyboardLayout::Qwertz),
+            "dvorak" => Ok(KeyboardLayout::Dvorak),
+            _ => Err(format!(
+                "Unknown keyboard layout: {} (expected qwerty, azerty, qwertz, or dvorak)",
+                text
+            )),
+        }
+    }
+
+    /// What the layout prints on the three letter rows. The number row prints its digits
+    /// in the same spots on all of them (shifted, on AZERTY).
+    fn rows(self) -> [&'static str; 3] {
+        match self {
+            KeyboardLayout::Qwerty => ["QWERTYUIOP", "ASDFGHJKL;", "ZXCVBNM,./"],
+            KeyboardLayout::Azerty => ["AZERTYUIOP", "QSDFGHJKLM", "WXCVBN,;:!"],
+            KeyboardLayout::Qwertz => ["QWERTZUIOP", "ASDFGHJKLÖ", "YXCVBNM,.-"],
+            KeyboardLayout::Dvorak => ["',.PYFGCRL", "AOEUIDHTNS", ";QJKXBMWVZ"],
+        }
+    }
+}
+
+/// The keyboard keys for the keypad, from 0 to F.
+#[cfg(feature = "gui")]
+pub fn keypad_keys(layout: KeyboardLayout, by_character: bool) -> [Key; 16] {
+    KEYPAD.map(|label| {
+        let wanted = match (by_character, READS_CHARACTERS) {
+            (false, true) => printed_at(layout, label),
+            (true, false) => position_of(layout, label),
+            _ => Some(label),
+        };
+        // Keys minifb has no name for can't be read, so those stay on the QWERTY key
+        wanted
+            .and_then(minifb_key)
+            .or_else(|| minifb_key(label))
+            .expect("Keypad labels are all keys minifb names")
+    })
+}
+
+/// What `layout` prints on the key in the spot of the QWERTY key `position`.
+fn printed_at(layout: KeyboardLayout, position: char) -> Option<char> {
+    translate(position, KeyboardLayout::Qwerty, layout)
+}
+
+/// The spot of the key `layout` prints `label` on, as the QWERTY key there.
+fn position_of(layout: KeyboardLayout, label: char) -> Option<char> {
+    translate(label, layout, KeyboardLayout::Qwerty)
+}
+
+fn translate(c: char, from: KeyboardLayout, to: KeyboardLayout) -> Option<char> {
+    if c.is_ascii_digit() {
+        return Some(c);
+    }
+    from.rows()
+        .iter()
+        .zip(to.rows().iter())
+        .find_map(|(from_row, to_row)| {
+            let column = from_row.chars().position(|x| x == c)?;
+            to_row.chars().nth(column)
+        })
+}
+
+#[cfg(feature = "gui")]
+fn minifb_key(c: char) -> Option<Key> {
+    #[rustfmt::skip]
+    const LETTERS: [Key; 26] = [Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H,
+        Key::I, Key::J, Key::K, Key::L, Key::M, Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S,
+        Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z];
+    #[rustfmt::skip]
+    const DIGITS: [Key; 10] = [Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5,
+        Key::Key6, Key::Key7, Key::Key8, Key::Key9];
+    match c {
+        'A'..='Z' => Some(LETTERS[c as usize - 'A' as usize]),
+        '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+        ';' => Some(Key::Semicolon),
+        ',' => Some(Key::Comma),
+        '.' => Some(Key::Period),
+        '/' => Some(Key::Slash),
+        '\'' => Some(Key::Apostrophe),
+        '-' => Some(Key::Minus),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_between_layouts() {
+        assert_eq!(printed_at(KeyboardLayout::Azerty, 'Q'), Some('A'));
+        assert_eq!(printed_at(KeyboardLayout::Qwertz, 'Z'), Some('Y'));
+        assert_eq!(printed_at(KeyboardLayout::Dvorak, 'W'), Some(','));
+        assert_eq!(printed_at(KeyboardLayout::Dvorak, '4'), Some('4'));
+        assert_eq!(position_of(KeyboardLayout::Azerty, 'W'), Some('Z'));
+        assert_eq!(position_of(KeyboardLayout::Dvorak, 'R'), Some('O'));
+        assert_eq!(position_of(KeyboardLayout::Qwerty, 'R'), Some('R'));
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn maps_keypad_for_platform() {
+        // QWERTY is the same either way
+        let qwerty = keypad_keys(KeyboardLayout::Qwerty, false);
+        assert_eq!(qwerty, keypad_keys(KeyboardLayout::Qwerty, true));
+        assert_eq!(
+            (qwerty[0x1], qwerty[0xD], qwerty[0xB]),
+            (Key::Key1, Key::R, Key::C)
+        );
+
+        // Keypad 5 sits where QWERTY has W, which AZERTY labels Z
+        let (by_position, by_label) = if READS_CHARACTERS {
+            (Key::Z, Key::W)
+        } else {
+            (Key::W, Key::Z)
+        };
+        assert_eq!(keypad_keys(KeyboardLayout::Azerty, false)[0x5], by_position);
+        assert_eq!(keypad_keys(KeyboardLayout::Azerty, true)[0x5], by_label);
+    }
+}
@@ -0,0 +1,117 @@
+//! The CHIP-8 hex keypad drawn into the window buffer, either as a clickable panel beside the
+//! game or as a small overlay showing which keys are held.
+use crate::chip8::{FONT, KEYPAD_LAYOUT};
+
+const KEY_COLOR: u32 = 0x40_40_40;
+const HELD_COLOR: u32 = 0xC0_C0_C0;
+const LABEL_COLOR: u32 = 0xFF_FF_FF;
+const BACKGROUND_COLOR: u32 = 0x10_10_10;
+
+/// Where and how large to draw the keypad within a buffer `stride` pixels wide.
+#[derive(Copy, Clone, Debug)]
+pub struct Keypad {
+    pub x: usize,
+    pub y: usize,
+    pub key_size: usize,
+}
+
+impl Keypad {
+    /// Width and height of the whole keypad.
+    pub fn size(&self) -> usize {
+        self.key_size * 4
+    }
+
+    /// The key under a point in buffer coordinates.
+    pub fn key_at(&self, x: usize, y: usize) -> Option<u8> {
+        if x < self.x || y < self.y {
+            return None;
+        }
+        let col = (x - self.x) / self.key_size;
+        let row = (y - self.y) / self.key_size;
+        KEYPAD_LAYOUT
+            .get(row)
+            .and_then(|keys| keys.get(col))
+            .copied()
+    }
+
+    /// Draws the keypad with held keys highlighted. Keys large enough to fit one are labelled
+    /// with the built-in font.
+    pub fn draw(&self, buffer: &mut [u32], stride: usize, held: &[bool; 16]) {
+        let gap = (self.key_size / 16).max(1);
+        let scale = self.key_size / 10;
+        for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+            for (col, &key) in keys.iter().enumerate() {
+                let left = self.x + col * self.key_size;
+                let top = self.y + row * self.key_size;
+                let color = if held[key as usize] {
+                    HELD_COLOR
+                } else {
+                    KEY_COLOR
+                };
+                for y in 0..self.key_size {
+                    let line = (top + y) * stride + left;
+                    let key_line = &mut buffer[line..line + self.key_size];
+                    key_line.fill(BACKGROUND_COLOR);
+                    if y >= gap && y < self.key_size - gap {
+                        key_line[gap..self.key_size - gap].fill(color);
+                    }
+                }
+                if scale > 0 {
+                    // Glyphs are 4x5 pixels, centered on the key
+                    let glyph = &FONT[key as usize * 5..key as usize * 5 + 5];
+                    let glyph_left = left + (self.key_size - 4 * scale) / 2;
+                    let glyph_top = top + (self.key_size - 5 * scale) / 2;
+                    for (gy, bits) in glyph.iter().enumerate() {
+                        for gx in 0..4 {
+                            if bits & (0x80 >> gx) == 0 {
+                                continue;
+                            }
+                            for y in 0..scale {
+                                let start =
+                                    (glyph_top + gy * scale + y) * stride + glyph_left + gx * scale;
+                                buffer[start..start + scale].fill(LABEL_COLOR);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_keys_under_points() {
+        let keypad = Keypad {
+            x: 100,
+            y: 0,
+            key_size: 10,
+        };
+        assert_eq!(keypad.key_at(100, 0), Some(0x1));
+        assert_eq!(keypad.key_at(139, 9), Some(0xC));
+        assert_eq!(keypad.key_at(115, 35), Some(0x0));
+        assert_eq!(keypad.key_at(99, 0), None);
+        assert_eq!(keypad.key_at(140, 0), None);
+        assert_eq!(keypad.key_at(100, 40), None);
+    }
+
+    #[test]
+    fn highlights_held_keys() {
+        let keypad = Keypad {
+            x: 0,
+            y: 0,
+            key_size: 4,
+        };
+        let mut buffer = vec![0; 16 * 16];
+        let mut held = [false; 16];
+        held[0x5] = true;
+        keypad.draw(&mut buffer, 16, &held);
+        // Key 5 is in the second row and column
+        assert_eq!(buffer[6 * 16 + 6], HELD_COLOR);
+        assert_eq!(buffer[2 * 16 + 2], KEY_COLOR);
+        assert_eq!(buffer[0], BACKGROUND_COLOR);
+    }
+}
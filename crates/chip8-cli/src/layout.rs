@@ -0,0 +1,170 @@
+//! Where things go in the window buffer at a given `--scale`: the game display, the clickable
+//! keypad beside it, and the overlays drawn on top of the game.
+use crate::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::keypad::Keypad;
+#[cfg(not(windows))]
+use std::env;
+use std::ops::RangeInclusive;
+
+/// Scales that can be picked with `--scale` or the zoom keys. At scale 1, each CHIP-8 pixel
+/// is 10 screen pixels across on a standard-DPI display, for a 640x320 window.
+pub const SCALES: RangeInclusive<usize> = 1..=8;
+pub const DEFAULT_SCALE: usize = 2;
+const BASE_PIXEL_SIZE: usize = 10;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Layout {
+    pub scale: usize,
+    /// Physical pixels per logical pixel on the display, from `display_scale_factor`.
+    pub dpi_factor: f64,
+    /// Screen pixels per CHIP-8 pixel, across and down.
+    pub pixel_size: usize,
+    pub keypad: Option<Keypad>,
+}
+
+impl Layout {
+    /// Lays out the window for a display with the given DPI factor. Pixels are rounded to a
+    /// whole number of physical pixels, so every CHIP-8 pixel comes out the same size.
+    pub fn new(scale: usize, dpi_factor: f64, with_keypad: bool) -> Layout {
+        let pixel_size = ((BASE_PIXEL_SIZE * scale) as f64 * dpi_factor).round() as usize;
+        // The clickable keypad sits to the right of the game, as tall as the window
+        let keypad = if with_keypad {
+            Some(Keypad {
+                x: SCREEN_WIDTH * pixel_size,
+                y: 0,
+                key_size: SCREEN_HEIGHT * pixel_size / 4,
+            })
+        } else {
+            None
+        };
+        Layout {
+            scale,
+            dpi_factor,
+            pixel_size,
+            keypad,
+        }
+    }
+
+    /// The same layout one scale larger or smaller, or None at either end of `SCALES`.
+    pub fn zoom(&self, steps: isize) -> Option<Layout> {
+        let scale = self.scale.checked_add_signed(steps)?;
+        if SCALES.contains(&scale) {
+            Some(Layout::new(scale, self.dpi_factor, self.keypad.is_some()))
+        } else {
+            None
+        }
+    }
+
+    /// Size of the game display.
+    pub fn width(&self) -> usize {
+        SCREEN_WIDTH * self.pixel_size
+    }
+
+    pub fn height(&self) -> usize {
+        SCREEN_HEIGHT * self.pixel_size
+    }
+
+    /// Width of the whole window, including the keypad.
+    pub fn window_width(&self) -> usize {
+        self.width() + self.keypad.map_or(0, |k| k.size())
+    }
+
+    /// Width of the border flashed by `--visual-beep`, which also spaces the `--show-keys`
+    /// overlay from the edges.
+    pub fn border(&self) -> usize {
+        self.pixel_size * 2 / 5
+    }
+
+    /// The `--show-keys` overlay, in the top right corner of the game display.
+    pub fn key_overlay(&self) -> Keypad {
+        Keypad {
+            x: self.width() - self.pixel_size * 4 - self.border(),
+            y: self.border(),
+            key_size: self.pixel_size,
+        }
+    }
+}
+
+/// How many physical pixels the desktop draws per logical pixel, 2 on a typical 4K setup.
+/// minifb sizes windows in physical pixels on Windows and X11, so without this the window
+/// comes out tiny on high-DPI displays. macOS sizes them in points and scales them up itself.
+pub fn display_scale_factor() -> f64 {
+    platform_scale_factor().unwrap_or(1.0)
+}
+
+#[cfg(windows)]
+fn platform_scale_factor() -> Option<f64> {
+    #[link(name = "user32")]
+    extern "system" {
+        fn SetProcessDPIAware() -> i32;
+        fn GetDpiForSystem() -> u32;
+    }
+    // Until the process declares itself DPI aware, Windows stretches its windows as bitmaps,
+    // which blurs the pixels
+    let dpi = unsafe {
+        SetProcessDPIAware();
+        GetDpiForSystem()
+    };
+    usable(f64::from(dpi) / 96.0)
+}
+
+#[cfg(not(windows))]
+fn platform_scale_factor() -> Option<f64> {
+    // X11 has no scale factor of its own, so follow the toolkit settings desktops use for it
+    ["GDK_SCALE", "QT_SCALE_FACTOR"]
+        .iter()
+        .find_map(|var| parse_factor(&env::var(var).ok()?))
+}
+
+#[cfg(any(not(windows), test))]
+fn parse_factor(text: &str) -> Option<f64> {
+    usable(text.trim().parse().ok()?)
+}
+
+/// Ignores factors that would shrink the window, or that aren't numbers at all.
+fn usable(factor: f64) -> Option<f64> {
+    if factor.is_finite() && factor >= 1.0 {
+        Some(factor)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_everything_by_scale() {
+        let layout = Layout::new(1, 1.0, true);
+        assert_eq!((layout.width(), layout.height()), (640, 320));
+        assert_eq!(layout.window_width(), 960);
+        assert_eq!(layout.key_overlay().x, 596);
+        let layout = Layout::new(3, 1.0, false);
+        assert_eq!((layout.window_width(), layout.height()), (1920, 960));
+        assert_eq!(layout.border(), 12);
+        // On a display at 150%, pixels grow to whole physical pixels
+        let layout = Layout::new(1, 1.5, false);
+        assert_eq!((layout.pixel_size, layout.width()), (15, 960));
+        assert_eq!(layout.zoom(1).unwrap().pixel_size, 30);
+    }
+
+    #[test]
+    fn parses_scale_factors() {
+        assert_eq!(parse_factor("2"), Some(2.0));
+        assert_eq!(parse_factor(" 1.25\n"), Some(1.25));
+        assert_eq!(parse_factor("0.5"), None);
+        assert_eq!(parse_factor("NaN"), None);
+        assert_eq!(parse_factor("big"), None);
+    }
+
+    #[test]
+    fn zooms_within_range() {
+        let layout = Layout::new(DEFAULT_SCALE, 1.0, true);
+        let bigger = layout.zoom(1).unwrap();
+        assert_eq!(bigger.scale, 3);
+        assert!(bigger.keypad.is_some());
+        assert!(Layout::new(1, 1.0, false).zoom(-1).is_none());
+        assert!(Layout::new(8, 1.0, false).zoom(1).is_none());
+    }
+}
@@ -0,0 +1,593 @@
+// Much of the emulator is only driven from the window, which builds without `gui` leave out
+#![cfg_attr(not(feature = "gui"), allow(dead_code))]
+#[cfg(feature = "gui")]
+extern crate minifb;
+extern crate rand;
+extern crate tracing;
+extern crate tracing_subscriber;
+
+// The core's modules, at the paths the rest of the emulator uses for them
+use chip8_core::{chip8, clock, hash, opcode, palette, png, quirks, tone};
+
+mod assembler;
+#[cfg(feature = "audio")]
+mod audio;
+mod batch;
+mod browser;
+mod debugger;
+mod decompiler;
+mod differential;
+mod disasm;
+mod draws;
+mod explain;
+mod headless;
+mod keymap;
+mod keypad;
+mod layout;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod monitor;
+mod movie;
+mod options;
+mod perf;
+mod profiler;
+mod recent;
+mod render;
+mod romcheck;
+#[cfg(feature = "rumble")]
+mod rumble;
+mod screenshot;
+mod selftest;
+mod session;
+mod slowdraw;
+mod source_map;
+mod speed;
+mod storage;
+mod symbols;
+mod trace;
+mod video;
+#[cfg(feature = "gui")]
+mod window;
+use assembler::Program;
+use batch::{Manifest, RunResult};
+use chip8::{Chip8, FLAG_COUNT};
+use debugger::Debugger;
+use differential::{Core, Outcome, ReferenceCore, TraceReference};
+use draws::DrawStats;
+use movie::{Movie, Player, Recorder};
+use options::{Mode, Options};
+use profiler::Profiler;
+use quirks::Quirks;
+use recent::RecentRoms;
+use romcheck::Platform;
+use screenshot::Screenshot;
+use session::Session;
+use source_map::SourceMap;
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+use symbols::Symbols;
+use trace::Tracer;
+use tracing_subscriber::EnvFilter;
+use video::Video;
+
+/// What a build without the `gui` feature says when asked for a window.
+#[cfg(not(feature = "gui"))]
+const NO_WINDOW: &str = "This chip8 was built without the gui feature, so it only runs ROMs with \
+                         --headless";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Only warnings are logged unless RUST_LOG asks for more: info for the ROM and how it's
+    // configured, debug for each frame, and trace for each instruction, e.g. RUST_LOG=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        )
+        .with_writer(io::stderr)
+        .init();
+    let options = Options::from_args()?;
+    if cfg!(not(feature = "metrics")) && options.metrics.is_some() {
+        return Err("--metrics needs a build with --features metrics".into());
+    }
+
+    if options.mode == Mode::SelfTest {
+        let outcomes = selftest::run();
+        print!("{}", selftest::table(&outcomes));
+        if !outcomes.iter().all(|o| o.passed()) {
+            std::process::exit(headless::EXIT_FAILED);
+        }
+        return Ok(());
+    }
+
+    if let (Mode::Explain, Some(word)) = (options.mode, &options.rom_path) {
+        print!("{}", explain::explain_word(word)?);
+        return Ok(());
+    }
+
+    let rom_path = match (&options.rom_path, &options.rom_dir) {
+        (Some(path), _) => path.clone(),
+        #[cfg(feature = "gui")]
+        (None, Some(dir)) => match window::browse_roms(dir, &options)? {
+            Some(path) => path,
+            None => return Ok(()),
+        },
+        #[cfg(not(feature = "gui"))]
+        (None, Some(_)) => return Err(NO_WINDOW.into()),
+        (None, None) => choose_recent_rom()?,
+    };
+    if options.mode == Mode::Batch {
+        return run_batch(Path::new(&rom_path), &options);
+    }
+    let program = load_rom(&rom_path)?;
+    let data = program.bytes;
+    let rom_hash = storage::rom_hash(&data);
+
+    if options.mode == Mode::Assemble {
+        let output = match options.output {
+            Some(ref output) => output.clone(),
+            None => Path::new(&rom_path)
+                .with_extension("ch8")
+                .to_string_lossy()
+                .into_owned(),
+        };
+        fs::write(&output, &data)?;
+        if let Some(ref path) = options.symbols {
+            fs::write(path, program.symbols.to_string())?;
+        }
+        if let Some(ref path) = options.source_map {
+            fs::write(path, program.source_map.to_string())?;
+        }
+        return Ok(());
+    }
+
+    if options.mode == Mode::Decompile {
+        let source = decompiler::decompile(&data);
+        match options.output {
+            Some(ref output) => fs::write(output, source)?,
+            None => print!("{}", source),
+        }
+        return Ok(());
+    }
+
+    let symbols = match options.symbols {
+        Some(ref path) => Symbols::parse(&fs::read_to_string(path)?)?,
+        None => program.symbols,
+    };
+    let source_map = match options.source_map {
+        Some(ref path) => SourceMap::parse(&fs::read_to_string(path)?)?,
+        None => program.source_map,
+    };
+
+    if options.mode == Mode::Disassemble {
+        print!("{}", disasm::disassemble(&data, 0x200, &symbols));
+        return Ok(());
+    }
+
+    if options.mode == Mode::Compare {
+        // Both cores need the same seed for Random to agree
+        let seed = options.seed.unwrap_or_else(rand::random);
+        let core = |quirks| {
+            let mut chip8 = Chip8::with_seed(seed);
+            chip8.set_instructions_per_frame(ipf_for(&rom_path, options.instructions_per_frame));
+            chip8.set_quirks(quirks);
+            if options.random_init {
+                chip8.randomize();
+            }
+            chip8.load_program(&data);
+            Core::new(chip8)
+        };
+        let mut reference: Box<dyn ReferenceCore> = match options.reference {
+            Some(ref path) => Box::new(TraceReference::new(io::BufReader::new(File::open(path)?))),
+            None => Box::new(core(options.reference_quirks.unwrap_or_default())),
+        };
+        let max_steps = options
+            .limits
+            .max_cycles
+            .unwrap_or(differential::DEFAULT_MAX_STEPS);
+        let outcome = differential::compare(
+            &mut core(quirks_for(&rom_path, &data, options.quirks)),
+            reference.as_mut(),
+            max_steps,
+        )?;
+        println!("{}", outcome);
+        if let Outcome::Diverged { .. } = outcome {
+            std::process::exit(headless::EXIT_FAILED);
+        }
+        return Ok(());
+    }
+
+    let player = match options.play {
+        Some(ref path) => {
+            let movie = Movie::parse(&fs::read_to_string(path)?)?;
+            if movie.rom_hash != rom_hash {
+                return Err("Movie was recorded with a different ROM".into());
+            }
+            Some(Player::new(movie))
+        }
+        None => None,
+    };
+
+    let screenshot = match options.from_screenshot {
+        Some(ref path) => {
+            let shot = Screenshot::read(&fs::read(path)?)?;
+            if shot.rom_hash != rom_hash {
+                return Err("Screenshot was taken with a different ROM".into());
+            }
+            if shot.state.is_none() {
+                return Err(
+                    "Screenshot has no save state to start from (take it with --screenshot-state)"
+                        .into(),
+                );
+            }
+            Some(shot)
+        }
+        None => None,
+    };
+
+    if let Err(e) = remember_rom(&rom_path, rom_hash) {
+        tracing::warn!("Could not update the recent ROMs list: {}", e);
+    }
+
+    let found = romcheck::check(&data);
+    tracing::info!(path = %rom_path, bytes = data.len(), hash = %format!("{:016x}", rom_hash), "loaded ROM");
+    // They still say which platform the ROM is for, even when the flag registers run
+    let unsupported: Vec<_> = (found.iter().copied())
+        .filter(|u| !(options.persist_flags && u.is_flags()))
+        .collect();
+    if let Some(warning) = romcheck::warning(&unsupported) {
+        tracing::warn!("{}", warning);
+    }
+    let rom_quirks = match (options.quirks, platform_for(&rom_path, &found)) {
+        (Some(quirks), _) => quirks,
+        (None, Some(platform)) => {
+            if platform.quirks() != Quirks::default() {
+                tracing::info!(
+                    "Running with {} quirks ({}); pick others with --quirks",
+                    platform,
+                    platform.quirks()
+                );
+            }
+            platform.quirks()
+        }
+        (None, None) => Quirks::default(),
+    };
+    let rom_ipf = ipf_for(&rom_path, options.instructions_per_frame);
+    if options.instructions_per_frame.is_none() {
+        if let Some(ipf) = rom_ipf {
+            tracing::info!(
+                "Running at {} instructions per frame for its extension; pick another speed \
+                 with --ipf",
+                ipf
+            );
+        }
+    }
+
+    // Create emulator
+    let seed = match player {
+        Some(ref p) => p.seed(),
+        None => options.seed.unwrap_or_else(rand::random),
+    };
+    let instructions_per_frame = match player {
+        Some(ref p) => p.instructions_per_frame(),
+        None => rom_ipf,
+    };
+    let quirks = match (&player, &screenshot) {
+        (Some(p), _) => p.quirks(),
+        (None, Some(shot)) => shot.quirks,
+        (None, None) => rom_quirks,
+    };
+    let random_init = match player {
+        Some(ref p) => p.random_init(),
+        None => options.random_init,
+    };
+    let key_hold = match player {
+        Some(ref p) => p.key_hold(),
+        None => options.key_hold,
+    };
+    tracing::info!(seed, ?instructions_per_frame, %quirks, ?key_hold, random_init, "configured machine");
+    let mut chip8 = Chip8::with_seed(seed);
+    chip8.set_instructions_per_frame(instructions_per_frame);
+    chip8.set_quirks(quirks);
+    chip8.set_key_hold(key_hold);
+    chip8.set_palette(options.palette);
+    if options.debug || options.monitor {
+        chip8.track_memory_access();
+    }
+    if options.show_collisions {
+        chip8.track_collisions();
+    }
+    if options.persist_flags {
+        chip8.enable_flags(load_flags(rom_hash)?);
+    }
+    if random_init {
+        chip8.randomize();
+    }
+    chip8.load_program(&data[..]);
+    let mut session = Session::new(chip8);
+    session.recorder = options.record.as_ref().map(|_| {
+        Recorder::new(
+            rom_hash,
+            seed,
+            instructions_per_frame,
+            quirks,
+            key_hold,
+            random_init,
+        )
+    });
+    session.player = player;
+    if options.debug || options.monitor {
+        session.debugger = Some(open_debugger(rom_hash));
+    }
+    session.symbols = symbols;
+    session.source_map = source_map;
+    session.random_init = random_init;
+    if let Some(ref path) = options.trace {
+        session.tracer = Some(Tracer::new(
+            Box::new(io::BufWriter::new(File::create(path)?)),
+            options.trace_format,
+            options.trace_filter.clone(),
+        ));
+    }
+    session.profiler = options.profile.as_ref().map(|_| Profiler::new(0x200));
+    session.draws = options.draw_stats.as_ref().map(|_| DrawStats::default());
+    session.explain = options.explain;
+    if let Some(ref path) = options.video {
+        session.video = Some(Video::create(path, options.beep)?);
+    }
+    if let Some(Screenshot {
+        state: Some(state),
+        frame,
+        ..
+    }) = screenshot
+    {
+        session.chip8.load_state(&state)?;
+        session.frame = frame;
+    }
+    if options.mode == Mode::Dump {
+        let limits = headless::Limits {
+            max_frames: Some(options.after_frames.unwrap_or(0)),
+            ..headless::Limits::default()
+        };
+        let stop = headless::run(&mut session, &limits, None)?;
+        let output = match options.output {
+            Some(ref output) => output.clone(),
+            None => {
+                let rom_name = Path::new(&rom_path).file_name().unwrap_or_default();
+                memory_dump_path(&rom_name.to_string_lossy(), session.frame)
+            }
+        };
+        fs::write(&output, session.chip8.memory())?;
+        println!("{} at frame {}", stop, session.frame);
+        println!("Wrote memory to {}", output);
+        return Ok(());
+    }
+    if options.headless {
+        let stop = headless::run(&mut session, &options.limits, options.dump.as_ref())?;
+        let code = headless::finish(&session, stop, options.expect_hash);
+        save_flags(&mut session.chip8, rom_hash)?;
+        if let (Some(path), Some(p)) = (&options.profile, &session.profiler) {
+            fs::write(path, p.to_callgrind(&session.symbols))?;
+        }
+        if let (Some(path), Some(d)) = (&options.draw_stats, &session.draws) {
+            fs::write(path, d.report(session.frame, &session.symbols))?;
+        }
+        if let Some(video) = session.video.take() {
+            video.finish()?;
+        }
+        // Exiting skips destructors, so drop the session first to flush the trace
+        drop(session);
+        std::process::exit(code);
+    }
+
+    #[cfg(feature = "gui")]
+    return window::run(
+        options,
+        session,
+        &rom_path,
+        data,
+        rom_hash,
+        instructions_per_frame,
+    );
+    #[cfg(not(feature = "gui"))]
+    Err(NO_WINDOW.into())
+}
+
+/// The platform to run the ROM at `path` as: the one its extension names, or else the one
+/// `found` shows it was written for.
+fn platform_for(path: &str, found: &[romcheck::Unsupported]) -> Option<Platform> {
+    Platform::from_extension(Path::new(path)).or_else(|| romcheck::platform(found))
+}
+
+/// The quirks to run the ROM at `path` with: `given` if any, or else its platform's.
+fn quirks_for(path: &str, rom: &[u8], given: Option<Quirks>) -> Quirks {
+    given.unwrap_or_else(|| {
+        platform_for(path, &romcheck::check(rom)).map_or_else(Quirks::default, Platform::quirks)
+    })
+}
+
+/// A debugger with the breakpoints left set the last time the ROM was debugged.
+fn open_debugger(rom_hash: u64) -> Debugger {
+    let mut debugger = Debugger::default();
+    let text = match storage::breakpoints_path(rom_hash).and_then(fs::read_to_string) {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return debugger,
+        Err(e) => {
+            tracing::warn!("Could not restore breakpoints: {}", e);
+            return debugger;
+        }
+    };
+    match debugger.restore_breakpoints(&text) {
+        Ok(()) => println!("Restored {} breakpoints", debugger.breakpoints().count()),
+        Err(e) => tracing::warn!("Could not restore breakpoints: {}", e),
+    }
+    debugger
+}
+
+/// Saves the debugger's breakpoints for next time, or forgets them once they're all cleared.
+fn save_breakpoints(debugger: &Debugger, rom_hash: u64) -> io::Result<()> {
+    let path = storage::breakpoints_path(rom_hash)?;
+    if debugger.breakpoints().next().is_some() {
+        return storage::write(&path, debugger.saved_breakpoints().as_bytes());
+    }
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// The flag registers the ROM saved last time, or zeros if it never saved any.
+fn load_flags(rom_hash: u64) -> Result<[u8; FLAG_COUNT], Box<dyn std::error::Error>> {
+    let path = storage::flags_path(rom_hash)?;
+    match fs::read(&path) {
+        Ok(data) if data.len() == FLAG_COUNT => {
+            let mut flags = [0; FLAG_COUNT];
+            flags.copy_from_slice(&data);
+            Ok(flags)
+        }
+        Ok(_) => Err(format!("{} should hold {} flags", path.display(), FLAG_COUNT).into()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok([0; FLAG_COUNT]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes out the flag registers if the ROM changed them since the last call.
+fn save_flags(chip8: &mut Chip8, rom_hash: u64) -> io::Result<()> {
+    match chip8.take_changed_flags() {
+        Some(flags) => storage::write(&storage::flags_path(rom_hash)?, &flags),
+        None => Ok(()),
+    }
+}
+
+/// Where to write the memory of the ROM named `rom_name` after `frame` frames, when not told
+/// otherwise: the current directory, named like screenshots.
+fn memory_dump_path(rom_name: &str, frame: u64) -> String {
+    format!("{}-{}.mem", rom_name, frame)
+}
+
+/// Instructions per frame for the ROM at `path`: `given` if any, or else the usual speed of
+/// the platform its extension names.
+fn ipf_for(path: &str, given: Option<u32>) -> Option<u32> {
+    given
+        .or_else(|| Platform::from_extension(Path::new(path)).map(Platform::instructions_per_frame))
+}
+
+/// Runs every ROM in `dir` and checks their final screens against the manifest from the last
+/// run, writing a new one when nothing changed (or with `--update`). Exits with a failure if
+/// any ROM changed.
+fn run_batch(dir: &Path, options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let manifest_path = match options.output {
+        Some(ref output) => PathBuf::from(output),
+        None => dir.join(batch::MANIFEST_NAME),
+    };
+    let frames = options.frames.unwrap_or(batch::DEFAULT_FRAMES);
+    let mut manifest = Manifest::default();
+    for path in browser::scan_roms(dir)? {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let result = load_rom(&path.to_string_lossy()).and_then(|program| {
+            // Runs have to be reproducible, so the seed is fixed
+            let mut chip8 = Chip8::with_seed(options.seed.unwrap_or(0));
+            let path = path.to_string_lossy();
+            chip8.set_instructions_per_frame(ipf_for(&path, options.instructions_per_frame));
+            chip8.set_quirks(quirks_for(&path, &program.bytes, options.quirks));
+            if options.random_init {
+                chip8.randomize();
+            }
+            chip8.load_program(&program.bytes);
+            Ok(batch::run(&mut chip8, frames)?)
+        });
+        let result = match result {
+            Ok(hash) => RunResult::Screen(hash),
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                RunResult::Error
+            }
+        };
+        manifest.results.insert(name.into_owned(), result);
+    }
+
+    let changes = match fs::read_to_string(&manifest_path) {
+        Ok(text) => manifest.changes_since(&Manifest::parse(&text)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    for change in changes.iter() {
+        println!("{}", change);
+    }
+    println!(
+        "{} ROMs run for {} frames, {} changed",
+        manifest.results.len(),
+        frames,
+        changes.len()
+    );
+    if changes.is_empty() || options.update {
+        fs::write(&manifest_path, manifest.to_string())?;
+    }
+    if !changes.is_empty() {
+        std::process::exit(headless::EXIT_FAILED);
+    }
+    Ok(())
+}
+
+/// Reads a ROM from disk, assembling it first if it is Octo source. Assembled programs also
+/// come with the symbols for their labels and a source map.
+fn load_rom(path: &str) -> Result<Program, Box<dyn std::error::Error>> {
+    if path.ends_with(".8o") {
+        let source = fs::read_to_string(path)?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let mut program =
+            assembler::assemble(&source, dir).map_err(|e| format!("{}: {}", path, e))?;
+        program.source_map.file = path.to_string();
+        chip8::check_program_size(&program.bytes).map_err(|e| format!("{}: {}", path, e))?;
+        return Ok(program);
+    }
+
+    let mut file = File::open(path)?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    chip8::check_program_size(&data).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(Program {
+        bytes: data,
+        ..Program::default()
+    })
+}
+
+fn read_recent_roms() -> Result<RecentRoms, Box<dyn std::error::Error>> {
+    match fs::read_to_string(storage::recent_path()?) {
+        Ok(text) => RecentRoms::parse(&text),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(RecentRoms::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn remember_rom(path: &str, rom_hash: u64) -> Result<(), Box<dyn std::error::Error>> {
+    // Relative paths would point somewhere else when launched from another directory
+    let path = fs::canonicalize(path)?;
+    let mut recent = read_recent_roms()?;
+    recent.add(&path.to_string_lossy(), rom_hash);
+    storage::write(&storage::recent_path()?, recent.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// Lists the recently played ROMs on the terminal and asks which one to run.
+fn choose_recent_rom() -> Result<String, Box<dyn std::error::Error>> {
+    let recent = read_recent_roms()?;
+    if recent.roms().is_empty() {
+        return Err(format!("No recently played ROMs\n{}", options::USAGE).into());
+    }
+    println!("Recently played:");
+    for (i, rom) in recent.roms().iter().enumerate() {
+        println!("  {}) {}", i + 1, rom.path);
+    }
+    print!("Play which? [1] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let choice: usize = match answer.trim() {
+        "" => 1,
+        n => n.parse().map_err(|_| format!("Not a number: {}", n))?,
+    };
+    match recent.roms().get(choice.wrapping_sub(1)) {
+        Some(rom) => Ok(rom.path.clone()),
+        None => Err(format!("No ROM numbered {}", choice).into()),
+    }
+}
@@ -0,0 +1,113 @@
+//! Health counters for long-running installs (`--metrics`, built with the `metrics` feature),
+//! reported as a log line on stderr at a fixed interval.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Counters since startup, plus how long frontend frames took over the last interval.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub instructions: u64,
+    pub frames_rendered: u64,
+    pub collisions: u64,
+    pub frame_time_p50: Duration,
+    pub frame_time_p95: Duration,
+    pub frame_time_p99: Duration,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "metrics instructions={} frames_rendered={} collisions={} \
+             frame_time_p50={:?} frame_time_p95={:?} frame_time_p99={:?}",
+            self.instructions,
+            self.frames_rendered,
+            self.collisions,
+            self.frame_time_p50,
+            self.frame_time_p95,
+            self.frame_time_p99
+        )
+    }
+}
+
+pub struct Metrics {
+    interval: Duration,
+    since: Instant,
+    frames_rendered: u64,
+    /// Frame times since the last report.
+    frame_times: Vec<Duration>,
+}
+
+impl Metrics {
+    pub fn new(interval: Duration, now: Instant) -> Metrics {
+        Metrics {
+            interval,
+            since: now,
+            frames_rendered: 0,
+            frame_times: Vec::new(),
+        }
+    }
+
+    /// Counts a pass of the frontend loop that took `frame_time`, given whether it rendered
+    /// and the totals the machine has reached. Returns a report once an interval has passed.
+    pub fn frame(
+        &mut self,
+        now: Instant,
+        frame_time: Duration,
+        rendered: bool,
+        instructions: u64,
+        collisions: u64,
+    ) -> Option<Report> {
+        self.frames_rendered += rendered as u64;
+        self.frame_times.push(frame_time);
+        if now.duration_since(self.since) < self.interval {
+            return None;
+        }
+        self.frame_times.sort();
+        let percentile = |p: usize| self.frame_times[(self.frame_times.len() - 1) * p / 100];
+        let report = Report {
+            instructions,
+            frames_rendered: self.frames_rendered,
+            collisions,
+            frame_time_p50: percentile(50),
+            frame_time_p95: percentile(95),
+            frame_time_p99: percentile(99),
+        };
+        self.since = now;
+        self.frame_times.clear();
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_percentiles_per_interval() {
+        let start = Instant::now();
+        let mut metrics = Metrics::new(Duration::from_secs(10), start);
+        for i in 1..100 {
+            let frame_time = Duration::from_millis(i);
+            assert_eq!(metrics.frame(start, frame_time, i % 2 == 0, i, 0), None);
+        }
+        let end = start + Duration::from_secs(10);
+        let report = metrics.frame(end, Duration::from_millis(100), true, 100, 7);
+        assert_eq!(
+            report,
+            Some(Report {
+                instructions: 100,
+                frames_rendered: 50,
+                collisions: 7,
+                frame_time_p50: Duration::from_millis(50),
+                frame_time_p95: Duration::from_millis(95),
+                frame_time_p99: Duration::from_millis(99),
+            })
+        );
+        // The frame times start over, but the counters keep going
+        let later = end + Duration::from_secs(10);
+        let report = metrics.frame(later, Duration::from_millis(3), true, 120, 7);
+        assert_eq!(report.unwrap().frame_time_p99, Duration::from_millis(3));
+        assert_eq!(report.unwrap().frames_rendered, 51);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::chip8::Chip8;
+use crate::disasm;
+use crate::symbols::Symbols;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// ANSI colors for how often instructions ran, from cold (blue) to hot (red).
+const HEAT_COLORS: [&str; 5] = ["34", "36", "32", "33", "31"];
+
+pub const HELP: &str = "\
+Commands (all numbers are hex, addresses may also be labels):
+  m <addr> [len]       dump memory
+  d <addr> [count]     disassemble, with execution counts colored from cold to hot
+  r                    show registers
+  bt                   show the call stack
+  set <reg> <value>    set V0-VF, I, PC, DT, or ST (while paused)
+  poke <addr> <byte>.. write bytes to memory
+  fill <addr> <len> <byte>
+                       write one byte over a range of memory
+  g <addr>             jump to address and continue
+  b <addr>             toggle breakpoint
+  bl                   list breakpoints
+  p                    pause
+  s                    step one instruction (while paused)
+  n                    step over a call, running it until it returns (while paused)
+  f                    run until the current subroutine returns (while paused)
+  c                    continue
+  heat <file>          export a PNG heatmap of memory reads (green) and writes (red)
+  ?                    show this help";
+
+/// A command entered at the machine monitor prompt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Memory(usize, usize),
+    Disassemble(usize, usize),
+    Registers,
+    CallStack,
+    Poke(usize, Vec<u8>),
+    Fill(usize, usize, u8),
+    Set(Register, usize),
+    Go(usize),
+    Breakpoint(usize),
+    ListBreakpoints,
+    Pause,
+    Step,
+    StepOver,
+    StepOut,
+    Continue,
+    Heatmap(String),
+    Help,
+}
+
+impl Command {
+    /// Parses a command line. Addresses may be given as labels from `symbols`.
+    pub fn parse(line: &str, symbols: &Symbols) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let addr = |word: &str| symbols.resolve(word);
+        let cmd = match words[..] {
+            ["m", a] => Command::Memory(addr(a)?, 0x10),
+            ["m", a, len] => Command::Memory(addr(a)?, parse_hex(len)?),
+            ["d", a] => Command::Disassemble(addr(a)?, 0x10),
+            ["d", a, count] => Command::Disassemble(addr(a)?, parse_hex(count)?),
+            ["r"] => Command::Registers,
+            ["bt"] => Command::CallStack,
+            ["poke", a, ref bytes @ ..] if !bytes.is_empty() => {
+                let bytes = bytes
+                    .iter()
+                    .map(|b| parse_byte(b))
+                    .collect::<Result<Vec<u8>, String>>()?;
+                Command::Poke(addr(a)?, bytes)
+            }
+            ["set", reg, value] => {
+                let reg = Register::parse(reg)?;
+                let value = match reg {
+                    Register::I | Register::Pc => addr(value)?,
+                    _ => usize::from(parse_byte(value)?),
+                };
+                if value > 0xFFF {
+                    return Err(format!("Not an address: {:X}", value));
+                }
+                Command::Set(reg, value)
+            }
+            ["fill", a, len, byte] => Command::Fill(addr(a)?, parse_hex(len)?, parse_byte(byte)?),
+            ["g", a] => Command::Go(addr(a)?),
+            ["b", a] => Command::Breakpoint(addr(a)?),
+            ["bl"] => Command::ListBreakpoints,
+            ["p"] => Command::Pause,
+            ["s"] => Command::Step,
+            ["n"] => Command::StepOver,
+            ["f"] => Command::StepOut,
+            ["c"] => Command::Continue,
+            ["heat", path] => Command::Heatmap(path.to_string()),
+            ["?"] | ["help"] => Command::Help,
+            _ => {
+                return Err(format!(
+                    "Unrecognized command: {} (? for help)",
+                    line.trim()
+                ))
+            }
+        };
+        Ok(cmd)
+    }
+}
+
+/// A register the monitor can set.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Register {
+    V(usize),
+    I,
+    Pc,
+    Dt,
+    St,
+}
+
+impl Register {
+    fn parse(name: &str) -> Result<Register, String> {
+        let reg = match name.to_ascii_uppercase().as_str() {
+            "I" => Register::I,
+            "PC" => Register::Pc,
+            "DT" => Register::Dt,
+            "ST" => Register::St,
+            v => match v.strip_prefix('V').map(|n| usize::from_str_radix(n, 16)) {
+                Some(Ok(n)) if n < 16 && v.len() == 2 => Register::V(n),
+                _ => return Err(format!("Not a register: {}", name)),
+            },
+        };
+        Ok(reg)
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(n) => write!(f, "V{:X}", n),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+        }
+    }
+}
+
+fn parse_hex(word: &str) -> Result<usize, String> {
+    let digits = word.trim_start_matches("0x");
+    usize::from_str_radix(digits, 16).map_err(|_| format!("Not a hex number: {}", word))
+}
+
+fn parse_byte(word: &str) -> Result<u8, String> {
+    match parse_hex(word)? {
+        b if b <= 0xFF => Ok(b as u8),
+        b => Err(format!("Not a byte: {:X}", b)),
+    }
+}
+
+/// Formats memory as a classic hex dump, 16 bytes per line.
+pub fn hexdump(chip8: &Chip8, addr: usize, len: usize) -> String {
+    let memory = chip8.memory();
+    let start = addr.min(memory.len());
+    let end = addr.saturating_add(len).min(memory.len());
+    memory[start..end]
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+            format!("{:03X}: {}", start + i * 16, bytes.join(" "))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Disassembles `count` instructions from `addr`, marking the one at PC. When memory access
+/// is tracked, each line also shows how many times it ran, colored by how hot it is compared
+/// to the hottest instruction.
+pub fn disassemble(chip8: &Chip8, symbols: &Symbols, addr: usize, count: usize) -> String {
+    let end = chip8.memory().len() - 1;
+    (addr..end)
+        .step_by(2)
+        .take(count)
+        .map(|a| {
+            let raw = chip8.instruction_at(a);
+            let text = match chip8.opcode_at(a) {
+                Some(op) => disasm::format_opcode(op, symbols),
+                None => format!("DW 0x{:04X}", raw),
+            };
+            let marker = if a == chip8.pc() { '>' } else { ' ' };
+            let line = format!("{}{:03X}: {:04X}  {}", marker, a, raw, text);
+            let access = match chip8.memory_access() {
+                Some(access) => access,
+                None => return line,
+            };
+            match access.execution_heat(a, HEAT_COLORS.len() as u32 + 1) {
+                0 => format!("{:>8} {}", "", line),
+                heat => format!(
+                    "\x1b[{}m{:>8} {}\x1b[0m",
+                    HEAT_COLORS[heat as usize - 1],
+                    access.executions[a],
+                    line
+                ),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Reads monitor input on a background thread so the emulator keeps running while the
+/// terminal waits for a line. The channel disconnects when stdin is closed.
+pub fn spawn_reader() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let sent = line.map(|line| tx.send(line).is_ok());
+            if !sent.unwrap_or(false) {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Command, String> {
+        Command::parse(line, &Symbols::parse("draw_score 0x2A0").unwrap())
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("m 200 20"), Ok(Command::Memory(0x200, 0x20)));
+        assert_eq!(parse("m 0x300"), Ok(Command::Memory(0x300, 0x10)));
+        assert_eq!(parse(" r "), Ok(Command::Registers));
+        assert_eq!(parse("bt"), Ok(Command::CallStack));
+        assert_eq!(
+            parse("poke 300 FF 0a"),
+            Ok(Command::Poke(0x300, vec![0xFF, 0x0A]))
+        );
+        assert_eq!(parse("fill 300 10 0"), Ok(Command::Fill(0x300, 0x10, 0)));
+        assert_eq!(parse("set va 2C"), Ok(Command::Set(Register::V(0xA), 0x2C)));
+        assert_eq!(parse("set PC 2A0"), Ok(Command::Set(Register::Pc, 0x2A0)));
+        assert_eq!(parse("g 2A0"), Ok(Command::Go(0x2A0)));
+        assert_eq!(parse("c"), Ok(Command::Continue));
+        assert_eq!(parse("n"), Ok(Command::StepOver));
+        assert_eq!(
+            parse("heat pong.png"),
+            Ok(Command::Heatmap("pong.png".into()))
+        );
+    }
+
+    #[test]
+    fn resolves_labels() {
+        assert_eq!(parse("b draw_score"), Ok(Command::Breakpoint(0x2A0)));
+        assert_eq!(parse("m draw_score 4"), Ok(Command::Memory(0x2A0, 4)));
+        assert_eq!(
+            parse("set i draw_score"),
+            Ok(Command::Set(Register::I, 0x2A0))
+        );
+        assert!(parse("g nowhere").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        assert!(parse("").is_err());
+        assert!(parse("m").is_err());
+        assert!(parse("m xyz").is_err());
+        assert!(parse("poke 300").is_err());
+        assert!(parse("poke 300 100").is_err());
+        assert!(parse("fill 300 10").is_err());
+        assert!(parse("set vg 1").is_err());
+        assert!(parse("set dt 100").is_err());
+        assert!(parse("set pc 1000").is_err());
+        assert!(parse("r 1").is_err());
+    }
+
+    #[test]
+    fn disassembles_with_execution_heat() {
+        assert_eq!(parse("d draw_score 4"), Ok(Command::Disassemble(0x2A0, 4)));
+        let mut chip8 = Chip8::with_seed(0);
+        // V0 += 1 four times through a loop, then halt
+        chip8.load_program(&[0x70, 0x01, 0x30, 0x04, 0x12, 0x00, 0x12, 0x06]);
+        let symbols = Symbols::default();
+        assert_eq!(
+            disassemble(&chip8, &symbols, 0x200, 2),
+            ">200: 7001  ADD V0, 0x01\n 202: 3004  SE V0, 0x04"
+        );
+        chip8.track_memory_access();
+        for _ in 0..12 {
+            chip8.step().unwrap();
+        }
+        assert_eq!(
+            disassemble(&chip8, &symbols, 0x200, 5),
+            "\x1b[31m       4  200: 7001  ADD V0, 0x01\x1b[0m\n\
+             \x1b[31m       4  202: 3004  SE V0, 0x04\x1b[0m\n\
+             \x1b[33m       3  204: 1200  JP 0x200\x1b[0m\n\
+             \x1b[32m       1 >206: 1206  JP 0x206\x1b[0m\n\
+             \x20         208: 0000  SYS"
+        );
+    }
+
+    #[test]
+    fn dumps_memory() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&[0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(hexdump(&chip8, 0x200, 4), "200: 00 E0 12 00");
+        assert_eq!(hexdump(&chip8, 0xFFE, 0x10), "FFE: 00 00");
+        assert_eq!(hexdump(&chip8, 0x1F0, 0x20).lines().count(), 2);
+        assert_eq!(hexdump(&chip8, 0xFFE, usize::MAX), "FFE: 00 00");
+    }
+}
@@ -0,0 +1,379 @@
+use crate::chip8::{Chip8, KeyHold};
+use crate::quirks::Quirks;
+use std::fmt;
+
+const MOVIE_HEADER: &str = "chip8-movie 1";
+/// How often (in frames) a state hash is recorded to catch playback desyncs.
+pub const CHECKPOINT_INTERVAL: u64 = 60;
+
+/// A change in a key's state, applied right before the given frame executes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// A recorded run: everything needed to reproduce it exactly from power-on, plus state
+/// hashes taken along the way to verify that playback did not diverge.
+///
+/// Movies are stored as plain text, one entry per line:
+///
+/// ```text
+/// chip8-movie 1
+/// rom 9f1b7c3a22e0d4f1
+/// seed 1234
+/// ipf 10
+/// quirks legacy-flags
+/// key-repeat
+/// key-release 3
+/// random-init
+/// key 12 5 down
+/// key 20 5 up
+/// checkpoint 59 0be3d1c07a9f44e2
+/// length 75
+/// ```
+///
+/// The `ipf`, `quirks`, `key-repeat`, `key-release`, and `random-init` lines are only there
+/// for movies recorded with the matching options.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    /// Instructions run per frame, or None if every frame is a single instruction.
+    pub instructions_per_frame: Option<u32>,
+    pub quirks: Quirks,
+    pub key_hold: KeyHold,
+    /// Whether the machine powered on with garbage in RAM, the registers, and the screen.
+    pub random_init: bool,
+    pub events: Vec<InputEvent>,
+    pub checkpoints: Vec<(u64, u64)>,
+    /// Number of frames recorded.
+    pub length: u64,
+}
+
+impl Movie {
+    pub fn parse(text: &str) -> Result<Movie, Box<dyn std::error::Error>> {
+        let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+        if lines.next() != Some(MOVIE_HEADER) {
+            return Err("Not a Chip-8 movie file".into());
+        }
+
+        let mut movie = Movie::default();
+        for line in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                ["rom", hash] => movie.rom_hash = u64::from_str_radix(hash, 16)?,
+                ["seed", seed] => movie.seed = seed.parse()?,
+                ["ipf", ipf] => movie.instructions_per_frame = Some(ipf.parse()?),
+                ["quirks", quirks] => movie.quirks = Quirks::parse(quirks)?,
+                ["key-repeat"] => movie.key_hold.repeat = true,
+                ["key-release", frames] => movie.key_hold.release_frames = frames.parse()?,
+                ["random-init"] => movie.random_init = true,
+                ["key", frame, key, state] => movie.events.push(InputEvent {
+                    frame: frame.parse()?,
+                    key: u8::from_str_radix(key, 16)?,
+                    pressed: match state {
+                        "down" => true,
+                        "up" => false,
+                        _ => return Err(format!("Invalid key state: {}", line).into()),
+                    },
+                }),
+                ["checkpoint", frame, hash] => movie
+                    .checkpoints
+                    .push((frame.parse()?, u64::from_str_radix(hash, 16)?)),
+                ["length", length] => movie.length = length.parse()?,
+                _ => return Err(format!("Invalid movie line: {}", line).into()),
+            }
+        }
+        Ok(movie)
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", MOVIE_HEADER)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "seed {}", self.seed)?;
+        if let Some(ipf) = self.instructions_per_frame {
+            writeln!(f, "ipf {}", ipf)?;
+        }
+        if self.quirks != Quirks::default() {
+            writeln!(f, "quirks {}", self.quirks)?;
+        }
+        if self.key_hold.repeat {
+            writeln!(f, "key-repeat")?;
+        }
+        if self.key_hold.release_frames > 0 {
+            writeln!(f, "key-release {}", self.key_hold.release_frames)?;
+        }
+        if self.random_init {
+            writeln!(f, "random-init")?;
+        }
+        for e in self.events.iter() {
+            let state = if e.pressed { "down" } else { "up" };
+            writeln!(f, "key {} {:X} {}", e.frame, e.key, state)?;
+        }
+        for (frame, hash) in self.checkpoints.iter() {
+            writeln!(f, "checkpoint {} {:016x}", frame, hash)?;
+        }
+        writeln!(f, "length {}", self.length)
+    }
+}
+
+/// Builds up a movie while the emulator runs.
+pub struct Recorder {
+    movie: Movie,
+    keys: [bool; 16],
+}
+
+impl Recorder {
+    pub fn new(
+        rom_hash: u64,
+        seed: u64,
+        instructions_per_frame: Option<u32>,
+        quirks: Quirks,
+        key_hold: KeyHold,
+        random_init: bool,
+    ) -> Recorder {
+        Recorder {
+            movie: Movie {
+                rom_hash,
+                seed,
+                instructions_per_frame,
+                quirks,
+                key_hold,
+                random_init,
+                ..Movie::default()
+            },
+            keys: [false; 16],
+        }
+    }
+
+    /// Records the key state that is about to be fed into `frame`.
+    pub fn record_input(&mut self, frame: u64, keys: &[bool; 16]) {
+        for (key, (old, new)) in self.keys.iter_mut().zip(keys.iter()).enumerate() {
+            if old != new {
+                self.movie.events.push(InputEvent {
+                    frame,
+                    key: key as u8,
+                    pressed: *new,
+                });
+                *old = *new;
+            }
+        }
+    }
+
+    /// Called after `frame` has executed.
+    pub fn end_frame(&mut self, frame: u64, chip8: &Chip8) {
+        self.movie.length = frame + 1;
+        if (frame + 1).is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.movie.checkpoints.push((frame, chip8.state_hash()));
+        }
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Detected when the state hash at a checkpoint doesn't match the one recorded.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: u64,
+    /// The last frame known to still match the recording, if any.
+    pub last_good_frame: Option<u64>,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Movie desynced at frame {}", self.frame)?;
+        match self.last_good_frame {
+            Some(good) => write!(f, " (last matching checkpoint: frame {})", good),
+            None => write!(f, " (no earlier checkpoint matched)"),
+        }
+    }
+}
+
+impl std::error::Error for Desync {}
+
+/// Feeds a recorded movie back into the emulator and checks it stays in sync.
+pub struct Player {
+    movie: Movie,
+    next_event: usize,
+    next_checkpoint: usize,
+    keys: [bool; 16],
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Player {
+        Player {
+            movie,
+            next_event: 0,
+            next_checkpoint: 0,
+            keys: [false; 16],
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.movie.seed
+    }
+
+    pub fn instructions_per_frame(&self) -> Option<u32> {
+        self.movie.instructions_per_frame
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.movie.quirks
+    }
+
+    pub fn key_hold(&self) -> KeyHold {
+        self.movie.key_hold
+    }
+
+    pub fn random_init(&self) -> bool {
+        self.movie.random_init
+    }
+
+    pub fn is_finished(&self, frame: u64) -> bool {
+        frame >= self.movie.length
+    }
+
+    /// Returns the key state to feed into `frame`.
+    pub fn input(&mut self, frame: u64) -> &[bool; 16] {
+        while let Some(e) = self.movie.events.get(self.next_event) {
+            if e.frame > frame {
+                break;
+            }
+            self.keys[e.key as usize & 0xF] = e.pressed;
+            self.next_event += 1;
+        }
+        &self.keys
+    }
+
+    /// Called after `frame` has executed, verifying the state against any checkpoint for it.
+    pub fn end_frame(&mut self, frame: u64, chip8: &Chip8) -> Result<(), Desync> {
+        while let Some((checkpoint, hash)) = self.movie.checkpoints.get(self.next_checkpoint) {
+            if *checkpoint > frame {
+                break;
+            }
+            if *checkpoint == frame && *hash != chip8.state_hash() {
+                let last_good_frame = self
+                    .next_checkpoint
+                    .checked_sub(1)
+                    .map(|i| self.movie.checkpoints[i].0);
+                return Err(Desync {
+                    frame,
+                    last_good_frame,
+                });
+            }
+            self.next_checkpoint += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    // Loops forever, picking a random digit sprite whenever key 5 is held.
+    const PROGRAM: [u8; 10] = [0x61, 0x05, 0xE1, 0xA1, 0xC2, 0x0F, 0xF2, 0x29, 0x12, 0x00];
+
+    fn run(movie: Option<&Movie>, seed: u64, keys_at: impl Fn(u64) -> [bool; 16]) -> Movie {
+        let mut chip8 = Chip8::with_seed(seed);
+        chip8.load_program(&PROGRAM);
+        let mut recorder = Recorder::new(
+            0xABCD,
+            seed,
+            None,
+            Quirks::default(),
+            KeyHold::default(),
+            false,
+        );
+        let mut player = movie.cloned().map(Player::new);
+        for frame in 0..150 {
+            let keys = match player.as_mut() {
+                Some(p) => *p.input(frame),
+                None => keys_at(frame),
+            };
+            recorder.record_input(frame, &keys);
+            apply_keys(&mut chip8, &keys);
+            chip8.tick().unwrap();
+            recorder.end_frame(frame, &chip8);
+            if let Some(p) = player.as_mut() {
+                p.end_frame(frame, &chip8).unwrap();
+            }
+        }
+        recorder.finish()
+    }
+
+    fn apply_keys(chip8: &mut Chip8, keys: &[bool; 16]) {
+        for (k, down) in keys.iter().enumerate() {
+            if *down {
+                chip8.set_key_down(k as u8);
+            } else {
+                chip8.set_key_up(k as u8);
+            }
+        }
+    }
+
+    fn key5_between(frame: u64, from: u64, to: u64) -> [bool; 16] {
+        let mut keys = [false; 16];
+        keys[5] = frame >= from && frame < to;
+        keys
+    }
+
+    #[test]
+    fn movie_round_trips_through_text() {
+        let movie = run(None, 42, |f| key5_between(f, 10, 20));
+        assert_eq!(movie.events.len(), 2);
+        assert_eq!(movie.checkpoints.len(), 2);
+        assert_eq!(Movie::parse(&movie.to_string()).unwrap(), movie);
+        let movie = Movie {
+            instructions_per_frame: Some(10),
+            quirks: Quirks::parse("legacy-flags").unwrap(),
+            key_hold: KeyHold {
+                repeat: true,
+                release_frames: 3,
+            },
+            random_init: true,
+            ..movie
+        };
+        assert_eq!(Movie::parse(&movie.to_string()).unwrap(), movie);
+    }
+
+    #[test]
+    fn playback_reproduces_recording() {
+        let movie = run(None, 42, |f| key5_between(f, 10, 20));
+        assert_eq!(run(Some(&movie), 42, |_| [false; 16]), movie);
+    }
+
+    #[test]
+    fn detects_first_divergent_checkpoint() {
+        let mut movie = run(None, 42, |f| key5_between(f, 10, 20));
+        movie.checkpoints[1].1 ^= 1;
+
+        let mut chip8 = Chip8::with_seed(42);
+        chip8.load_program(&PROGRAM);
+        let mut player = Player::new(movie);
+        let mut result = Ok(());
+        for frame in 0..150 {
+            let keys = *player.input(frame);
+            apply_keys(&mut chip8, &keys);
+            chip8.tick().unwrap();
+            result = player.end_frame(frame, &chip8);
+            if result.is_err() {
+                break;
+            }
+        }
+        assert_eq!(
+            result,
+            Err(Desync {
+                frame: 119,
+                last_good_frame: Some(59)
+            })
+        );
+    }
+}
@@ -0,0 +1,699 @@
+use crate::chip8::KeyHold;
+use crate::headless::{FrameDump, Limits};
+use crate::keymap::KeyboardLayout;
+use crate::layout::SCALES;
+use crate::palette::Palette;
+use crate::quirks::Quirks;
+use crate::tone::{Beep, Waveform};
+use crate::trace::{TraceFilter, TraceFormat};
+use std::env;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: chip8 [options] [<rom>]
+       chip8 disasm [--symbols <file>] <rom>
+       chip8 assemble [-o <rom>] [--symbols <file>] [--source-map <file>] <source.8o>
+       chip8 decompile [-o <source.8o>] <rom>
+       chip8 compare (--reference <trace.jsonl> | --reference-quirks <names>) <rom>
+       chip8 batch [--frames <n>] [-o <manifest>] [--update] <romdir>
+       chip8 dump [--after-frames <n>] [-o <file>] <rom>
+       chip8 explain <word>
+       chip8 selftest
+
+Octo source files (.8o) can also be run directly. Without a ROM, pick from the recently
+played ones, or from a --romdir using the keypad (5 and 8 to move, 6 to play). Hold Tab for
+turbo and ` for slow motion, and press \\ to play back each sprite drawn a line at a time.
+Insert mutes the buzzer (in a build with --features audio).
+F8 resets the machine and F9 reloads the ROM from disk. F12 saves a screenshot to the current
+directory, and F10 the 4KB of memory, as dump does after running the ROM without a window.
+
+Options:
+  --headless          run without a window until the program exits (00FD) or a limit
+                      below ends the run. Exits with 0 on a halt or when exiting with
+                      V0 = 0, 3 when exiting with any other V0 or on a screen hash
+                      mismatch, 2 at a limit, or 1 on an error
+  --max-cycles <n>    in headless mode, stop after n instructions
+  --max-frames <n>    in headless mode, stop after n frames
+  --exit-on-halt      in headless mode, stop once the program jumps to itself
+  --expect-hash <hex> in headless mode, fail unless the final screen has this hash
+  --dump-frames <dir> in headless mode, save the screen as numbered PNGs in dir
+  --every <n>         with --dump-frames, save only every nth frame
+  --autosave          save on exit and offer to resume next time
+  --debug             enable debugger hotkeys (F5 pause, F6 step, F7 step back, F11 step
+                      over, Shift+F11 step out)
+  --monitor           open a machine monitor prompt on the terminal
+  --visual-beep       flash the window border while the sound timer is active
+  --volume <n>        buzzer volume from 0 to 100 (20 by default)
+  --beep-hz <n>       frequency of the beep played by all but XO-CHIP programs with
+                      their own audio, from 20 to 20000 (250 by default)
+  --waveform <shape>  the beep's shape: square (the default), triangle, or sine
+  --pause-unfocused   pause and go quiet while the window is in the background
+  --keypad            show a clickable hex keypad beside the game
+  --vsync             advance the game by one display refresh per window update instead
+                      of by the time that passed, falling back to 60Hz steps where
+                      updates don't wait for the display
+  --scale <n>         window size from 1 (640x320, larger on high-DPI displays) to 8,
+                      2 by default. + and - zoom while playing
+  --turbo-skip <n>    while holding turbo, draw only every nth window update (4 by
+                      default), so drawing doesn't hold fast-forward back
+  --perf              show performance stats over the game and log them every second:
+                      speed, time per window update, and timer drift. Backspace
+                      shows and hides them while playing
+  --show-keys         overlay the keys the emulator sees as held
+  --show-collisions   light up pixels where sprites collided (setting VF) for a moment
+  --keyboard <layout> qwerty (the default), azerty, qwertz, or dvorak, so the keypad
+                      stays on the keys in the spots of QWERTY's 1-4, Q-R, A-F, and Z-V
+  --keys-by-character play the keypad on the keys labeled 1-4, Q-R, A-F, and Z-V instead,
+                      wherever the layout puts them
+  --key-repeat        let a held key answer every wait for a key press (Fx0A), not just
+                      the first
+  --key-release <n>   keep released keys reading as held to skip-if-key instructions
+                      for n frames, so quick taps aren't missed
+  --romdir <dir>      choose a ROM from this directory on screen when none is given
+  --palette <colors>  classic, high-contrast, colorblind, or hex colors like 000000,FFB000
+  --symbols <file>    load labels for the debugger and disassembler
+  --source-map <file> load source lines for the debugger and error messages
+  --quirks <names>    interpreter quirks to emulate, comma-separated: legacy-flags,
+                      memory-increment, exclusive-range, shift-vy, jump-vx,
+                      wrap-sprites, vf-reset, memory-stack (default: those of the
+                      platform the ROM's extension names or it looks written for)
+  --ipf <n>           run n instructions per 60Hz frame instead of one per timer tick
+                      (default for .ch8, .sc8, and .xo8 files: 15, 30, and 200)
+  --random-init       power on with seeded garbage in RAM, registers, and the screen
+  --persist-flags     enable SCHIP's flag registers (Fx75/Fx85, saveflags/loadflags in
+                      Octo) and keep them in a file per ROM, so homebrew games can save
+                      high scores. Not part of CHIP-8, so without it they're invalid
+  --metrics <secs>    print health counters every secs seconds (needs a build with
+                      --features metrics)
+  --trace <file>      write every instruction executed to a file
+  --trace-format <f>  text (the default) or jsonl, one JSON object per instruction
+  --trace-pc <range>  trace only instructions at addresses like 0x200..0x260 (end excluded)
+  --trace-ops <ops>   trace only these instructions, comma-separated like DRW,CALL,RET
+  --explain           print each instruction executed, or stepped through in the debugger,
+                      with a plain-English explanation of what it did
+  --profile <file>    write a callgrind profile of the ROM's subroutines on exit
+  --draw-stats <file> write how often each sprite was drawn, and where, on exit
+  --reference <file>  for compare, a JSON Lines trace to check each instruction against
+  --reference-quirks <names>
+                      for compare, check against this emulator with other quirks
+  --frames <n>        for batch, frames to run each ROM for (600 by default)
+  --update            for batch, rewrite the manifest even when results changed
+  --after-frames <n>  for dump, frames to run before writing out memory (0 by default)
+  --seed <n>          seed the random number generator
+  --record <movie>    record input to a movie file
+  --play <movie>      play back a movie file
+  --video <file>      record gameplay to a video: raw YUV4MPEG2 frames to a .y4m file or
+                      - (stdout), or encoded with the buzzer by ffmpeg to any other file
+  --screenshot-state  include a save state in screenshots, to start from with
+                      --from-screenshot
+  --from-screenshot <png>
+                      start the ROM from the moment a screenshot was taken";
+
+/// What the program was asked to do.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Run the ROM in a window.
+    #[default]
+    Run,
+    /// Print a disassembly of the ROM.
+    Disassemble,
+    /// Assemble Octo source into a ROM.
+    Assemble,
+    /// Reconstruct Octo source from a ROM.
+    Decompile,
+    /// Run the ROM in lockstep with a reference, reporting where they first differ.
+    Compare,
+    /// Run every ROM in a directory and compare their final screens with the last run.
+    Batch,
+    /// Run the built-in opcode checks, without a ROM.
+    SelfTest,
+    /// Run the ROM without a window, then write out its memory.
+    Dump,
+    /// Describe a single instruction word, without a ROM.
+    Explain,
+}
+
+/// Command line options for the emulator frontend.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub mode: Mode,
+    /// The ROM to load, or for `explain`, the instruction word. Only `Run` may leave it out, to
+    /// pick from the recent ROMs instead.
+    pub rom_path: Option<String>,
+    /// Where `assemble` writes the ROM, `decompile` the source, `batch` the manifest, or `dump`
+    /// the memory.
+    pub output: Option<String>,
+    /// Run without a window, as fast as possible, until one of the `limits` is reached.
+    pub headless: bool,
+    pub limits: Limits,
+    /// Save the screen during a headless run.
+    pub dump: Option<FrameDump>,
+    /// Screen hash a headless run has to end with to pass.
+    pub expect_hash: Option<u64>,
+    /// Save the machine state on exit and offer to resume it next time the ROM is loaded.
+    pub autosave: bool,
+    /// Enable the debugger hotkeys (F5 pause, F6 step, F7 step back, F11 step over, Shift+F11
+    /// step out).
+    pub debug: bool,
+    /// Open a machine monitor prompt on the terminal (implies `debug`).
+    pub monitor: bool,
+    /// Flash the window border while the sound timer is active, for players who can't hear
+    /// the buzzer.
+    pub visual_beep: bool,
+    /// How loud the buzzer is, and how its beep sounds.
+    pub beep: Beep,
+    /// Stop the game while another window has focus, so it can't be lost while alt-tabbed.
+    pub pause_unfocused: bool,
+    /// Show a hex keypad beside the game that can be clicked to press keys.
+    pub keypad: bool,
+    /// Tie emulation to the display refresh rather than to the wall clock.
+    pub vsync: bool,
+    /// Window size, as a multiple of 640x320.
+    pub scale: Option<usize>,
+    /// While turbo is held, draw only every nth window update.
+    pub turbo_skip: Option<u32>,
+    /// Show performance stats from the start.
+    pub perf: bool,
+    /// Overlay the hex keypad on the game, highlighting the keys the emulator sees as held.
+    pub show_keys: bool,
+    /// Light up the pixels where sprites collided, to check hitboxes by eye.
+    pub show_collisions: bool,
+    /// The keyboard's layout, for finding the keys in the keypad's spots.
+    pub keyboard: KeyboardLayout,
+    /// Map the keypad by the characters on the keys rather than their positions.
+    pub keys_by_character: bool,
+    /// How held and released keys read to the program.
+    pub key_hold: KeyHold,
+    /// Directory to pick a ROM from on screen, with the keypad, when no ROM is given.
+    pub rom_dir: Option<String>,
+    /// Display colors, from a preset or given as hex.
+    pub palette: Palette,
+    /// Symbol file with labels to use in place of raw addresses (written by `assemble`).
+    pub symbols: Option<String>,
+    /// Source map tying addresses to lines of Octo source (written by `assemble`).
+    pub source_map: Option<String>,
+    /// Interpreter behaviors to emulate where CHIP-8 implementations differ. When left out,
+    /// they're picked for the platform the ROM looks written for.
+    pub quirks: Option<Quirks>,
+    /// Instructions to run per 60Hz frame (Octo's "cycles per frame"). When left out, the CPU
+    /// runs one instruction per timer tick.
+    pub instructions_per_frame: Option<u32>,
+    /// Power on with garbage in RAM, the registers, and the screen instead of zeros, to catch
+    /// ROMs that rely on memory being cleared.
+    pub random_init: bool,
+    /// Keep the flag registers between runs, a non-standard extension for saving high scores.
+    pub persist_flags: bool,
+    /// Seconds between `--metrics` reports, which need the `metrics` feature.
+    pub metrics: Option<u64>,
+    /// Write a trace of every instruction executed to this file.
+    pub trace: Option<String>,
+    pub trace_format: TraceFormat,
+    /// Which instructions to leave in the trace.
+    pub trace_filter: TraceFilter,
+    /// Print each instruction executed with an explanation, for learning what programs do.
+    pub explain: bool,
+    /// Write a callgrind profile of instructions run per subroutine here on exit.
+    pub profile: Option<String>,
+    /// Write counts of sprite draws by sprite address and screen position here on exit.
+    pub draw_stats: Option<String>,
+    /// For `compare`, a recorded JSON Lines trace to compare against.
+    pub reference: Option<String>,
+    /// For `compare`, quirks for a second instance of this emulator to compare against.
+    pub reference_quirks: Option<Quirks>,
+    /// For `batch`, frames to run each ROM for.
+    pub frames: Option<u64>,
+    /// For `batch`, replace the manifest even if results changed since it was written.
+    pub update: bool,
+    /// For `dump`, frames to run before writing out memory.
+    pub after_frames: Option<u64>,
+    /// Seed for the random number generator, for reproducible runs.
+    pub seed: Option<u64>,
+    /// Record input to a movie file, written on exit.
+    pub record: Option<String>,
+    /// Play back a previously recorded movie file.
+    pub play: Option<String>,
+    /// Record a video of every frame run, as y4m or encoded by ffmpeg.
+    pub video: Option<String>,
+    /// Embed a save state in screenshots.
+    pub screenshot_state: bool,
+    /// Start from the save state in a screenshot taken with `screenshot_state`.
+    pub from_screenshot: Option<String>,
+}
+
+impl Options {
+    pub fn from_args() -> Result<Options, Box<dyn std::error::Error>> {
+        Options::parse(env::args().skip(1))
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(
+        mut args: I,
+    ) -> Result<Options, Box<dyn std::error::Error>> {
+        let mut options = Options::default();
+        let (mut dump_dir, mut dump_every) = (None, None);
+        let mut first = true;
+        while let Some(arg) = args.next() {
+            let is_first = first;
+            first = false;
+            match arg.as_str() {
+                "disasm" if is_first => options.mode = Mode::Disassemble,
+                "assemble" if is_first => options.mode = Mode::Assemble,
+                "decompile" if is_first => options.mode = Mode::Decompile,
+                "compare" if is_first => options.mode = Mode::Compare,
+                "batch" if is_first => options.mode = Mode::Batch,
+                "selftest" if is_first => options.mode = Mode::SelfTest,
+                "dump" if is_first => options.mode = Mode::Dump,
+                "explain" if is_first => options.mode = Mode::Explain,
+                "-o" | "--output" => options.output = Some(value(&mut args, &arg)?),
+                "--headless" => options.headless = true,
+                "--max-cycles" => {
+                    options.limits.max_cycles = Some(value(&mut args, &arg)?.parse()?)
+                }
+                "--max-frames" => {
+                    options.limits.max_frames = Some(value(&mut args, &arg)?.parse()?)
+                }
+                "--exit-on-halt" => options.limits.exit_on_halt = true,
+                "--dump-frames" => dump_dir = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--every" => {
+                    let every = value(&mut args, &arg)?.parse()?;
+                    if every == 0 {
+                        return Err("--every must be at least 1".into());
+                    }
+                    dump_every = Some(every);
+                }
+                "--expect-hash" => {
+                    options.expect_hash = Some(u64::from_str_radix(&value(&mut args, &arg)?, 16)?)
+                }
+                "--autosave" => options.autosave = true,
+                "--debug" => options.debug = true,
+                "--monitor" => options.monitor = true,
+                "--visual-beep" => options.visual_beep = true,
+                "--volume" => {
+                    let volume: u8 = value(&mut args, &arg)?.parse()?;
+                    if volume > 100 {
+                        return Err("--volume must be from 0 to 100".into());
+                    }
+                    options.beep.volume = f32::from(volume) / 100.0;
+                }
+                "--beep-hz" => {
+                    let hz: u32 = value(&mut args, &arg)?.parse()?;
+                    if !(20..=20000).contains(&hz) {
+                        return Err("--beep-hz must be from 20 to 20000".into());
+                    }
+                    options.beep.frequency = f64::from(hz);
+                }
+                "--waveform" => options.beep.waveform = Waveform::parse(&value(&mut args, &arg)?)?,
+                "--pause-unfocused" => options.pause_unfocused = true,
+                "--keypad" => options.keypad = true,
+                "--vsync" => options.vsync = true,
+                "--scale" => {
+                    let scale = value(&mut args, &arg)?.parse()?;
+                    if !SCALES.contains(&scale) {
+                        return Err(format!(
+                            "--scale must be from {} to {}",
+                            SCALES.start(),
+                            SCALES.end()
+                        )
+                        .into());
+                    }
+                    options.scale = Some(scale);
+                }
+                "--turbo-skip" => {
+                    let skip = value(&mut args, &arg)?.parse()?;
+                    if skip == 0 {
+                        return Err("--turbo-skip must be at least 1".into());
+                    }
+                    options.turbo_skip = Some(skip);
+                }
+                "--perf" => options.perf = true,
+                "--show-keys" => options.show_keys = true,
+                "--show-collisions" => options.show_collisions = true,
+                "--keyboard" => options.keyboard = KeyboardLayout::parse(&value(&mut args, &arg)?)?,
+                "--keys-by-character" => options.keys_by_character = true,
+                "--key-repeat" => options.key_hold.repeat = true,
+                "--key-release" => {
+                    options.key_hold.release_frames = value(&mut args, &arg)?.parse()?
+                }
+                "--romdir" => options.rom_dir = Some(value(&mut args, &arg)?),
+                "--palette" => options.palette = Palette::parse(&value(&mut args, &arg)?)?,
+                "--symbols" => options.symbols = Some(value(&mut args, &arg)?),
+                "--source-map" => options.source_map = Some(value(&mut args, &arg)?),
+                "--quirks" => options.quirks = Some(Quirks::parse(&value(&mut args, &arg)?)?),
+                "--ipf" => {
+                    let ipf = value(&mut args, &arg)?.parse()?;
+                    if ipf == 0 {
+                        return Err("--ipf must be at least 1".into());
+                    }
+                    options.instructions_per_frame = Some(ipf);
+                }
+                "--random-init" => options.random_init = true,
+                "--persist-flags" => options.persist_flags = true,
+                "--metrics" => {
+                    let secs = value(&mut args, &arg)?.parse()?;
+                    if secs == 0 {
+                        return Err("--metrics must be at least 1 second".into());
+                    }
+                    options.metrics = Some(secs);
+                }
+                "--trace" => options.trace = Some(value(&mut args, &arg)?),
+                "--trace-format" => {
+                    options.trace_format = TraceFormat::parse(&value(&mut args, &arg)?)?
+                }
+                "--trace-pc" => {
+                    options.trace_filter.pc = Some(TraceFilter::parse_pc(&value(&mut args, &arg)?)?)
+                }
+                "--trace-ops" => {
+                    options.trace_filter.ops =
+                        Some(TraceFilter::parse_ops(&value(&mut args, &arg)?)?)
+                }
+                "--explain" => options.explain = true,
+                "--profile" => options.profile = Some(value(&mut args, &arg)?),
+                "--draw-stats" => options.draw_stats = Some(value(&mut args, &arg)?),
+                "--reference" => options.reference = Some(value(&mut args, &arg)?),
+                "--reference-quirks" => {
+                    options.reference_quirks = Some(Quirks::parse(&value(&mut args, &arg)?)?)
+                }
+                "--frames" => options.frames = Some(value(&mut args, &arg)?.parse()?),
+                "--update" => options.update = true,
+                "--after-frames" => options.after_frames = Some(value(&mut args, &arg)?.parse()?),
+                "--seed" => options.seed = Some(value(&mut args, &arg)?.parse()?),
+                "--record" => options.record = Some(value(&mut args, &arg)?),
+                "--play" => options.play = Some(value(&mut args, &arg)?),
+                "--video" => options.video = Some(value(&mut args, &arg)?),
+                "--screenshot-state" => options.screenshot_state = true,
+                "--from-screenshot" => options.from_screenshot = Some(value(&mut args, &arg)?),
+                flag if flag.starts_with("--") => {
+                    return Err(format!("Unknown option: {}\n{}", flag, USAGE).into());
+                }
+                _ => options.rom_path = Some(arg),
+            }
+        }
+        if dump_every.is_some() && dump_dir.is_none() {
+            return Err("--every needs --dump-frames".into());
+        }
+        options.dump = dump_dir.map(|dir| FrameDump {
+            dir,
+            every: dump_every.unwrap_or(1),
+        });
+        let needs_rom = !matches!(options.mode, Mode::Run | Mode::SelfTest);
+        if options.rom_path.is_none() && needs_rom {
+            return Err(USAGE.into());
+        }
+        if options.headless && options.rom_path.is_none() {
+            return Err("--headless needs a ROM".into());
+        }
+        if options.headless && options.limits.is_unbounded() {
+            return Err("--headless needs --max-cycles, --max-frames, or --exit-on-halt".into());
+        }
+        let references =
+            options.reference.is_some() as u8 + options.reference_quirks.is_some() as u8;
+        if (options.mode == Mode::Compare) != (references == 1) {
+            return Err("compare takes one of --reference or --reference-quirks".into());
+        }
+        if options.mode == Mode::Compare && options.limits.max_frames.is_some() {
+            return Err("compare counts instructions, so it only takes --max-cycles".into());
+        }
+        let limited = !options.limits.is_unbounded() && options.mode != Mode::Compare;
+        if !options.headless && (limited || options.expect_hash.is_some()) {
+            return Err(
+                "--max-cycles, --max-frames, --exit-on-halt, and --expect-hash need --headless"
+                    .into(),
+            );
+        }
+        if !options.headless && options.dump.is_some() {
+            return Err("--dump-frames needs --headless".into());
+        }
+        if options.after_frames.is_some() && options.mode != Mode::Dump {
+            return Err("--after-frames is for dump".into());
+        }
+        if options.trace.is_none() && options.trace_filter != TraceFilter::default() {
+            return Err("--trace-pc and --trace-ops need --trace".into());
+        }
+        if options.headless && options.vsync {
+            return Err("--vsync needs a window to sync to".into());
+        }
+        if options.headless && options.show_collisions {
+            return Err("--show-collisions needs a window to show them in".into());
+        }
+        if options.headless && options.perf {
+            return Err("--perf needs a window to show the stats in".into());
+        }
+        if options.headless && options.turbo_skip.is_some() {
+            return Err("--turbo-skip needs a window, since headless runs draw nothing".into());
+        }
+        if options.headless && options.record.is_some() {
+            return Err("Cannot record a movie without a window to take input from".into());
+        }
+        if options.record.is_some() && options.play.is_some() {
+            return Err("Cannot record and play a movie at the same time".into());
+        }
+        // Movies always start from power-on, which flags saved by other runs would change
+        if options.persist_flags && (options.record.is_some() || options.play.is_some()) {
+            return Err("Cannot keep flags between runs while recording or playing a movie".into());
+        }
+        if options.from_screenshot.is_some() && (options.record.is_some() || options.play.is_some())
+        {
+            return Err("Cannot start a movie from a screenshot".into());
+        }
+        Ok(options)
+    }
+}
+
+/// Takes the value following a flag that requires one.
+fn value<I: Iterator<Item = String>>(
+    args: &mut I,
+    flag: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}\n{}", flag, USAGE).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, Box<dyn std::error::Error>> {
+        Options::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_rom_and_flags() {
+        let options = parse(&[
+            "--autosave",
+            "--visual-beep",
+            "--pause-unfocused",
+            "--random-init",
+            "--explain",
+            "games/chip/PONG",
+        ])
+        .unwrap();
+        assert_eq!(options.rom_path.as_deref(), Some("games/chip/PONG"));
+        assert!(options.autosave);
+        assert!(options.visual_beep);
+        assert!(options.pause_unfocused);
+        assert!(options.random_init);
+        assert!(options.explain);
+        assert_eq!(parse(&["--scale", "3", "PONG"]).unwrap().scale, Some(3));
+        assert!(parse(&["--scale", "9", "PONG"]).is_err());
+        assert!(!parse(&["games/chip/PONG"]).unwrap().autosave);
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        let options = parse(&["disasm", "--symbols", "pong.sym", "PONG"]).unwrap();
+        assert_eq!(options.mode, Mode::Disassemble);
+        assert_eq!(options.symbols.as_deref(), Some("pong.sym"));
+        assert_eq!(parse(&["PONG"]).unwrap().mode, Mode::Run);
+        let options = parse(&["assemble", "-o", "game.ch8", "game.8o"]).unwrap();
+        assert_eq!(options.mode, Mode::Assemble);
+        assert_eq!(options.output.as_deref(), Some("game.ch8"));
+        let options = parse(&["assemble", "--source-map", "game.map", "game.8o"]).unwrap();
+        assert_eq!(options.source_map.as_deref(), Some("game.map"));
+        assert_eq!(parse(&["decompile", "PONG"]).unwrap().mode, Mode::Decompile);
+        let options = parse(&["compare", "--reference-quirks", "shift-vy", "PONG"]).unwrap();
+        assert_eq!(options.mode, Mode::Compare);
+        assert!(options.reference_quirks.unwrap().shift_vy);
+        let options = parse(&[
+            "compare",
+            "--reference",
+            "a.jsonl",
+            "--max-cycles",
+            "9",
+            "PONG",
+        ]);
+        assert_eq!(options.unwrap().limits.max_cycles, Some(9));
+        assert!(parse(&["compare", "PONG"]).is_err());
+        let options = parse(&["batch", "--frames", "60", "--update", "games/chip"]).unwrap();
+        assert_eq!(options.mode, Mode::Batch);
+        assert_eq!(options.frames, Some(60));
+        assert!(options.update);
+        assert_eq!(options.rom_path.as_deref(), Some("games/chip"));
+        assert!(parse(&["--reference", "a.jsonl", "PONG"]).is_err());
+        assert_eq!(parse(&["selftest"]).unwrap().mode, Mode::SelfTest);
+        let options = parse(&["dump", "--after-frames", "120", "-o", "pong.mem", "PONG"]).unwrap();
+        assert_eq!(options.mode, Mode::Dump);
+        assert_eq!(options.after_frames, Some(120));
+        assert_eq!(options.output.as_deref(), Some("pong.mem"));
+        assert!(parse(&["dump"]).is_err());
+        assert!(parse(&["--after-frames", "120", "PONG"]).is_err());
+        let options = parse(&["explain", "DXY5"]).unwrap();
+        assert_eq!(options.mode, Mode::Explain);
+        assert_eq!(options.rom_path.as_deref(), Some("DXY5"));
+        // Only the first argument names a subcommand
+        assert_eq!(
+            parse(&["--debug", "disasm"]).unwrap().rom_path.as_deref(),
+            Some("disasm")
+        );
+    }
+
+    #[test]
+    fn parses_flag_values() {
+        let options = parse(&["--seed", "42", "--record", "pong.movie", "PONG"]).unwrap();
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.record.as_deref(), Some("pong.movie"));
+        assert_eq!(options.rom_path.as_deref(), Some("PONG"));
+        assert_eq!(
+            parse(&["--romdir", "games/chip"])
+                .unwrap()
+                .rom_dir
+                .as_deref(),
+            Some("games/chip")
+        );
+        assert_eq!(options.instructions_per_frame, None);
+        assert_eq!(options.quirks, None);
+        let options = parse(&["--quirks", "legacy-flags", "PONG"]).unwrap();
+        assert!(options.quirks.unwrap().legacy_flags);
+        assert!(parse(&["--quirks", "bogus", "PONG"]).is_err());
+        let options = parse(&["--ipf", "15", "PONG"]).unwrap();
+        assert_eq!(options.instructions_per_frame, Some(15));
+        assert!(parse(&["--ipf", "0", "PONG"]).is_err());
+        assert_eq!(options.palette, Palette::default());
+        let options = parse(&["--palette", "high-contrast", "PONG"]).unwrap();
+        assert_eq!(options.palette, Palette::parse("high-contrast").unwrap());
+        assert!(parse(&["--palette", "sepia", "PONG"]).is_err());
+        assert_eq!(options.beep, Beep::default());
+        let options = parse(&[
+            "--volume",
+            "50",
+            "--beep-hz",
+            "440",
+            "--waveform",
+            "sine",
+            "PONG",
+        ]);
+        let beep = options.unwrap().beep;
+        assert_eq!((beep.volume, beep.frequency), (0.5, 440.0));
+        assert_eq!(beep.waveform, Waveform::Sine);
+        assert!(parse(&["--volume", "101", "PONG"]).is_err());
+        assert!(parse(&["--beep-hz", "10", "PONG"]).is_err());
+        assert!(parse(&["--waveform", "sawtooth", "PONG"]).is_err());
+        let options = parse(&[
+            "--headless",
+            "--max-frames",
+            "600",
+            "--exit-on-halt",
+            "PONG",
+        ]);
+        let options = options.unwrap();
+        assert!(options.headless);
+        assert_eq!(options.limits.max_frames, Some(600));
+        assert_eq!(options.limits.max_cycles, None);
+        assert!(options.limits.exit_on_halt);
+        let options = parse(&[
+            "--headless",
+            "--exit-on-halt",
+            "--expect-hash",
+            "0bE3",
+            "PONG",
+        ]);
+        assert_eq!(options.unwrap().expect_hash, Some(0xBE3));
+        let options = parse(&[
+            "--headless",
+            "--max-frames",
+            "60",
+            "--dump-frames",
+            "frames",
+            "--every",
+            "10",
+            "PONG",
+        ]);
+        let dump = options.unwrap().dump.unwrap();
+        assert_eq!((dump.dir, dump.every), (PathBuf::from("frames"), 10));
+        assert!(parse(&["--dump-frames", "frames", "PONG"]).is_err());
+        assert!(parse(&["--headless", "--exit-on-halt", "--every", "2", "PONG"]).is_err());
+        assert_eq!(
+            parse(&["--metrics", "60", "PONG"]).unwrap().metrics,
+            Some(60)
+        );
+        assert!(parse(&["--metrics", "0", "PONG"]).is_err());
+        let options = parse(&["--trace", "pong.jsonl", "--trace-format", "jsonl", "PONG"]);
+        let options = options.unwrap();
+        assert_eq!(options.trace.as_deref(), Some("pong.jsonl"));
+        assert_eq!(options.trace_format, TraceFormat::Jsonl);
+        assert!(parse(&["--trace-format", "xml", "PONG"]).is_err());
+        let options = parse(&[
+            "--trace",
+            "pong.txt",
+            "--trace-pc",
+            "0x200..0x260",
+            "--trace-ops",
+            "DRW,CALL,RET",
+            "PONG",
+        ]);
+        let filter = options.unwrap().trace_filter;
+        assert_eq!(filter.pc, Some(0x200..0x260));
+        assert_eq!(filter.ops.unwrap(), ["DRW", "CALL", "RET"]);
+        assert!(parse(&["--trace-ops", "DRW", "PONG"]).is_err());
+        let options = parse(&["--keyboard", "azerty", "--keys-by-character", "PONG"]).unwrap();
+        assert_eq!(options.keyboard, KeyboardLayout::Azerty);
+        assert!(options.keys_by_character);
+        assert!(parse(&["--keyboard", "colemak", "PONG"]).is_err());
+        let options = parse(&["--key-repeat", "--key-release", "3", "PONG"]).unwrap();
+        assert_eq!(
+            options.key_hold,
+            KeyHold {
+                repeat: true,
+                release_frames: 3
+            }
+        );
+        let options = parse(&["--profile", "pong.callgrind", "PONG"]).unwrap();
+        assert_eq!(options.profile.as_deref(), Some("pong.callgrind"));
+        let options = parse(&["--draw-stats", "pong.draws", "PONG"]).unwrap();
+        assert_eq!(options.draw_stats.as_deref(), Some("pong.draws"));
+        let options = parse(&["--screenshot-state", "--from-screenshot", "a.png", "PONG"]);
+        let options = options.unwrap();
+        assert!(options.screenshot_state);
+        assert_eq!(options.from_screenshot.as_deref(), Some("a.png"));
+        assert!(parse(&["--from-screenshot", "a.png", "--play", "m", "PONG"]).is_err());
+        let options = parse(&["--video", "pong.mp4", "PONG"]).unwrap();
+        assert_eq!(options.video.as_deref(), Some("pong.mp4"));
+    }
+
+    #[test]
+    fn rejects_missing_rom_and_unknown_flags() {
+        assert_eq!(parse(&[]).unwrap().rom_path, None);
+        assert!(parse(&["disasm"]).is_err());
+        assert!(parse(&["--bogus", "games/chip/PONG"]).is_err());
+        assert!(parse(&["games/chip/PONG", "--seed"]).is_err());
+        assert!(parse(&["--record", "a", "--play", "b", "PONG"]).is_err());
+        assert!(parse(&["--headless", "PONG"]).is_err());
+        assert!(parse(&["--headless", "--exit-on-halt"]).is_err());
+        assert!(parse(&["--max-frames", "10", "PONG"]).is_err());
+        assert!(parse(&["--headless", "--exit-on-halt", "--vsync", "PONG"]).is_err());
+        assert!(
+            parse(&["--show-collisions", "PONG"])
+                .unwrap()
+                .show_collisions
+        );
+        assert!(parse(&["--headless", "--exit-on-halt", "--show-collisions", "PONG"]).is_err());
+        assert!(parse(&["--persist-flags", "PONG"]).unwrap().persist_flags);
+        assert!(parse(&["--persist-flags", "--play", "pong.movie", "PONG"]).is_err());
+        assert_eq!(
+            parse(&["--turbo-skip", "8", "PONG"]).unwrap().turbo_skip,
+            Some(8)
+        );
+        assert!(parse(&["--turbo-skip", "0", "PONG"]).is_err());
+        assert!(parse(&["--headless", "--exit-on-halt", "--turbo-skip", "2", "PONG"]).is_err());
+        assert!(parse(&["--perf", "PONG"]).unwrap().perf);
+        assert!(parse(&["--headless", "--exit-on-halt", "--perf", "PONG"]).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! Performance stats (`--perf`, or Backspace while playing): how fast the emulator runs, where
+//! each window update's time goes, and how far the timers have drifted from real time, to check
+//! the emulator keeps up on the hardware it runs on. They're shown over the game and logged
+//! once a second.
+use crate::clock::FRAME_DURATION;
+use crate::speed::REPORT_INTERVAL;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// What one window update did, and how long each part of it took.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Update {
+    /// Running the frames that were due.
+    pub emulation: Duration,
+    /// Drawing the window buffer, on the render thread.
+    pub render: Duration,
+    /// Handing the buffer to the window, and with `--vsync` waiting for the display.
+    pub present: Duration,
+    /// Frames run, each ticking the timers once.
+    pub frames: u64,
+    /// Real time that passed while the game was running, scaled by turbo or slow motion.
+    pub real_time: Duration,
+}
+
+/// Averages over the last report interval.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Report {
+    /// Instructions executed per second.
+    pub ips: u64,
+    /// Window updates per second.
+    pub ups: u64,
+    /// Time per update spent on emulation, rendering, and presenting.
+    pub emulation: Duration,
+    pub render: Duration,
+    pub present: Duration,
+    /// How far ahead of real time the timers are, in seconds, behind when negative. Time the
+    /// game deliberately isn't running for, like while paused, doesn't count.
+    pub drift: f64,
+}
+
+impl Report {
+    /// The report as short lines for the overlay.
+    pub fn lines(&self) -> Vec<String> {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        vec![
+            format!("{} IPS, {} UPS", self.ips, self.ups),
+            format!("EMU {:.2} MS", ms(self.emulation)),
+            format!("RENDER {:.2} MS", ms(self.render)),
+            format!("PRESENT {:.2} MS", ms(self.present)),
+            format!("DRIFT {:.1} MS", self.drift * 1000.0),
+        ]
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "perf ips={} ups={} emulation={:?} render={:?} present={:?} drift={:.1}ms",
+            self.ips,
+            self.ups,
+            self.emulation,
+            self.render,
+            self.present,
+            self.drift * 1000.0
+        )
+    }
+}
+
+pub struct PerfStats {
+    since: Instant,
+    updates: u32,
+    instructions: u64,
+    /// Totals since the last report.
+    emulation: Duration,
+    render: Duration,
+    present: Duration,
+    drift: f64,
+}
+
+impl PerfStats {
+    pub fn new(now: Instant, instructions: u64) -> PerfStats {
+        PerfStats {
+            since: now,
+            updates: 0,
+            instructions,
+            emulation: Duration::ZERO,
+            render: Duration::ZERO,
+            present: Duration::ZERO,
+            drift: 0.0,
+        }
+    }
+
+    /// Counts a window update, given the total number of instructions executed so far.
+    /// Returns a report once a full report interval has passed.
+    pub fn update(&mut self, now: Instant, update: &Update, instructions: u64) -> Option<Report> {
+        self.updates += 1;
+        self.emulation += update.emulation;
+        self.render += update.render;
+        self.present += update.present;
+        let ticked = FRAME_DURATION.as_secs_f64() * update.frames as f64;
+        self.drift += ticked - update.real_time.as_secs_f64();
+        let elapsed = now.duration_since(self.since);
+        if elapsed < REPORT_INTERVAL {
+            return None;
+        }
+        let per_second = |count: u64| (count as f64 / elapsed.as_secs_f64()).round() as u64;
+        let report = Report {
+            ips: per_second(instructions.saturating_sub(self.instructions)),
+            ups: per_second(u64::from(self.updates)),
+            emulation: self.emulation / self.updates,
+            render: self.render / self.updates,
+            present: self.present / self.updates,
+            drift: self.drift,
+        };
+        // Drift keeps adding up, since falling behind is only ever caught up on later
+        *self = PerfStats {
+            drift: self.drift,
+            ..PerfStats::new(now, instructions)
+        };
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_time_per_update_and_drift() {
+        let start = Instant::now();
+        let mut stats = PerfStats::new(start, 0);
+        // Four updates a frame apart, the last of which ran no frame
+        let update = Update {
+            emulation: Duration::from_millis(2),
+            render: Duration::from_millis(4),
+            present: Duration::from_millis(1),
+            frames: 1,
+            real_time: FRAME_DURATION,
+        };
+        for i in 1..4 {
+            assert_eq!(
+                stats.update(start + FRAME_DURATION * i, &update, i.into()),
+                None
+            );
+        }
+        let late = Update {
+            frames: 0,
+            ..update
+        };
+        let report = stats.update(start + REPORT_INTERVAL, &late, 6).unwrap();
+        assert_eq!((report.ips, report.ups), (6, 4));
+        assert_eq!(report.emulation, Duration::from_millis(2));
+        assert!((report.drift + FRAME_DURATION.as_secs_f64()).abs() < 1e-9);
+        assert_eq!(report.lines()[4], "DRIFT -16.7 MS");
+
+        // The drift carries over until the late frame is caught up on
+        let catch_up = Update {
+            frames: 2,
+            ..update
+        };
+        let report = stats
+            .update(start + REPORT_INTERVAL * 2, &catch_up, 12)
+            .unwrap();
+        assert!(report.drift.abs() < 1e-9);
+    }
+}
@@ -0,0 +1,171 @@
+//! Subroutine profiler (`--profile`), counting executed instructions per subroutine and along
+//! each call, written in callgrind format for KCachegrind and QCacheGrind to show the call
+//! tree with inclusive and exclusive costs.
+use crate::chip8::Executed;
+use crate::opcode::Opcode;
+use crate::symbols::Symbols;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// A subroutine that is currently running.
+struct Frame {
+    entry: usize,
+    /// Address of the CALL that entered it.
+    callsite: usize,
+    /// Instructions executed before it was entered.
+    start: u64,
+}
+
+/// Totals for one call site: how many calls it made and how many instructions they ran.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Calls {
+    count: u64,
+    inclusive: u64,
+}
+
+pub struct Profiler {
+    /// Where the program starts, which stands in for the entry of the outermost "subroutine".
+    root: usize,
+    stack: Vec<Frame>,
+    instructions: u64,
+    /// Instructions executed at each address, keyed by subroutine entry and address.
+    costs: BTreeMap<(usize, usize), u64>,
+    /// Completed calls, keyed by caller entry, call site, and callee entry.
+    calls: BTreeMap<(usize, usize, usize), Calls>,
+}
+
+impl Profiler {
+    pub fn new(root: usize) -> Profiler {
+        Profiler {
+            root,
+            stack: Vec::new(),
+            instructions: 0,
+            costs: BTreeMap::new(),
+            calls: BTreeMap::new(),
+        }
+    }
+
+    fn current(&self) -> usize {
+        self.stack.last().map_or(self.root, |f| f.entry)
+    }
+
+    /// Forgets the subroutines that were running, for when the machine is reset. What they
+    /// ran so far stays counted.
+    pub fn restart(&mut self) {
+        self.stack.clear();
+    }
+
+    /// Counts an instruction that has just executed.
+    pub fn record(&mut self, executed: &Executed) {
+        let pc = executed.pc;
+        self.instructions += 1;
+        *self.costs.entry((self.current(), pc)).or_default() += 1;
+        match executed.opcode {
+            Opcode::CallSubroutine(nnn) => self.stack.push(Frame {
+                entry: nnn,
+                callsite: pc,
+                start: self.instructions,
+            }),
+            Opcode::Return => {
+                if let Some(frame) = self.stack.pop() {
+                    let calls = self
+                        .calls
+                        .entry((self.current(), frame.callsite, frame.entry));
+                    let calls = calls.or_default();
+                    calls.count += 1;
+                    calls.inclusive += self.instructions - frame.start;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Formats the profile in callgrind format. Subroutines that are still running count as
+    /// called, with what they have run so far.
+    pub fn to_callgrind(&self, symbols: &Symbols) -> String {
+        let mut calls = self.calls.clone();
+        for (i, frame) in self.stack.iter().enumerate() {
+            let caller = i.checked_sub(1).map_or(self.root, |i| self.stack[i].entry);
+            let c = calls
+                .entry((caller, frame.callsite, frame.entry))
+                .or_default();
+            c.count += 1;
+            c.inclusive += self.instructions - frame.start;
+        }
+
+        let name = |addr: usize| match symbols.name_of(addr) {
+            Some(name) => name.to_string(),
+            None => format!("0x{:03X}", addr),
+        };
+        let mut out = String::new();
+        out += "# callgrind format\nversion: 1\ncreator: chip8\n";
+        out += "positions: instr\nevents: Instructions\n";
+        let _ = writeln!(out, "totals: {}", self.instructions);
+        let mut functions: Vec<usize> = self.costs.keys().map(|(entry, _)| *entry).collect();
+        functions.dedup();
+        for entry in functions {
+            let _ = write!(out, "\nfn={}\n", name(entry));
+            for ((_, pc), cost) in self.costs.range((entry, 0)..(entry + 1, 0)) {
+                let _ = writeln!(out, "0x{:03X} {}", pc, cost);
+            }
+            for ((_, callsite, callee), c) in calls.range((entry, 0, 0)..(entry + 1, 0, 0)) {
+                let _ = writeln!(out, "cfn={}", name(*callee));
+                let _ = writeln!(out, "calls={} 0x{:03X}", c.count, callee);
+                let _ = writeln!(out, "0x{:03X} {}", callsite, c.inclusive);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn writes_call_tree_in_callgrind_format() {
+        // main: CALL draw from two places, then CALL a subroutine that never returns
+        // draw: ADD V0, 1; RET
+        let program = [
+            0x22, 0x08, 0x22, 0x08, 0x22, 0x0C, 0x00, 0x00, 0x70, 0x01, 0x00, 0xEE, 0x12, 0x0C,
+        ];
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&program);
+        let mut profiler = Profiler::new(0x200);
+        for _ in 0..10 {
+            profiler.record(&chip8.step().unwrap().unwrap());
+        }
+        let symbols = Symbols::parse("main 0x200\ndraw 0x208").unwrap();
+        assert_eq!(
+            profiler.to_callgrind(&symbols),
+            "# callgrind format\n\
+             version: 1\n\
+             creator: chip8\n\
+             positions: instr\n\
+             events: Instructions\n\
+             totals: 10\n\
+             \n\
+             fn=main\n\
+             0x200 1\n\
+             0x202 1\n\
+             0x204 1\n\
+             cfn=draw\n\
+             calls=1 0x208\n\
+             0x200 2\n\
+             cfn=draw\n\
+             calls=1 0x208\n\
+             0x202 2\n\
+             cfn=0x20C\n\
+             calls=1 0x20C\n\
+             0x204 3\n\
+             \n\
+             fn=draw\n\
+             0x208 2\n\
+             0x20A 2\n\
+             \n\
+             fn=0x20C\n\
+             0x20C 3\n"
+        );
+    }
+}
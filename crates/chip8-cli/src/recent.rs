@@ -0,0 +1,84 @@
+//! The list of recently played ROMs, offered when the emulator is started without one. Stored
+//! with one `<hash> <path>` line per ROM, most recent first.
+use std::fmt;
+
+/// How many ROMs are remembered.
+pub const MAX_RECENT: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecentRom {
+    pub hash: u64,
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecentRoms {
+    roms: Vec<RecentRom>,
+}
+
+impl RecentRoms {
+    pub fn parse(text: &str) -> Result<RecentRoms, Box<dyn std::error::Error>> {
+        let mut roms = Vec::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let (hash, path) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("Invalid recent ROM entry: {}", line))?;
+            let hash = u64::from_str_radix(hash, 16)
+                .map_err(|_| format!("Invalid recent ROM entry: {}", line))?;
+            roms.push(RecentRom {
+                hash,
+                path: path.to_string(),
+            });
+        }
+        Ok(RecentRoms { roms })
+    }
+
+    /// Moves a ROM to the top of the list, dropping the oldest entries past `MAX_RECENT`.
+    pub fn add(&mut self, path: &str, hash: u64) {
+        self.roms.retain(|rom| rom.path != path);
+        self.roms.insert(
+            0,
+            RecentRom {
+                hash,
+                path: path.to_string(),
+            },
+        );
+        self.roms.truncate(MAX_RECENT);
+    }
+
+    pub fn roms(&self) -> &[RecentRom] {
+        &self.roms
+    }
+}
+
+impl fmt::Display for RecentRoms {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rom in self.roms.iter() {
+            writeln!(f, "{:016x} {}", rom.hash, rom.path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_most_recent_first() {
+        let mut recent = RecentRoms::default();
+        for i in 0..12 {
+            recent.add(&format!("games/rom{}", i), i);
+        }
+        recent.add("games/rom with spaces", 0xAB);
+        recent.add("games/rom5", 5);
+        let paths: Vec<&str> = recent.roms().iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths.len(), MAX_RECENT);
+        assert_eq!(
+            paths[..3],
+            ["games/rom5", "games/rom with spaces", "games/rom11"]
+        );
+        assert_eq!(RecentRoms::parse(&recent.to_string()).unwrap(), recent);
+        assert!(RecentRoms::parse("nothex games/rom").is_err());
+    }
+}
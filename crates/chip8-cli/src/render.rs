@@ -0,0 +1,496 @@
+//! Rasterizing the window buffer on its own thread, so that zooming the display up to large
+//! scales and drawing the overlays never holds up emulation. Finished buffers are handed back
+//! through a triple buffer: the render thread always has a buffer of its own to draw into, and
+//! the main thread always has the newest complete one to present, without either waiting on
+//! the other.
+//!
+//! Presenting stays on the main thread, since minifb windows can't leave the thread that
+//! created them (on macOS, the main thread).
+use crate::browser;
+use crate::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::layout::Layout;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Color of the border flashed by `--visual-beep`.
+const BEEP_COLOR: u32 = 0xFF_C0_00;
+/// Color of pixels lit up by `--show-collisions`.
+const COLLISION_COLOR: u32 = 0xFF_30_30;
+/// Frames a collision stays lit for, a quarter of a second at full speed.
+const COLLISION_FRAMES: u8 = 15;
+/// Colors of the message panel, such as the one explaining a crash.
+const MESSAGE_BACKGROUND: u32 = 0x80_00_00;
+const MESSAGE_COLOR: u32 = 0xFF_FF_FF;
+/// Colors of the performance stats panel.
+const STATS_BACKGROUND: u32 = 0x10_10_10;
+const STATS_COLOR: u32 = 0x30_FF_30;
+
+/// Everything needed to draw one window buffer.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub layout: Layout,
+    /// The 64x32 screen in display colors.
+    pub screen: Vec<u32>,
+    /// Keys held on the clickable keypad, if the layout has one.
+    pub keys: [bool; 16],
+    /// Keys for the `--show-keys` overlay, or None to leave it off.
+    pub shown_keys: Option<[bool; 16]>,
+    /// Flash the `--visual-beep` border.
+    pub beeping: bool,
+    /// Screen pixels to light up for `--show-collisions`.
+    pub collisions: Vec<usize>,
+    /// Text to show in a panel across the bottom of the display, or None for no panel.
+    pub message: Option<String>,
+    /// Performance stats to show across the top of the display, a line each.
+    pub stats: Option<Vec<String>>,
+}
+
+/// Draws a frame into `buffer`, resizing it to fit the layout.
+pub fn rasterize(buffer: &mut Vec<u32>, frame: &Frame) {
+    let layout = &frame.layout;
+    buffer.resize(layout.window_width() * layout.height(), 0);
+    draw_display(buffer, layout, &frame.screen);
+    for pixel in frame.collisions.iter() {
+        draw_pixel(buffer, layout, *pixel, COLLISION_COLOR);
+    }
+    if frame.beeping {
+        draw_border(buffer, layout, BEEP_COLOR);
+    }
+    if let Some(ref keypad) = layout.keypad {
+        keypad.draw(buffer, layout.window_width(), &frame.keys);
+    }
+    if let Some(ref held) = frame.shown_keys {
+        layout
+            .key_overlay()
+            .draw(buffer, layout.window_width(), held);
+    }
+    if let Some(ref message) = frame.message {
+        draw_message(buffer, layout, message);
+    }
+    if let Some(ref stats) = frame.stats {
+        draw_panel(buffer, layout, stats, 0, (STATS_BACKGROUND, STATS_COLOR));
+    }
+}
+
+/// Paints a 64x32 framebuffer into the game display part of a window buffer, zooming in to
+/// the layout's pixel size.
+pub fn draw_display(buffer: &mut [u32], layout: &Layout, framebuffer: &[u32]) {
+    let (stride, pixel_size) = (layout.window_width(), layout.pixel_size);
+    for (y, row) in framebuffer.chunks(SCREEN_WIDTH).enumerate() {
+        // Zoom the first line of the row, then copy it down over the rest
+        let top = y * pixel_size * stride;
+        for (x, color) in row.iter().enumerate() {
+            let left = top + x * pixel_size;
+            buffer[left..left + pixel_size].fill(*color);
+        }
+        for j in 1..pixel_size {
+            buffer.copy_within(top..top + layout.width(), top + j * stride);
+        }
+    }
+}
+
+/// Paints over one pixel of the game display.
+fn draw_pixel(buffer: &mut [u32], layout: &Layout, pixel: usize, color: u32) {
+    let (stride, pixel_size) = (layout.window_width(), layout.pixel_size);
+    let (x, y) = (pixel % SCREEN_WIDTH, pixel / SCREEN_WIDTH);
+    for j in 0..pixel_size {
+        let left = (y * pixel_size + j) * stride + x * pixel_size;
+        buffer[left..left + pixel_size].fill(color);
+    }
+}
+
+/// Where sprites collided lately, for `--show-collisions` to light up. Collisions fade after a
+/// number of emulated frames, so they stay on screen while the game is paused or slowed down.
+pub struct CollisionGlow {
+    frames_left: Vec<u8>,
+}
+
+impl Default for CollisionGlow {
+    fn default() -> Self {
+        CollisionGlow {
+            frames_left: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+}
+
+impl CollisionGlow {
+    /// Fades the glow by the frames that have run, then lights up the pixels that collided
+    /// during them.
+    pub fn update(&mut self, frames: u64, collided: &[usize]) {
+        let frames = frames.min(u64::from(COLLISION_FRAMES)) as u8;
+        for left in self.frames_left.iter_mut() {
+            *left = left.saturating_sub(frames);
+        }
+        for pixel in collided {
+            self.frames_left[*pixel] = COLLISION_FRAMES;
+        }
+    }
+
+    /// The pixels still lit.
+    pub fn pixels(&self) -> Vec<usize> {
+        (0..self.frames_left.len())
+            .filter(|p| self.frames_left[*p] > 0)
+            .collect()
+    }
+}
+
+/// Paints a frame around the edge of the game display.
+fn draw_border(buffer: &mut [u32], layout: &Layout, color: u32) {
+    let (width, height, border) = (layout.width(), layout.height(), layout.border());
+    let stride = layout.window_width();
+    for y in 0..height {
+        let line = &mut buffer[y * stride..y * stride + width];
+        if y < border || y >= height - border {
+            line.fill(color);
+        } else {
+            line[..border].fill(color);
+            line[width - border..].fill(color);
+        }
+    }
+}
+
+/// The scale the 3x5 font is drawn at, growing with the display so it stays readable.
+fn text_scale(layout: &Layout) -> usize {
+    (layout.pixel_size / 5).max(1)
+}
+
+/// Paints a panel of text across the bottom of the game display, wrapping it at spaces to
+/// fit.
+fn draw_message(buffer: &mut [u32], layout: &Layout, message: &str) {
+    let scale = text_scale(layout);
+    let columns = ((layout.width() - 2 * scale) / (4 * scale)).max(1);
+    let lines = wrap(message, columns);
+    let top = layout
+        .height()
+        .saturating_sub(lines.len() * 6 * scale + scale);
+    draw_panel(
+        buffer,
+        layout,
+        &lines,
+        top,
+        (MESSAGE_BACKGROUND, MESSAGE_COLOR),
+    );
+}
+
+/// Paints lines of text across the game display from row `top` down, on a panel of the
+/// background color. Lines too long for the display are cut off.
+fn draw_panel(
+    buffer: &mut [u32],
+    layout: &Layout,
+    lines: &[String],
+    top: usize,
+    (background, color): (u32, u32),
+) {
+    let scale = text_scale(layout);
+    let (char_width, line_height) = (4 * scale, 6 * scale);
+    let stride = layout.window_width();
+    let bottom = (top + lines.len() * line_height + scale).min(layout.height());
+    for row in buffer[top * stride..bottom * stride].chunks_mut(stride) {
+        row[..layout.width()].fill(background);
+    }
+    let columns = (layout.width() - scale) / char_width;
+    for (i, line) in lines.iter().enumerate() {
+        let y = top + scale + i * line_height;
+        for (j, c) in line.chars().take(columns).enumerate() {
+            let x = scale + j * char_width;
+            for (dy, bits) in browser::glyph(c).iter().enumerate() {
+                for dx in 0..3 {
+                    if bits & (0b100 >> dx) == 0 {
+                        continue;
+                    }
+                    for py in 0..scale {
+                        let start = (y + dy * scale + py) * stride + x + dx * scale;
+                        if start + scale <= buffer.len() {
+                            buffer[start..start + scale].fill(color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Splits text into lines of at most `columns` characters, breaking at spaces where it can.
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let mut word: Vec<char> = word.chars().collect();
+        let len = line.chars().count();
+        if len > 0 && len + 1 + word.len() > columns {
+            lines.push(mem::take(&mut line));
+        }
+        while word.len() > columns {
+            lines.push(word.drain(..columns).collect());
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+    lines.push(line);
+    lines
+}
+
+/// The buffer passed between the two ends of a triple buffer, and whether it holds a frame
+/// the reader hasn't taken yet. The writer and reader each own the other two buffers, and
+/// trade theirs for this one, so the lock is only ever held for a swap.
+#[derive(Default)]
+struct TripleBuffer {
+    middle: Mutex<(Vec<u32>, bool)>,
+}
+
+impl TripleBuffer {
+    /// Trades a freshly drawn buffer for the middle one, replacing any frame not yet taken.
+    fn publish(&self, back: &mut Vec<u32>) {
+        let mut middle = self.middle.lock().unwrap();
+        mem::swap(&mut middle.0, back);
+        middle.1 = true;
+    }
+
+    /// Trades `front` for the newest frame, returning false if there is none since last time.
+    fn take(&self, front: &mut Vec<u32>) -> bool {
+        let mut middle = self.middle.lock().unwrap();
+        if !middle.1 {
+            return false;
+        }
+        mem::swap(&mut middle.0, front);
+        middle.1 = false;
+        true
+    }
+}
+
+/// Frames waiting for the render thread. Only the latest is kept, so a slow render skips
+/// frames rather than falling behind.
+#[derive(Default)]
+struct Mailbox {
+    frame: Option<Frame>,
+    closed: bool,
+}
+
+pub struct Renderer {
+    mailbox: Arc<(Mutex<Mailbox>, Condvar)>,
+    output: Arc<TripleBuffer>,
+    /// Nanoseconds spent rasterizing so far, for the performance stats.
+    busy: Arc<AtomicU64>,
+    front: Vec<u32>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Renderer {
+    pub fn spawn() -> Renderer {
+        let mailbox = Arc::new((Mutex::new(Mailbox::default()), Condvar::new()));
+        let output = Arc::new(TripleBuffer::default());
+        let busy = Arc::new(AtomicU64::new(0));
+        let thread = {
+            let (mailbox, output, busy) = (mailbox.clone(), output.clone(), busy.clone());
+            thread::spawn(move || {
+                let mut back = Vec::new();
+                loop {
+                    let frame = {
+                        let (lock, wake) = &*mailbox;
+                        let mut mailbox = lock.lock().unwrap();
+                        while mailbox.frame.is_none() && !mailbox.closed {
+                            mailbox = wake.wait(mailbox).unwrap();
+                        }
+                        match mailbox.frame.take() {
+                            Some(frame) => frame,
+                            None => return,
+                        }
+                    };
+                    let start = Instant::now();
+                    rasterize(&mut back, &frame);
+                    busy.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                    output.publish(&mut back);
+                }
+            })
+        };
+        Renderer {
+            mailbox,
+            output,
+            busy,
+            front: Vec::new(),
+            thread: Some(thread),
+        }
+    }
+
+    /// Queues a frame to draw, replacing one the render thread hasn't started on.
+    pub fn submit(&self, frame: Frame) {
+        let (lock, wake) = &*self.mailbox;
+        lock.lock().unwrap().frame = Some(frame);
+        wake.notify_one();
+    }
+
+    /// Moves the newest buffer drawn into `buffer`, returning false if nothing was drawn
+    /// since the last call.
+    pub fn take_rendered(&mut self) -> bool {
+        self.output.take(&mut self.front)
+    }
+
+    /// Total time the render thread has spent drawing.
+    pub fn busy_time(&self) -> Duration {
+        Duration::from_nanos(self.busy.load(Ordering::Relaxed))
+    }
+
+    /// The last buffer taken with `take_rendered`, empty until the first one.
+    pub fn buffer(&self) -> &[u32] {
+        &self.front
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        let (lock, wake) = &*self.mailbox;
+        lock.lock().unwrap().closed = true;
+        wake.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn hands_over_newest_frame() {
+        let buffers = TripleBuffer::default();
+        let mut front = Vec::new();
+        assert!(!buffers.take(&mut front));
+        buffers.publish(&mut vec![1]);
+        let mut back = vec![2];
+        buffers.publish(&mut back);
+        // The writer gets the unread frame back to draw over
+        assert_eq!(back, vec![1]);
+        assert!(buffers.take(&mut front));
+        assert_eq!(front, vec![2]);
+        assert!(!buffers.take(&mut front));
+    }
+
+    #[test]
+    fn draws_message_and_stats_panels() {
+        assert_eq!(
+            wrap("Invalid opcode at 202: FFFF", 10),
+            vec!["Invalid", "opcode at", "202: FFFF"]
+        );
+        assert_eq!(wrap("ABCDEFG", 3), vec!["ABC", "DEF", "G"]);
+
+        let layout = Layout::new(1, 1.0, false);
+        let mut buffer = vec![];
+        rasterize(
+            &mut buffer,
+            &Frame {
+                layout,
+                screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+                keys: [false; 16],
+                shown_keys: None,
+                beeping: false,
+                collisions: vec![],
+                message: Some("Crashed".to_string()),
+                stats: Some(vec!["60 IPS".to_string()]),
+            },
+        );
+        let bottom = (layout.height() - 1) * layout.window_width();
+        assert_eq!(buffer[bottom], MESSAGE_BACKGROUND);
+        assert!(buffer.contains(&MESSAGE_COLOR));
+        assert_eq!(buffer[0], STATS_BACKGROUND);
+        assert!(buffer.contains(&STATS_COLOR));
+        let middle = layout.height() / 2 * layout.window_width();
+        assert_eq!(buffer[middle], 0);
+    }
+
+    #[test]
+    fn fades_collisions() {
+        let mut glow = CollisionGlow::default();
+        glow.update(1, &[3, 70]);
+        assert_eq!(glow.pixels(), vec![3, 70]);
+        glow.update(u64::from(COLLISION_FRAMES) - 1, &[5]);
+        assert_eq!(glow.pixels(), vec![3, 5, 70]);
+        glow.update(1, &[]);
+        assert_eq!(glow.pixels(), vec![5]);
+
+        let layout = Layout::new(1, 1.0, false);
+        let mut buffer = vec![];
+        rasterize(
+            &mut buffer,
+            &Frame {
+                layout,
+                screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+                keys: [false; 16],
+                shown_keys: None,
+                beeping: false,
+                collisions: vec![SCREEN_WIDTH + 1],
+                message: None,
+                stats: None,
+            },
+        );
+        let size = layout.pixel_size;
+        let stride = layout.window_width();
+        assert_eq!(buffer[size * stride + size], COLLISION_COLOR);
+        assert_eq!(
+            buffer[(2 * size - 1) * stride + 2 * size - 1],
+            COLLISION_COLOR
+        );
+        assert_eq!(buffer[size * stride + 2 * size], 0);
+    }
+
+    #[test]
+    fn flashes_border_around_display() {
+        let layout = Layout::new(2, 1.0, true);
+        let mut buffer = vec![];
+        rasterize(
+            &mut buffer,
+            &Frame {
+                layout,
+                screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+                keys: [false; 16],
+                shown_keys: None,
+                beeping: true,
+                collisions: vec![],
+                message: None,
+                stats: None,
+            },
+        );
+        let (stride, border) = (layout.window_width(), layout.border());
+        let middle = layout.height() / 2 * stride;
+        assert_eq!(buffer[0], BEEP_COLOR);
+        assert_eq!(buffer[middle + border - 1], BEEP_COLOR);
+        assert_eq!(buffer[middle + border], 0);
+        assert_eq!(buffer[middle + layout.width() - 1], BEEP_COLOR);
+        // The keypad beside the display is left alone
+        assert_ne!(buffer[middle + layout.width()], BEEP_COLOR);
+    }
+
+    #[test]
+    fn renders_on_its_own_thread() {
+        let mut renderer = Renderer::spawn();
+        let layout = Layout::new(1, 1.0, false);
+        let mut screen = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        screen[0] = 7;
+        renderer.submit(Frame {
+            layout,
+            screen,
+            keys: [false; 16],
+            shown_keys: None,
+            beeping: false,
+            collisions: vec![],
+            message: None,
+            stats: None,
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !renderer.take_rendered() {
+            assert!(Instant::now() < deadline, "frame never rendered");
+            thread::sleep(Duration::from_millis(1));
+        }
+        let buffer = renderer.buffer();
+        assert_eq!(buffer.len(), 640 * 320);
+        assert_eq!(buffer[9 * 640 + 9], 7);
+        assert_eq!(buffer[10], 0);
+        assert!(renderer.busy_time() > Duration::ZERO);
+    }
+}
@@ -0,0 +1,243 @@
+//! Checks a ROM before it runs for instructions from CHIP-8's descendants that this emulator
+//! doesn't run, so a game written for SUPER-CHIP or XO-CHIP gets a warning up front rather
+//! than stopping partway through, and runs with that platform's quirks unless `--quirks` says
+//! otherwise. Only code execution can reach is checked, since sprites and tables often look
+//! like instructions.
+//!
+//! A ROM named with the CHIP-8 Archive's extensions (`.ch8`, `.sc8`, `.xo8`) says its platform
+//! outright, which beats guessing, and also gets that platform's usual speed.
+use crate::opcode::Opcode;
+use crate::quirks::Quirks;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+
+const BASE_ADDRESS: usize = 0x200;
+/// How many instructions a warning lists before summing up the rest.
+const LISTED: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::SuperChip => write!(f, "SUPER-CHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+impl Platform {
+    /// The platform a ROM's file extension names, if it's one of the CHIP-8 Archive's.
+    pub fn from_extension(path: &Path) -> Option<Platform> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ch8" => Some(Platform::Chip8),
+            "sc8" => Some(Platform::SuperChip),
+            "xo8" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// The speed the CHIP-8 Archive runs most of the platform's games at.
+    pub fn instructions_per_frame(self) -> u32 {
+        match self {
+            Platform::Chip8 => 15,
+            Platform::SuperChip => 30,
+            Platform::XoChip => 200,
+        }
+    }
+
+    /// How the platform's interpreter behaves where interpreters differ, as the games written
+    /// for it expect.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            // Modern SUPER-CHIP shifts in place and clips sprites, and reads Bnnn as BXNN
+            Platform::SuperChip => Quirks {
+                jump_vx: true,
+                ..Quirks::default()
+            },
+            // Octo's XO-CHIP went back to the COSMAC VIP's shifts and I increments
+            Platform::XoChip => Quirks {
+                memory_increment: true,
+                shift_vy: true,
+                wrap_sprites: true,
+                ..Quirks::default()
+            },
+        }
+    }
+}
+
+/// An instruction the ROM reaches that this emulator doesn't run as its platform intends.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Unsupported {
+    pub addr: usize,
+    pub word: u16,
+    pub name: &'static str,
+    pub platform: Platform,
+}
+
+impl Unsupported {
+    /// Whether this is SUPER-CHIP's Fx75 or Fx85, which do run with `--persist-flags`.
+    pub fn is_flags(&self) -> bool {
+        matches!(self.word & 0xF0FF, 0xF075 | 0xF085)
+    }
+}
+
+/// The unsupported instructions `rom` can reach from its entry point, in address order.
+pub fn check(rom: &[u8]) -> Vec<Unsupported> {
+    let end = BASE_ADDRESS + rom.len();
+    let word_at = |addr: usize| -> Option<u16> {
+        if addr >= BASE_ADDRESS && addr + 1 < end {
+            let i = addr - BASE_ADDRESS;
+            Some(u16::from_be_bytes([rom[i], rom[i + 1]]))
+        } else {
+            None
+        }
+    };
+
+    let mut found = Vec::new();
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![BASE_ADDRESS];
+    while let Some(addr) = pending.pop() {
+        if !visited.insert(addr) {
+            continue;
+        }
+        let word = match word_at(addr) {
+            Some(word) => word,
+            None => continue,
+        };
+        if let Some((name, platform)) = extension(word) {
+            found.push(Unsupported {
+                addr,
+                word,
+                name,
+                platform,
+            });
+        }
+        // Past an instruction that doesn't decode, there's no telling where execution goes
+        let op = match Opcode::decode(word) {
+            Some(op) => op,
+            None => continue,
+        };
+        match op {
+            Opcode::Return | Opcode::Exit => {}
+            Opcode::Jump(nnn) | Opcode::JumpPlus(nnn) => pending.push(nnn),
+            Opcode::CallSubroutine(nnn) => {
+                pending.push(nnn);
+                pending.push(addr + 2);
+            }
+            Opcode::SkipIfConstantEqual(..)
+            | Opcode::SkipIfConstantNotEqual(..)
+            | Opcode::SkipIfRegistersEqual(..)
+            | Opcode::SkipIfRegistersNotEqual(..)
+            | Opcode::SkipIfPressed(..)
+            | Opcode::SkipIfNotPressed(..) => {
+                pending.push(addr + 2);
+                pending.push(addr + 4);
+            }
+            _ => pending.push(addr + 2),
+        }
+    }
+    found.sort_by_key(|u| u.addr);
+    found
+}
+
+/// Names an instruction from a later platform, other than the few this emulator runs (00FD
+/// exit, and XO-CHIP's audio).
+fn extension(word: u16) -> Option<(&'static str, Platform)> {
+    let found = match word >> 12 {
+        0x0 if word & 0xFFF0 == 0x00C0 => ("scroll down", Platform::SuperChip),
+        0x0 if word & 0xFFF0 == 0x00D0 => ("scroll up", Platform::XoChip),
+        0x0 => match word {
+            0x00FB => ("scroll right", Platform::SuperChip),
+            0x00FC => ("scroll left", Platform::SuperChip),
+            0x00FE => ("low-res", Platform::SuperChip),
+            0x00FF => ("high-res", Platform::SuperChip),
+            _ => return None,
+        },
+        0x5 => match word & 0xF {
+            0x2 => ("save range", Platform::XoChip),
+            0x3 => ("load range", Platform::XoChip),
+            _ => return None,
+        },
+        0xD if word & 0xF == 0 => ("16x16 sprite", Platform::SuperChip),
+        0xF if word == 0xF000 => ("long i", Platform::XoChip),
+        0xF => match word & 0xFF {
+            0x01 => ("plane", Platform::XoChip),
+            0x30 => ("big digit", Platform::SuperChip),
+            0x75 => ("save flags", Platform::SuperChip),
+            0x85 => ("load flags", Platform::SuperChip),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(found)
+}
+
+/// The platform a ROM using `found` was written for, or None for plain CHIP-8.
+pub fn platform(found: &[Unsupported]) -> Option<Platform> {
+    // Any XO-CHIP instruction means XO-CHIP, which includes SUPER-CHIP's
+    found.iter().map(|u| u.platform).max()
+}
+
+/// A warning about what `check` found, or None if there's nothing to warn about.
+pub fn warning(found: &[Unsupported]) -> Option<String> {
+    let platform = platform(found)?;
+    let mut listed: Vec<String> = found
+        .iter()
+        .take(LISTED)
+        .map(|u| format!("{:04X} ({}) at {:03X}", u.word, u.name, u.addr))
+        .collect();
+    if found.len() > LISTED {
+        listed.push(format!("{} more", found.len() - LISTED));
+    }
+    Some(format!(
+        "This ROM looks written for {}, and uses instructions this emulator doesn't \
+         run: {}. It may stop or draw wrongly when it gets to them.",
+        platform,
+        listed.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_reachable_extensions() {
+        // hires; JP 206; (data: 00FF); scroll-right; CLS; JP 20A
+        let rom = [
+            0x00, 0xFF, 0x12, 0x06, 0x00, 0xFF, 0x00, 0xFB, 0x00, 0xE0, 0x12, 0x0A,
+        ];
+        let found = check(&rom);
+        let addrs: Vec<usize> = found.iter().map(|u| u.addr).collect();
+        assert_eq!(addrs, vec![0x200, 0x206]);
+        let text = warning(&found).unwrap();
+        assert!(text.contains("SUPER-CHIP"));
+        assert!(text.contains("00FB (scroll right) at 206"));
+
+        // plane 1; exit
+        let found = check(&[0xF1, 0x01, 0x00, 0xFD]);
+        assert_eq!(platform(&found), Some(Platform::XoChip));
+        assert!(Platform::XoChip.quirks().shift_vy);
+        assert_eq!(
+            Platform::from_extension(Path::new("roms/Super.SC8")),
+            Some(Platform::SuperChip)
+        );
+        assert_eq!(Platform::from_extension(Path::new("PONG")), None);
+        // CLS; LD V0, 1; audio; exit
+        assert!(check(&[0x00, 0xE0, 0x60, 0x01, 0xF0, 0x02, 0x00, 0xFD]).is_empty());
+        // LD R, V3; exit
+        let found = check(&[0xF3, 0x75, 0x00, 0xFD]);
+        assert!(found[0].is_flags() && !check(&rom)[0].is_flags());
+        assert_eq!(warning(&[]), None);
+    }
+}
@@ -0,0 +1,80 @@
+//! Rumble on connected controllers while the buzzer sounds (`--features rumble`), to feel the
+//! beeps on handheld setups where the speaker is small, muted, or missing.
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks};
+use gilrs::{EventType, Gilrs};
+use std::error::Error;
+
+/// How long each pulse lasts, and the gap before the next.
+const PULSE_MS: u32 = 60;
+/// Weak rather than strong motors, and not at full strength, as beeps can go on for a while.
+const MAGNITUDE: u16 = 0x6000;
+
+/// Pulses every controller that can rumble, while on.
+pub struct Rumble {
+    gilrs: Gilrs,
+    effect: Effect,
+    on: bool,
+}
+
+impl Rumble {
+    /// Starts watching for controllers, including ones plugged in later.
+    pub fn start() -> Result<Rumble, Box<dyn Error>> {
+        let mut gilrs = Gilrs::new()?;
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Weak {
+                    magnitude: MAGNITUDE,
+                },
+                scheduling: Replay {
+                    play_for: Ticks::from_ms(PULSE_MS),
+                    with_delay: Ticks::from_ms(PULSE_MS),
+                    ..Replay::default()
+                },
+                envelope: Default::default(),
+            })
+            .finish(&mut gilrs)?;
+        let rumble = Rumble {
+            gilrs,
+            effect,
+            on: false,
+        };
+        rumble.attach_controllers()?;
+        Ok(rumble)
+    }
+
+    /// Starts or stops pulsing. Meant to be called every frame, as it's also when controllers
+    /// connecting and disconnecting are noticed.
+    pub fn set(&mut self, on: bool) {
+        let mut connections_changed = false;
+        while let Some(event) = self.gilrs.next_event() {
+            if let EventType::Connected | EventType::Disconnected = event.event {
+                connections_changed = true;
+            }
+        }
+        // A controller that can't be reached just doesn't rumble
+        if connections_changed {
+            let _ = self.attach_controllers();
+            if self.on {
+                let _ = self.effect.play();
+            }
+        }
+        if on != self.on {
+            self.on = on;
+            let _ = if on {
+                self.effect.play()
+            } else {
+                self.effect.stop()
+            };
+        }
+    }
+
+    fn attach_controllers(&self) -> Result<(), gilrs::ff::Error> {
+        let ids: Vec<_> = self
+            .gilrs
+            .gamepads()
+            .filter(|(_, gamepad)| gamepad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+        self.effect.set_gamepads(&ids, &self.gilrs)
+    }
+}
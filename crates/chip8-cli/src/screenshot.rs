@@ -0,0 +1,193 @@
+//! Screenshots that remember the moment they were taken. Alongside the picture, the PNG's text
+//! chunks record the ROM's hash, the frame, and the quirks, and optionally a save state, so
+//! `--from-screenshot` can pick the game up from exactly there.
+use crate::chip8::{Chip8, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::png;
+use crate::quirks::Quirks;
+use std::error::Error;
+use std::io::{self, Write};
+
+/// Output pixels per side of each CHIP-8 pixel.
+const SCALE: usize = 8;
+const ROM_HASH: &str = "chip8-rom-hash";
+const FRAME: &str = "chip8-frame";
+const QUIRKS: &str = "chip8-quirks";
+/// The save state, run-length packed and then written as hex, since tEXt only holds text.
+const STATE: &str = "chip8-state";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screenshot {
+    pub rom_hash: u64,
+    pub frame: u64,
+    pub quirks: Quirks,
+    pub state: Option<Vec<u8>>,
+}
+
+impl Screenshot {
+    /// Captures a running machine's details, with its save state if `with_state`.
+    pub fn of(chip8: &Chip8, rom_hash: u64, frame: u64, with_state: bool) -> Screenshot {
+        Screenshot {
+            rom_hash,
+            frame,
+            quirks: chip8.quirks(),
+            state: Some(chip8.save_state()).filter(|_| with_state),
+        }
+    }
+
+    /// Writes `chip8`'s screen as a PNG carrying these details.
+    pub fn write_png<W: Write>(&self, out: &mut W, chip8: &Chip8) -> io::Result<()> {
+        let mut text = vec![
+            (ROM_HASH, format!("{:016x}", self.rom_hash)),
+            (FRAME, self.frame.to_string()),
+            (QUIRKS, self.quirks.to_string()),
+        ];
+        if let Some(ref state) = self.state {
+            let hex = pack(state).iter().map(|b| format!("{:02x}", b)).collect();
+            text.push((STATE, hex));
+        }
+        write_screen(out, chip8, &text)
+    }
+
+    /// Reads the details back out of a screenshot.
+    pub fn read(data: &[u8]) -> Result<Screenshot, Box<dyn Error>> {
+        let text = png::read_text(data)?;
+        let field = |keyword| {
+            text.iter()
+                .find(|(k, _)| k == keyword)
+                .map(|(_, v)| v.as_str())
+        };
+        let rom_hash = field(ROM_HASH).ok_or("Not a screenshot taken by this emulator")?;
+        let state = match field(STATE) {
+            Some(hex) => {
+                let packed = (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(hex.get(i..i + 2).unwrap_or(""), 16))
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map_err(|_| "Screenshot's save state is not valid hex")?;
+                Some(unpack(&packed)?)
+            }
+            None => None,
+        };
+        Ok(Screenshot {
+            rom_hash: u64::from_str_radix(rom_hash, 16)?,
+            frame: field(FRAME).unwrap_or("0").parse()?,
+            quirks: Quirks::parse(field(QUIRKS).unwrap_or(""))?,
+            state,
+        })
+    }
+}
+
+/// Writes `chip8`'s screen as a PNG, scaled up so each pixel can be seen, with `text` in its
+/// text chunks.
+pub fn write_screen<W: Write>(
+    out: &mut W,
+    chip8: &Chip8,
+    text: &[(&str, String)],
+) -> io::Result<()> {
+    let rgb: Vec<u8> = chip8
+        .render_to_image(SCALE, chip8.palette())
+        .chunks(4)
+        .flat_map(|rgba| &rgba[..3])
+        .copied()
+        .collect();
+    let (width, height) = (SCREEN_WIDTH * SCALE, SCREEN_HEIGHT * SCALE);
+    png::write(out, &rgb, width, height, text)
+}
+
+/// PackBits: a header byte n up to 127 is followed by n + 1 bytes to copy, and one from 129
+/// by a single byte to repeat 257 - n times. Save states are mostly empty memory, so this
+/// shrinks them to a fraction of their size.
+fn pack(data: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::new();
+    let mut literals = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(128)
+            .take_while(|b| **b == data[i])
+            .count();
+        if run >= 3 {
+            flush_literals(&mut packed, &mut literals);
+            packed.push((257 - run) as u8);
+            packed.push(data[i]);
+            i += run;
+        } else {
+            literals.push(data[i]);
+            if literals.len() == 128 {
+                flush_literals(&mut packed, &mut literals);
+            }
+            i += 1;
+        }
+    }
+    flush_literals(&mut packed, &mut literals);
+    packed
+}
+
+fn flush_literals(packed: &mut Vec<u8>, literals: &mut Vec<u8>) {
+    if !literals.is_empty() {
+        packed.push(literals.len() as u8 - 1);
+        packed.append(literals);
+    }
+}
+
+fn unpack(packed: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    let mut rest = packed;
+    while let Some((&n, tail)) = rest.split_first() {
+        if n <= 127 {
+            let len = usize::from(n) + 1;
+            let bytes = tail
+                .get(..len)
+                .ok_or("Screenshot's save state is cut short")?;
+            data.extend_from_slice(bytes);
+            rest = &tail[len..];
+        } else {
+            let byte = tail.first().ok_or("Screenshot's save state is cut short")?;
+            data.extend(std::iter::repeat_n(*byte, 257 - usize::from(n)));
+            rest = &tail[1..];
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_runs() {
+        let data = [[0u8; 300].as_ref(), &[1, 2, 3], &[7; 2], &[9]].concat();
+        let packed = pack(&data);
+        assert!(packed.len() < 20);
+        assert_eq!(unpack(&packed).unwrap(), data);
+        let varied: Vec<u8> = (0..=255).chain(0..=255).collect();
+        assert_eq!(unpack(&pack(&varied)).unwrap(), varied);
+        assert!(unpack(&[5, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn restores_from_screenshot() {
+        let mut chip8 = Chip8::default();
+        chip8.set_quirks(Quirks::parse("vf-reset").unwrap());
+        // LD V0, 5; LD F, V0; DRW V0, V0, 5
+        chip8.load_program(&[0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05]);
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        chip8.present();
+        let shot = Screenshot::of(&chip8, 0x1234, 99, true);
+        let mut png = Vec::new();
+        shot.write_png(&mut png, &chip8).unwrap();
+        assert_eq!(Screenshot::read(&png).unwrap(), shot);
+
+        let mut restored = Chip8::default();
+        restored.load_state(shot.state.as_ref().unwrap()).unwrap();
+        assert_eq!(restored.framebuffer(), chip8.framebuffer());
+
+        let without = Screenshot::of(&chip8, 0x1234, 99, false);
+        let mut png = Vec::new();
+        without.write_png(&mut png, &chip8).unwrap();
+        assert_eq!(Screenshot::read(&png).unwrap().state, None);
+    }
+}
@@ -0,0 +1,502 @@
+//! A battery of opcode checks built into the emulator (`chip8 selftest`), so a build can be
+//! verified without any ROMs. Each check loads a few instructions, sets up the state before
+//! them, steps through them, and compares what the machine ends up with against the state
+//! expected afterwards.
+use crate::chip8::{Chip8, SCREEN_WIDTH};
+use crate::quirks::Quirks;
+use std::fmt::Write;
+
+/// Part of the machine state. Before a check, only the registers, I, and memory are set up;
+/// everything else starts as at power-on. Afterwards all of it is compared, except that only
+/// the memory listed is looked at. Registers left out are zero.
+#[derive(Copy, Clone)]
+struct Vector {
+    v: &'static [(usize, u8)],
+    i: usize,
+    pc: usize,
+    stack: &'static [usize],
+    delay_timer: u8,
+    sound_timer: u8,
+    memory: &'static [(usize, &'static [u8])],
+    /// Lit pixels as (x, y).
+    pixels: &'static [(usize, usize)],
+}
+
+/// Power-on state, with the program about to start.
+const START: Vector = Vector {
+    v: &[],
+    i: 0,
+    pc: 0x200,
+    stack: &[],
+    delay_timer: 0,
+    sound_timer: 0,
+    memory: &[],
+    pixels: &[],
+};
+/// Power-on state after stepping over a single instruction.
+const NEXT: Vector = Vector { pc: 0x202, ..START };
+
+const DEFAULT_QUIRKS: Quirks = Quirks {
+    legacy_flags: false,
+    memory_increment: false,
+    exclusive_range: false,
+    shift_vy: false,
+    jump_vx: false,
+    wrap_sprites: false,
+    vf_reset: false,
+    memory_stack: false,
+};
+
+struct Check {
+    name: &'static str,
+    quirks: Quirks,
+    /// Loaded at 0x200 and stepped through once per instruction.
+    program: &'static [u16],
+    before: Vector,
+    after: Vector,
+}
+
+#[rustfmt::skip]
+const CHECKS: &[Check] = &[
+    Check {
+        name: "00E0 clear screen",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xD015, 0x00E0],
+        before: START,
+        after: Vector { pc: 0x204, ..START },
+    },
+    Check {
+        name: "00EE return",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x2202, 0x00EE],
+        before: START,
+        after: NEXT,
+    },
+    Check {
+        name: "1nnn jump",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x1234],
+        before: START,
+        after: Vector { pc: 0x234, ..START },
+    },
+    Check {
+        name: "2nnn call",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x2400],
+        before: START,
+        after: Vector { pc: 0x400, stack: &[0x202], ..START },
+    },
+    Check {
+        name: "3xnn skip if equal",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x3A42],
+        before: Vector { v: &[(0xA, 0x42)], ..START },
+        after: Vector { v: &[(0xA, 0x42)], pc: 0x204, ..START },
+    },
+    Check {
+        name: "4xnn skip if not equal",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x4A42],
+        before: Vector { v: &[(0xA, 0x42)], ..START },
+        after: Vector { v: &[(0xA, 0x42)], ..NEXT },
+    },
+    Check {
+        name: "5xy0 skip if registers equal",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x5120],
+        before: Vector { v: &[(1, 7), (2, 7)], ..START },
+        after: Vector { v: &[(1, 7), (2, 7)], pc: 0x204, ..START },
+    },
+    Check {
+        name: "6xnn load",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x6A5C],
+        before: START,
+        after: Vector { v: &[(0xA, 0x5C)], ..NEXT },
+    },
+    Check {
+        name: "7xnn add without carry",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x7AFF],
+        before: Vector { v: &[(0xA, 2)], ..START },
+        after: Vector { v: &[(0xA, 1)], ..NEXT },
+    },
+    Check {
+        name: "8xy0 copy",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x8120],
+        before: Vector { v: &[(2, 9)], ..START },
+        after: Vector { v: &[(1, 9), (2, 9)], ..NEXT },
+    },
+    Check {
+        name: "8xy1 or",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x8121],
+        before: Vector { v: &[(1, 0x0C), (2, 0x0A), (0xF, 1)], ..START },
+        after: Vector { v: &[(1, 0x0E), (2, 0x0A), (0xF, 1)], ..NEXT },
+    },
+    Check {
+        name: "8xy2 and",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x8122],
+        before: Vector { v: &[(1, 0x0C), (2, 0x0A)], ..START },
+        after: Vector { v: &[(1, 0x08), (2, 0x0A)], ..NEXT },
+    },
+    Check {
+        name: "8xy3 xor",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x8123],
+        before: Vector { v: &[(1, 0x0C), (2, 0x0A)], ..START },
+        after: Vector { v: &[(1, 0x06), (2, 0x0A)], ..NEXT },
+    },
+    Check {
+        name: "8xy4 add with carry",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x8124],
+        before: Vector { v: &[(1, 0xFF), (2, 2)], ..START },
+        after: Vector { v: &[(1, 1), (2, 2), (0xF, 1)], ..NEXT },
+    },
+    Check {
+        name: "8xy5 subtract with borrow",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x8125],
+        before: Vector { v: &[(1, 2), (2, 3), (0xF, 1)], ..START },
+        after: Vector { v: &[(1, 0xFF), (2, 3)], ..NEXT },
+    },
+    Check {
+        name: "8xy6 shift right",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x8126],
+        before: Vector { v: &[(1, 5), (2, 0x80)], ..START },
+        after: Vector { v: &[(1, 2), (2, 0x80), (0xF, 1)], ..NEXT },
+    },
+    Check {
+        name: "8xy7 reverse subtract",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x8127],
+        before: Vector { v: &[(1, 3), (2, 5)], ..START },
+        after: Vector { v: &[(1, 2), (2, 5), (0xF, 1)], ..NEXT },
+    },
+    Check {
+        name: "8xyE shift left",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x812E],
+        before: Vector { v: &[(1, 0x81), (2, 1)], ..START },
+        after: Vector { v: &[(1, 2), (2, 1), (0xF, 1)], ..NEXT },
+    },
+    Check {
+        name: "9xy0 skip if registers differ",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0x9120],
+        before: Vector { v: &[(1, 1), (2, 2)], ..START },
+        after: Vector { v: &[(1, 1), (2, 2)], pc: 0x204, ..START },
+    },
+    Check {
+        name: "Annn load I",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xA123],
+        before: START,
+        after: Vector { i: 0x123, ..NEXT },
+    },
+    Check {
+        name: "Bnnn jump plus V0",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xB300],
+        before: Vector { v: &[(0, 4), (3, 1)], ..START },
+        after: Vector { v: &[(0, 4), (3, 1)], pc: 0x304, ..START },
+    },
+    Check {
+        name: "Cxnn random under mask",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xC100],
+        before: Vector { v: &[(1, 0xFF)], ..START },
+        after: NEXT,
+    },
+    Check {
+        name: "Dxyn draw",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xD121],
+        before: Vector { v: &[(1, 3), (2, 4)], i: 0x300, memory: &[(0x300, &[0x80])], ..START },
+        after: Vector { v: &[(1, 3), (2, 4)], i: 0x300, pixels: &[(3, 4)], ..NEXT },
+    },
+    Check {
+        name: "Dxyn collision",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xD121, 0xD121],
+        before: Vector { v: &[(1, 3), (2, 4)], i: 0x300, memory: &[(0x300, &[0x80])], ..START },
+        after: Vector { v: &[(1, 3), (2, 4), (0xF, 1)], i: 0x300, pc: 0x204, ..START },
+    },
+    Check {
+        name: "Ex9E skip if key down",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xE09E],
+        before: START,
+        after: NEXT,
+    },
+    Check {
+        name: "ExA1 skip if key up",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xE0A1],
+        before: START,
+        after: Vector { pc: 0x204, ..START },
+    },
+    Check {
+        name: "Fx15 Fx07 delay timer",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xF115, 0xF207],
+        before: Vector { v: &[(1, 0x20)], ..START },
+        after: Vector { v: &[(1, 0x20), (2, 0x20)], pc: 0x204, delay_timer: 0x20, ..START },
+    },
+    Check {
+        name: "Fx18 sound timer",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xF118],
+        before: Vector { v: &[(1, 9)], ..START },
+        after: Vector { v: &[(1, 9)], sound_timer: 9, ..NEXT },
+    },
+    Check {
+        name: "Fx1E add to I",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xF11E],
+        before: Vector { v: &[(1, 0x10)], i: 0x100, ..START },
+        after: Vector { v: &[(1, 0x10)], i: 0x110, ..NEXT },
+    },
+    Check {
+        name: "Fx29 font character",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xF129],
+        before: Vector { v: &[(1, 0xA)], ..START },
+        after: Vector { v: &[(1, 0xA)], i: 50, ..NEXT },
+    },
+    Check {
+        name: "Fx33 decimal digits",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xF133],
+        before: Vector { v: &[(1, 254)], i: 0x300, ..START },
+        after: Vector { v: &[(1, 254)], i: 0x300, memory: &[(0x300, &[2, 5, 4])], ..NEXT },
+    },
+    Check {
+        name: "Fx55 store registers",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xF255],
+        before: Vector { v: &[(0, 1), (1, 2), (2, 3), (3, 4)], i: 0x300, ..START },
+        after: Vector {
+            v: &[(0, 1), (1, 2), (2, 3), (3, 4)],
+            i: 0x300,
+            memory: &[(0x300, &[1, 2, 3, 0])],
+            ..NEXT
+        },
+    },
+    Check {
+        name: "Fx65 load registers",
+        quirks: DEFAULT_QUIRKS,
+        program: &[0xF265],
+        before: Vector { i: 0x300, memory: &[(0x300, &[4, 5, 6, 7])], ..START },
+        after: Vector { v: &[(0, 4), (1, 5), (2, 6)], i: 0x300, ..NEXT },
+    },
+    Check {
+        name: "8xy6 shift-vy",
+        quirks: Quirks { shift_vy: true, ..DEFAULT_QUIRKS },
+        program: &[0x8126],
+        before: Vector { v: &[(1, 0x80), (2, 5)], ..START },
+        after: Vector { v: &[(1, 2), (2, 5), (0xF, 1)], ..NEXT },
+    },
+    Check {
+        name: "8xy1 vf-reset",
+        quirks: Quirks { vf_reset: true, ..DEFAULT_QUIRKS },
+        program: &[0x8121],
+        before: Vector { v: &[(1, 0x0C), (2, 0x0A), (0xF, 1)], ..START },
+        after: Vector { v: &[(1, 0x0E), (2, 0x0A)], ..NEXT },
+    },
+    Check {
+        name: "Bxnn jump-vx",
+        quirks: Quirks { jump_vx: true, ..DEFAULT_QUIRKS },
+        program: &[0xB300],
+        before: Vector { v: &[(0, 4), (3, 1)], ..START },
+        after: Vector { v: &[(0, 4), (3, 1)], pc: 0x301, ..START },
+    },
+    Check {
+        name: "Fx55 memory-increment",
+        quirks: Quirks { memory_increment: true, ..DEFAULT_QUIRKS },
+        program: &[0xF255],
+        before: Vector { v: &[(0, 1), (1, 2), (2, 3)], i: 0x300, ..START },
+        after: Vector {
+            v: &[(0, 1), (1, 2), (2, 3)],
+            i: 0x303,
+            memory: &[(0x300, &[1, 2, 3])],
+            ..NEXT
+        },
+    },
+];
+
+/// How one check went.
+pub struct Outcome {
+    pub name: &'static str,
+    /// What differed from the expected state, empty if the check passed.
+    pub mismatches: Vec<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Runs every check.
+pub fn run() -> Vec<Outcome> {
+    CHECKS.iter().map(run_check).collect()
+}
+
+fn run_check(check: &Check) -> Outcome {
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.set_quirks(check.quirks);
+    let program: Vec<u8> = check.program.iter().flat_map(|w| w.to_be_bytes()).collect();
+    chip8.load_program(&program);
+    for (index, value) in check.before.v {
+        chip8.set_register(*index, *value);
+    }
+    chip8.set_i_addr(check.before.i);
+    for (addr, bytes) in check.before.memory {
+        chip8.write_memory(*addr, bytes);
+    }
+    let mut mismatches = Vec::new();
+    for step in chip8.steps().take(check.program.len()) {
+        if let Err(fault) = step {
+            mismatches.push(fault.to_string());
+        }
+    }
+    if mismatches.is_empty() {
+        mismatches = compare(&chip8, &check.after);
+    }
+    Outcome {
+        name: check.name,
+        mismatches,
+    }
+}
+
+/// Describes everything about the machine that differs from `expected`.
+fn compare(chip8: &Chip8, expected: &Vector) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    let mut differs = |what: String, actual: String, wanted: String| {
+        if actual != wanted {
+            mismatches.push(format!("{} is {}, expected {}", what, actual, wanted));
+        }
+    };
+    let mut v = [0u8; 16];
+    for (index, value) in expected.v {
+        v[*index] = *value;
+    }
+    for (index, (actual, wanted)) in chip8.registers().iter().zip(v.iter()).enumerate() {
+        differs(
+            format!("V{:X}", index),
+            format!("{:02X}", actual),
+            format!("{:02X}", wanted),
+        );
+    }
+    differs(
+        "I".to_string(),
+        format!("{:03X}", chip8.i_addr()),
+        format!("{:03X}", expected.i),
+    );
+    differs(
+        "PC".to_string(),
+        format!("{:03X}", chip8.pc()),
+        format!("{:03X}", expected.pc),
+    );
+    differs(
+        "the stack".to_string(),
+        format!("{:03X?}", chip8.stack()),
+        format!("{:03X?}", expected.stack),
+    );
+    differs(
+        "DT".to_string(),
+        format!("{:02X}", chip8.delay_timer()),
+        format!("{:02X}", expected.delay_timer),
+    );
+    differs(
+        "ST".to_string(),
+        format!("{:02X}", chip8.sound_timer()),
+        format!("{:02X}", expected.sound_timer),
+    );
+    for (addr, bytes) in expected.memory {
+        differs(
+            format!("memory at {:03X}", addr),
+            format!("{:02X?}", &chip8.memory()[*addr..*addr + bytes.len()]),
+            format!("{:02X?}", bytes),
+        );
+    }
+    let lit: Vec<(usize, usize)> = chip8
+        .screen()
+        .iter()
+        .enumerate()
+        .filter(|(_, pixel)| **pixel != 0)
+        .map(|(i, _)| (i % SCREEN_WIDTH, i / SCREEN_WIDTH))
+        .collect();
+    differs(
+        "lit pixels".to_string(),
+        format!("{:?}", lit),
+        format!("{:?}", expected.pixels),
+    );
+    mismatches
+}
+
+/// Lays out the outcomes as a table, one check per line, ending with a count of passes.
+pub fn table(outcomes: &[Outcome]) -> String {
+    let mut table = String::new();
+    for outcome in outcomes {
+        let result = if outcome.passed() { "pass" } else { "FAIL" };
+        let _ = write!(table, "{:<30} {}", outcome.name, result);
+        if !outcome.passed() {
+            let _ = write!(table, "  {}", outcome.mismatches.join("; "));
+        }
+        table.push('\n');
+    }
+    let passed = outcomes.iter().filter(|o| o.passed()).count();
+    let _ = writeln!(table, "{} of {} checks passed", passed, outcomes.len());
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_checks_pass() {
+        for outcome in run() {
+            assert!(
+                outcome.passed(),
+                "{}: {:?}",
+                outcome.name,
+                outcome.mismatches
+            );
+        }
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let check = Check {
+            name: "6xnn load",
+            quirks: DEFAULT_QUIRKS,
+            program: &[0x6A5C, 0xA123],
+            before: START,
+            after: Vector {
+                v: &[(0xA, 0x5D)],
+                ..NEXT
+            },
+        };
+        let outcome = run_check(&check);
+        assert_eq!(
+            outcome.mismatches,
+            vec![
+                "VA is 5C, expected 5D",
+                "I is 123, expected 000",
+                "PC is 204, expected 202",
+            ]
+        );
+        assert_eq!(
+            table(&[outcome]),
+            "6xnn load                      FAIL  VA is 5C, expected 5D; I is 123, expected \
+             000; PC is 204, expected 202\n0 of 1 checks passed\n"
+        );
+    }
+}
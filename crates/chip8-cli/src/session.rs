@@ -0,0 +1,603 @@
+use crate::chip8::{Chip8, Executed, Fault};
+use crate::clock::FrameClock;
+use crate::debugger::{self, Debugger, Frame, Stop};
+use crate::draws::DrawStats;
+use crate::explain::{self, Before};
+use crate::monitor::{self, Command, Register};
+use crate::movie::{Player, Recorder};
+use crate::profiler::Profiler;
+use crate::slowdraw::Drawing;
+use crate::source_map::SourceMap;
+use crate::symbols::Symbols;
+use crate::trace::{Edit, Entry, Tracer};
+use crate::video::Video;
+use std::fs::File;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Everything that advances along with the emulated machine, one frame at a time.
+pub struct Session {
+    pub chip8: Chip8,
+    pub frame: u64,
+    /// How many instructions of the current frame have run, when it was paused partway through.
+    pub instruction: u32,
+    pub recorder: Option<Recorder>,
+    pub player: Option<Player>,
+    pub debugger: Option<Debugger>,
+    pub symbols: Symbols,
+    pub source_map: SourceMap,
+    /// Paces frames while they are run one at a time by `run_frame`.
+    pub clock: FrameClock,
+    /// Power on with garbage in RAM, the registers, and the screen rather than zeros.
+    pub random_init: bool,
+    pub tracer: Option<Tracer>,
+    pub profiler: Option<Profiler>,
+    pub draws: Option<DrawStats>,
+    /// What each instruction drew, collected for slow draw to play back.
+    pub drawings: Option<Vec<Drawing>>,
+    /// Print each instruction executed with an explanation of what it did.
+    pub explain: bool,
+    pub video: Option<Video>,
+    /// How the frame in progress started, kept while debugging to step back through it.
+    journal: Option<Journal>,
+}
+
+/// The frame in progress as it started: the state from before, for the history once it ends,
+/// and the state after its input and timers, to run it again from when stepping back.
+struct Journal {
+    before: Vec<u8>,
+    input: [bool; 16],
+    started: Vec<u8>,
+}
+
+impl Session {
+    /// A session for a machine that's ready to run, from frame 0, with nothing recording,
+    /// playing, or watching it.
+    pub fn new(chip8: Chip8) -> Session {
+        Session {
+            chip8,
+            frame: 0,
+            instruction: 0,
+            recorder: None,
+            player: None,
+            debugger: None,
+            symbols: Symbols::default(),
+            source_map: SourceMap::default(),
+            clock: FrameClock::default(),
+            random_init: false,
+            tracer: None,
+            profiler: None,
+            draws: None,
+            drawings: None,
+            explain: false,
+            video: None,
+            journal: None,
+        }
+    }
+
+    pub fn is_recording_or_playing(&self) -> bool {
+        self.recorder.is_some() || self.player.is_some()
+    }
+
+    /// Powers the machine back on with `program` loaded, keeping the RNG seed so the run can
+    /// still be reproduced, along with the rest of the configuration: quirks, instructions per
+    /// frame, key hold, stack kind, palette, memory access and collision tracking, flag
+    /// registers, and whether it powers on with garbage. Step-back history and access counts
+    /// belong to the old run, so they are dropped.
+    pub fn reset(&mut self, program: &[u8]) {
+        let mut chip8 = Chip8::with_seed(self.chip8.seed());
+        chip8.set_quirks(self.chip8.quirks());
+        chip8.set_key_hold(self.chip8.key_hold());
+        chip8
+            .set_stack_kind(self.chip8.stack_kind())
+            .expect("A new machine's stack is empty, so it fits either kind");
+        chip8.set_instructions_per_frame(self.chip8.instructions_per_frame());
+        chip8.set_palette(self.chip8.palette());
+        if self.chip8.memory_access().is_some() {
+            chip8.track_memory_access();
+        }
+        if self.chip8.is_tracking_collisions() {
+            chip8.track_collisions();
+        }
+        if let Some(flags) = self.chip8.flags() {
+            chip8.enable_flags(flags);
+        }
+        if self.random_init {
+            chip8.randomize();
+        }
+        self.chip8 = chip8;
+        self.chip8.load_program(program);
+        self.frame = 0;
+        self.instruction = 0;
+        self.journal = None;
+        if let Some(ref mut d) = self.debugger {
+            d.history().clear();
+        }
+        if let Some(ref mut p) = self.profiler {
+            p.restart();
+        }
+    }
+
+    /// Replaces the machine's state with a saved one. The step-back history and the position
+    /// in the run belong to the run being left, so they start over, as on reset.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.chip8.load_state(state)?;
+        self.frame = 0;
+        self.instruction = 0;
+        self.journal = None;
+        if let Some(ref mut d) = self.debugger {
+            d.history().clear();
+        }
+        Ok(())
+    }
+
+    /// Runs the frames due after `dt` of real time, all fed with the same keys. They go
+    /// through `run_frame` one at a time when the debugger, a movie, a trace, the profiler, draw
+    /// stats, slow draw, explanations, or a video has to see each of them, and are otherwise
+    /// left to `Chip8::advance`.
+    pub fn advance(
+        &mut self,
+        dt: Duration,
+        keys: &[bool; 16],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let instrumented = self.tracer.is_some()
+            || self.profiler.is_some()
+            || self.draws.is_some()
+            || self.drawings.is_some()
+            || self.explain
+            || self.video.is_some();
+        if self.debugger.is_none() && !self.is_recording_or_playing() && !instrumented {
+            self.set_keys(keys);
+            let frames = self.chip8.advance(dt).map_err(|e| self.locate_error(e))?;
+            self.frame += u64::from(frames);
+            return Ok(());
+        }
+
+        let frames = self.clock.advance(dt);
+        if !self.is_paused() {
+            for _ in 0..frames {
+                self.run_frame(keys)?;
+                if self.is_paused() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the rest of the current frame, fed with the given keys unless a movie is providing
+    /// input. This mirrors `Chip8::run_frame`, except that a breakpoint pauses partway through
+    /// the frame, which picks up where it left off when run again.
+    pub fn run_frame(&mut self, keys: &[bool; 16]) -> Result<(), Box<dyn std::error::Error>> {
+        let frame = self.frame;
+        let _span = tracing::debug_span!("frame", frame).entered();
+        while self.frame == frame {
+            if self.run_instruction(keys)?.is_some() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the next instruction of the current frame, starting the frame first if it hasn't
+    /// been, and finishing it after its last instruction. Returns why the debugger paused after
+    /// it, if it did.
+    pub fn run_instruction(
+        &mut self,
+        keys: &[bool; 16],
+    ) -> Result<Option<Stop>, Box<dyn std::error::Error>> {
+        if self.instruction == 0 {
+            let frame = self.frame;
+            let before = self.debugger.as_ref().map(|_| self.chip8.save_state());
+            let input = match self.player {
+                Some(ref mut p) if !p.is_finished(frame) => *p.input(frame),
+                _ => *keys,
+            };
+            if let Some(ref mut r) = self.recorder {
+                r.record_input(frame, &input);
+            }
+            self.start_frame(&input);
+            self.journal = before.map(|before| Journal {
+                before,
+                input,
+                started: self.chip8.save_state(),
+            });
+        }
+        let (stop, frame_done) = match self.chip8.instructions_per_frame() {
+            None => (self.execute(Chip8::tick)?, true),
+            Some(n) => {
+                let stop = self.execute(Chip8::step)?;
+                self.instruction += 1;
+                (stop, self.instruction >= n)
+            }
+        };
+        if let Some(stop) = stop {
+            // A breakpoint shows the screen as far as the frame got
+            self.chip8.present();
+            println!(
+                "{}\n{}",
+                stop,
+                debugger::describe(&self.chip8, &self.symbols, &self.source_map)
+            );
+        }
+        if frame_done {
+            self.end_frame()?;
+        }
+        Ok(stop)
+    }
+
+    /// Feeds a frame its input and, when it runs several instructions, counts the timers down
+    /// first, as `Chip8::run_frame` does.
+    fn start_frame(&mut self, input: &[bool; 16]) {
+        self.set_keys(input);
+        if self.chip8.instructions_per_frame().is_some() {
+            self.chip8.tick_timers();
+        }
+    }
+
+    /// Rewinds the most recently run instruction. History is only kept a frame at a time, so
+    /// to land partway through one, the frame is started over and run up to there again.
+    /// Returns false if there is no history left to step back through.
+    pub fn step_back(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let debugger = self
+            .debugger
+            .as_mut()
+            .ok_or("Stepping back needs the debugger")?;
+        if self.instruction == 0 {
+            let frame = match debugger.history().step_back(&mut self.chip8)? {
+                Some(frame) => frame,
+                None => return Ok(false),
+            };
+            self.frame = frame.number;
+            let n = match self.chip8.instructions_per_frame() {
+                Some(n) => n,
+                // Frames of one instruction each are already rewound
+                None => return Ok(true),
+            };
+            let before = self.chip8.save_state();
+            self.start_frame(&frame.input);
+            self.journal = Some(Journal {
+                before,
+                input: frame.input,
+                started: self.chip8.save_state(),
+            });
+            self.instruction = n;
+        }
+
+        let journal = self
+            .journal
+            .take()
+            .ok_or("No history of how this frame started")?;
+        self.instruction -= 1;
+        if self.instruction == 0 {
+            self.chip8.load_state(&journal.before)?;
+        } else {
+            self.chip8.load_state(&journal.started)?;
+            for _ in 0..self.instruction {
+                self.chip8.step()?;
+            }
+            self.chip8.present();
+            self.journal = Some(journal);
+        }
+        Ok(true)
+    }
+
+    /// Shows the frame and hands it to whatever records or checks finished frames.
+    fn end_frame(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let frame = self.frame;
+        self.chip8.present();
+        if let (Some(d), Some(journal)) = (self.debugger.as_mut(), self.journal.take()) {
+            let input = journal.input;
+            d.history().record(
+                journal.before,
+                &self.chip8,
+                Frame {
+                    number: frame,
+                    input,
+                },
+            )?;
+        }
+        if let Some(ref mut r) = self.recorder {
+            r.end_frame(frame, &self.chip8);
+        }
+        if let Some(ref mut v) = self.video {
+            v.frame(&self.chip8)?;
+        }
+        if let Some(ref mut p) = self.player {
+            if let Err(desync) = p.end_frame(frame, &self.chip8) {
+                tracing::warn!("{}", desync);
+                self.player = None;
+            } else if p.is_finished(frame + 1) {
+                println!("Movie playback finished at frame {}", frame + 1);
+                self.player = None;
+            }
+        }
+        tracing::debug!(
+            pc = self.chip8.pc(),
+            delay_timer = self.chip8.delay_timer(),
+            sound_timer = self.chip8.sound_timer(),
+            waiting_for_key = self.chip8.is_waiting_for_key(),
+            "frame done"
+        );
+        self.frame += 1;
+        self.instruction = 0;
+        Ok(())
+    }
+
+    /// Executes one instruction with `run`, tracing, profiling, explaining, and counting or
+    /// collecting its draws if asked to, and returns why the debugger paused after it, if it
+    /// did. Steps stalled waiting for a key run no instruction, so they are left out of traces
+    /// and profiles and never hit breakpoints.
+    fn execute(
+        &mut self,
+        run: fn(&mut Chip8) -> Result<Option<Executed>, Fault>,
+    ) -> Result<Option<Stop>, Box<dyn std::error::Error>> {
+        let before = self
+            .drawings
+            .as_ref()
+            .map(|_| (self.chip8.screen(), *self.chip8.registers()));
+        let explaining = self.explain.then(|| Before::of(&self.chip8));
+        let executed = match run(&mut self.chip8).map_err(|e| self.locate_error(e))? {
+            Some(executed) => executed,
+            None => return Ok(None),
+        };
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.write(&Entry::new(&executed, &self.chip8, &self.symbols))?;
+        }
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.record(&executed);
+        }
+        if let Some(ref before) = explaining {
+            println!(
+                "{}",
+                explain::explain_line(&executed, before, &self.chip8, &self.symbols)
+            );
+        }
+        if let Some(ref mut draws) = self.draws {
+            draws.record(&executed, &self.chip8);
+        }
+        if let (Some(drawings), Some((screen, registers))) = (self.drawings.as_mut(), before) {
+            let chip8 = &self.chip8;
+            drawings.extend(Drawing::new(
+                executed.opcode,
+                screen,
+                &registers,
+                &chip8.screen(),
+                chip8.quirks(),
+            ));
+        }
+        let chip8 = &self.chip8;
+        Ok(self
+            .debugger
+            .as_mut()
+            .and_then(|d| d.check_breakpoint(chip8)))
+    }
+
+    /// Steps over the instruction at PC while paused: a call runs until it returns, and
+    /// anything else is single-stepped. Returns what to show.
+    pub fn step_over(&mut self, keys: &[bool; 16]) -> Result<String, Box<dyn std::error::Error>> {
+        let debugger = self
+            .debugger
+            .as_mut()
+            .ok_or("Stepping needs the debugger")?;
+        if debugger.step_over(&self.chip8) {
+            return Ok("Running until the call returns".to_string());
+        }
+        self.run_instruction(keys)?;
+        Ok(debugger::describe(
+            &self.chip8,
+            &self.symbols,
+            &self.source_map,
+        ))
+    }
+
+    /// Runs until the current subroutine returns. Returns what to show.
+    pub fn step_out(&mut self) -> Result<String, Box<dyn std::error::Error>> {
+        let debugger = self
+            .debugger
+            .as_mut()
+            .ok_or("Stepping needs the debugger")?;
+        Ok(if debugger.step_out(&self.chip8) {
+            "Running until the subroutine returns".to_string()
+        } else {
+            "Not in a subroutine".to_string()
+        })
+    }
+
+    fn set_keys(&mut self, keys: &[bool; 16]) {
+        for (key, down) in keys.iter().enumerate() {
+            if *down {
+                self.chip8.set_key_down(key as u8);
+            } else {
+                self.chip8.set_key_up(key as u8);
+            }
+        }
+    }
+
+    /// Adds the source line of the failed instruction (where PC was left) to an error.
+    fn locate_error(&self, e: Fault) -> Box<dyn std::error::Error> {
+        match self.source_map.locate(self.chip8.pc()) {
+            Some(line) => format!("{} ({})", e, line).into(),
+            None => e.into(),
+        }
+    }
+
+    /// Writes bytes into memory from the debugger, noting the edit in the trace so that it
+    /// explains what follows. Bytes past the end of memory are dropped.
+    fn poke(&mut self, addr: usize, bytes: &[u8]) -> io::Result<()> {
+        self.chip8.write_memory(addr, bytes);
+        let end = (addr + bytes.len()).min(self.chip8.memory().len());
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.write_edit(&Edit::Poke {
+                addr,
+                bytes: &bytes[..end.saturating_sub(addr)],
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Sets a register from the debugger, noting the edit in the trace.
+    fn set_register(&mut self, reg: Register, value: usize) -> io::Result<()> {
+        match reg {
+            Register::V(n) => self.chip8.set_register(n, value as u8),
+            Register::I => self.chip8.set_i_addr(value),
+            Register::Pc => self.chip8.set_pc(value),
+            Register::Dt => self.chip8.set_delay_timer(value as u8),
+            Register::St => self.chip8.set_sound_timer(value as u8),
+        }
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.write_edit(&Edit::Set {
+                register: reg.to_string(),
+                value,
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.debugger.as_ref().is_some_and(|d| d.is_paused())
+    }
+
+    /// Runs a machine monitor command, returning the output to show at the prompt.
+    pub fn run_monitor_command(
+        &mut self,
+        cmd: Command,
+        keys: &[bool; 16],
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let debugger = self
+            .debugger
+            .as_mut()
+            .ok_or("The monitor requires the debugger")?;
+        let symbols = &self.symbols;
+        let output = match cmd {
+            Command::Memory(addr, len) => monitor::hexdump(&self.chip8, addr, len),
+            Command::Disassemble(addr, count) => {
+                monitor::disassemble(&self.chip8, symbols, addr, count)
+            }
+            Command::Registers => debugger::describe(&self.chip8, &self.symbols, &self.source_map),
+            Command::CallStack => match debugger::call_stack(&self.chip8, symbols) {
+                stack if stack.is_empty() => "No subroutines running".to_string(),
+                stack => stack,
+            },
+            Command::Poke(addr, bytes) => {
+                self.poke(addr, &bytes)?;
+                monitor::hexdump(&self.chip8, addr, bytes.len())
+            }
+            Command::Set(reg, value) => {
+                if !debugger.is_paused() {
+                    return Ok("Pause first (p) to set registers".to_string());
+                }
+                self.set_register(reg, value)?;
+                debugger::describe(&self.chip8, &self.symbols, &self.source_map)
+            }
+            Command::Fill(addr, len, byte) => {
+                let len = len.min(self.chip8.memory().len().saturating_sub(addr));
+                self.poke(addr, &vec![byte; len])?;
+                monitor::hexdump(&self.chip8, addr, len)
+            }
+            Command::Go(addr) => {
+                self.chip8.set_pc(addr);
+                debugger.set_paused(false);
+                format!("Running from {:03X}", addr)
+            }
+            Command::Breakpoint(addr) => {
+                let state = if debugger.toggle_breakpoint(addr) {
+                    "set"
+                } else {
+                    "cleared"
+                };
+                format!("Breakpoint {} at {:03X}", state, addr)
+            }
+            Command::ListBreakpoints => {
+                let list: Vec<String> = debugger
+                    .breakpoints()
+                    .map(|addr| match symbols.locate(addr) {
+                        Some(label) => format!("{:03X} <{}>", addr, label),
+                        None => format!("{:03X}", addr),
+                    })
+                    .collect();
+                if list.is_empty() {
+                    "No breakpoints set".to_string()
+                } else {
+                    list.join("\n")
+                }
+            }
+            Command::Pause => {
+                debugger.set_paused(true);
+                format!(
+                    "Paused\n{}",
+                    debugger::describe(&self.chip8, &self.symbols, &self.source_map)
+                )
+            }
+            Command::Step => {
+                if !debugger.is_paused() {
+                    return Ok("Pause first (p) to single-step".to_string());
+                }
+                self.run_instruction(keys)?;
+                debugger::describe(&self.chip8, &self.symbols, &self.source_map)
+            }
+            Command::StepOver | Command::StepOut if !debugger.is_paused() => {
+                return Ok("Pause first (p) to step".to_string());
+            }
+            Command::StepOver => self.step_over(keys)?,
+            Command::StepOut => self.step_out()?,
+            Command::Continue => {
+                debugger.set_paused(false);
+                "Resumed".to_string()
+            }
+            Command::Heatmap(path) => {
+                let access = self
+                    .chip8
+                    .memory_access()
+                    .ok_or("Memory access is not being tracked")?;
+                let mut file = io::BufWriter::new(File::create(&path)?);
+                access.write_png(&mut file)?;
+                file.flush()?;
+                format!("Wrote memory heatmap to {}", path)
+            }
+            Command::Help => monitor::HELP.to_string(),
+        };
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_one_instruction_at_a_time() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_instructions_per_frame(Some(3));
+        // Counts up in V0 unless key 0 is held, forever
+        chip8.load_program(&[0xE0, 0x9E, 0x70, 0x01, 0x12, 0x00]);
+        let mut debugger = Debugger::default();
+        debugger.set_paused(true);
+        let mut session = Session::new(chip8);
+        session.debugger = Some(debugger);
+        let mut states = vec![];
+        for _ in 0..7 {
+            let keys = [session.frame == 1; 16];
+            states.push((
+                session.frame,
+                session.instruction,
+                session.chip8.save_state(),
+            ));
+            session.run_instruction(&keys).unwrap();
+        }
+        assert_eq!((session.frame, session.instruction), (2, 1));
+
+        // Back through both frames, one instruction at a time, playing them with their input
+        while let Some((frame, instruction, state)) = states.pop() {
+            assert!(session.step_back().unwrap());
+            assert_eq!((session.frame, session.instruction), (frame, instruction));
+            assert_eq!(session.chip8.save_state(), state);
+        }
+        assert!(!session.step_back().unwrap());
+
+        // A paused frame picks up where it left off
+        session.run_instruction(&[false; 16]).unwrap();
+        session.run_frame(&[false; 16]).unwrap();
+        assert_eq!((session.frame, session.instruction), (1, 0));
+    }
+}
@@ -0,0 +1,163 @@
+//! Slow draw, which replays each sprite drawn a line at a time so the XOR compositing can be
+//! watched as it happens. Emulation waits while the drawings play back. Each line lights up
+//! the pixels it turned on in green and the ones it turned off in red, as those are the ones
+//! that set VF.
+use crate::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::opcode::Opcode;
+use crate::palette::Palette;
+use crate::quirks::Quirks;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long each line of a sprite stays on screen.
+const LINE_TIME: Duration = Duration::from_millis(150);
+const DRAWN_COLOR: u32 = 0x30_FF_30;
+const ERASED_COLOR: u32 = 0xFF_30_30;
+
+/// A change to the screen by one instruction, with the lines it drew in the order it drew them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Drawing {
+    before: Vec<u8>,
+    after: Vec<u8>,
+    /// Screen rows, top to bottom of the sprite. Clearing the screen draws no lines and just
+    /// shows what it cleared for a moment.
+    rows: Vec<usize>,
+}
+
+impl Drawing {
+    /// Describes what `opcode` did to the screen, given the screen and registers from before it
+    /// ran, or None if it didn't draw.
+    pub fn new(
+        opcode: Opcode,
+        before: Vec<u8>,
+        registers: &[u8; 16],
+        after: &[u8],
+        quirks: Quirks,
+    ) -> Option<Drawing> {
+        let rows = match opcode {
+            Opcode::ClearDisplay => vec![],
+            Opcode::DisplaySprite(_, vy, n) => {
+                let y = registers[vy as usize] as usize % SCREEN_HEIGHT;
+                (y..y + n as usize)
+                    .map(|row| row % SCREEN_HEIGHT)
+                    .take(match quirks.wrap_sprites {
+                        true => n as usize,
+                        false => SCREEN_HEIGHT - y,
+                    })
+                    .collect()
+            }
+            _ => return None,
+        };
+        Some(Drawing {
+            before,
+            after: after.to_vec(),
+            rows,
+        })
+    }
+
+    /// The screen once `lines` lines are drawn, lighting up the changes in the last of them.
+    fn screen(&self, lines: usize, palette: Palette) -> Vec<u32> {
+        let mut screen: Vec<u32> = self.before.iter().map(|p| palette.color(*p)).collect();
+        for (i, row) in self.rows[..lines].iter().enumerate() {
+            let pixels = row * SCREEN_WIDTH..(row + 1) * SCREEN_WIDTH;
+            for p in pixels {
+                screen[p] = match self.after[p] {
+                    _ if i + 1 < lines || self.before[p] == self.after[p] => {
+                        palette.color(self.after[p])
+                    }
+                    0 => ERASED_COLOR,
+                    _ => DRAWN_COLOR,
+                };
+            }
+        }
+        screen
+    }
+}
+
+/// Drawings waiting to be played back, and when the one playing started.
+#[derive(Debug, Default)]
+pub struct SlowDraw {
+    queue: VecDeque<Drawing>,
+    started: Option<Instant>,
+}
+
+impl SlowDraw {
+    pub fn push(&mut self, drawings: Vec<Drawing>) {
+        self.queue.extend(drawings);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Drops the drawings still to play, for when the screen they lead up to is gone.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.started = None;
+    }
+
+    /// What to show on screen at `now`, or None once every drawing has played.
+    pub fn screen(&mut self, now: Instant, palette: Palette) -> Option<Vec<u32>> {
+        loop {
+            let drawing = self.queue.front()?;
+            let started = *self.started.get_or_insert(now);
+            // Show the screen from before, then each line drawn onto it
+            let steps = drawing.rows.len() as u32 + 1;
+            let lines = (now.saturating_duration_since(started).as_millis() / LINE_TIME.as_millis())
+                as usize;
+            if lines < steps as usize {
+                return Some(drawing.screen(lines, palette));
+            }
+            self.started = Some(started + LINE_TIME * steps);
+            self.queue.pop_front();
+            if self.queue.is_empty() {
+                self.started = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Register;
+
+    #[test]
+    fn plays_sprites_line_by_line() {
+        let palette = Palette::parse("000000,FFFFFF").unwrap();
+        let (off, on) = (palette.color(0), palette.color(1));
+        let size = SCREEN_WIDTH * SCREEN_HEIGHT;
+        let mut before = vec![0; size];
+        before[SCREEN_WIDTH * 31] = 1;
+        let mut after = before.clone();
+        after[SCREEN_WIDTH * 31] = 0;
+        after[1] = 1;
+        // A two line sprite at the bottom, wrapping around to the top
+        let mut registers = [0; 16];
+        registers[1] = 31;
+        let drawing = Drawing::new(
+            Opcode::DisplaySprite(Register::V0, Register::V1, 2),
+            before,
+            &registers,
+            &after,
+            Quirks {
+                wrap_sprites: true,
+                ..Quirks::default()
+            },
+        );
+        assert_eq!(drawing.as_ref().unwrap().rows, vec![31, 0]);
+
+        let mut slow_draw = SlowDraw::default();
+        slow_draw.push(vec![drawing.unwrap()]);
+        let start = Instant::now();
+        let screen = slow_draw.screen(start, palette).unwrap();
+        assert_eq!((screen[SCREEN_WIDTH * 31], screen[1]), (on, off));
+        let screen = slow_draw.screen(start + LINE_TIME, palette).unwrap();
+        assert_eq!((screen[SCREEN_WIDTH * 31], screen[1]), (ERASED_COLOR, off));
+        let screen = slow_draw.screen(start + LINE_TIME * 2, palette).unwrap();
+        assert_eq!((screen[SCREEN_WIDTH * 31], screen[1]), (off, DRAWN_COLOR));
+        assert!(slow_draw.is_playing());
+        assert_eq!(slow_draw.screen(start + LINE_TIME * 3, palette), None);
+        assert!(!slow_draw.is_playing());
+    }
+}
@@ -0,0 +1,82 @@
+//! Source maps tie addresses in an assembled program back to the lines of source that produced
+//! them, so the debugger and error messages can say `game.8o:42` rather than `0x2D4`. The file
+//! format names the source file on its first line, followed by one address and line per
+//! statement that emitted bytes.
+//!
+//! ```text
+//! file game.8o
+//! 0x200 1
+//! 0x202 4
+//! ```
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    pub file: String,
+    lines: BTreeMap<usize, usize>,
+}
+
+impl SourceMap {
+    pub fn parse(text: &str) -> Result<SourceMap, Box<dyn std::error::Error>> {
+        let mut map = SourceMap::default();
+        for (i, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                [] => {}
+                ["file", file] if i == 0 => map.file = file.to_string(),
+                [addr, source_line] => {
+                    let addr = usize::from_str_radix(addr.trim_start_matches("0x"), 16);
+                    match (addr, source_line.parse()) {
+                        (Ok(addr), Ok(source_line)) => map.insert(addr, source_line),
+                        _ => return Err(format!("Invalid source map entry: {}", line).into()),
+                    }
+                }
+                _ => return Err(format!("Invalid source map entry: {}", line).into()),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Records that the bytes from `addr` up to the next entry came from `line`.
+    pub fn insert(&mut self, addr: usize, line: usize) {
+        self.lines.insert(addr, line);
+    }
+
+    /// The source line that produced `addr`.
+    pub fn line_of(&self, addr: usize) -> Option<usize> {
+        self.lines.range(..=addr).next_back().map(|(_, line)| *line)
+    }
+
+    /// Describes `addr` as `file:line`.
+    pub fn locate(&self, addr: usize) -> Option<String> {
+        self.line_of(addr)
+            .map(|line| format!("{}:{}", self.file, line))
+    }
+}
+
+impl fmt::Display for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "file {}", self.file)?;
+        for (addr, line) in self.lines.iter() {
+            writeln!(f, "0x{:03X} {}", addr, line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_addresses() {
+        let map = SourceMap::parse("file game.8o\n0x200 1\n0x202 4\n").unwrap();
+        assert_eq!(map.locate(0x200), Some("game.8o:1".to_string()));
+        assert_eq!(map.locate(0x2FF), Some("game.8o:4".to_string()));
+        assert_eq!(map.line_of(0x100), None);
+        assert_eq!(SourceMap::parse(&map.to_string()).unwrap(), map);
+        assert!(SourceMap::parse("0x200").is_err());
+        assert!(SourceMap::parse("0x200 x").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+/// How often the measured speed is reported.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Emulation speed over the last report interval.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Speed {
+    /// Instructions executed per second.
+    pub ips: u64,
+    /// Window updates per second.
+    pub fps: u64,
+}
+
+/// Measures emulation and frontend speed by counting executed instructions and drawn frames.
+pub struct SpeedMeter {
+    since: Instant,
+    frames: u64,
+    instructions: u64,
+}
+
+impl SpeedMeter {
+    pub fn new(now: Instant, instructions: u64) -> SpeedMeter {
+        SpeedMeter {
+            since: now,
+            frames: 0,
+            instructions,
+        }
+    }
+
+    /// Counts a drawn frame, given the total number of instructions executed so far. Returns
+    /// the speed once a full report interval has passed.
+    pub fn frame(&mut self, now: Instant, instructions: u64) -> Option<Speed> {
+        self.frames += 1;
+        let elapsed = now.duration_since(self.since);
+        if elapsed < REPORT_INTERVAL {
+            return None;
+        }
+        let per_second = |count: u64| (count as f64 / elapsed.as_secs_f64()).round() as u64;
+        let speed = Speed {
+            ips: per_second(instructions.saturating_sub(self.instructions)),
+            fps: per_second(self.frames),
+        };
+        *self = SpeedMeter::new(now, instructions);
+        Some(speed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_once_per_interval() {
+        let start = Instant::now();
+        let mut meter = SpeedMeter::new(start, 100);
+        for i in 1..30 {
+            let now = start + Duration::from_millis(i * 20);
+            assert_eq!(meter.frame(now, 100 + i), None);
+        }
+        let speed = meter.frame(start + Duration::from_secs(2), 220);
+        assert_eq!(speed, Some(Speed { ips: 60, fps: 15 }));
+        assert_eq!(meter.frame(start + Duration::from_secs(2), 220), None);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::hash;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Identifies a ROM across runs regardless of what the file happens to be named.
+pub fn rom_hash(data: &[u8]) -> u64 {
+    hash::fnv1a(data)
+}
+
+/// Root directory for all persisted emulator data, following the platform's conventions.
+pub fn data_dir() -> io::Result<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if let Some(xdg) = env::var_os("XDG_DATA_HOME") {
+        Some(PathBuf::from(xdg))
+    } else {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+    };
+    base.map(|dir| dir.join("chip8"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory available"))
+}
+
+/// Directory holding everything persisted for a single ROM, keyed by its hash.
+pub fn rom_dir(hash: u64) -> io::Result<PathBuf> {
+    Ok(data_dir()?.join(format!("{:016x}", hash)))
+}
+
+pub fn autosave_path(hash: u64) -> io::Result<PathBuf> {
+    Ok(rom_dir(hash)?.join("autosave.state"))
+}
+
+pub fn slot_path(hash: u64, slot: usize) -> io::Result<PathBuf> {
+    Ok(rom_dir(hash)?.join(format!("slot{}.state", slot)))
+}
+
+/// Breakpoints set in the debugger, restored the next time the ROM is debugged.
+pub fn breakpoints_path(hash: u64) -> io::Result<PathBuf> {
+    Ok(rom_dir(hash)?.join("breakpoints.txt"))
+}
+
+/// The flag registers a ROM saved with Fx75, with `--persist-flags`.
+pub fn flags_path(hash: u64) -> io::Result<PathBuf> {
+    Ok(rom_dir(hash)?.join("flags.bin"))
+}
+
+/// The list of recently played ROMs.
+pub fn recent_path() -> io::Result<PathBuf> {
+    Ok(data_dir()?.join("recent.txt"))
+}
+
+/// Writes a file under the data directory, creating any missing parent directories.
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, data)
+}
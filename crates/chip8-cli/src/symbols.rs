@@ -0,0 +1,109 @@
+//! Symbol files map label names to addresses, so tools can show `draw_score` instead of
+//! `0x2A0`. Each line holds one Octo-style label and its address, with `#` starting a comment.
+//!
+//! ```text
+//! # game.8o
+//! main 0x200
+//! draw_score 0x2A0
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    by_addr: BTreeMap<usize, String>,
+    by_name: HashMap<String, usize>,
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Symbols, Box<dyn std::error::Error>> {
+        let mut symbols = Symbols::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                [name, addr] => {
+                    let addr = usize::from_str_radix(addr.trim_start_matches("0x"), 16)
+                        .map_err(|_| format!("Invalid address on line {}: {}", i + 1, line))?;
+                    symbols.insert(name, addr);
+                }
+                _ => return Err(format!("Invalid symbol on line {}: {}", i + 1, line).into()),
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn insert(&mut self, name: &str, addr: usize) {
+        // The first name for an address is the one shown, so aliases don't replace it
+        self.by_addr.entry(addr).or_insert_with(|| name.to_string());
+        self.by_name.insert(name.to_string(), addr);
+    }
+
+    /// The label at exactly `addr`, if any.
+    pub fn name_of(&self, addr: usize) -> Option<&str> {
+        self.by_addr.get(&addr).map(String::as_str)
+    }
+
+    pub fn addr_of(&self, name: &str) -> Option<usize> {
+        self.by_name.get(name).copied()
+    }
+
+    /// Describes `addr` relative to the closest preceding label, e.g. `draw_score+4`.
+    pub fn locate(&self, addr: usize) -> Option<String> {
+        self.by_addr
+            .range(..=addr)
+            .next_back()
+            .map(|(base, name)| match addr - base {
+                0 => name.clone(),
+                offset => format!("{}+{}", name, offset),
+            })
+    }
+
+    /// Parses an address given either as a label name or as a hex number.
+    pub fn resolve(&self, word: &str) -> Result<usize, String> {
+        if let Some(addr) = self.addr_of(word) {
+            return Ok(addr);
+        }
+        usize::from_str_radix(word.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("Not a hex number or known label: {}", word))
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<(&String, &usize)> = self.by_name.iter().collect();
+        names.sort_by_key(|(name, addr)| (**addr, (*name).clone()));
+        for (name, addr) in names {
+            writeln!(f, "{} 0x{:03X}", name, addr)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_symbol_files() {
+        let symbols = Symbols::parse("# header\nmain 0x200\n\ndraw_score 2A0 # score\n").unwrap();
+        assert_eq!(symbols.addr_of("draw_score"), Some(0x2A0));
+        assert_eq!(symbols.name_of(0x200), Some("main"));
+        assert_eq!(symbols.locate(0x2A4), Some("draw_score+4".to_string()));
+        assert_eq!(symbols.locate(0x100), None);
+        assert_eq!(Symbols::parse(&symbols.to_string()).unwrap(), symbols);
+        assert!(Symbols::parse("main").is_err());
+        assert!(Symbols::parse("main zz").is_err());
+    }
+
+    #[test]
+    fn resolves_names_or_hex() {
+        let symbols = Symbols::parse("loop 0x210").unwrap();
+        assert_eq!(symbols.resolve("loop"), Ok(0x210));
+        assert_eq!(symbols.resolve("2A0"), Ok(0x2A0));
+        assert!(symbols.resolve("nowhere").is_err());
+    }
+}
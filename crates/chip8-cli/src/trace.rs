@@ -0,0 +1,427 @@
+//! Instruction traces (`--trace`), one line per instruction executed, either as text to read
+//! or as JSON Lines to diff and analyze with tools like jq. Memory and registers edited from
+//! the debugger get a line of their own where it happened. Filters keep traces of long runs
+//! down to the addresses and kinds of instruction under investigation.
+use crate::chip8::{Chip8, Executed};
+use crate::disasm;
+use crate::symbols::Symbols;
+use std::io::{self, Write};
+use std::ops::Range;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    #[default]
+    Text,
+    Jsonl,
+}
+
+impl TraceFormat {
+    pub fn parse(text: &str) -> Result<TraceFormat, String> {
+        match text {
+            "text" => Ok(TraceFormat::Text),
+            "jsonl" => Ok(TraceFormat::Jsonl),
+            _ => Err(format!(
+                "Unknown trace format: {} (expected text or jsonl)",
+                text
+            )),
+        }
+    }
+}
+
+/// The mnemonics instructions disassemble to, which `--trace-ops` picks from.
+const MNEMONICS: [&str; 23] = [
+    "CLS", "RET", "EXIT", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB",
+    "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "AUDIO", "PITCH",
+];
+
+/// Which instructions make it into a trace. Edits from the debugger always do.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only instructions at these addresses.
+    pub pc: Option<Range<usize>>,
+    /// Only instructions with these mnemonics, like DRW or CALL.
+    pub ops: Option<Vec<String>>,
+}
+
+impl TraceFilter {
+    /// Parses an address range like `0x200..0x260`, which leaves out the end as Rust ranges do.
+    pub fn parse_pc(text: &str) -> Result<Range<usize>, String> {
+        let invalid = || {
+            format!(
+                "Invalid address range: {} (expected like 0x200..0x260)",
+                text
+            )
+        };
+        let addr = |addr: &str| usize::from_str_radix(addr.trim_start_matches("0x"), 16);
+        let (start, end) = text.split_once("..").ok_or_else(invalid)?;
+        match (addr(start), addr(end)) {
+            (Ok(start), Ok(end)) if start < end => Ok(start..end),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Parses a comma-separated list of mnemonics, in any case.
+    pub fn parse_ops(text: &str) -> Result<Vec<String>, String> {
+        text.split(',')
+            .map(|op| {
+                let op = op.trim().to_ascii_uppercase();
+                match MNEMONICS.contains(&op.as_str()) {
+                    true => Ok(op),
+                    false => Err(format!(
+                        "Unknown instruction: {} (expected one of {})",
+                        op,
+                        MNEMONICS.join(", ")
+                    )),
+                }
+            })
+            .collect()
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let op = entry.mnemonic.split(' ').next().unwrap_or_default();
+        self.pc.as_ref().is_none_or(|pc| pc.contains(&entry.pc))
+            && self
+                .ops
+                .as_ref()
+                .is_none_or(|ops| ops.iter().any(|o| o == op))
+    }
+}
+
+/// One executed instruction and what it left behind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub pc: usize,
+    pub raw: u16,
+    pub mnemonic: String,
+    /// Registers the instruction changed, with their new values.
+    pub changed: Vec<(usize, u8)>,
+    pub i_addr: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl Entry {
+    /// Describes an instruction that just ran on `chip8`.
+    pub fn new(executed: &Executed, chip8: &Chip8, symbols: &Symbols) -> Entry {
+        let changed = (0..16)
+            .filter(|r| executed.changed_registers & 1 << r != 0)
+            .map(|r| (r, chip8.registers()[r]))
+            .collect();
+        Entry {
+            pc: executed.pc,
+            raw: chip8.instruction_at(executed.pc),
+            mnemonic: disasm::format_opcode(executed.opcode, symbols),
+            changed,
+            i_addr: chip8.i_addr(),
+            delay_timer: chip8.delay_timer(),
+            sound_timer: chip8.sound_timer(),
+        }
+    }
+
+    /// Formats the entry like a disassembly line, followed by the changes and machine state.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{:03X}: {:04X}  {:<20}", self.pc, self.raw, self.mnemonic);
+        for (r, value) in self.changed.iter() {
+            text += &format!(" V{:X}={:02X}", r, value);
+        }
+        text += &format!(
+            " I={:03X} DT={:02X} ST={:02X}",
+            self.i_addr, self.delay_timer, self.sound_timer
+        );
+        text
+    }
+
+    /// Formats the entry as a JSON object. Numbers are left as numbers rather than hex so
+    /// they can be compared and filtered on directly.
+    pub fn to_json(&self) -> String {
+        let changed: Vec<String> = self
+            .changed
+            .iter()
+            .map(|(r, value)| format!("\"V{:X}\":{}", r, value))
+            .collect();
+        format!(
+            "{{\"pc\":{},\"raw\":{},\"mnemonic\":\"{}\",\"changed\":{{{}}},\"i\":{},\"dt\":{},\"st\":{}}}",
+            self.pc,
+            self.raw,
+            self.mnemonic.replace('\\', "\\\\").replace('"', "\\\""),
+            changed.join(","),
+            self.i_addr,
+            self.delay_timer,
+            self.sound_timer
+        )
+    }
+}
+
+impl Entry {
+    /// Reads back an entry written by `to_json`, e.g. from a reference trace.
+    pub fn parse_json(line: &str) -> Result<Entry, String> {
+        let invalid = || format!("Invalid trace entry: {}", line);
+        let mut json = Json {
+            text: line.trim().as_bytes(),
+            pos: 0,
+        };
+        let fields = match json.value() {
+            Some(Value::Object(fields)) if json.pos == json.text.len() => fields,
+            _ => return Err(invalid()),
+        };
+        let field = |name: &str| fields.iter().find(|(k, _)| k == name).map(|(_, v)| v);
+        let number = |name: &str| match field(name) {
+            Some(Value::Number(n)) => Ok(*n),
+            _ => Err(invalid()),
+        };
+        let changed = match field("changed") {
+            Some(Value::Object(changed)) => changed
+                .iter()
+                .map(|(name, value)| {
+                    let r = name
+                        .strip_prefix('V')
+                        .and_then(|r| usize::from_str_radix(r, 16).ok())
+                        .filter(|r| *r < 16);
+                    match (r, value) {
+                        (Some(r), Value::Number(n)) if *n <= 0xFF => Ok((r, *n as u8)),
+                        _ => Err(invalid()),
+                    }
+                })
+                .collect::<Result<Vec<(usize, u8)>, String>>()?,
+            _ => return Err(invalid()),
+        };
+        Ok(Entry {
+            pc: number("pc")? as usize,
+            raw: number("raw")? as u16,
+            mnemonic: match field("mnemonic") {
+                Some(Value::String(s)) => s.clone(),
+                _ => return Err(invalid()),
+            },
+            changed,
+            i_addr: number("i")? as usize,
+            delay_timer: number("dt")? as u8,
+            sound_timer: number("st")? as u8,
+        })
+    }
+}
+
+/// The parts of JSON that traces use: objects, strings, and unsigned integers.
+enum Value {
+    Object(Vec<(String, Value)>),
+    String(String),
+    Number(u64),
+}
+
+struct Json<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Json<'_> {
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.text.get(self.pos) == Some(&byte);
+        self.pos += found as usize;
+        found
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        match self.text.get(self.pos)? {
+            b'{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                while !self.eat(b'}') {
+                    if !fields.is_empty() && !self.eat(b',') {
+                        return None;
+                    }
+                    let key = match self.value()? {
+                        Value::String(key) => key,
+                        _ => return None,
+                    };
+                    if !self.eat(b':') {
+                        return None;
+                    }
+                    fields.push((key, self.value()?));
+                }
+                Some(Value::Object(fields))
+            }
+            b'"' => {
+                self.pos += 1;
+                let mut s = Vec::new();
+                loop {
+                    match *self.text.get(self.pos)? {
+                        b'"' => break,
+                        b'\\' => {
+                            self.pos += 1;
+                            s.push(*self.text.get(self.pos)?);
+                        }
+                        byte => s.push(byte),
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+                String::from_utf8(s).ok().map(Value::String)
+            }
+            _ => {
+                let start = self.pos;
+                while self.text.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                    self.pos += 1;
+                }
+                let digits = std::str::from_utf8(&self.text[start..self.pos]).ok()?;
+                digits.parse().ok().map(Value::Number)
+            }
+        }
+    }
+}
+
+/// Writes trace entries as they happen.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    format: TraceFormat,
+    filter: TraceFilter,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>, format: TraceFormat, filter: TraceFilter) -> Tracer {
+        Tracer {
+            out,
+            format,
+            filter,
+        }
+    }
+
+    /// Writes the entry, unless the filter leaves it out.
+    pub fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        if !self.filter.matches(entry) {
+            return Ok(());
+        }
+        match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", entry.to_text()),
+            TraceFormat::Jsonl => writeln!(self.out, "{}", entry.to_json()),
+        }
+    }
+
+    pub fn write_edit(&mut self, edit: &Edit) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", edit.to_text()),
+            TraceFormat::Jsonl => writeln!(self.out, "{}", edit.to_json()),
+        }
+    }
+}
+
+/// A change made to the machine from outside the program, by the debugger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit<'a> {
+    /// Bytes written into memory from `addr`.
+    Poke { addr: usize, bytes: &'a [u8] },
+    /// A register, by the name the monitor gives it, set to a value.
+    Set { register: String, value: usize },
+}
+
+impl Edit<'_> {
+    pub fn to_text(&self) -> String {
+        match self {
+            Edit::Poke { addr, bytes } => {
+                let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                format!("poke {:03X}: {}", addr, bytes.join(" "))
+            }
+            Edit::Set { register, value } => format!("set {}: {:X}", register, value),
+        }
+    }
+
+    /// Formats the edit as a JSON object of the new value at each address or register.
+    pub fn to_json(&self) -> String {
+        match self {
+            Edit::Poke { addr, bytes } => {
+                let bytes: Vec<String> = (*addr..)
+                    .zip(bytes.iter())
+                    .map(|(addr, b)| format!("\"{}\":{}", addr, b))
+                    .collect();
+                format!("{{\"poke\":{{{}}}}}", bytes.join(","))
+            }
+            Edit::Set { register, value } => {
+                format!("{{\"set\":{{\"{}\":{}}}}}", register, value)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_entries() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&[0x6A, 0x2C, 0xA2, 0x00]);
+        let symbols = Symbols::parse("main 0x200").unwrap();
+        let executed = chip8.step().unwrap().unwrap();
+        let entry = Entry::new(&executed, &chip8, &symbols);
+        assert_eq!(
+            entry.to_text(),
+            "200: 6A2C  LD VA, 0x2C          VA=2C I=000 DT=00 ST=00"
+        );
+        assert_eq!(
+            entry.to_json(),
+            "{\"pc\":512,\"raw\":27180,\"mnemonic\":\"LD VA, 0x2C\",\"changed\":{\"VA\":44},\
+             \"i\":0,\"dt\":0,\"st\":0}"
+        );
+
+        let executed = chip8.step().unwrap().unwrap();
+        let entry = Entry::new(&executed, &chip8, &symbols);
+        assert_eq!(entry.changed, vec![]);
+        assert_eq!(entry.mnemonic, "LD I, main");
+        assert!(entry.to_json().contains("\"changed\":{},\"i\":512"));
+    }
+
+    #[test]
+    fn filters_entries() {
+        let entry = |pc: usize, mnemonic: &str| Entry {
+            pc,
+            raw: 0,
+            mnemonic: mnemonic.to_string(),
+            changed: vec![],
+            i_addr: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+        };
+        assert!(TraceFilter::default().matches(&entry(0x300, "CLS")));
+        let filter = TraceFilter {
+            pc: Some(TraceFilter::parse_pc("0x200..0x260").unwrap()),
+            ops: Some(TraceFilter::parse_ops("drw,CALL, RET").unwrap()),
+        };
+        assert!(filter.matches(&entry(0x200, "DRW V0, V1, 5")));
+        assert!(filter.matches(&entry(0x25E, "CALL draw_score")));
+        assert!(!filter.matches(&entry(0x260, "RET")));
+        assert!(!filter.matches(&entry(0x204, "LD VA, 0x2C")));
+        assert!(TraceFilter::parse_pc("0x260..0x200").is_err());
+        assert!(TraceFilter::parse_pc("0x200").is_err());
+        assert!(TraceFilter::parse_ops("DRW,JMP").is_err());
+    }
+
+    #[test]
+    fn notes_edits() {
+        let poke = Edit::Poke {
+            addr: 0x300,
+            bytes: &[0xFF, 0x0A],
+        };
+        assert_eq!(poke.to_text(), "poke 300: FF 0A");
+        assert_eq!(poke.to_json(), "{\"poke\":{\"768\":255,\"769\":10}}");
+        let set = Edit::Set {
+            register: "VA".to_string(),
+            value: 0x2C,
+        };
+        assert_eq!(set.to_text(), "set VA: 2C");
+        assert_eq!(set.to_json(), "{\"set\":{\"VA\":44}}");
+    }
+
+    #[test]
+    fn parses_json_entries() {
+        let entry = Entry {
+            pc: 0x2A4,
+            raw: 0x2300,
+            mnemonic: "CALL \"odd\\name\"".to_string(),
+            changed: vec![(0, 1), (0xF, 0xFF)],
+            i_addr: 0x123,
+            delay_timer: 3,
+            sound_timer: 0,
+        };
+        assert_eq!(Entry::parse_json(&entry.to_json()), Ok(entry));
+        assert!(Entry::parse_json("{\"pc\":512}").is_err());
+        assert!(Entry::parse_json("not json").is_err());
+    }
+}
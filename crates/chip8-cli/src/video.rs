@@ -0,0 +1,173 @@
+//! Gameplay video (`--video <file>`), one video frame per emulated frame. `-` or a `.y4m`
+//! file gets the raw frames as YUV4MPEG2, which players and encoders read directly, e.g.
+//! `chip8 --video - PONG | ffmpeg -i - pong.mp4`. Any other file is handed to ffmpeg to
+//! encode, along with the buzzer as an audio track, so its extension picks the format.
+use crate::chip8::{Chip8, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::clock::FRAME_RATE;
+use crate::tone::{Beep, Synth, Tone};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+const SAMPLE_RATE: u32 = 44100;
+/// How many times larger ffmpeg scales the frames, since players blur a 64x32 video.
+const ENCODE_SCALE: usize = 10;
+
+/// Writes frames as they're run.
+pub struct Video {
+    frames: Box<dyn Write>,
+    /// Set when encoding with ffmpeg, which can only start once the frames and audio are
+    /// both written.
+    encode: Option<Encode>,
+}
+
+/// Where ffmpeg's inputs go while recording, and where its output goes after.
+struct Encode {
+    output: String,
+    frames_path: PathBuf,
+    synth: Synth,
+    samples: Vec<i16>,
+}
+
+impl Video {
+    pub fn create(path: &str, beep: Beep) -> Result<Video, Box<dyn Error>> {
+        if path == "-" {
+            return Video::new(Box::new(BufWriter::new(io::stdout())), None);
+        }
+        if path.ends_with(".y4m") {
+            return Video::new(Box::new(BufWriter::new(File::create(path)?)), None);
+        }
+        let frames_path = PathBuf::from(format!("{}.y4m.tmp", path));
+        let encode = Encode {
+            output: path.to_string(),
+            frames_path: frames_path.clone(),
+            synth: Synth::new(SAMPLE_RATE, beep),
+            samples: Vec::new(),
+        };
+        let frames = BufWriter::new(File::create(&frames_path)?);
+        Video::new(Box::new(frames), Some(encode))
+    }
+
+    fn new(mut frames: Box<dyn Write>, encode: Option<Encode>) -> Result<Video, Box<dyn Error>> {
+        // 4:4:4 chroma, so pixels keep their exact colors
+        writeln!(
+            frames,
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+            SCREEN_WIDTH, SCREEN_HEIGHT, FRAME_RATE
+        )?;
+        Ok(Video { frames, encode })
+    }
+
+    /// Adds the frame the machine just finished, and its share of the buzzer.
+    pub fn frame(&mut self, chip8: &Chip8) -> io::Result<()> {
+        let yuv: Vec<[u8; 3]> = chip8.framebuffer().iter().map(|c| to_yuv(*c)).collect();
+        self.frames.write_all(b"FRAME\n")?;
+        for plane in 0..3 {
+            let bytes: Vec<u8> = yuv.iter().map(|pixel| pixel[plane]).collect();
+            self.frames.write_all(&bytes)?;
+        }
+        if let Some(ref mut encode) = self.encode {
+            let (pattern, pitch) = chip8.audio_pattern();
+            let on = chip8.sound_timer() > 0;
+            encode.synth.tone = Tone { pattern, pitch, on };
+            for _ in 0..SAMPLE_RATE / FRAME_RATE {
+                let sample = encode.synth.sample();
+                encode.samples.push((sample * f32::from(i16::MAX)) as i16);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes the frames, and encodes them with ffmpeg if the output isn't raw.
+    pub fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        self.frames.flush()?;
+        drop(self.frames);
+        let encode = match self.encode {
+            Some(encode) => encode,
+            None => return Ok(()),
+        };
+        let audio_path = PathBuf::from(format!("{}.wav.tmp", encode.output));
+        write_wav(
+            &mut BufWriter::new(File::create(&audio_path)?),
+            &encode.samples,
+        )?;
+        let scale = format!(
+            "scale=iw*{}:ih*{}:flags=neighbor",
+            ENCODE_SCALE, ENCODE_SCALE
+        );
+        let status = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-f", "yuv4mpegpipe", "-i"])
+            .arg(&encode.frames_path)
+            .args(["-f", "wav", "-i"])
+            .arg(&audio_path)
+            .args(["-vf", &scale, "-pix_fmt", "yuv420p", "-shortest"])
+            .arg(&encode.output)
+            .status();
+        let _ = fs::remove_file(&encode.frames_path);
+        let _ = fs::remove_file(&audio_path);
+        match status {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(format!("ffmpeg failed ({})", status).into()),
+            Err(e) => Err(format!("Could not run ffmpeg: {}", e).into()),
+        }
+    }
+}
+
+/// Converts a display color to studio-range BT.601 YUV, as video expects.
+fn to_yuv(color: u32) -> [u8; 3] {
+    let [_, r, g, b] = color.to_be_bytes();
+    let (r, g, b) = (i32::from(r), i32::from(g), i32::from(b));
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    [y as u8, u as u8, v as u8]
+}
+
+/// Writes 16-bit mono samples as a WAV file.
+fn write_wav<W: Write>(out: &mut W, samples: &[i16]) -> io::Result<()> {
+    let data_len = (samples.len() * 2) as u32;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // PCM, one channel
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        out.write_all(&sample.to_le_bytes())?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn writes_y4m_frames() {
+        assert_eq!(to_yuv(0x000000), [16, 128, 128]);
+        assert_eq!(to_yuv(0xFFFFFF), [235, 128, 128]);
+
+        let path = env::temp_dir().join("chip8-video-test.y4m");
+        let mut video = Video::create(path.to_str().unwrap(), Beep::default()).unwrap();
+        let chip8 = Chip8::default();
+        video.frame(&chip8).unwrap();
+        video.frame(&chip8).unwrap();
+        video.finish().unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let header = b"YUV4MPEG2 W64 H32 F60:1 Ip A1:1 C444\n";
+        assert!(data.starts_with(header));
+        let frame_len = b"FRAME\n".len() + SCREEN_WIDTH * SCREEN_HEIGHT * 3;
+        assert_eq!(data.len(), header.len() + 2 * frame_len);
+    }
+}
@@ -0,0 +1,627 @@
+//! The emulator in a window: the game with its overlays, and hotkeys for save states, speed,
+//! and zoom, with the debugger and monitor alongside. Only built with the `gui` feature.
+use crate::browser::Browser;
+use crate::clock::{self, Pacing, VsyncPacer};
+use crate::debugger;
+use crate::keymap;
+use crate::keypad::Keypad;
+use crate::layout::{self, Layout};
+use crate::monitor::{self, Command};
+use crate::options::Options;
+use crate::perf::{self, PerfStats};
+use crate::render::{self, CollisionGlow, Frame, Renderer};
+use crate::screenshot::Screenshot;
+use crate::session::Session;
+use crate::slowdraw::SlowDraw;
+use crate::speed::SpeedMeter;
+use crate::{load_rom, memory_dump_path, save_breakpoints, save_flags, storage, Chip8};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Hotkeys for the numbered save state slots. Shift+key saves, the key alone loads.
+const SLOT_KEYS: [Key; 4] = [Key::F1, Key::F2, Key::F3, Key::F4];
+/// Restarts the loaded ROM.
+const RESET_KEY: Key = Key::F8;
+/// Reads the ROM from disk again (reassembling Octo source) and restarts it.
+const RELOAD_KEY: Key = Key::F9;
+/// Saves the screen as a PNG in the current directory.
+const SCREENSHOT_KEY: Key = Key::F12;
+/// Writes the 4KB of memory to a file in the current directory, for a hex editor.
+const MEMORY_DUMP_KEY: Key = Key::F10;
+/// Hold to run faster or slower. Speeds are percentages of normal speed.
+const TURBO_KEY: Key = Key::Tab;
+const TURBO_SPEED: u32 = 800;
+/// While turbo is held, only every this many window updates draw, unless `--turbo-skip` says
+/// otherwise.
+const TURBO_SKIP: u32 = 4;
+const SLOW_MOTION_KEY: Key = Key::Backquote;
+const SLOW_MOTION_SPEED: u32 = 10;
+/// Turns slow draw on and off, playing back each sprite drawn a line at a time.
+const SLOW_DRAW_KEY: Key = Key::Backslash;
+/// Shows and hides the performance stats.
+const PERF_KEY: Key = Key::Backspace;
+/// Silences the buzzer, or lets it be heard again.
+#[cfg(feature = "audio")]
+const MUTE_KEY: Key = Key::Insert;
+/// Make the window a scale larger or smaller, on the main keyboard or the numpad.
+const ZOOM_IN_KEYS: [Key; 2] = [Key::Equal, Key::NumPadPlus];
+const ZOOM_OUT_KEYS: [Key; 2] = [Key::Minus, Key::NumPadMinus];
+/// Hotkeys for the debugger, active when running with `--debug`.
+const PAUSE_KEY: Key = Key::F5;
+const STEP_KEY: Key = Key::F6;
+const STEP_BACK_KEY: Key = Key::F7;
+/// Steps over a call, or with shift held, out of the current subroutine.
+const STEP_OVER_KEY: Key = Key::F11;
+/// How long to sleep between window updates while the game can't make progress (waiting
+/// for a key or stuck in a loop), instead of spinning through frames that change nothing.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs the loaded ROM in a window until it's closed, then writes out what the options ask
+/// for on exit.
+pub fn run(
+    options: Options,
+    mut session: Session,
+    rom_path: &str,
+    mut data: Vec<u8>,
+    mut rom_hash: u64,
+    instructions_per_frame: Option<u32>,
+) -> Result<(), Box<dyn Error>> {
+    let mut layout = layout_for(&options);
+    let key_map = keymap::keypad_keys(options.keyboard, options.keys_by_character);
+
+    // Movies always start from power-on, so resuming would throw them off
+    let autosave = options.autosave && !session.is_recording_or_playing();
+    if autosave && options.from_screenshot.is_none() {
+        offer_resume(&mut session, rom_hash)?;
+    }
+
+    let rom_name = Path::new(rom_path).file_name().map_or_else(
+        || rom_path.to_string(),
+        |n| n.to_string_lossy().into_owned(),
+    );
+    let mut title = format!("{} - ESC to exit", rom_name);
+    let mut window = open_window(&title, &layout)?;
+    let mut renderer = Renderer::spawn();
+
+    let mut monitor_input = if options.monitor {
+        println!("{}", monitor::HELP);
+        print_prompt()?;
+        Some(monitor::spawn_reader())
+    } else {
+        None
+    };
+
+    // Start update loop
+    let mut last_update = Instant::now();
+    let mut vsync = if options.vsync {
+        Some(VsyncPacer::default())
+    } else {
+        None
+    };
+    let mut speed_meter = SpeedMeter::new(last_update, session.frame);
+    #[cfg(feature = "audio")]
+    let mut audio = crate::audio::Audio::start(options.beep)
+        .map_err(|e| tracing::warn!("No sound: {}", e))
+        .ok();
+    #[cfg(feature = "rumble")]
+    let mut rumble = crate::rumble::Rumble::start()
+        .map_err(|e| tracing::warn!("No rumble: {}", e))
+        .ok();
+    #[cfg(feature = "metrics")]
+    let mut metrics = options
+        .metrics
+        .map(|secs| crate::metrics::Metrics::new(Duration::from_secs(secs), last_update));
+    let mut last_overlays = None;
+    let turbo_skip = options.turbo_skip.unwrap_or(TURBO_SKIP);
+    // Updates in a row that left drawing for later while fast-forwarding
+    let mut skipped_draws = 0;
+    let mut collision_glow = CollisionGlow::default();
+    let mut slow_draw = SlowDraw::default();
+    // Every frame runs the same number of instructions (barring breakpoints), so the frame
+    // count doubles as an instruction count
+    let instructions_run =
+        |session: &Session| session.frame * u64::from(instructions_per_frame.unwrap_or(1));
+    let mut perf_stats = options
+        .perf
+        .then(|| PerfStats::new(last_update, instructions_run(&session)));
+    // The last report, shown until the next one
+    let mut perf_lines = perf_stats.as_ref().map(|_| vec!["MEASURING".to_string()]);
+    let mut render_busy = renderer.busy_time();
+    // Why the game stopped, shown over the display until it's reset or the window closed
+    let mut crash: Option<String> = None;
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let keys = read_keys(&window, &key_map, layout.keypad);
+
+        // minifb can't resize the buffer of an open window, so zooming opens a new one
+        let zoom = if ZOOM_IN_KEYS
+            .iter()
+            .any(|k| window.is_key_pressed(*k, KeyRepeat::No))
+        {
+            layout.zoom(1)
+        } else if ZOOM_OUT_KEYS
+            .iter()
+            .any(|k| window.is_key_pressed(*k, KeyRepeat::No))
+        {
+            layout.zoom(-1)
+        } else {
+            None
+        };
+        if let Some(zoomed) = zoom {
+            layout = zoomed;
+            window = open_window(&title, &layout)?;
+            last_overlays = None;
+        }
+
+        let shift_down = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        for (i, k) in SLOT_KEYS.iter().enumerate() {
+            if window.is_key_pressed(*k, KeyRepeat::No) {
+                let slot = i + 1;
+                let result = if session.is_recording_or_playing() && !shift_down {
+                    Err("Cannot load states while a movie is recording or playing".into())
+                } else if shift_down {
+                    save_slot(&session.chip8, rom_hash, slot)
+                } else {
+                    slow_draw.clear();
+                    load_slot(&mut session, rom_hash, slot)
+                };
+                if let Err(e) = result {
+                    eprintln!("Save state slot {}: {}", slot, e);
+                }
+            }
+        }
+        let reset = window.is_key_pressed(RESET_KEY, KeyRepeat::No);
+        let reload = window.is_key_pressed(RELOAD_KEY, KeyRepeat::No);
+        if (reset || reload) && session.is_recording_or_playing() {
+            eprintln!("Cannot reset while a movie is recording or playing");
+        } else if reset {
+            session.reset(&data);
+            slow_draw.clear();
+            crash = None;
+            println!("Reset");
+        } else if reload {
+            match load_rom(rom_path) {
+                Ok(program) => {
+                    data = program.bytes;
+                    rom_hash = storage::rom_hash(&data);
+                    if options.symbols.is_none() {
+                        session.symbols = program.symbols;
+                    }
+                    if options.source_map.is_none() {
+                        session.source_map = program.source_map;
+                    }
+                    session.reset(&data);
+                    slow_draw.clear();
+                    crash = None;
+                    println!("Reloaded {}", rom_path);
+                }
+                // Keep running the old version so a typo doesn't end the session
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        if window.is_key_pressed(SCREENSHOT_KEY, KeyRepeat::No) {
+            if let Err(e) = save_screenshot(&session, rom_hash, &rom_name, options.screenshot_state)
+            {
+                eprintln!("Screenshot: {}", e);
+            }
+        }
+        if window.is_key_pressed(MEMORY_DUMP_KEY, KeyRepeat::No) {
+            let path = memory_dump_path(&rom_name, session.frame);
+            match fs::write(&path, session.chip8.memory()) {
+                Ok(()) => println!("Wrote memory to {}", path),
+                Err(e) => eprintln!("Memory dump: {}", e),
+            }
+        }
+        if window.is_key_pressed(SLOW_DRAW_KEY, KeyRepeat::No) {
+            if session.drawings.take().is_some() {
+                slow_draw.clear();
+                println!("Slow draw off");
+            } else {
+                session.drawings = Some(Vec::new());
+                println!("Slow draw on");
+            }
+        }
+        #[cfg(feature = "audio")]
+        if window.is_key_pressed(MUTE_KEY, KeyRepeat::No) {
+            if let Some(ref mut audio) = audio {
+                println!("Sound {}", if audio.toggle_mute() { "off" } else { "on" });
+            }
+        }
+        if window.is_key_pressed(PERF_KEY, KeyRepeat::No) {
+            if perf_stats.take().is_some() {
+                perf_lines = None;
+            } else {
+                perf_stats = Some(PerfStats::new(Instant::now(), instructions_run(&session)));
+                perf_lines = Some(vec!["MEASURING".to_string()]);
+                render_busy = renderer.busy_time();
+            }
+        }
+        handle_debug_keys(&mut session, &window, &keys)?;
+        if let Some(ref rx) = monitor_input {
+            match rx.try_recv() {
+                Ok(line) => {
+                    match Command::parse(&line, &session.symbols) {
+                        Ok(cmd) => println!("{}", session.run_monitor_command(cmd, &keys)?),
+                        Err(e) => println!("{}", e),
+                    }
+                    print_prompt()?;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => monitor_input = None,
+            }
+        }
+
+        // Run Chip-8 emulator at 60 frames per second, by handing it the time elapsed since
+        // the last update. Input is applied per frame (rather than per window update) so that
+        // movies can reproduce exactly which frame saw which keys.
+        // With --vsync, the time handed over is one display refresh per update instead.
+        // Holding turbo or slow motion scales the time fed in, so the timers speed up and
+        // slow down in step with the CPU.
+        let turbo = window.is_key_down(TURBO_KEY);
+        let speed = if turbo {
+            TURBO_SPEED
+        } else if window.is_key_down(SLOW_MOTION_KEY) {
+            SLOW_MOTION_SPEED
+        } else {
+            100
+        };
+        let now = Instant::now();
+        let elapsed = match vsync {
+            Some(ref mut pacer) => {
+                let calibrating = pacer.pacing() == Pacing::Calibrating;
+                let dt = pacer.update(now);
+                match pacer.pacing() {
+                    Pacing::Locked(hz) if calibrating => tracing::info!("Locked to {}Hz vsync", hz),
+                    Pacing::Unsynced if calibrating => tracing::info!(
+                        "Window updates don't wait for vsync here, pacing to {}Hz instead",
+                        clock::FRAME_RATE
+                    ),
+                    _ => {}
+                }
+                dt
+            }
+            None => now.duration_since(last_update),
+        };
+        // The time spent in the background is dropped rather than caught up on
+        let away = options.pause_unfocused && !window.is_active();
+        let frame_before = session.frame;
+        // The game waits while slow draw plays back what it drew
+        let running = !away && crash.is_none() && !slow_draw.is_playing();
+        // Time the timers should have ticked for, to measure drift against
+        let real_time = match running && !session.is_paused() {
+            true => now.duration_since(last_update) * speed / 100,
+            false => Duration::ZERO,
+        };
+        if running {
+            if let Err(e) = session.advance(elapsed * speed / 100, &keys) {
+                tracing::error!("{}", e);
+                title = format!("{} - crashed, F8 to reset, ESC to exit", rom_name);
+                window.set_title(&title);
+                crash = Some(e.to_string());
+                last_overlays = None;
+            }
+        }
+        // Saved as soon as they change, so a high score survives a crash or a kill
+        if let Err(e) = save_flags(&mut session.chip8, rom_hash) {
+            eprintln!("Saving flags: {}", e);
+        }
+        let emulated = Instant::now();
+        if let Some(ref mut drawings) = session.drawings {
+            slow_draw.push(std::mem::take(drawings));
+        }
+        #[cfg(any(feature = "audio", feature = "rumble"))]
+        let sounding = session.chip8.sound_timer() > 0 && !session.is_paused() && !away;
+        #[cfg(feature = "audio")]
+        if let Some(ref mut audio) = audio {
+            let (pattern, pitch) = session.chip8.audio_pattern();
+            audio.set_tone(crate::tone::Tone {
+                pattern,
+                pitch,
+                on: sounding,
+            });
+        }
+        #[cfg(feature = "rumble")]
+        if let Some(ref mut rumble) = rumble {
+            rumble.set(sounding);
+        }
+
+        // Only redraw when the game drew something or an overlay changed. Drawing happens on
+        // the render thread, and whatever it has finished since the last update is presented
+        // here. Otherwise the window just processes events, skipping the upload of an
+        // unchanged buffer. With --vsync every update presents, since presenting is what
+        // waits for the display. Turbo draws only every few updates, as the frames go by
+        // too fast to follow anyway, and drawing would take time from running them.
+        let beeping = options.visual_beep && session.chip8.sound_timer() > 0;
+        // Resets and reloads start the frame count over, which leaves the glow as it was
+        let frames = session.frame.saturating_sub(frame_before);
+        collision_glow.update(frames, &session.chip8.take_collisions());
+        let collisions = collision_glow.pixels();
+        let playback = slow_draw.screen(now, session.chip8.palette());
+        let overlays = (
+            keys,
+            *session.chip8.keys_down(),
+            beeping,
+            collisions.clone(),
+            playback.is_some(),
+            perf_lines.clone(),
+        );
+        let display_dirty = session.chip8.take_display_dirty() || skipped_draws > 0;
+        let wanted =
+            display_dirty || playback.is_some() || last_overlays.as_ref() != Some(&overlays);
+        let skipped = wanted && turbo && skipped_draws + 1 < turbo_skip;
+        skipped_draws = if skipped { skipped_draws + 1 } else { 0 };
+        let rendered = wanted && !skipped;
+        if rendered {
+            renderer.submit(Frame {
+                layout,
+                screen: playback.unwrap_or_else(|| session.chip8.framebuffer().to_vec()),
+                keys,
+                shown_keys: Some(*session.chip8.keys_down()).filter(|_| options.show_keys),
+                beeping,
+                collisions,
+                message: crash.clone(),
+                stats: perf_lines.clone(),
+            });
+            last_overlays = Some(overlays);
+        }
+        let fresh = renderer.take_rendered();
+        // Right after zooming, buffers drawn for the old window size can still come through
+        let fits = renderer.buffer().len() == layout.window_width() * layout.height();
+        let presenting = Instant::now();
+        if fits && (fresh || vsync.is_some()) {
+            window.update_with_buffer(renderer.buffer())?;
+        } else {
+            window.update();
+        }
+        let instructions = instructions_run(&session);
+        if let Some(ref mut stats) = perf_stats {
+            let busy = renderer.busy_time();
+            let update = perf::Update {
+                emulation: emulated.duration_since(now),
+                render: busy - render_busy,
+                present: presenting.elapsed(),
+                frames: session.frame.saturating_sub(frame_before),
+                real_time,
+            };
+            render_busy = busy;
+            if let Some(report) = stats.update(now, &update, instructions) {
+                eprintln!("{}", report);
+                perf_lines = Some(report.lines());
+            }
+        }
+        let measured = speed_meter.frame(now, instructions);
+        if let Some(speed) = measured.filter(|_| crash.is_none()) {
+            title = format!(
+                "{} - {} IPS, {} FPS - ESC to exit",
+                rom_name, speed.ips, speed.fps
+            );
+            window.set_title(&title);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(ref mut metrics) = metrics {
+            let frame_time = now.duration_since(last_update);
+            let collisions = session.chip8.collisions();
+            if let Some(report) = metrics.frame(now, frame_time, rendered, instructions, collisions)
+            {
+                eprintln!("{}", report);
+            }
+        }
+        last_update = now;
+
+        // Keys are still polled, just less often, and the time slept is caught up on in the
+        // next update so the timers keep their pace. That doesn't hold with --vsync, which
+        // only counts updates.
+        let chip8 = &session.chip8;
+        let idle = (chip8.is_waiting_for_key() || chip8.is_halted() || away || crash.is_some())
+            && !slow_draw.is_playing();
+        if let Some(ref pacer) = vsync {
+            thread::sleep(pacer.wait(Instant::now()));
+        } else if idle && !beeping && !session.is_paused() {
+            thread::sleep(IDLE_POLL_INTERVAL);
+        }
+    }
+
+    // Resuming a crashed game would only crash it again
+    if autosave && crash.is_none() {
+        storage::write(
+            &storage::autosave_path(rom_hash)?,
+            &session.chip8.save_state(),
+        )?;
+    }
+    if let Some(ref debugger) = session.debugger {
+        if let Err(e) = save_breakpoints(debugger, rom_hash) {
+            tracing::warn!("Could not save breakpoints: {}", e);
+        }
+    }
+    if let (Some(path), Some(r)) = (options.record, session.recorder) {
+        fs::write(path, r.finish().to_string())?;
+    }
+    if let (Some(path), Some(p)) = (options.profile, session.profiler) {
+        fs::write(path, p.to_callgrind(&session.symbols))?;
+    }
+    if let (Some(path), Some(d)) = (options.draw_stats, session.draws) {
+        fs::write(path, d.report(session.frame, &session.symbols))?;
+    }
+    if let Some(video) = session.video {
+        video.finish()?;
+    }
+
+    match crash {
+        Some(message) => Err(message.into()),
+        None => Ok(()),
+    }
+}
+
+/// Handles the debugger hotkeys, printing the machine state whenever it changes while paused.
+fn handle_debug_keys(
+    session: &mut Session,
+    window: &Window,
+    keys: &[bool; 16],
+) -> Result<(), Box<dyn Error>> {
+    let paused = match session.debugger {
+        Some(ref d) => d.is_paused(),
+        None => return Ok(()),
+    };
+
+    if window.is_key_pressed(PAUSE_KEY, KeyRepeat::No) {
+        session.debugger.as_mut().unwrap().set_paused(!paused);
+        if paused {
+            println!("Resumed");
+        } else {
+            println!(
+                "Paused\n{}",
+                debugger::describe(&session.chip8, &session.symbols, &session.source_map)
+            );
+        }
+    } else if paused && window.is_key_pressed(STEP_KEY, KeyRepeat::Yes) {
+        session.run_instruction(keys)?;
+        println!(
+            "{}",
+            debugger::describe(&session.chip8, &session.symbols, &session.source_map)
+        );
+    } else if paused && window.is_key_pressed(STEP_OVER_KEY, KeyRepeat::No) {
+        let shift_down = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        let output = if shift_down {
+            session.step_out()?
+        } else {
+            session.step_over(keys)?
+        };
+        println!("{}", output);
+    } else if paused && window.is_key_pressed(STEP_BACK_KEY, KeyRepeat::Yes) {
+        if session.is_recording_or_playing() {
+            eprintln!("Cannot step back while a movie is recording or playing");
+            return Ok(());
+        }
+        if session.step_back()? {
+            println!(
+                "{}\n({} more frames of history)",
+                debugger::describe(&session.chip8, &session.symbols, &session.source_map),
+                session.debugger.as_mut().unwrap().history().len()
+            );
+        } else {
+            println!("No more history to step back through");
+        }
+    }
+    Ok(())
+}
+
+fn layout_for(options: &Options) -> Layout {
+    Layout::new(
+        options.scale.unwrap_or(layout::DEFAULT_SCALE),
+        layout::display_scale_factor(),
+        options.keypad,
+    )
+}
+
+/// The hex keys held on the keyboard, plus the one clicked on the keypad panel if it is shown.
+fn read_keys(window: &Window, key_map: &[Key; 16], keypad: Option<Keypad>) -> [bool; 16] {
+    let mut keys = [false; 16];
+    for (i, k) in key_map.iter().enumerate() {
+        keys[i] = window.is_key_down(*k);
+    }
+    if let Some(ref keypad) = keypad {
+        if window.get_mouse_down(MouseButton::Left) {
+            let clicked = window
+                .get_mouse_pos(MouseMode::Discard)
+                .and_then(|(x, y)| keypad.key_at(x as usize, y as usize));
+            if let Some(key) = clicked {
+                keys[key as usize] = true;
+            }
+        }
+    }
+    keys
+}
+
+/// Opens a window sized for the layout. Its buffer is drawn one to one, since the layout
+/// already accounts for the scale.
+fn open_window(title: &str, layout: &Layout) -> Result<Window, minifb::Error> {
+    Window::new(
+        title,
+        layout.window_width(),
+        layout.height(),
+        WindowOptions::default(),
+    )
+}
+
+/// Shows the ROM browser in its own window until a ROM is picked with the keypad, or None if
+/// the window is closed first.
+pub fn browse_roms(dir: &str, options: &Options) -> Result<Option<String>, Box<dyn Error>> {
+    let layout = &layout_for(options);
+    let key_map = &keymap::keypad_keys(options.keyboard, options.keys_by_character);
+    let palette = &options.palette;
+    let mut browser = Browser::scan(Path::new(dir))?;
+    let mut buffer: Vec<u32> = vec![0; layout.window_width() * layout.height()];
+    let mut window = open_window(
+        "Choose a ROM (5/8 to move, 6 to play) - ESC to exit",
+        layout,
+    )?;
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let keys = read_keys(&window, key_map, layout.keypad);
+        if let Some(path) = browser.press(&keys) {
+            return Ok(Some(path.to_string_lossy().into_owned()));
+        }
+        let screen: Vec<u32> = browser.render().iter().map(|p| palette.color(*p)).collect();
+        render::draw_display(&mut buffer, layout, &screen);
+        if let Some(ref keypad) = layout.keypad {
+            keypad.draw(&mut buffer, layout.window_width(), &keys);
+        }
+        window.update_with_buffer(&buffer)?;
+    }
+    Ok(None)
+}
+
+fn print_prompt() -> io::Result<()> {
+    print!("> ");
+    io::stdout().flush()
+}
+
+fn save_slot(chip8: &Chip8, rom_hash: u64, slot: usize) -> Result<(), Box<dyn Error>> {
+    storage::write(&storage::slot_path(rom_hash, slot)?, &chip8.save_state())?;
+    println!("Saved state to slot {}", slot);
+    Ok(())
+}
+
+fn load_slot(session: &mut Session, rom_hash: u64, slot: usize) -> Result<(), Box<dyn Error>> {
+    session.load_state(&fs::read(storage::slot_path(rom_hash, slot)?)?)?;
+    println!("Loaded state from slot {}", slot);
+    Ok(())
+}
+
+fn save_screenshot(
+    session: &Session,
+    rom_hash: u64,
+    rom_name: &str,
+    with_state: bool,
+) -> Result<(), Box<dyn Error>> {
+    let path = format!("{}-{}.png", rom_name, session.frame);
+    let shot = Screenshot::of(&session.chip8, rom_hash, session.frame, with_state);
+    let mut file = io::BufWriter::new(File::create(&path)?);
+    shot.write_png(&mut file, &session.chip8)?;
+    file.flush()?;
+    println!("Saved screenshot to {}", path);
+    Ok(())
+}
+
+/// Asks on the terminal whether to resume from the autosave left behind by a previous run
+/// of the same ROM, if there is one.
+fn offer_resume(session: &mut Session, rom_hash: u64) -> Result<(), Box<dyn Error>> {
+    let state = match fs::read(storage::autosave_path(rom_hash)?) {
+        Ok(state) => state,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    print!("Found an autosave for this ROM. Resume? [Y/n] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("n") {
+        session.load_state(&state)?;
+    }
+    Ok(())
+}
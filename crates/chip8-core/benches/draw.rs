@@ -0,0 +1,37 @@
+//! Times sprite drawing, which dominates draw-heavy ROMs at turbo speeds. Run with
+//! `cargo bench -p chip8-core`.
+use chip8_core::chip8::Chip8;
+use chip8_core::quirks::Quirks;
+use std::time::Instant;
+
+const FRAMES: u32 = 2_000;
+const INSTRUCTIONS_PER_FRAME: u32 = 1_000;
+
+fn main() {
+    // Draw 15 rows of the font all over the screen, forever:
+    // DRW V0, V1, 15; ADD V0, 7; ADD V1, 3; JP 0x200
+    let program = [0xD0, 0x1F, 0x70, 0x07, 0x71, 0x03, 0x12, 0x00];
+    for quirks in ["", "wrap-sprites"] {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_quirks(Quirks::parse(quirks).unwrap());
+        chip8.set_instructions_per_frame(Some(INSTRUCTIONS_PER_FRAME));
+        chip8.load_program(&program);
+        let start = Instant::now();
+        for _ in 0..FRAMES {
+            chip8.run_frame().unwrap();
+        }
+        let elapsed = start.elapsed();
+        // One instruction in four draws
+        let draws = f64::from(FRAMES * INSTRUCTIONS_PER_FRAME / 4);
+        println!(
+            "{:<14} {:>8.1} ms  {:>6.1} ns per draw",
+            if quirks.is_empty() {
+                "clipping"
+            } else {
+                quirks
+            },
+            elapsed.as_secs_f64() * 1000.0,
+            elapsed.as_secs_f64() * 1e9 / draws
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! Runs arbitrary bytes as a ROM. The first byte picks the quirks and the next two the held
+//! keys, and the machine runs until it faults or has been going for a few seconds.
+#![no_main]
+use chip8_core::chip8::Chip8;
+use chip8_core::quirks::Quirks;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 3 {
+        return;
+    }
+    let (settings, rom) = data.split_at(3);
+    let bit = |n: u8| settings[0] & 1 << n != 0;
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.set_quirks(Quirks {
+        legacy_flags: bit(0),
+        memory_increment: bit(1),
+        exclusive_range: bit(2),
+        shift_vy: bit(3),
+        jump_vx: bit(4),
+        wrap_sprites: bit(5),
+        vf_reset: bit(6),
+        memory_stack: bit(7),
+    });
+    chip8.track_memory_access();
+    chip8.load_program(rom);
+    let keys = u16::from_be_bytes([settings[1], settings[2]]);
+    for key in 0..16 {
+        if keys & 1 << key != 0 {
+            chip8.set_key_down(key);
+        }
+    }
+    for _ in 0..300 {
+        if chip8.tick().is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,21 @@
+// A curved CRT tube with scanlines between the rows of pixels.
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+@group(2) @binding(0) var screen: texture_2d<f32>;
+@group(2) @binding(1) var screen_sampler: sampler;
+
+const BULGE: vec2<f32> = vec2<f32>(5.0, 4.0);
+const SCANLINE: f32 = 0.35;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    var uv = in.uv * 2.0 - 1.0;
+    let offset = abs(uv.yx) / BULGE;
+    uv = (uv + uv * offset * offset) * 0.5 + 0.5;
+    let color = textureSample(screen, screen_sampler, uv);
+
+    let rows = f32(textureDimensions(screen).y);
+    let scanline = 1.0 - SCANLINE * pow(sin(uv.y * rows * 3.14159), 2.0);
+    let inside = all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0));
+    return select(vec4<f32>(0.0, 0.0, 0.0, 1.0), vec4<f32>(color.rgb * scanline, 1.0), inside);
+}
@@ -0,0 +1,25 @@
+// Lit pixels bleed light onto their neighbours, like phosphor.
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+@group(2) @binding(0) var screen: texture_2d<f32>;
+@group(2) @binding(1) var screen_sampler: sampler;
+
+const RADIUS: i32 = 2;
+const STRENGTH: f32 = 0.6;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(screen));
+    let color = textureSample(screen, screen_sampler, in.uv);
+    var glow = vec3<f32>(0.0);
+    var total = 0.0;
+    for (var y = -RADIUS; y <= RADIUS; y++) {
+        for (var x = -RADIUS; x <= RADIUS; x++) {
+            let weight = 1.0 / (1.0 + f32(x * x + y * y));
+            let uv = in.uv + vec2<f32>(f32(x), f32(y)) * texel * 0.5;
+            glow += textureSampleLevel(screen, screen_sampler, uv, 0.0).rgb * weight;
+            total += weight;
+        }
+    }
+    return vec4<f32>(max(color.rgb, glow / total * (1.0 + STRENGTH)), 1.0);
+}
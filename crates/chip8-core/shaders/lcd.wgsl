@@ -0,0 +1,16 @@
+// A handheld LCD, with dark gaps between the pixels.
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+@group(2) @binding(0) var screen: texture_2d<f32>;
+@group(2) @binding(1) var screen_sampler: sampler;
+
+const GAP: f32 = 0.12;
+const GAP_BRIGHTNESS: f32 = 0.25;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(screen, screen_sampler, in.uv);
+    let cell = fract(in.uv * vec2<f32>(textureDimensions(screen)));
+    let lit = step(GAP, cell.x) * step(GAP, cell.y);
+    return vec4<f32>(color.rgb * mix(GAP_BRIGHTNESS, 1.0, lit), 1.0);
+}
@@ -0,0 +1,11 @@
+// The screen as it is, and the starting point for new looks. The cabinet's screen is bound
+// with nearest filtering, and textureDimensions gives its size in CHIP-8 pixels.
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+@group(2) @binding(0) var screen: texture_2d<f32>;
+@group(2) @binding(1) var screen_sampler: sampler;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(screen, screen_sampler, in.uv);
+}
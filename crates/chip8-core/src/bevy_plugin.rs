@@ -0,0 +1,159 @@
+//! Working CHIP-8 machines inside Bevy games (`--features bevy`). Each `Cabinet` component
+//! runs its own machine in real time and draws its screen into an image, to put on a sprite
+//! or on a mesh's material as the cabinet's monitor:
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins((DefaultPlugins, Chip8Plugin))
+//!     .add_systems(Startup, |mut commands: Commands, mut images: ResMut<Assets<Image>>| {
+//!         let cabinet = Cabinet::new(include_bytes!("PONG"), &mut images).unwrap();
+//!         commands.spawn(SpriteBundle {
+//!             texture: cabinet.screen.clone(),
+//!             transform: Transform::from_scale(Vec3::splat(8.0)),
+//!             ..default()
+//!         });
+//!         commands.spawn(cabinet);
+//!     })
+//!     .run();
+//! ```
+use crate::chip8::{Chip8, Fault, SCREEN_HEIGHT, SCREEN_WIDTH};
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+use std::error::Error;
+
+/// The keys every new cabinet listens to. The rows 1-4, Q-R, A-F and Z-V stand where the
+/// rows of `KEYPAD_LAYOUT` do, so players used to the desktop emulator feel at home.
+#[rustfmt::skip]
+pub const DEFAULT_KEYS: [KeyCode; 16] = [KeyCode::KeyX, KeyCode::Digit1, KeyCode::Digit2,
+    KeyCode::Digit3, KeyCode::KeyQ, KeyCode::KeyW, KeyCode::KeyE,
+    KeyCode::KeyA, KeyCode::KeyS, KeyCode::KeyD,
+    KeyCode::KeyZ, KeyCode::KeyC,
+    KeyCode::Digit4, KeyCode::KeyR, KeyCode::KeyF, KeyCode::KeyV];
+
+/// Runs every `Cabinet`, once per frame.
+pub struct Chip8Plugin;
+
+impl Plugin for Chip8Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (press_keys, run_cabinets).chain());
+    }
+}
+
+#[derive(Component)]
+pub struct Cabinet {
+    pub chip8: Chip8,
+    /// The 64x32 screen, with nearest filtering so pixels stay sharp when stretched.
+    pub screen: Handle<Image>,
+    /// Keyboard keys for the keypad, from 0 to F.
+    pub keys: [KeyCode; 16],
+    /// Whether the keyboard plays this cabinet. Only the one the player is standing at
+    /// should, or every machine in the arcade reacts to the same keys.
+    pub focused: bool,
+    /// Why the cabinet went dark, if its program crashed. `Chip8Plugin` skips it while set.
+    pub fault: Option<Fault>,
+}
+
+impl Cabinet {
+    /// Powers on a machine with a ROM loaded, and adds an image for its screen.
+    pub fn new(rom: &[u8], images: &mut Assets<Image>) -> Result<Cabinet, Box<dyn Error>> {
+        let mut chip8 = Chip8::default();
+        chip8.try_load_program(rom)?;
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: SCREEN_WIDTH as u32,
+                height: SCREEN_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0, 0, 0, 0xFF],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        image.sampler = ImageSampler::nearest();
+        Ok(Cabinet {
+            chip8,
+            screen: images.add(image),
+            keys: DEFAULT_KEYS,
+            focused: true,
+            fault: None,
+        })
+    }
+
+    /// Whether the buzzer is sounding, for the game to play a sound from the cabinet.
+    pub fn beeping(&self) -> bool {
+        self.chip8.sound_timer() > 0
+    }
+}
+
+fn press_keys(keyboard: Res<ButtonInput<KeyCode>>, mut cabinets: Query<&mut Cabinet>) {
+    for mut cabinet in cabinets.iter_mut().filter(|c| c.focused) {
+        let keys = cabinet.keys;
+        for (key, code) in (0..16).zip(keys.iter()) {
+            if keyboard.pressed(*code) {
+                cabinet.chip8.set_key_down(key);
+            } else {
+                cabinet.chip8.set_key_up(key);
+            }
+        }
+    }
+}
+
+fn run_cabinets(
+    time: Res<Time>,
+    mut images: ResMut<Assets<Image>>,
+    mut cabinets: Query<&mut Cabinet>,
+) {
+    for mut cabinet in cabinets.iter_mut().filter(|c| c.fault.is_none()) {
+        if let Err(fault) = cabinet.chip8.advance(time.delta()) {
+            tracing::warn!(%fault, "cabinet crashed");
+            cabinet.fault = Some(fault);
+        }
+        if !cabinet.chip8.take_display_dirty() {
+            continue;
+        }
+        if let Some(image) = images.get_mut(&cabinet.screen) {
+            image.data = cabinet.chip8.render_to_image(1, cabinet.chip8.palette());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+    use std::time::Duration;
+
+    #[test]
+    fn runs_cabinets_with_keyboard() {
+        let mut app = App::new();
+        app.add_plugins(Chip8Plugin)
+            .init_resource::<Assets<Image>>()
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<Time>();
+        // Wait for a key, then draw its digit: LD V0, K; LD F, V0; DRW V1, V1, 5
+        let rom = [0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06];
+        let cabinet = Cabinet::new(&rom, &mut app.world_mut().resource_mut()).unwrap();
+        let screen = cabinet.screen.clone();
+        app.world_mut().spawn(cabinet);
+        let frame = |app: &mut App| {
+            app.world_mut()
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_millis(100));
+            app.update();
+        };
+        frame(&mut app);
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::Digit1);
+        frame(&mut app);
+
+        let images = app.world().resource::<Assets<Image>>();
+        let pixels = &images.get(&screen).unwrap().data;
+        // The top of the 1 is the third pixel from the left
+        let lit = (Palette::default().color(1) << 8 | 0xFF).to_be_bytes();
+        assert_eq!(pixels[2 * 4..3 * 4], lit);
+        assert_ne!(pixels[..4], lit);
+    }
+}
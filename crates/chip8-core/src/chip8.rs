@@ -0,0 +1,2560 @@
+use crate::clock::FrameClock;
+use crate::hash;
+use crate::heatmap::MemoryAccess;
+use crate::opcode::Opcode;
+use crate::palette::Palette;
+use crate::quirks::Quirks;
+use num_traits::FromPrimitive;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::fmt;
+use std::ops::Range;
+use std::time::Duration;
+
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+// Following font is pulled from: http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#0.1
+#[rustfmt::skip]
+pub const FONT: [u8; 5 * 16] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+const BASE_FONT_ADDRESS: usize = 0x000;
+/// Keys in the order they sit on the original COSMAC VIP keypad, for frontends that show one.
+#[rustfmt::skip]
+pub const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+/// Identifies a save state blob, followed by a version byte so the format can evolve.
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u8 = 3;
+/// Programs load at 0x200 and can fill the rest of memory.
+pub const MAX_PROGRAM_SIZE: usize = 0x1000 - 0x200;
+/// How many nested subroutine calls fit on the stack, as on the COSMAC VIP interpreter.
+pub const STACK_SIZE: usize = 16;
+/// How deep a growable stack goes before calls fault, so runaway recursion still stops. Save
+/// states keep the depth in a byte.
+pub const MAX_STACK_DEPTH: usize = 255;
+/// Where the stack's two-byte return addresses start with the memory stack quirk, in the
+/// area the COSMAC VIP interpreter kept its own variables.
+pub const STACK_ADDRESS: usize = 0xEA0;
+/// I is a 16-bit register, as in save states, so adding to it wraps around past 0xFFFF rather
+/// than growing without bound. Addresses past memory fault when used.
+const I_MASK: usize = 0xFFFF;
+/// How many flag registers Fx75 and Fx85 have, as in XO-CHIP (SCHIP had 8).
+pub const FLAG_COUNT: usize = 16;
+/// XO-CHIP's pitch register starts at 64, which plays the audio pattern at 4000 bits/second.
+const DEFAULT_PITCH: u8 = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Primitive)]
+pub enum Register {
+    V0 = 0,
+    V1 = 1,
+    V2 = 2,
+    V3 = 3,
+    V4 = 4,
+    V5 = 5,
+    V6 = 6,
+    V7 = 7,
+    V8 = 8,
+    V9 = 9,
+    VA = 10,
+    VB = 11,
+    VC = 12,
+    VD = 13,
+    VE = 14,
+    VF = 15,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "V{:X}", *self as u8)
+    }
+}
+
+/// Why the machine could not execute an instruction, and which instruction it was. Kept free
+/// of heap data so that running instructions never allocates.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    /// Where the instruction is. PC is left pointing at it.
+    pub pc: usize,
+    /// The raw instruction word.
+    pub word: u16,
+    /// What the word decodes as, or None if it isn't an instruction.
+    pub opcode: Option<Opcode>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+    InvalidOpcode,
+    StackUnderflow,
+    /// A call with the stack holding as many levels as it can.
+    StackOverflow {
+        levels: usize,
+    },
+    /// The instruction reached past the end of memory, reading or writing from I.
+    OutOfBounds {
+        i: usize,
+    },
+    /// PC ran past the last instruction in memory.
+    PcOutOfBounds,
+    /// Fx75 or Fx85 ran without the flag registers enabled.
+    FlagsDisabled,
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FaultKind::InvalidOpcode => write!(f, "Invalid opcode"),
+            FaultKind::StackUnderflow => write!(f, "Tried to return from empty stack"),
+            FaultKind::StackOverflow { levels } => write!(
+                f,
+                "Tried to call a subroutine with the stack full ({} levels)",
+                levels
+            ),
+            FaultKind::OutOfBounds { i } => {
+                write!(f, "Reached past the end of memory from I = {:03X}", i)
+            }
+            FaultKind::PcOutOfBounds => write!(f, "Ran off the end of memory"),
+            FaultKind::FlagsDisabled => write!(f, "Used the flag registers, which aren't enabled"),
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {:03X}: {:04X}", self.kind, self.pc, self.word)?;
+        match self.opcode {
+            Some(op) => write!(f, " ({})", op),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+/// How the keypad treats held keys. Games disagree on what they expect, and with keys polled
+/// once per window update, short taps can land between polls. Like the quirks, this is
+/// configuration rather than machine state.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyHold {
+    /// Let a key that is already held answer Fx0A, so holding it repeats the input, rather
+    /// than waiting for a new press.
+    pub repeat: bool,
+    /// Frames a released key still reads as held to Ex9E and ExA1.
+    pub release_frames: u8,
+}
+
+/// Runs an instruction added with `Chip8::register_opcode`, given the machine (with PC already
+/// past the instruction) and the instruction word.
+pub type OpcodeHandler = Box<dyn FnMut(&mut Chip8, u16) -> Result<(), FaultKind> + Send + Sync>;
+
+/// A handler for the instruction words whose bits under `mask` equal `bits`.
+struct CustomOpcode {
+    mask: u16,
+    bits: u16,
+    handler: OpcodeHandler,
+}
+
+/// A device mapped into memory with `Chip8::map_peripheral`, such as switches, a sensor, or a
+/// serial port. Programs reach it through the instructions that read and write data in memory
+/// (Dxyn, Fx33, Fx55, Fx65, F002, and the memory stack), never through instruction fetches.
+pub trait Peripheral: Send + Sync {
+    /// The byte a program is about to read at `addr`.
+    fn read(&mut self, addr: usize) -> u8;
+    /// A byte a program just wrote at `addr`.
+    fn write(&mut self, addr: usize, value: u8);
+}
+
+/// How the stack holds return addresses. Like the quirks, this is configuration rather than
+/// machine state.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StackKind {
+    /// The COSMAC VIP's 16 levels, in an array of their own, or in memory at `STACK_ADDRESS`
+    /// with the memory stack quirk.
+    #[default]
+    Fixed,
+    /// As many levels as the program calls for, up to `MAX_STACK_DEPTH`, for programs that nest
+    /// deeper than the VIP allowed. These never live in memory, so the memory stack quirk
+    /// doesn't apply.
+    Growable,
+}
+
+/// An instruction `step` ran, and what it did besides moving on to the next one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Executed {
+    pub pc: usize,
+    pub opcode: Opcode,
+    /// Bit n is set for each register Vn the instruction changed.
+    pub changed_registers: u16,
+    /// Memory the instruction wrote to (Fx33 and Fx55).
+    pub wrote: Option<Range<usize>>,
+    /// Whether the instruction drew to or cleared the screen.
+    pub drew: bool,
+    /// Whether it jumped, called, returned, or skipped, rather than moving on to the next
+    /// instruction.
+    pub branched: bool,
+    /// Whether it left the machine waiting for a key (Fx0A).
+    pub waits_for_key: bool,
+}
+
+/// What a run of instructions did, so frontends can skip work when nothing changed. From
+/// `Chip8::run_for` and `Chip8::run_frame`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunReport {
+    /// Instructions executed. This falls short of the cycles asked for if the machine
+    /// started waiting for a key.
+    pub instructions: u32,
+    pub display_changed: bool,
+    /// Whether the sound timer went from stopped to running, or back, over the run.
+    pub sound_started: bool,
+    pub sound_stopped: bool,
+    pub waiting_for_key: bool,
+}
+
+/// Steps a machine until it stops: at a fault (which is the last item), while it waits for a
+/// key, or once the program halts. From `Chip8::steps`.
+pub struct Steps<'a> {
+    chip8: &'a mut Chip8,
+    faulted: bool,
+}
+
+impl Iterator for Steps<'_> {
+    type Item = Result<Executed, Fault>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.faulted || self.chip8.is_halted() {
+            return None;
+        }
+        match self.chip8.step() {
+            Ok(executed) => executed.map(Ok),
+            Err(fault) => {
+                self.faulted = true;
+                Some(Err(fault))
+            }
+        }
+    }
+}
+
+pub struct Chip8 {
+    memory: Box<[u8; 4096]>,
+    reg: [u8; 16],
+    pc: usize,
+    /// Return addresses, unless they're in memory. A fixed stack never grows this past the
+    /// `STACK_SIZE` entries it starts with, so calls don't allocate.
+    stack: Vec<usize>,
+    sp: usize,
+    stack_kind: StackKind,
+    i_addr: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    /// One bit per pixel, a row to a word with the leftmost pixel in the top bit, so sprites
+    /// are drawn and checked for collisions a whole line at a time.
+    screen: [u64; SCREEN_HEIGHT],
+    /// The screen as of the last frame boundary, and the same in display colors, so frontends
+    /// never show a sprite half drawn by the instructions in between. Like the palette the
+    /// colors are drawn with, these are not part of save states.
+    shown: [u64; SCREEN_HEIGHT],
+    framebuffer: Box<[u32; SCREEN_WIDTH * SCREEN_HEIGHT]>,
+    palette: Palette,
+    /// Set whenever the screen is drawn on or recolored, until a frontend takes it.
+    display_dirty: bool,
+    key_status: [bool; 16],
+    key_hold: KeyHold,
+    /// Frames left before each released key stops reading as held, for `KeyHold`.
+    release_countdown: [u8; 16],
+    waiting_for_key: Option<Register>,
+    /// XO-CHIP 1-bit audio pattern, played back while the sound timer is active.
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    /// Seed of the RNG backing `Random`. It is kept (along with the number of values drawn) so
+    /// that runs can be reproduced exactly, e.g. for save states and movie playback.
+    seed: u64,
+    rng: ChaCha20Rng,
+    rng_draws: u64,
+    /// Interpreter quirks and instructions per frame (None to run one instruction per timer
+    /// tick). These are configuration rather than machine state, so they are not part of save
+    /// states.
+    quirks: Quirks,
+    instructions_per_frame: Option<u32>,
+    clock: FrameClock,
+    /// Data reads and writes per address, counted only while a debugger asks for them. Like
+    /// the quirks, this is not part of save states.
+    memory_access: Option<MemoryAccess>,
+    /// Memory written by the instruction being stepped, for `Executed`.
+    last_write: Option<Range<usize>>,
+    /// Screen pixels where sprites collided since the frontend last took them, collected only
+    /// while it asks for them. Not part of save states.
+    collided: Option<Vec<usize>>,
+    /// SCHIP's flag registers, which outlive the machine (HP48 calculators kept them between
+    /// programs), so games can keep high scores. Only there once enabled, since they're not part
+    /// of CHIP-8, and not part of save states so loading one never loses a score.
+    flags: Option<[u8; FLAG_COUNT]>,
+    /// Set whenever Fx75 changes the flags, until a frontend takes it.
+    flags_changed: bool,
+    /// Instructions added by the application, most specific mask first. Like the quirks,
+    /// these are configuration and not part of save states.
+    custom_opcodes: Vec<CustomOpcode>,
+    /// Devices and the addresses they answer at. Memory there holds the last byte read or
+    /// written. Configuration too, so not part of save states.
+    peripherals: Vec<(Range<usize>, Box<dyn Peripheral>)>,
+    /// Sprite draws that collided since power-on, for `--metrics`.
+    #[cfg(feature = "metrics")]
+    collisions: u64,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::with_seed(rand::random())
+    }
+}
+
+impl Chip8 {
+    pub fn with_seed(seed: u64) -> Self {
+        let mut c8 = Chip8 {
+            memory: Box::new([0u8; 4096]),
+            reg: [0u8; 16],
+            pc: 0x200,
+            stack: vec![0; STACK_SIZE],
+            sp: 0,
+            stack_kind: StackKind::default(),
+            i_addr: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            screen: [0; SCREEN_HEIGHT],
+            shown: [0; SCREEN_HEIGHT],
+            framebuffer: Box::new([Palette::default().color(0); SCREEN_WIDTH * SCREEN_HEIGHT]),
+            palette: Palette::default(),
+            display_dirty: true,
+            key_status: [false; 16],
+            key_hold: KeyHold::default(),
+            release_countdown: [0; 16],
+            waiting_for_key: None,
+            audio_pattern: [0u8; 16],
+            pitch: DEFAULT_PITCH,
+            seed,
+            rng: ChaCha20Rng::seed_from_u64(seed),
+            rng_draws: 0,
+            quirks: Quirks::default(),
+            instructions_per_frame: None,
+            clock: FrameClock::default(),
+            memory_access: None,
+            last_write: None,
+            collided: None,
+            flags: None,
+            flags_changed: false,
+            custom_opcodes: Vec::new(),
+            peripherals: Vec::new(),
+            #[cfg(feature = "metrics")]
+            collisions: 0,
+        };
+
+        // Load system font. 16 characters, each 5 bytes long
+        for i in 0..16 {
+            for j in 0..5 {
+                c8.memory[BASE_FONT_ADDRESS + (i * 5) + j] = FONT[(i * 5) + j];
+            }
+        }
+        c8
+    }
+
+    /// Fills RAM, the registers, and the screen with garbage, as real hardware can power on
+    /// with, leaving only the font in place. It comes from its own stream of the seeded RNG, so
+    /// runs stay reproducible and `Random` draws the same values either way. Call it before
+    /// loading the program.
+    pub fn randomize(&mut self) {
+        let mut rng = ChaCha20Rng::seed_from_u64(self.seed);
+        rng.set_stream(1);
+        let font = BASE_FONT_ADDRESS..BASE_FONT_ADDRESS + FONT.len();
+        for (addr, byte) in self.memory.iter_mut().enumerate() {
+            if !font.contains(&addr) {
+                *byte = rng.next_u32() as u8;
+            }
+        }
+        rng.fill_bytes(&mut self.reg);
+        let pixels: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|_| (rng.next_u32() & 1) as u8)
+            .collect();
+        self.screen = pack_screen(&pixels);
+        self.redraw();
+    }
+
+    /// Copies a program into memory at 0x200. Bytes past the end of memory are dropped, so
+    /// ROMs from outside should go through `try_load_program` instead.
+    pub fn load_program(&mut self, data: &[u8]) {
+        self.write_memory(0x200, data);
+    }
+
+    /// Copies a program into memory at 0x200, unless it's too large to fit.
+    pub fn try_load_program(&mut self, data: &[u8]) -> Result<(), String> {
+        check_program_size(data)?;
+        self.load_program(data);
+        Ok(())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Changes the quirks. Calls already on the stack stay there, even when the memory stack
+    /// quirk moves it in or out of memory.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        let stack = self.stack();
+        self.quirks = quirks;
+        self.set_stack(stack);
+    }
+
+    pub fn key_hold(&self) -> KeyHold {
+        self.key_hold
+    }
+
+    pub fn stack_kind(&self) -> StackKind {
+        self.stack_kind
+    }
+
+    /// Chooses how the stack holds return addresses. Calls already on the stack stay there, so
+    /// this fails if there are more of them than the new kind of stack holds.
+    pub fn set_stack_kind(
+        &mut self,
+        stack_kind: StackKind,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let stack = self.stack();
+        let previous = std::mem::replace(&mut self.stack_kind, stack_kind);
+        if stack.len() > self.stack_limit() {
+            let limit = self.stack_limit();
+            self.stack_kind = previous;
+            return Err(format!(
+                "Stack is {} calls deep, more than the {} that fit",
+                stack.len(),
+                limit
+            )
+            .into());
+        }
+        self.set_stack(stack);
+        Ok(())
+    }
+
+    /// How many subroutine calls the stack holds before calls fault.
+    pub fn stack_limit(&self) -> usize {
+        match self.stack_kind {
+            StackKind::Fixed => STACK_SIZE,
+            StackKind::Growable => MAX_STACK_DEPTH,
+        }
+    }
+
+    pub fn set_key_hold(&mut self, key_hold: KeyHold) {
+        self.key_hold = key_hold;
+    }
+
+    pub fn instructions_per_frame(&self) -> Option<u32> {
+        self.instructions_per_frame
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: Option<u32>) {
+        self.instructions_per_frame = instructions_per_frame;
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    /// Changes the colors the framebuffer is drawn with, redrawing it.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.redraw();
+    }
+
+    /// The screen as 64x32 pixels in display colors, ready to blit. It shows the screen as it
+    /// was at the end of the last frame (see `present`).
+    pub fn framebuffer(&self) -> &[u32] {
+        &self.framebuffer[..]
+    }
+
+    /// The screen as RGBA bytes in `palette`'s colors, with each pixel drawn as a `scale` by
+    /// `scale` square, for a snapshot the size a frontend shows it at. Rows run top to
+    /// bottom, each 64 * `scale` pixels wide. Like the framebuffer, it is the screen as of the
+    /// last frame.
+    pub fn render_to_image(&self, scale: usize, palette: Palette) -> Vec<u8> {
+        let width = SCREEN_WIDTH * scale;
+        let mut image = Vec::with_capacity(width * SCREEN_HEIGHT * scale * 4);
+        for row in self.shown.iter() {
+            let line: Vec<u8> = (0..SCREEN_WIDTH)
+                .flat_map(|x| {
+                    (palette.color(pixel(*row, x)) << 8 | 0xFF)
+                        .to_be_bytes()
+                        .repeat(scale)
+                })
+                .collect();
+            for _ in 0..scale {
+                image.extend_from_slice(&line);
+            }
+        }
+        image
+    }
+
+    /// Starts counting reads and writes to each address, for a heatmap.
+    pub fn track_memory_access(&mut self) {
+        self.memory_access = Some(MemoryAccess::new(self.memory.len()));
+    }
+
+    pub fn memory_access(&self) -> Option<&MemoryAccess> {
+        self.memory_access.as_ref()
+    }
+
+    /// Starts collecting the pixels where sprites collide, for `take_collisions`.
+    pub fn track_collisions(&mut self) {
+        self.collided = Some(Vec::new());
+    }
+
+    pub fn is_tracking_collisions(&self) -> bool {
+        self.collided.is_some()
+    }
+
+    /// Screen pixels (as indexes into `screen`) that sprites were drawn over since the last
+    /// call, turning them off. Empty unless `track_collisions` was called.
+    pub fn take_collisions(&mut self) -> Vec<usize> {
+        self.collided
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Enables the flag registers for Fx75 and Fx85, starting from `flags` as saved by a
+    /// previous run.
+    pub fn enable_flags(&mut self, flags: [u8; FLAG_COUNT]) {
+        self.flags = Some(flags);
+    }
+
+    pub fn flags(&self) -> Option<[u8; FLAG_COUNT]> {
+        self.flags
+    }
+
+    /// The flag registers if Fx75 changed them since the last call, for saving.
+    pub fn take_changed_flags(&mut self) -> Option<[u8; FLAG_COUNT]> {
+        match std::mem::take(&mut self.flags_changed) {
+            true => self.flags,
+            false => None,
+        }
+    }
+
+    /// Runs the instruction words whose bits under `mask` equal `bits` with `handler`, ahead of
+    /// the standard instruction set, for experiments like debug output from the 0nnn SYS space
+    /// or extra hardware without forking the decoder. As with `Decoder::register`, the mask
+    /// must cover the top nibble, and a later handler takes precedence over an earlier one
+    /// with an equally specific mask.
+    pub fn register_opcode<F>(&mut self, mask: u16, bits: u16, handler: F)
+    where
+        F: FnMut(&mut Chip8, u16) -> Result<(), FaultKind> + Send + Sync + 'static,
+    {
+        assert_eq!(
+            mask & 0xF000,
+            0xF000,
+            "Decoder masks must cover the top nibble"
+        );
+        let custom = &mut self.custom_opcodes;
+        let specificity = mask.count_ones();
+        let pos = custom
+            .iter()
+            .position(|c| c.mask.count_ones() <= specificity)
+            .unwrap_or(custom.len());
+        let handler = Box::new(handler);
+        custom.insert(
+            pos,
+            CustomOpcode {
+                mask,
+                bits,
+                handler,
+            },
+        );
+    }
+
+    /// Maps `peripheral` into memory at `addrs`, for embedders simulating memory-mapped I/O.
+    /// Pick addresses programs don't load code or sprites from, like the top of memory.
+    pub fn map_peripheral(&mut self, addrs: Range<usize>, peripheral: impl Peripheral + 'static) {
+        assert!(
+            addrs.end <= self.memory.len(),
+            "Peripherals must be mapped inside memory"
+        );
+        assert!(
+            self.peripherals
+                .iter()
+                .all(|(range, _)| overlap(range, &addrs).is_empty()),
+            "Peripherals can't share addresses"
+        );
+        self.peripherals.push((addrs, Box::new(peripheral)));
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn collisions(&self) -> u64 {
+        self.collisions
+    }
+
+    /// Whether the framebuffer changed since the last call, so frontends can skip presenting
+    /// frames where nothing was drawn.
+    pub fn take_display_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.display_dirty, false)
+    }
+
+    /// Shows the screen as it is now. Frames end with this, and debuggers that stop partway
+    /// through one call it to show what the instructions so far drew.
+    pub fn present(&mut self) {
+        for (y, (shown, row)) in self.shown.iter_mut().zip(self.screen.iter()).enumerate() {
+            for x in columns(*shown ^ *row) {
+                self.framebuffer[y * SCREEN_WIDTH + x] = self.palette.color(pixel(*row, x));
+            }
+            *shown = *row;
+        }
+    }
+
+    fn redraw(&mut self) {
+        self.shown = self.screen;
+        for (color, pixel) in self.framebuffer.iter_mut().zip(unpack_screen(&self.shown)) {
+            *color = self.palette.color(pixel);
+        }
+        self.display_dirty = true;
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn i_addr(&self) -> usize {
+        self.i_addr
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.reg
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// How many subroutine calls deep the machine is.
+    pub fn stack_depth(&self) -> usize {
+        self.sp
+    }
+
+    /// The return addresses on the stack, oldest first.
+    pub fn stack(&self) -> Vec<usize> {
+        (0..self.sp).map(|level| self.stack_entry(level)).collect()
+    }
+
+    /// The XO-CHIP audio pattern and pitch, for playing the buzzer.
+    pub fn audio_pattern(&self) -> ([u8; 16], u8) {
+        (self.audio_pattern, self.pitch)
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory[..]
+    }
+
+    /// The 64x32 screen, one byte per pixel, 1 where it is lit. This is the screen the
+    /// instructions draw on, which can be partway through a frame.
+    pub fn screen(&self) -> Vec<u8> {
+        unpack_screen(&self.screen)
+    }
+
+    /// Whether the program has stopped for good, either by exiting or by jumping to itself,
+    /// the usual way for a CHIP-8 program to end.
+    pub fn is_halted(&self) -> bool {
+        self.has_exited() || self.opcode_at(self.pc) == Some(Opcode::Jump(self.pc))
+    }
+
+    /// Whether the CPU is stalled on Fx0A until a key is pressed.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key.is_some()
+    }
+
+    /// Whether the program has stopped with the SCHIP exit instruction.
+    pub fn has_exited(&self) -> bool {
+        self.opcode_at(self.pc) == Some(Opcode::Exit)
+    }
+
+    pub fn set_pc(&mut self, addr: usize) {
+        self.pc = addr;
+    }
+
+    /// Sets a V register directly, for debugging tools.
+    pub fn set_register(&mut self, index: usize, value: u8) {
+        self.reg[index] = value;
+    }
+
+    pub fn set_i_addr(&mut self, addr: usize) {
+        self.i_addr = addr & I_MASK;
+    }
+
+    /// Sets the timers directly, for debugging tools.
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// Writes bytes directly into memory, for debugging tools. Bytes past the end of memory
+    /// are dropped.
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) {
+        for (i, b) in bytes.iter().enumerate() {
+            if let Some(dest) = self.memory.get_mut(addr + i) {
+                *dest = *b;
+            }
+        }
+    }
+
+    /// Reads the raw instruction word stored at `addr`.
+    pub fn instruction_at(&self, addr: usize) -> u16 {
+        let hi = self.memory.get(addr).copied().unwrap_or(0);
+        let lo = self.memory.get(addr + 1).copied().unwrap_or(0);
+        u16::from(hi) << 8 | u16::from(lo)
+    }
+
+    /// Decodes the instruction stored at `addr` without executing it, or None if the word
+    /// there isn't a valid instruction.
+    pub fn opcode_at(&self, addr: usize) -> Option<Opcode> {
+        let word = self.instruction_at(addr);
+        match self.custom_opcodes.iter().any(|c| word & c.mask == c.bits) {
+            true => Some(Opcode::Custom(word)),
+            false => Opcode::decode(word),
+        }
+    }
+
+    /// Runs the machine for `dt` of real time: as many 60Hz frames as are due, carrying any
+    /// leftover time over to the next call. Returns how many frames ran.
+    pub fn advance(&mut self, dt: Duration) -> Result<u32, Fault> {
+        let frames = self.clock.advance(dt);
+        for _ in 0..frames {
+            self.run_frame()?;
+        }
+        Ok(frames)
+    }
+
+    /// Runs a single frame: the timers count down once, alongside either one instruction or
+    /// the configured number of instructions per frame. The screen is presented at the end.
+    pub fn run_frame(&mut self) -> Result<RunReport, Fault> {
+        let _span = tracing::debug_span!("frame").entered();
+        let sounding = self.sound_timer > 0;
+        self.tick_timers();
+        let report = self.run_for(self.instructions_per_frame.unwrap_or(1));
+        self.present();
+        let report = report?;
+        tracing::debug!(
+            instructions = report.instructions,
+            display_changed = report.display_changed,
+            waiting_for_key = report.waiting_for_key,
+            "frame done"
+        );
+        Ok(RunReport {
+            sound_started: !sounding && self.sound_timer > 0,
+            sound_stopped: sounding && self.sound_timer == 0,
+            ..report
+        })
+    }
+
+    /// Executes up to `cycles` instructions without touching the timers, stopping early if
+    /// the machine starts waiting for a key.
+    pub fn run_for(&mut self, cycles: u32) -> Result<RunReport, Fault> {
+        let sounding = self.sound_timer > 0;
+        let mut report = RunReport::default();
+        for _ in 0..cycles {
+            match self.step()? {
+                Some(executed) => {
+                    report.instructions += 1;
+                    report.display_changed |= executed.drew;
+                }
+                None => break,
+            }
+        }
+        report.sound_started = !sounding && self.sound_timer > 0;
+        report.sound_stopped = sounding && self.sound_timer == 0;
+        report.waiting_for_key = self.is_waiting_for_key();
+        Ok(report)
+    }
+
+    /// Counts the timers down and executes a single instruction, the original model where
+    /// the CPU and timers run in lockstep.
+    pub fn tick(&mut self) -> Result<Option<Executed>, Fault> {
+        self.tick_timers();
+        self.step()
+    }
+
+    /// Counts the delay and sound timers down, which should happen at 60Hz.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        for frames in self.release_countdown.iter_mut() {
+            *frames = frames.saturating_sub(1);
+        }
+    }
+
+    /// Executes a single instruction without touching the timers, returning what it did. If
+    /// the instruction fails, PC is left pointing at it. Nothing runs while Fx0A is waiting
+    /// for a key, so that returns None.
+    pub fn step(&mut self) -> Result<Option<Executed>, Fault> {
+        if self.is_waiting_for_key() {
+            return Ok(None);
+        }
+        // Similar to EAP register in x86, we will increment PC counter after retrieval
+        // but before execution. This will help make it more straightforward for branch
+        // instructions to "skip next instruction" by incrementing a single two-byte instruction.
+        let pc = self.pc;
+        let registers = self.reg;
+        // Tell this instruction's drawing apart from any the frontend hasn't taken yet
+        let dirty = std::mem::replace(&mut self.display_dirty, false);
+        self.last_write = None;
+        let opcode = self.opcode_at(pc);
+        let result = match opcode {
+            _ if pc + 1 >= self.memory.len() => Err(FaultKind::PcOutOfBounds),
+            Some(op) => {
+                if let Some(ref mut access) = self.memory_access {
+                    access.execute(pc);
+                }
+                self.pc += 2;
+                self.execute_opcode(op).map(|()| op)
+            }
+            None => Err(FaultKind::InvalidOpcode),
+        };
+        let drew = self.display_dirty;
+        self.display_dirty |= dirty;
+        match result {
+            Ok(opcode) => {
+                tracing::trace!(pc, %opcode, "step");
+                Ok(Some(Executed {
+                    pc,
+                    opcode,
+                    changed_registers: (0..16)
+                        .filter(|&r| registers[r] != self.reg[r])
+                        .fold(0, |mask, r| mask | 1 << r),
+                    wrote: self.last_write.take(),
+                    drew,
+                    branched: self.pc != pc + 2,
+                    waits_for_key: self.is_waiting_for_key(),
+                }))
+            }
+            Err(kind) => {
+                let fault = Fault {
+                    kind,
+                    pc,
+                    word: self.instruction_at(pc),
+                    opcode,
+                };
+                tracing::error!(%fault, "fault");
+                self.pc = pc;
+                Err(fault)
+            }
+        }
+    }
+
+    /// Steps the machine one instruction at a time, for tools that follow along.
+    pub fn steps(&mut self) -> Steps<'_> {
+        Steps {
+            chip8: self,
+            faulted: false,
+        }
+    }
+
+    pub fn set_key_down(&mut self, key: u8) {
+        if key > 15 {
+            panic!("Key is not between 0 and 15: {}", key);
+        }
+        // Is this a new key press?
+        if !self.key_status[key as usize] {
+            // Are we waiting for a key press?
+            if self.waiting_for_key.is_some() {
+                self.reg[self.waiting_for_key.unwrap() as usize] = key;
+                self.waiting_for_key = None;
+            }
+            self.key_status[key as usize] = true;
+        }
+    }
+
+    /// Which keys the machine currently sees as held.
+    pub fn keys_down(&self) -> &[bool; 16] {
+        &self.key_status
+    }
+
+    pub fn set_key_up(&mut self, key: u8) {
+        if key > 15 {
+            panic!("Key is not between 0 and 15: {}", key);
+        }
+        if self.key_status[key as usize] {
+            self.release_countdown[key as usize] = self.key_hold.release_frames;
+        }
+        self.key_status[key as usize] = false;
+    }
+
+    /// Serializes the complete machine state (except for currently held keys, which are
+    /// owned by whatever frontend is feeding input) into a versioned binary blob.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(4096 + SCREEN_WIDTH * SCREEN_HEIGHT + 64);
+        data.extend_from_slice(STATE_MAGIC);
+        data.push(STATE_VERSION);
+        data.extend_from_slice(&self.memory[..]);
+        data.extend_from_slice(&self.reg);
+        data.extend_from_slice(&(self.pc as u16).to_be_bytes());
+        data.extend_from_slice(&(self.i_addr as u16).to_be_bytes());
+        data.push(self.delay_timer);
+        data.push(self.sound_timer);
+        data.push(self.waiting_for_key.map_or(0xFF, |r| r as u8));
+        data.push(self.sp as u8);
+        for addr in self.stack().iter() {
+            data.extend_from_slice(&(*addr as u16).to_be_bytes());
+        }
+        data.extend_from_slice(&self.screen());
+        data.extend_from_slice(&self.audio_pattern);
+        data.push(self.pitch);
+        data.extend_from_slice(&self.seed.to_be_bytes());
+        data.extend_from_slice(&self.rng_draws.to_be_bytes());
+        data
+    }
+
+    /// Hash of the full machine state, cheap enough to compare runs against each other
+    /// frame by frame (e.g. to detect movie desyncs).
+    pub fn state_hash(&self) -> u64 {
+        hash::fnv1a(&self.save_state())
+    }
+
+    /// Hash of just the screen, for checking what a program drew regardless of how it got
+    /// there.
+    pub fn screen_hash(&self) -> u64 {
+        hash::fnv1a(&self.screen())
+    }
+
+    /// Restores a machine state previously produced by `save_state`, including by older
+    /// versions of the emulator. The current state is left untouched if the blob is not a
+    /// valid save state.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if data.len() < 5 || &data[..4] != STATE_MAGIC {
+            return Err("Not a Chip-8 save state".into());
+        }
+        let migrated;
+        let data = if data[4] == STATE_VERSION {
+            data
+        } else {
+            migrated = self.migrate_state(data.to_vec())?;
+            &migrated[..]
+        };
+        let mut reader = StateReader { data, pos: 5 };
+
+        let mut memory = Box::new([0u8; 4096]);
+        memory.copy_from_slice(reader.take(4096, "memory")?);
+        let mut reg = [0u8; 16];
+        reg.copy_from_slice(reader.take(16, "registers")?);
+        let pc = reader.word("PC")? as usize;
+        let i_addr = reader.word("I")? as usize;
+        let delay_timer = reader.byte("delay timer")?;
+        let sound_timer = reader.byte("sound timer")?;
+        let waiting_for_key = match reader.byte("key wait")? {
+            0xFF => None,
+            r => Some(Register::from_u8(r).ok_or_else(|| {
+                format!(
+                    "Save state waits for a key in V{:X}, which doesn't exist",
+                    r
+                )
+            })?),
+        };
+        let sp = reader.byte("stack pointer")? as usize;
+        if sp > self.stack_limit() {
+            return Err(format!(
+                "Save state's stack is {} calls deep, more than the {} that fit",
+                sp,
+                self.stack_limit()
+            )
+            .into());
+        }
+        let mut stack = Vec::with_capacity(sp);
+        for _ in 0..sp {
+            stack.push(reader.word("stack")? as usize);
+        }
+        let screen = pack_screen(reader.take(SCREEN_WIDTH * SCREEN_HEIGHT, "screen")?);
+        let mut audio_pattern = [0u8; 16];
+        audio_pattern.copy_from_slice(reader.take(16, "audio pattern")?);
+        let pitch = reader.byte("pitch")?;
+        let seed = reader.u64("RNG seed")?;
+        let rng_draws = reader.u64("RNG position")?;
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        // Every draw consumes exactly one 32-bit word of the stream
+        rng.set_word_pos(u128::from(rng_draws));
+        if reader.pos != data.len() {
+            return Err(format!(
+                "Save state has {} unexpected bytes at the end",
+                data.len() - reader.pos
+            )
+            .into());
+        }
+
+        self.memory = memory;
+        self.reg = reg;
+        self.pc = pc;
+        self.sp = sp;
+        self.set_stack(stack);
+        self.i_addr = i_addr;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.screen = screen;
+        self.redraw();
+        self.waiting_for_key = waiting_for_key;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.seed = seed;
+        self.rng = rng;
+        self.rng_draws = rng_draws;
+        Ok(())
+    }
+
+    /// Brings a save state written in an older version of the format up to the current
+    /// one, a version at a time.
+    fn migrate_state(&self, mut data: Vec<u8>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let version = data[4];
+        if version == 0 || version > STATE_VERSION {
+            return Err(format!(
+                "Save state is version {}, but this emulator reads versions 1 to {}",
+                version, STATE_VERSION
+            )
+            .into());
+        }
+        if version < 2 {
+            // Version 1 didn't record the RNG, so Random carries on from this machine's seed
+            data.extend_from_slice(&self.seed.to_be_bytes());
+            data.extend_from_slice(&0u64.to_be_bytes());
+        }
+        if version < 3 {
+            // Version 3 added the XO-CHIP audio pattern and pitch, between the screen and the
+            // RNG. Older programs never changed them
+            let rng_start = data
+                .len()
+                .checked_sub(16)
+                .ok_or("Save state is truncated")?;
+            let audio = [0u8; 16]
+                .iter()
+                .copied()
+                .chain(std::iter::once(DEFAULT_PITCH));
+            data.splice(rng_start..rng_start, audio);
+        }
+        data[4] = STATE_VERSION;
+        Ok(data)
+    }
+
+    /// Whether return addresses live in memory, as with the memory stack quirk on a fixed stack.
+    fn stack_in_memory(&self) -> bool {
+        self.quirks.memory_stack && self.stack_kind == StackKind::Fixed
+    }
+
+    /// The return address at `level` of the stack, wherever it's kept.
+    fn stack_entry(&self, level: usize) -> usize {
+        if self.stack_in_memory() {
+            self.instruction_at(STACK_ADDRESS + level * 2) as usize
+        } else {
+            self.stack[level]
+        }
+    }
+
+    /// Puts return addresses back on the stack, oldest first, wherever it's now kept.
+    fn set_stack(&mut self, stack: Vec<usize>) {
+        for (level, addr) in stack.into_iter().enumerate() {
+            self.set_stack_entry(level, addr);
+        }
+    }
+
+    fn set_stack_entry(&mut self, level: usize, addr: usize) {
+        if self.stack_in_memory() {
+            let word = (addr as u16).to_be_bytes();
+            self.memory[STACK_ADDRESS + level * 2..][..2].copy_from_slice(&word);
+        } else if level == self.stack.len() {
+            self.stack.push(addr);
+        } else {
+            self.stack[level] = addr;
+        }
+    }
+
+    /// The `len` bytes of memory starting at I, or a fault if they run past the end.
+    fn memory_from_i(&self, len: usize) -> Result<Range<usize>, FaultKind> {
+        let range = self.i_addr..self.i_addr + len;
+        if range.end > self.memory.len() {
+            return Err(FaultKind::OutOfBounds { i: self.i_addr });
+        }
+        Ok(range)
+    }
+
+    /// Whether Ex9E and ExA1 see a key as held, counting recent releases.
+    /// Only the low nibble of `key` counts, as on the COSMAC VIP.
+    fn reads_held(&self, key: u8) -> bool {
+        let key = (key & 0x0F) as usize;
+        self.key_status[key] || self.release_countdown[key] > 0
+    }
+
+    /// Counts reads of `addrs` about to happen, first fetching any mapped to a peripheral.
+    fn note_reads(&mut self, addrs: Range<usize>) {
+        for (range, peripheral) in self.peripherals.iter_mut() {
+            for addr in overlap(range, &addrs) {
+                self.memory[addr] = peripheral.read(addr);
+            }
+        }
+        if let Some(ref mut access) = self.memory_access {
+            access.read(addrs);
+        }
+    }
+
+    /// Counts writes to `addrs` that just happened, passing on any mapped to a peripheral.
+    fn note_writes(&mut self, addrs: Range<usize>) {
+        for (range, peripheral) in self.peripherals.iter_mut() {
+            for addr in overlap(range, &addrs) {
+                peripheral.write(addr, self.memory[addr]);
+            }
+        }
+        self.last_write = Some(addrs.clone());
+        if let Some(ref mut access) = self.memory_access {
+            access.write(addrs);
+        }
+    }
+
+    fn next_random(&mut self) -> u8 {
+        self.rng_draws += 1;
+        self.rng.next_u32() as u8
+    }
+
+    /// Stores the result of OR/AND/XOR. The original interpreter ran these through the same
+    /// routine as the arithmetic ops, which left VF cleared.
+    fn set_logic_result(&mut self, vx: Register, result: u8) {
+        self.reg[vx as usize] = result;
+        if self.quirks.vf_reset {
+            self.reg[Register::VF as usize] = 0;
+        }
+    }
+
+    /// Stores the result of an 8xyN instruction in Vx and its flag in VF. The flag is written
+    /// last, so it wins when Vx is VF. With the legacy flags quirk, VF is only ever raised,
+    /// and before the result.
+    fn set_alu_result(&mut self, vx: Register, result: u8, flag: bool) {
+        if self.quirks.legacy_flags {
+            if flag {
+                self.reg[Register::VF as usize] = 1;
+            }
+            self.reg[vx as usize] = result;
+        } else {
+            self.reg[vx as usize] = result;
+            self.reg[Register::VF as usize] = flag as u8;
+        }
+    }
+
+    /// The register 8xy6/8xyE shift. The result always goes to Vx.
+    fn shift_source(&self, vx: Register, vy: Register) -> Register {
+        if self.quirks.shift_vy {
+            vy
+        } else {
+            vx
+        }
+    }
+
+    /// How many registers Fx55/Fx65 transfer, starting from V0.
+    fn register_count(&self, vx: Register) -> usize {
+        if self.quirks.exclusive_range {
+            vx as usize
+        } else {
+            vx as usize + 1
+        }
+    }
+
+    // Whatever a ROM holds, executing it must never panic, since the core runs inside other
+    // applications. Anything the machine can't do comes back as a fault instead.
+    fn execute_opcode(&mut self, op: Opcode) -> Result<(), FaultKind> {
+        match op {
+            Opcode::ClearDisplay => {
+                self.screen = [0; SCREEN_HEIGHT];
+                self.display_dirty = true;
+            }
+            Opcode::Noop => {
+                // Do nothing
+            }
+            Opcode::Return => {
+                if self.sp == 0 {
+                    return Err(FaultKind::StackUnderflow);
+                }
+                self.sp -= 1;
+                if self.stack_in_memory() {
+                    let addr = STACK_ADDRESS + self.sp * 2;
+                    self.note_reads(addr..addr + 2);
+                }
+                self.pc = self.stack_entry(self.sp);
+            }
+            Opcode::Exit => {
+                // There is no interpreter to return to, so stay here for good
+                self.pc -= 2;
+            }
+            Opcode::Jump(nnn) => {
+                self.pc = nnn;
+            }
+            Opcode::CallSubroutine(nnn) => {
+                if self.sp >= self.stack_limit() {
+                    return Err(FaultKind::StackOverflow { levels: self.sp });
+                }
+                tracing::trace!(from = self.pc - 2, to = nnn, depth = self.sp + 1, "call");
+                self.set_stack_entry(self.sp, self.pc);
+                if self.stack_in_memory() {
+                    let addr = STACK_ADDRESS + self.sp * 2;
+                    self.note_writes(addr..addr + 2);
+                }
+                self.sp += 1;
+                self.pc = nnn;
+            }
+            Opcode::SkipIfConstantEqual(vx, kk) => {
+                if self.reg[vx as usize] == kk {
+                    self.pc += 2;
+                }
+            }
+            Opcode::SkipIfConstantNotEqual(vx, kk) => {
+                if self.reg[vx as usize] != kk {
+                    self.pc += 2;
+                }
+            }
+            Opcode::SkipIfRegistersEqual(vx, vy) => {
+                if self.reg[vx as usize] == self.reg[vy as usize] {
+                    self.pc += 2;
+                }
+            }
+            Opcode::LoadConstant(vx, kk) => {
+                self.reg[vx as usize] = kk;
+            }
+            Opcode::AddConstant(vx, kk) => {
+                let r = self.reg[vx as usize];
+                // Wrap on overflow
+                self.reg[vx as usize] = (u16::from(r) + u16::from(kk) % 256) as u8;
+            }
+            Opcode::LoadRegister(vx, vy) => {
+                self.reg[vx as usize] = self.reg[vy as usize];
+            }
+            Opcode::Or(vx, vy) => {
+                let result = self.reg[vx as usize] | self.reg[vy as usize];
+                self.set_logic_result(vx, result);
+            }
+            Opcode::And(vx, vy) => {
+                let result = self.reg[vx as usize] & self.reg[vy as usize];
+                self.set_logic_result(vx, result);
+            }
+            Opcode::Xor(vx, vy) => {
+                let result = self.reg[vx as usize] ^ self.reg[vy as usize];
+                self.set_logic_result(vx, result);
+            }
+            Opcode::AddRegister(vx, vy) => {
+                let (val, carry) = self.reg[vx as usize].overflowing_add(self.reg[vy as usize]);
+                self.set_alu_result(vx, val, carry);
+            }
+            Opcode::SubtractRightRegister(vx, vy) => {
+                let vx_val = self.reg[vx as usize];
+                let vy_val = self.reg[vy as usize];
+                // VF is set when there is no borrow
+                let no_borrow = if self.quirks.legacy_flags {
+                    vx_val > vy_val
+                } else {
+                    vx_val >= vy_val
+                };
+                self.set_alu_result(vx, vx_val.wrapping_sub(vy_val), no_borrow);
+            }
+            Opcode::ShiftRight(vx, vy) => {
+                let val = self.reg[self.shift_source(vx, vy) as usize];
+                // Signal the least significant bit that was shifted off in VF
+                self.set_alu_result(vx, val >> 1, val & 0x01 == 1);
+            }
+            Opcode::SubtractLeftRegister(vx, vy) => {
+                let vx_val = self.reg[vx as usize];
+                let vy_val = self.reg[vy as usize];
+                let no_borrow = if self.quirks.legacy_flags {
+                    vy_val > vx_val
+                } else {
+                    vy_val >= vx_val
+                };
+                self.set_alu_result(vx, vy_val.wrapping_sub(vx_val), no_borrow);
+            }
+            Opcode::ShiftLeft(vx, vy) => {
+                let val = self.reg[self.shift_source(vx, vy) as usize];
+                // Signal the most significant bit that was shifted off in VF
+                self.set_alu_result(vx, val << 1, val & 0b1000_0000 != 0);
+            }
+            Opcode::SkipIfRegistersNotEqual(vx, vy) => {
+                if self.reg[vx as usize] != self.reg[vy as usize] {
+                    self.pc += 2;
+                }
+            }
+            Opcode::LoadAddress(nnn) => {
+                self.i_addr = nnn;
+            }
+            Opcode::JumpPlus(nnn) => {
+                // CHIP-48 read this as BXNN, taking the offset from the register in the high
+                // nibble of the address
+                let offset = if self.quirks.jump_vx { nnn >> 8 } else { 0 };
+                self.pc = self.reg[offset] as usize + nnn;
+            }
+            Opcode::Random(vx, kk) => {
+                self.reg[vx as usize] = self.next_random() & kk;
+            }
+            Opcode::DisplaySprite(vx, vy, n) => {
+                let sprite = self.memory_from_i(n as usize)?;
+                self.note_reads(sprite.clone());
+                // The starting position always wraps around the screen
+                let x = self.reg[vx as usize] as usize % SCREEN_WIDTH;
+                let y = self.reg[vy as usize] as usize % SCREEN_HEIGHT;
+
+                let mut collision = false;
+                for (y_offset, line) in self.memory[sprite].iter().enumerate() {
+                    // Rows past the bottom are clipped, unless the quirk wraps them around
+                    let mut dest_y = y + y_offset;
+                    if self.quirks.wrap_sprites {
+                        dest_y %= SCREEN_HEIGHT;
+                    } else if dest_y >= SCREEN_HEIGHT {
+                        break;
+                    }
+                    // Line the sprite's byte up with x in the row, shifting pixels past the
+                    // right edge off it or rotating them around to the left
+                    let line = u64::from(*line) << (64 - 8);
+                    let bits = match self.quirks.wrap_sprites {
+                        true => line.rotate_right(x as u32),
+                        false => line >> x,
+                    };
+                    let hits = self.screen[dest_y] & bits;
+                    if hits != 0 {
+                        collision = true;
+                        if let Some(ref mut collided) = self.collided {
+                            collided.extend(columns(hits).map(|x| dest_y * SCREEN_WIDTH + x));
+                        }
+                    }
+                    self.screen[dest_y] ^= bits;
+                }
+                if collision || !self.quirks.legacy_flags {
+                    self.reg[Register::VF as usize] = collision as u8;
+                }
+                tracing::trace!(x, y, n, collision, "draw");
+                #[cfg(feature = "metrics")]
+                {
+                    self.collisions += collision as u64;
+                }
+                self.display_dirty = true;
+            }
+            Opcode::SkipIfPressed(vx) => {
+                if self.reads_held(self.reg[vx as usize]) {
+                    self.pc += 2;
+                }
+            }
+            Opcode::SkipIfNotPressed(vx) => {
+                if !self.reads_held(self.reg[vx as usize]) {
+                    self.pc += 2;
+                }
+            }
+            Opcode::LoadDelayTimer(vx) => {
+                self.reg[vx as usize] = self.delay_timer;
+            }
+            Opcode::WaitForPress(vx) => {
+                let held = (0..16).find(|&k| self.key_status[k as usize]);
+                match held.filter(|_| self.key_hold.repeat) {
+                    Some(key) => self.reg[vx as usize] = key,
+                    None => self.waiting_for_key = Some(vx),
+                }
+            }
+            Opcode::SetDelayTimer(vx) => {
+                self.delay_timer = self.reg[vx as usize];
+            }
+            Opcode::SetSoundTimer(vx) => {
+                self.sound_timer = self.reg[vx as usize];
+            }
+            Opcode::AddAddress(vx) => {
+                self.i_addr = (self.i_addr + self.reg[vx as usize] as usize) & I_MASK;
+            }
+            Opcode::LoadAddressOfSprite(vx) => {
+                // Each built-in character is 5-bytes long. Only the low nibble picks one.
+                self.i_addr = BASE_FONT_ADDRESS + (self.reg[vx as usize] & 0x0F) as usize * 5;
+            }
+            Opcode::LoadDigits(vx) => {
+                let digits = self.memory_from_i(3)?;
+                let val = self.reg[vx as usize];
+                self.memory[self.i_addr] = val / 100;
+                self.memory[self.i_addr + 1] = val / 10 % 10;
+                self.memory[self.i_addr + 2] = val % 10;
+                self.note_writes(digits);
+            }
+            Opcode::StoreRegisters(vx) => {
+                let count = self.register_count(vx);
+                let dest = self.memory_from_i(count)?;
+                self.memory[dest.clone()].copy_from_slice(&self.reg[..count]);
+                self.note_writes(dest);
+                if self.quirks.memory_increment {
+                    self.i_addr = (self.i_addr + count) & I_MASK;
+                }
+            }
+            Opcode::LoadRegisters(vx) => {
+                let count = self.register_count(vx);
+                let src = self.memory_from_i(count)?;
+                self.note_reads(src.clone());
+                self.reg[..count].copy_from_slice(&self.memory[src]);
+                if self.quirks.memory_increment {
+                    self.i_addr = (self.i_addr + count) & I_MASK;
+                }
+            }
+            Opcode::SaveFlags(vx) => {
+                let flags = self.flags.as_mut().ok_or(FaultKind::FlagsDisabled)?;
+                let count = vx as usize + 1;
+                if flags[..count] != self.reg[..count] {
+                    flags[..count].copy_from_slice(&self.reg[..count]);
+                    self.flags_changed = true;
+                }
+            }
+            Opcode::LoadFlags(vx) => {
+                let flags = self.flags.ok_or(FaultKind::FlagsDisabled)?;
+                let count = vx as usize + 1;
+                self.reg[..count].copy_from_slice(&flags[..count]);
+            }
+            Opcode::Custom(word) => {
+                // The handler gets the whole machine, so it's lent the handlers' list too
+                let mut custom = std::mem::take(&mut self.custom_opcodes);
+                let result = match custom.iter_mut().find(|c| word & c.mask == c.bits) {
+                    Some(c) => (c.handler)(self, word),
+                    None => Err(FaultKind::InvalidOpcode),
+                };
+                self.custom_opcodes = custom;
+                result?;
+            }
+            Opcode::LoadAudioPattern => {
+                let pattern = self.memory_from_i(16)?;
+                self.note_reads(pattern.clone());
+                self.audio_pattern.copy_from_slice(&self.memory[pattern]);
+            }
+            Opcode::SetPitch(vx) => {
+                self.pitch = self.reg[vx as usize];
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The addresses in both `a` and `b`.
+/// Fails if a program is larger than the `MAX_PROGRAM_SIZE` bytes that fit in memory.
+pub fn check_program_size(data: &[u8]) -> Result<(), String> {
+    if data.len() > MAX_PROGRAM_SIZE {
+        return Err(format!(
+            "ROM is too large: {} bytes, more than the {} that fit",
+            data.len(),
+            MAX_PROGRAM_SIZE
+        ));
+    }
+    Ok(())
+}
+
+fn overlap(a: &Range<usize>, b: &Range<usize>) -> Range<usize> {
+    a.start.max(b.start)..a.end.min(b.end)
+}
+
+/// Whether the pixel at column `x` of a packed screen row is lit, as 0 or 1.
+fn pixel(row: u64, x: usize) -> u8 {
+    (row >> (63 - x) & 1) as u8
+}
+
+/// The columns of the pixels set in a packed screen row, left to right.
+fn columns(mut row: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        let x = row.leading_zeros() as usize;
+        (row != 0).then(|| {
+            row &= !(1 << (63 - x));
+            x
+        })
+    })
+}
+
+/// Screen rows from one byte per pixel, any of them nonzero counting as lit.
+fn pack_screen(pixels: &[u8]) -> [u64; SCREEN_HEIGHT] {
+    let mut rows = [0; SCREEN_HEIGHT];
+    for (row, line) in rows.iter_mut().zip(pixels.chunks(SCREEN_WIDTH)) {
+        *row = line
+            .iter()
+            .fold(0, |row, pixel| row << 1 | u64::from(*pixel != 0));
+    }
+    rows
+}
+
+fn unpack_screen(rows: &[u64; SCREEN_HEIGHT]) -> Vec<u8> {
+    rows.iter()
+        .flat_map(|row| (0..SCREEN_WIDTH).map(move |x| pixel(*row, x)))
+        .collect()
+}
+
+/// Small cursor over a save state blob that turns running out of data into an error.
+struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Reads the next `len` bytes, which hold `what`, for saying what's missing if the
+    /// state ends early.
+    fn take(&mut self, len: usize, what: &str) -> Result<&'a [u8], Box<dyn std::error::Error>> {
+        let end = self.pos + len;
+        if end > self.data.len() {
+            return Err(format!("Save state is truncated, in the {}", what).into());
+        }
+        let slice = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn byte(&mut self, what: &str) -> Result<u8, Box<dyn std::error::Error>> {
+        Ok(self.take(1, what)?[0])
+    }
+
+    fn word(&mut self, what: &str) -> Result<u16, Box<dyn std::error::Error>> {
+        let bytes = self.take(2, what)?;
+        Ok(u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+    }
+
+    fn u64(&mut self, what: &str) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8, what)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+/// Serde support (`--features serde`). The machine goes through the same state as
+/// `save_state`, with memory and the screen as plain byte sequences, plus the configuration
+/// a deserialized machine needs to carry on the same way: quirks, instructions per frame,
+/// and key hold. Like save states, it leaves out held keys and the palette.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::*;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::convert::TryInto;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename = "Chip8")]
+    struct State {
+        memory: Vec<u8>,
+        registers: [u8; 16],
+        pc: u16,
+        i: u16,
+        delay_timer: u8,
+        sound_timer: u8,
+        stack: Vec<u16>,
+        waiting_for_key: Option<u8>,
+        screen: Vec<u8>,
+        audio_pattern: [u8; 16],
+        pitch: u8,
+        seed: u64,
+        rng_draws: u64,
+        /// In the form `--quirks` takes, so it reads the same as on the command line.
+        quirks: String,
+        instructions_per_frame: Option<u32>,
+        key_hold: KeyHold,
+        #[serde(default)]
+        stack_kind: StackKind,
+    }
+
+    impl Serialize for Chip8 {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            State {
+                memory: self.memory.to_vec(),
+                registers: self.reg,
+                pc: self.pc as u16,
+                i: self.i_addr as u16,
+                delay_timer: self.delay_timer,
+                sound_timer: self.sound_timer,
+                stack: self.stack().iter().map(|addr| *addr as u16).collect(),
+                waiting_for_key: self.waiting_for_key.map(|r| r as u8),
+                screen: self.screen(),
+                audio_pattern: self.audio_pattern,
+                pitch: self.pitch,
+                seed: self.seed,
+                rng_draws: self.rng_draws,
+                quirks: self.quirks.to_string(),
+                instructions_per_frame: self.instructions_per_frame,
+                key_hold: self.key_hold,
+                stack_kind: self.stack_kind,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Chip8 {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Chip8, D::Error> {
+            let state = State::deserialize(deserializer)?;
+            let memory: [u8; 4096] = state.memory.try_into().map_err(|memory: Vec<u8>| {
+                D::Error::invalid_length(memory.len(), &"4096 bytes of memory")
+            })?;
+            let screen: [u8; SCREEN_WIDTH * SCREEN_HEIGHT] =
+                state.screen.try_into().map_err(|screen: Vec<u8>| {
+                    D::Error::invalid_length(screen.len(), &"2048 bytes of screen")
+                })?;
+            let mut chip8 = Chip8::with_seed(state.seed);
+            chip8
+                .set_stack_kind(state.stack_kind)
+                .map_err(D::Error::custom)?;
+            if state.stack.len() > chip8.stack_limit() {
+                return Err(D::Error::custom(format!(
+                    "Stack is {} calls deep, more than the {} that fit",
+                    state.stack.len(),
+                    chip8.stack_limit()
+                )));
+            }
+            let waiting_for_key = match state.waiting_for_key {
+                Some(r) => Some(Register::from_u8(r).ok_or_else(|| {
+                    D::Error::custom(format!("Invalid register to wait for a key in: {}", r))
+                })?),
+                None => None,
+            };
+
+            chip8.set_quirks(Quirks::parse(&state.quirks).map_err(D::Error::custom)?);
+            chip8.set_instructions_per_frame(state.instructions_per_frame);
+            chip8.set_key_hold(state.key_hold);
+            *chip8.memory = memory;
+            chip8.reg = state.registers;
+            chip8.pc = usize::from(state.pc);
+            chip8.i_addr = usize::from(state.i);
+            chip8.delay_timer = state.delay_timer;
+            chip8.sound_timer = state.sound_timer;
+            for (level, addr) in state.stack.iter().enumerate() {
+                chip8.set_stack_entry(level, usize::from(*addr));
+            }
+            chip8.sp = state.stack.len();
+            chip8.waiting_for_key = waiting_for_key;
+            chip8.screen = pack_screen(&screen);
+            chip8.redraw();
+            chip8.audio_pattern = state.audio_pattern;
+            chip8.pitch = state.pitch;
+            chip8.rng_draws = state.rng_draws;
+            // Every draw consumes exactly one 32-bit word of the stream
+            chip8.rng.set_word_pos(u128::from(state.rng_draws));
+            Ok(chip8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_state_round_trips() {
+        let mut original = Chip8::default();
+        original.load_program(&[0x22, 0x04, 0x00, 0xE0, 0x6A, 0x42, 0xFA, 0x0A]);
+        original
+            .execute_opcode(Opcode::CallSubroutine(0x204))
+            .unwrap();
+        original
+            .execute_opcode(Opcode::LoadConstant(Register::VA, 0x42))
+            .unwrap();
+        original
+            .execute_opcode(Opcode::DisplaySprite(Register::V0, Register::V0, 5))
+            .unwrap();
+        original
+            .execute_opcode(Opcode::WaitForPress(Register::V3))
+            .unwrap();
+
+        let mut restored = Chip8::default();
+        restored.load_state(&original.save_state()).unwrap();
+        assert_eq!(original.save_state(), restored.save_state());
+        assert_eq!(restored.reg[Register::VA as usize], 0x42);
+        assert_eq!(restored.stack(), vec![0x200]);
+        assert_eq!(restored.waiting_for_key, Some(Register::V3));
+        assert_eq!(restored.screen()[0], 1);
+        assert_eq!(restored.framebuffer()[0], Palette::default().color(1));
+    }
+
+    #[test]
+    fn loads_xo_chip_audio_pattern() {
+        let mut chip8 = Chip8::with_seed(0);
+        let pattern: Vec<u8> = (0..16).map(|i| i * 17).collect();
+        chip8.load_program(&[0xA2, 0x06, 0xF0, 0x02, 0xF1, 0x3A]);
+        chip8.write_memory(0x206, &pattern);
+        chip8.reg[1] = 0x70;
+        assert_eq!(chip8.pitch, DEFAULT_PITCH);
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.audio_pattern[..], pattern[..]);
+        assert_eq!(chip8.pitch, 0x70);
+
+        let mut restored = Chip8::with_seed(0);
+        restored.load_state(&chip8.save_state()).unwrap();
+        assert_eq!(restored.audio_pattern, chip8.audio_pattern);
+        assert_eq!(restored.pitch, 0x70);
+    }
+
+    #[test]
+    fn steps_without_ticking_timers() {
+        let mut chip8 = Chip8::with_seed(0);
+        // LD V0, 0x05; LD DT, V0; JP 0x204
+        chip8.load_program(&[0x60, 0x05, 0xF0, 0x15, 0x12, 0x04]);
+        for _ in 0..10 {
+            chip8.step().unwrap();
+        }
+        assert_eq!(chip8.delay_timer(), 5);
+        chip8.tick_timers();
+        assert_eq!(chip8.delay_timer(), 4);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.delay_timer(), 3);
+    }
+
+    #[test]
+    fn advances_by_elapsed_time() {
+        let mut chip8 = Chip8::with_seed(0);
+        // LD V0, 0xFF; LD DT, V0; ADD V1, 1; JP 0x204
+        chip8.load_program(&[0x60, 0xFF, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04]);
+        chip8.set_instructions_per_frame(Some(10));
+        assert_eq!(chip8.advance(Duration::from_millis(40)).unwrap(), 2);
+        assert_eq!(chip8.delay_timer(), 0xFE);
+        assert_eq!(chip8.advance(Duration::from_millis(10)).unwrap(), 1);
+        assert_eq!(chip8.delay_timer(), 0xFD);
+        // The two setup instructions, then a loop of two per increment
+        assert_eq!(chip8.reg[1], 14);
+    }
+
+    #[test]
+    fn keeps_framebuffer_in_display_colors() {
+        let mut chip8 = Chip8::with_seed(0);
+        let palette = Palette::parse("101010,FFB000").unwrap();
+        chip8.set_palette(palette);
+        assert_eq!(chip8.framebuffer()[0], 0x10_10_10);
+        // Draw the "0" glyph at the top left corner
+        chip8
+            .execute_opcode(Opcode::DisplaySprite(Register::V0, Register::V0, 5))
+            .unwrap();
+        chip8.present();
+        assert_eq!(chip8.framebuffer()[0], 0xFF_B0_00);
+        assert_eq!(chip8.framebuffer()[SCREEN_WIDTH + 1], 0x10_10_10);
+        chip8.set_palette(Palette::default());
+        assert_eq!(chip8.framebuffer()[0], 0xFF_FF_FF);
+        chip8.execute_opcode(Opcode::ClearDisplay).unwrap();
+        chip8.present();
+        assert!(chip8.framebuffer().iter().all(|c| *c == 0));
+    }
+
+    #[test]
+    fn presents_whole_frames() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_instructions_per_frame(Some(2));
+        // CLS; DRW V0, V0, 5; then erase it and draw it again, with the frame ending in between
+        chip8.load_program(&[0x00, 0xE0, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05]);
+        chip8.run_frame().unwrap();
+        let lit = Palette::default().color(1);
+        assert_eq!(chip8.framebuffer()[0], lit);
+        chip8.step().unwrap();
+        assert_eq!(chip8.screen()[0], 0);
+        assert_eq!(chip8.framebuffer()[0], lit);
+        chip8.step().unwrap();
+        chip8.present();
+        assert_eq!(chip8.framebuffer()[0], lit);
+    }
+
+    #[test]
+    fn flags_display_changes() {
+        let mut chip8 = Chip8::with_seed(0);
+        // LD V0, 0x01; CLS; DRW V0, V0, 1
+        chip8.load_program(&[0x60, 0x01, 0x00, 0xE0, 0xD0, 0x01]);
+        assert!(chip8.take_display_dirty());
+        chip8.step().unwrap();
+        assert!(!chip8.take_display_dirty());
+        chip8.step().unwrap();
+        assert!(chip8.take_display_dirty());
+        chip8.step().unwrap();
+        assert!(chip8.take_display_dirty());
+        assert!(!chip8.take_display_dirty());
+        chip8.set_palette(Palette::default());
+        assert!(chip8.take_display_dirty());
+    }
+
+    #[test]
+    fn reports_what_steps_did() {
+        let mut chip8 = Chip8::with_seed(0);
+        // LD V3, 0x7B; LD I, 0x300; LD B, V3; DRW V0, V0, 1; SE V0, 0; (skipped); LD V1, K
+        chip8.load_program(&[
+            0x63, 0x7B, 0xA3, 0x00, 0xF3, 0x33, 0xD0, 0x01, 0x30, 0x00, 0x00, 0x00, 0xF1, 0x0A,
+        ]);
+        let steps: Vec<Executed> = chip8.steps().map(Result::unwrap).collect();
+        assert_eq!(steps.len(), 6);
+        assert_eq!(steps[0].opcode, Opcode::LoadConstant(Register::V3, 0x7B));
+        assert_eq!(steps[0].changed_registers, 1 << 3);
+        assert_eq!(steps[2].wrote, Some(0x300..0x303));
+        assert!(steps[3].drew && !steps[2].drew);
+        assert_eq!((steps[4].pc, steps[4].branched), (0x208, true));
+        assert!(steps[5].waits_for_key);
+        // The power-on redraw is still there for the frontend
+        assert!(chip8.take_display_dirty());
+        assert_eq!(chip8.step(), Ok(None));
+    }
+
+    #[test]
+    fn renders_scaled_images() {
+        let mut chip8 = Chip8::default();
+        // Draw the top row of the 0 digit at the top left: 1111 then blank
+        chip8.load_program(&[0xD0, 0x01]);
+        chip8.run_frame().unwrap();
+        let palette = Palette::default();
+        let image = chip8.render_to_image(2, palette);
+        assert_eq!(image.len(), 128 * 64 * 4);
+        let lit = (palette.color(1) << 8 | 0xFF).to_be_bytes();
+        let unlit = (palette.color(0) << 8 | 0xFF).to_be_bytes();
+        let pixel = |x: usize, y: usize| &image[(y * 128 + x) * 4..(y * 128 + x + 1) * 4];
+        assert_eq!((pixel(0, 0), pixel(7, 1)), (&lit[..], &lit[..]));
+        assert_eq!((pixel(8, 0), pixel(0, 2)), (&unlit[..], &unlit[..]));
+    }
+
+    #[test]
+    fn reports_runs() {
+        let mut chip8 = Chip8::with_seed(0);
+        // LD V0, 2; LD ST, V0; CLS; LD V1, K
+        chip8.load_program(&[0x60, 0x02, 0xF0, 0x18, 0x00, 0xE0, 0xF1, 0x0A]);
+        let report = chip8.run_for(2).unwrap();
+        assert_eq!(report.instructions, 2);
+        assert!(report.sound_started && !report.display_changed);
+        // Stops at the key wait, short of the cycles asked for
+        let report = chip8.run_for(5).unwrap();
+        assert_eq!(report.instructions, 2);
+        assert!(report.display_changed && report.waiting_for_key);
+        assert!(!report.sound_started);
+        chip8.run_frame().unwrap();
+        assert!(chip8.run_frame().unwrap().sound_stopped);
+    }
+
+    /// Runs a single 8xyN instruction on V1 and V2 with VF starting out as 0x55, returning
+    /// the result in V1 and the resulting VF.
+    fn alu(legacy_flags: bool, n: u8, v1: u8, v2: u8) -> (u8, u8) {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_quirks(Quirks {
+            legacy_flags,
+            ..Quirks::default()
+        });
+        chip8.load_program(&[0x81, 0x20 | n]);
+        chip8.reg[1] = v1;
+        chip8.reg[2] = v2;
+        chip8.reg[0xF] = 0x55;
+        chip8.step().unwrap();
+        (chip8.reg[1], chip8.reg[0xF])
+    }
+
+    #[test]
+    fn sets_and_clears_alu_flags() {
+        assert_eq!(alu(false, 0x4, 0xFF, 0x01), (0x00, 1));
+        assert_eq!(alu(false, 0x4, 0x10, 0x20), (0x30, 0));
+        assert_eq!(alu(false, 0x5, 5, 3), (2, 1));
+        assert_eq!(alu(false, 0x5, 3, 5), (0xFE, 0));
+        assert_eq!(alu(false, 0x5, 4, 4), (0, 1));
+        assert_eq!(alu(false, 0x6, 0x03, 0), (0x01, 1));
+        assert_eq!(alu(false, 0x6, 0x02, 0), (0x01, 0));
+        assert_eq!(alu(false, 0x7, 3, 5), (2, 1));
+        assert_eq!(alu(false, 0x7, 5, 3), (0xFE, 0));
+        assert_eq!(alu(false, 0x7, 4, 4), (0, 1));
+        assert_eq!(alu(false, 0xE, 0x81, 0), (0x02, 1));
+        assert_eq!(alu(false, 0xE, 0x41, 0), (0x82, 0));
+
+        // The flag wins when VF is the destination
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&[0x8F, 0x24]);
+        chip8.reg[0xF] = 0xFF;
+        chip8.reg[2] = 0x01;
+        chip8.step().unwrap();
+        assert_eq!(chip8.reg[0xF], 1);
+    }
+
+    #[test]
+    fn legacy_flags_only_raise_vf() {
+        assert_eq!(alu(true, 0x4, 0xFF, 0x01), (0x00, 1));
+        assert_eq!(alu(true, 0x4, 0x10, 0x20), (0x30, 0x55));
+        assert_eq!(alu(true, 0x5, 5, 3), (2, 1));
+        assert_eq!(alu(true, 0x5, 3, 5), (0xFE, 0x55));
+        assert_eq!(alu(true, 0x5, 4, 4), (0, 0x55));
+        assert_eq!(alu(true, 0x6, 0x03, 0), (0x01, 1));
+        assert_eq!(alu(true, 0x6, 0x02, 0), (0x01, 0x55));
+        assert_eq!(alu(true, 0x7, 3, 5), (2, 1));
+        assert_eq!(alu(true, 0x7, 4, 4), (0, 0x55));
+        assert_eq!(alu(true, 0xE, 0x81, 0), (0x02, 1));
+        assert_eq!(alu(true, 0xE, 0x41, 0), (0x82, 0x55));
+
+        // The result wins when VF is the destination
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_quirks(Quirks::parse("legacy-flags").unwrap());
+        chip8.load_program(&[0x8F, 0x24]);
+        chip8.reg[0xF] = 0xFF;
+        chip8.reg[2] = 0x01;
+        chip8.step().unwrap();
+        assert_eq!(chip8.reg[0xF], 0);
+    }
+
+    #[test]
+    fn resets_vf_on_logic_ops() {
+        for (quirks, vf) in [("", 0x55), ("vf-reset", 0)] {
+            for n in 1..=3 {
+                let mut chip8 = Chip8::with_seed(0);
+                chip8.set_quirks(Quirks::parse(quirks).unwrap());
+                chip8.load_program(&[0x81, 0x20 | n]);
+                chip8.reg[1] = 0x0C;
+                chip8.reg[2] = 0x0A;
+                chip8.reg[0xF] = 0x55;
+                chip8.step().unwrap();
+                assert_eq!(chip8.reg[1], [0x0E, 0x08, 0x06][n as usize - 1]);
+                assert_eq!(chip8.reg[0xF], vf);
+            }
+        }
+    }
+
+    #[test]
+    fn clears_vf_without_collision() {
+        for legacy_flags in [false, true] {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_quirks(Quirks {
+                legacy_flags,
+                ..Quirks::default()
+            });
+            chip8.reg[0xF] = 0x55;
+            chip8
+                .execute_opcode(Opcode::DisplaySprite(Register::V0, Register::V0, 5))
+                .unwrap();
+            assert_eq!(chip8.reg[0xF], if legacy_flags { 0x55 } else { 0 });
+            chip8
+                .execute_opcode(Opcode::DisplaySprite(Register::V0, Register::V0, 5))
+                .unwrap();
+            assert_eq!(chip8.reg[0xF], 1);
+        }
+    }
+
+    /// Stores V0..V3 = 1..4 at 0x300 with Fx55 for x = 2, then loads them back with Fx65
+    /// into cleared registers, returning the memory and registers touched and the final I.
+    fn store_and_load(quirks: &str) -> ([u8; 4], [u8; 4], usize) {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_quirks(Quirks::parse(quirks).unwrap());
+        chip8.reg[..4].copy_from_slice(&[1, 2, 3, 4]);
+        chip8.i_addr = 0x300;
+        chip8
+            .execute_opcode(Opcode::StoreRegisters(Register::V2))
+            .unwrap();
+        let mut memory = [0; 4];
+        memory.copy_from_slice(&chip8.memory[0x300..0x304]);
+        chip8.reg = [0; 16];
+        chip8.i_addr = 0x300;
+        chip8
+            .execute_opcode(Opcode::LoadRegisters(Register::V2))
+            .unwrap();
+        let mut reg = [0; 4];
+        reg.copy_from_slice(&chip8.reg[..4]);
+        (memory, reg, chip8.i_addr)
+    }
+
+    #[test]
+    fn keeps_flags_only_once_enabled() {
+        // LD R, V1; LD V2, R
+        let program = [0xF1, 0x75, 0xF2, 0x85];
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&program);
+        let fault = chip8.step().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::FlagsDisabled);
+
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&program);
+        chip8.enable_flags([9; FLAG_COUNT]);
+        chip8.reg[..3].copy_from_slice(&[1, 2, 3]);
+        chip8.step().unwrap();
+        let mut flags = [9; FLAG_COUNT];
+        flags[..2].copy_from_slice(&[1, 2]);
+        assert_eq!(chip8.take_changed_flags(), Some(flags));
+        assert_eq!(chip8.take_changed_flags(), None);
+        chip8.step().unwrap();
+        assert_eq!(chip8.reg[..4], [1, 2, 9, 0]);
+    }
+
+    #[test]
+    fn runs_custom_opcodes() {
+        let mut chip8 = Chip8::with_seed(0);
+        // A debug print of Vx carved out of the SYS space, which otherwise faults
+        let printed = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = printed.clone();
+        chip8.register_opcode(0xF000, 0x0000, |_, _| Err(FaultKind::InvalidOpcode));
+        chip8.register_opcode(0xF0FF, 0x0010, move |chip8, word| {
+            log.lock()
+                .unwrap()
+                .push(chip8.registers()[usize::from(word >> 8 & 0xF)]);
+            chip8.set_register(0xF, 1);
+            Ok(())
+        });
+        // LD V3, 7; print V3; SYS 123
+        chip8.load_program(&[0x63, 0x07, 0x03, 0x10, 0x01, 0x23]);
+        chip8.step().unwrap();
+        let executed = chip8.step().unwrap().unwrap();
+        assert_eq!(executed.opcode, Opcode::Custom(0x0310));
+        assert_eq!(executed.opcode.to_string(), "CUSTOM 0x0310");
+        assert_eq!(*printed.lock().unwrap(), vec![7]);
+        assert_eq!(chip8.registers()[0xF], 1);
+        assert_eq!(chip8.step().unwrap_err().kind, FaultKind::InvalidOpcode);
+        assert_eq!(chip8.pc(), 0x204);
+    }
+
+    /// A serial port that collects the bytes written to it, and reads back how many it has.
+    struct Serial(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Peripheral for Serial {
+        fn read(&mut self, _: usize) -> u8 {
+            self.0.lock().unwrap().len() as u8
+        }
+
+        fn write(&mut self, _: usize, value: u8) {
+            self.0.lock().unwrap().push(value);
+        }
+    }
+
+    #[test]
+    fn maps_peripherals_into_memory() {
+        let sent = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.map_peripheral(0xF00..0xF01, Serial(sent.clone()));
+        // LD I, 0xEFF; LD V0, 0x48; LD V1, 0x49; LD [I], V1; LD V0, 0; LD V1, [I]
+        chip8.load_program(&[
+            0xAE, 0xFF, 0x60, 0x48, 0x61, 0x49, 0xF1, 0x55, 0x60, 0x00, 0xF1, 0x65,
+        ]);
+        for _ in 0..6 {
+            chip8.step().unwrap();
+        }
+        // Only the byte at 0xF00 went to the port, which then read back one byte sent
+        assert_eq!(*sent.lock().unwrap(), vec![0x49]);
+        assert_eq!(chip8.reg[..2], [0x48, 1]);
+        assert_eq!(chip8.memory()[0xF00], 1);
+    }
+
+    #[test]
+    fn shifts_vx_or_vy() {
+        for (quirks, expected) in [("", (0x01, 1)), ("shift-vy", (0x40, 0))] {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_quirks(Quirks::parse(quirks).unwrap());
+            // SHR V1, V2; SHL V3, V4
+            chip8.load_program(&[0x81, 0x26, 0x83, 0x4E]);
+            chip8.reg[1] = 0x03;
+            chip8.reg[2] = 0x80;
+            chip8.reg[3] = 0x81;
+            chip8.reg[4] = 0x20;
+            chip8.step().unwrap();
+            assert_eq!((chip8.reg[1], chip8.reg[0xF]), expected);
+            chip8.step().unwrap();
+            let shifted_left = if quirks.is_empty() {
+                (0x02, 1)
+            } else {
+                (0x40, 0)
+            };
+            assert_eq!((chip8.reg[3], chip8.reg[0xF]), shifted_left);
+            assert_eq!((chip8.reg[2], chip8.reg[4]), (0x80, 0x20));
+        }
+    }
+
+    #[test]
+    fn jumps_with_v0_or_vx_offset() {
+        for (quirks, expected) in [("", 0x311), ("jump-vx", 0x333)] {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_quirks(Quirks::parse(quirks).unwrap());
+            chip8.reg[0] = 0x11;
+            chip8.reg[3] = 0x33;
+            chip8.execute_opcode(Opcode::JumpPlus(0x300)).unwrap();
+            assert_eq!(chip8.pc(), expected);
+        }
+    }
+
+    #[test]
+    fn clips_or_wraps_sprites() {
+        for (quirks, wrapped) in [("", 0), ("wrap-sprites", 1)] {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_quirks(Quirks::parse(quirks).unwrap());
+            // The "0" glyph at (62, 30): the top left corner always lands on the screen
+            chip8.reg[1] = 62;
+            chip8.reg[2] = 30;
+            chip8
+                .execute_opcode(Opcode::DisplaySprite(Register::V1, Register::V2, 5))
+                .unwrap();
+            assert_eq!(chip8.screen()[30 * SCREEN_WIDTH + 62], 1);
+            assert_eq!(chip8.screen()[30 * SCREEN_WIDTH + 1], wrapped);
+            assert_eq!(chip8.screen()[2 * SCREEN_WIDTH + 62], wrapped);
+
+            // Only the starting position wraps when clipping
+            chip8.execute_opcode(Opcode::ClearDisplay).unwrap();
+            chip8.reg[1] = 64 + 2;
+            chip8.reg[2] = 32 + 1;
+            chip8
+                .execute_opcode(Opcode::DisplaySprite(Register::V1, Register::V2, 5))
+                .unwrap();
+            assert_eq!(chip8.screen()[SCREEN_WIDTH + 2], 1);
+        }
+    }
+
+    #[test]
+    fn transfers_registers_through_memory() {
+        assert_eq!(store_and_load(""), ([1, 2, 3, 0], [1, 2, 3, 0], 0x300));
+        assert_eq!(
+            store_and_load("memory-increment"),
+            ([1, 2, 3, 0], [1, 2, 3, 0], 0x303)
+        );
+        assert_eq!(
+            store_and_load("exclusive-range"),
+            ([1, 2, 0, 0], [1, 2, 0, 0], 0x300)
+        );
+        assert_eq!(
+            store_and_load("memory-increment,exclusive-range"),
+            ([1, 2, 0, 0], [1, 2, 0, 0], 0x302)
+        );
+    }
+
+    #[test]
+    fn stalls_until_key_press() {
+        let mut chip8 = Chip8::with_seed(0);
+        // LD V3, K; ADD V3, 1
+        chip8.load_program(&[0xF3, 0x0A, 0x73, 0x01]);
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(chip8.reg[3], 0);
+        chip8.set_key_down(0xB);
+        chip8.step().unwrap();
+        assert!(!chip8.is_waiting_for_key());
+        assert_eq!(chip8.reg[3], 0xC);
+    }
+
+    #[test]
+    fn holds_keys_as_configured() {
+        // LD V3, K; LD V3, K; SKP V4
+        let program = [0xF3, 0x0A, 0xF3, 0x0A, 0xE4, 0x9E];
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&program);
+        chip8.set_key_down(0x7);
+        // Without repeat, a key held from before the wait doesn't answer it
+        chip8.step().unwrap();
+        assert!(chip8.is_waiting_for_key());
+
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_key_hold(KeyHold {
+            repeat: true,
+            release_frames: 2,
+        });
+        chip8.load_program(&program);
+        chip8.set_key_down(0x7);
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        assert!(!chip8.is_waiting_for_key());
+        assert_eq!(chip8.reg[3], 0x7);
+        // The release lingers for two frames
+        chip8.set_key_up(0x7);
+        chip8.reg[4] = 0x7;
+        chip8.tick_timers();
+        chip8.step().unwrap();
+        assert_eq!(chip8.pc(), 0x208);
+        chip8.set_pc(0x204);
+        chip8.tick_timers();
+        chip8.step().unwrap();
+        assert_eq!(chip8.pc(), 0x206);
+    }
+
+    #[test]
+    fn counts_memory_access_when_tracking() {
+        let mut chip8 = Chip8::with_seed(0);
+        // LD I, 0x300; LD [I], V2; DRW V0, V0, 4
+        chip8.load_program(&[0xA3, 0x00, 0xF2, 0x55, 0xD0, 0x04]);
+        chip8.step().unwrap();
+        assert_eq!(chip8.memory_access(), None);
+        chip8.track_memory_access();
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        let access = chip8.memory_access().unwrap();
+        assert_eq!(&access.writes[0x2FF..0x304], &[0, 1, 1, 1, 0]);
+        assert_eq!(&access.reads[0x2FF..0x305], &[0, 1, 1, 1, 1, 0]);
+        assert_eq!(&access.executions[0x200..0x206], &[0, 0, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn collects_collisions_when_tracking() {
+        let mut chip8 = Chip8::with_seed(0);
+        // DRW V0, V0, 1 four times, drawing and erasing the top row of the font's 0 (11110000)
+        chip8.load_program(&[0xD0, 0x01, 0xD0, 0x01, 0xD0, 0x01, 0xD0, 0x01]);
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        assert!(chip8.take_collisions().is_empty());
+        chip8.track_collisions();
+        chip8.step().unwrap();
+        assert!(chip8.take_collisions().is_empty());
+        chip8.step().unwrap();
+        assert_eq!(chip8.take_collisions(), vec![0, 1, 2, 3]);
+        assert!(chip8.take_collisions().is_empty());
+    }
+
+    #[test]
+    fn randomizes_power_on_state() {
+        let mut chip8 = Chip8::with_seed(7);
+        chip8.randomize();
+        assert_eq!(&chip8.memory[..FONT.len()], &FONT[..]);
+        assert!(chip8.memory[0x200..].iter().any(|b| *b != 0));
+        assert!(chip8.reg.iter().any(|r| *r != 0));
+        assert!(chip8.screen.iter().any(|row| *row != 0));
+
+        // The same seed powers on the same way, without disturbing the random stream
+        let mut again = Chip8::with_seed(7);
+        again.randomize();
+        assert_eq!(again.state_hash(), chip8.state_hash());
+        let mut zeroed = Chip8::with_seed(7);
+        assert_eq!(chip8.next_random(), zeroed.next_random());
+    }
+
+    #[test]
+    fn keeps_stack_in_memory() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_quirks(Quirks::parse("memory-stack").unwrap());
+        // CALL 0x204; (unused); RET
+        chip8.load_program(&[0x22, 0x04, 0x00, 0x00, 0x00, 0xEE]);
+        chip8.step().unwrap();
+        assert_eq!(
+            chip8.memory()[STACK_ADDRESS..STACK_ADDRESS + 2],
+            [0x02, 0x02]
+        );
+        assert_eq!(chip8.stack(), vec![0x202]);
+        // Programs can rewrite where they return to
+        chip8.write_memory(STACK_ADDRESS, &[0x03, 0x00]);
+        chip8.step().unwrap();
+        assert_eq!(chip8.pc(), 0x300);
+        chip8.set_pc(0x204);
+        assert_eq!(chip8.step().unwrap_err().kind, FaultKind::StackUnderflow);
+    }
+
+    #[test]
+    fn limits_both_kinds_of_stack() {
+        for (kind, limit) in [
+            (StackKind::Fixed, STACK_SIZE),
+            (StackKind::Growable, MAX_STACK_DEPTH),
+        ] {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_stack_kind(kind).unwrap();
+            // CALL 0x200 until the stack is full, then RET all the way back and once more
+            chip8.load_program(&[0x22, 0x00, 0x00, 0xEE]);
+            for _ in 0..limit {
+                chip8.step().unwrap();
+            }
+            assert_eq!(chip8.stack_depth(), limit);
+            let fault = chip8.step().unwrap_err();
+            assert_eq!(fault.kind, FaultKind::StackOverflow { levels: limit });
+
+            let state = chip8.save_state();
+            let mut restored = Chip8::with_seed(0);
+            restored.set_stack_kind(kind).unwrap();
+            restored.load_state(&state).unwrap();
+            assert_eq!(restored.stack(), chip8.stack());
+            restored.set_pc(0x202);
+            for _ in 0..limit {
+                restored.step().unwrap();
+                restored.set_pc(0x202);
+            }
+            assert_eq!(restored.step().unwrap_err().kind, FaultKind::StackUnderflow);
+        }
+        // A fixed stack can't take in a deeper growable one
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_stack_kind(StackKind::Growable).unwrap();
+        chip8.load_program(&[0x22, 0x00]);
+        for _ in 0..=STACK_SIZE {
+            chip8.step().unwrap();
+        }
+        assert!(Chip8::with_seed(0).load_state(&chip8.save_state()).is_err());
+    }
+
+    #[test]
+    fn keeps_calls_when_the_stack_moves() {
+        for quirks in ["", "memory-stack"] {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_quirks(Quirks::parse(quirks).unwrap());
+            chip8.set_stack_kind(StackKind::Growable).unwrap();
+            // CALL 0x200 forever
+            chip8.load_program(&[0x22, 0x00]);
+            for _ in 0..=STACK_SIZE {
+                chip8.step().unwrap();
+            }
+            // Too deep to fit a fixed stack, so it stays growable
+            assert!(chip8.set_stack_kind(StackKind::Fixed).is_err());
+            assert_eq!(chip8.stack_kind(), StackKind::Growable);
+
+            // Partway down a chain that fits, the calls move across and keep returning
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_quirks(Quirks::parse(quirks).unwrap());
+            chip8.set_stack_kind(StackKind::Growable).unwrap();
+            // CALL 0x204; (unused); CALL 0x208; (unused); RET
+            chip8.load_program(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00, 0x00, 0xEE]);
+            chip8.step().unwrap();
+            chip8.step().unwrap();
+            chip8.set_stack_kind(StackKind::Fixed).unwrap();
+            assert_eq!(chip8.stack(), vec![0x202, 0x206]);
+            chip8.step().unwrap();
+            assert_eq!(chip8.pc(), 0x206);
+            // Toggling the memory stack quirk moves the calls in or out of memory too
+            let other = if quirks.is_empty() {
+                "memory-stack"
+            } else {
+                ""
+            };
+            chip8.set_quirks(Quirks::parse(other).unwrap());
+            chip8.set_pc(0x208);
+            chip8.step().unwrap();
+            assert_eq!(chip8.pc(), 0x202);
+        }
+    }
+
+    #[test]
+    fn turns_away_programs_too_large_to_fit() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.try_load_program(&[0x12; MAX_PROGRAM_SIZE]).unwrap();
+        assert_eq!(chip8.memory()[0xFFF], 0x12);
+        let error = chip8
+            .try_load_program(&[0; MAX_PROGRAM_SIZE + 1])
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "ROM is too large: 3585 bytes, more than the 3584 that fit"
+        );
+        assert_eq!(chip8.memory()[0xFFF], 0x12);
+    }
+
+    #[test]
+    fn reports_faults() {
+        let mut chip8 = Chip8::with_seed(0);
+        // CALL 0x200 forever, then RET on an empty stack
+        chip8.load_program(&[0x22, 0x00]);
+        for _ in 0..STACK_SIZE {
+            chip8.step().unwrap();
+        }
+        let fault = chip8.step().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::StackOverflow { levels: STACK_SIZE });
+        assert_eq!(chip8.pc(), 0x200);
+        assert_eq!(
+            fault.to_string(),
+            "Tried to call a subroutine with the stack full (16 levels) at 200: 2200 (CALL 0x200)"
+        );
+
+        let mut chip8 = Chip8::with_seed(0);
+        // RET; (invalid); LD I, 0xFFE; LD V2, [I]
+        chip8.load_program(&[0x00, 0xEE, 0xFF, 0xFF, 0xAF, 0xFE, 0xF2, 0x65]);
+        assert_eq!(chip8.step().unwrap_err().kind, FaultKind::StackUnderflow);
+        chip8.set_pc(0x202);
+        assert_eq!(
+            chip8.step(),
+            Err(Fault {
+                kind: FaultKind::InvalidOpcode,
+                pc: 0x202,
+                word: 0xFFFF,
+                opcode: None,
+            })
+        );
+        chip8.set_pc(0x204);
+        chip8.step().unwrap();
+        let fault = chip8.step().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::OutOfBounds { i: 0xFFE });
+        assert_eq!(fault.opcode, Some(Opcode::LoadRegisters(Register::V2)));
+        chip8.set_pc(0xFFF);
+        assert_eq!(chip8.step().unwrap_err().kind, FaultKind::PcOutOfBounds);
+
+        // ADD I, V0 forever wraps I around rather than overflowing
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&[0xF0, 0x1E, 0x12, 0x00]);
+        chip8.reg[0] = 0xFF;
+        chip8.set_i_addr(0xFFF0);
+        chip8.step().unwrap();
+        assert_eq!(chip8.i_addr(), 0xEF);
+    }
+
+    #[test]
+    fn save_state_preserves_random_stream() {
+        let mut original = Chip8::with_seed(1234);
+        original
+            .execute_opcode(Opcode::Random(Register::V0, 0xFF))
+            .unwrap();
+        let mut restored = Chip8::default();
+        restored.load_state(&original.save_state()).unwrap();
+
+        for _ in 0..8 {
+            original
+                .execute_opcode(Opcode::Random(Register::V1, 0xFF))
+                .unwrap();
+            restored
+                .execute_opcode(Opcode::Random(Register::V1, 0xFF))
+                .unwrap();
+            assert_eq!(original.reg[1], restored.reg[1]);
+        }
+        assert_eq!(original.state_hash(), restored.state_hash());
+    }
+
+    #[test]
+    fn rejects_invalid_save_states() {
+        let mut c8 = Chip8::default();
+        assert!(c8.load_state(b"nope").is_err());
+
+        let mut data = c8.save_state();
+        data.pop();
+        let error = c8.load_state(&data).unwrap_err().to_string();
+        assert_eq!(error, "Save state is truncated, in the RNG position");
+
+        let mut data = c8.save_state();
+        data[4] = STATE_VERSION + 1;
+        let error = c8.load_state(&data).unwrap_err().to_string();
+        assert!(error.contains("reads versions 1 to"));
+    }
+
+    #[test]
+    fn migrates_old_save_states() {
+        let mut c8 = Chip8::with_seed(5);
+        // LD V0, 7; CALL 206; (unused); JP 206
+        c8.load_program(&[0x60, 0x07, 0x22, 0x06, 0x00, 0x00, 0x12, 0x06]);
+        c8.step().unwrap();
+        c8.step().unwrap();
+        let current = c8.save_state();
+
+        // Version 2 had no audio, which sits before the 16 bytes of RNG
+        let audio = current.len() - 16 - 17..current.len() - 16;
+        let mut v2 = current.clone();
+        v2.drain(audio);
+        v2[4] = 2;
+        let mut loaded = Chip8::with_seed(5);
+        loaded.load_state(&v2).unwrap();
+        assert_eq!(loaded.save_state(), current);
+
+        // And version 1 had no RNG either
+        let mut v1 = v2.clone();
+        v1.truncate(v2.len() - 16);
+        v1[4] = 1;
+        let mut loaded = Chip8::with_seed(5);
+        loaded.load_state(&v1).unwrap();
+        assert_eq!(loaded.save_state(), current);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn round_trips_through_serde() {
+        let mut c8 = Chip8::with_seed(7);
+        c8.set_quirks(Quirks::parse("vf-reset").unwrap());
+        c8.set_instructions_per_frame(Some(11));
+        // CALL 204; RND V0, FF; LD V1, K
+        c8.load_program(&[0x22, 0x04, 0x00, 0x00, 0xC0, 0xFF, 0xF1, 0x0A]);
+        for _ in 0..3 {
+            c8.step().unwrap();
+        }
+        let json = serde_json::to_string(&c8).unwrap();
+        let mut restored: Chip8 = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.save_state(), c8.save_state());
+        assert_eq!(restored.quirks(), c8.quirks());
+        assert_eq!(restored.instructions_per_frame(), Some(11));
+        // The RNG picks up where it left off
+        restored.load_program(&[0xC0, 0xFF]);
+        c8.load_program(&[0xC0, 0xFF]);
+        restored.pc = 0x200;
+        c8.pc = 0x200;
+        restored.step().unwrap();
+        c8.step().unwrap();
+        assert_eq!(restored.registers(), c8.registers());
+
+        // Memory starts with the font, whose first byte is 0xF0
+        let short = json.replacen("\"memory\":[240,", "\"memory\":[", 1);
+        assert_ne!(short, json);
+        assert!(serde_json::from_str::<Chip8>(&short).is_err());
+    }
+
+    proptest::proptest! {
+        // Any ROM, under any quirks and with any keys held, runs or faults but never panics
+        #[test]
+        fn never_panics_on_arbitrary_roms(
+            rom in proptest::collection::vec(proptest::num::u8::ANY, 0..0x1000),
+            quirks in proptest::num::u8::ANY,
+            keys in proptest::num::u16::ANY,
+        ) {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_quirks(Quirks {
+                legacy_flags: quirks & 1 != 0,
+                memory_increment: quirks & 2 != 0,
+                exclusive_range: quirks & 4 != 0,
+                shift_vy: quirks & 8 != 0,
+                jump_vx: quirks & 16 != 0,
+                wrap_sprites: quirks & 32 != 0,
+                vf_reset: quirks & 64 != 0,
+                memory_stack: quirks & 128 != 0,
+            });
+            chip8.track_memory_access();
+            chip8.load_program(&rom);
+            for key in 0..16 {
+                if keys & 1 << key != 0 {
+                    chip8.set_key_down(key);
+                }
+            }
+            for _ in 0..1000 {
+                if chip8.tick().is_err() {
+                    break;
+                }
+            }
+        }
+
+        // Drawing whole rows at once matches drawing pixel by pixel
+        #[test]
+        fn draws_sprites_like_pixel_by_pixel(
+            screen in proptest::collection::vec(0..2u8, SCREEN_WIDTH * SCREEN_HEIGHT),
+            sprite in proptest::collection::vec(proptest::num::u8::ANY, 15),
+            x in proptest::num::u8::ANY,
+            y in proptest::num::u8::ANY,
+            wrap in proptest::bool::ANY,
+        ) {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_quirks(Quirks { wrap_sprites: wrap, ..Quirks::default() });
+            chip8.screen = pack_screen(&screen);
+            chip8.memory[0x300..0x30F].copy_from_slice(&sprite);
+            chip8.i_addr = 0x300;
+            chip8.reg[0] = x;
+            chip8.reg[1] = y;
+            chip8.track_collisions();
+            chip8.execute_opcode(Opcode::DisplaySprite(Register::V0, Register::V1, 15)).unwrap();
+
+            let mut expected = screen;
+            let mut collided = vec![];
+            let (x, y) = (x as usize % SCREEN_WIDTH, y as usize % SCREEN_HEIGHT);
+            for (y_offset, line) in sprite.iter().enumerate() {
+                for x_offset in 0..8 {
+                    let (mut dest_x, mut dest_y) = (x + x_offset, y + y_offset);
+                    if wrap {
+                        dest_x %= SCREEN_WIDTH;
+                        dest_y %= SCREEN_HEIGHT;
+                    } else if dest_x >= SCREEN_WIDTH || dest_y >= SCREEN_HEIGHT {
+                        continue;
+                    }
+                    let dest_index = dest_y * SCREEN_WIDTH + dest_x;
+                    if line >> (7 - x_offset) & 1 == 1 {
+                        if expected[dest_index] == 1 {
+                            collided.push(dest_index);
+                        }
+                        expected[dest_index] ^= 1;
+                    }
+                }
+            }
+            proptest::prop_assert_eq!(chip8.screen(), expected);
+            proptest::prop_assert_eq!(chip8.reg[0xF], !collided.is_empty() as u8);
+            collided.sort_unstable();
+            let mut hits = chip8.take_collisions();
+            hits.sort_unstable();
+            proptest::prop_assert_eq!(hits, collided);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::{Duration, Instant};
+
+/// Frames per second. The timers count down once per frame.
+pub const FRAME_RATE: u32 = 60;
+/// The ideal frame duration at FRAME_RATE.
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / FRAME_RATE as u64);
+/// Window updates timed before settling on a refresh rate for `--vsync`.
+const CALIBRATION_UPDATES: usize = 30;
+/// Updates coming back faster than any display refreshes mean they don't wait for vsync.
+const MAX_REFRESH_RATE: u32 = 240;
+
+/// Turns elapsed wall-clock time into a number of whole frames to run. Any leftover time is
+/// carried over so that even if the caller's timing is inconsistent, the frame rate will
+/// largely remain fairly stable.
+#[derive(Clone, Debug, Default)]
+pub struct FrameClock {
+    elapsed: Duration,
+}
+
+impl FrameClock {
+    /// Adds `dt` to the time carried over from earlier calls and returns how many frames are
+    /// now due.
+    pub fn advance(&mut self, dt: Duration) -> u32 {
+        self.elapsed += dt;
+        let frames = self.elapsed.as_nanos() / FRAME_DURATION.as_nanos();
+        self.elapsed -= FRAME_DURATION * frames as u32;
+        frames as u32
+    }
+}
+
+/// How `--vsync` is pacing the emulator.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pacing {
+    /// Still timing window updates, which run on wall-clock time in the meantime.
+    Calibrating,
+    /// Window updates wait for a display refreshing this many times a second, and each one
+    /// advances the emulator by exactly one refresh.
+    Locked(u32),
+    /// Window updates don't wait for the display, so they are slept to FRAME_RATE instead.
+    Unsynced,
+}
+
+/// Ties emulation to window updates for `--vsync`. When updates wait for the display, every
+/// update stands for one refresh, so at 60Hz each one runs exactly one frame with no jitter
+/// from the wall clock. The refresh rate is measured from the first updates rather than asked
+/// of the display.
+#[derive(Clone, Debug)]
+pub struct VsyncPacer {
+    pacing: Pacing,
+    last_update: Option<Instant>,
+    intervals: Vec<Duration>,
+}
+
+impl Default for VsyncPacer {
+    fn default() -> Self {
+        VsyncPacer {
+            pacing: Pacing::Calibrating,
+            last_update: None,
+            intervals: Vec::with_capacity(CALIBRATION_UPDATES),
+        }
+    }
+}
+
+impl VsyncPacer {
+    pub fn pacing(&self) -> Pacing {
+        self.pacing
+    }
+
+    /// Notes a window update and returns how much emulated time it stands for.
+    pub fn update(&mut self, now: Instant) -> Duration {
+        let elapsed = self
+            .last_update
+            .map_or(Duration::ZERO, |last| now.duration_since(last));
+        self.last_update = Some(now);
+        match self.pacing {
+            Pacing::Locked(hz) => Duration::from_secs(1) / hz,
+            Pacing::Unsynced => FRAME_DURATION,
+            Pacing::Calibrating => {
+                if !elapsed.is_zero() {
+                    self.intervals.push(elapsed);
+                }
+                if self.intervals.len() == CALIBRATION_UPDATES {
+                    // The median shrugs off updates delayed by the OS
+                    self.intervals.sort();
+                    let interval = self.intervals[CALIBRATION_UPDATES / 2];
+                    let hz = (1.0 / interval.as_secs_f64()).round() as u32;
+                    self.pacing = if hz <= MAX_REFRESH_RATE {
+                        Pacing::Locked(hz)
+                    } else {
+                        Pacing::Unsynced
+                    };
+                }
+                elapsed
+            }
+        }
+    }
+
+    /// How long to sleep before the next window update, which is only needed when updates
+    /// don't wait for the display themselves.
+    pub fn wait(&self, now: Instant) -> Duration {
+        match (self.pacing, self.last_update) {
+            (Pacing::Unsynced, Some(last)) => {
+                (last + FRAME_DURATION).saturating_duration_since(now)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_leftover_time() {
+        let mut clock = FrameClock::default();
+        assert_eq!(clock.advance(Duration::from_millis(50)), 3);
+        assert_eq!(clock.advance(Duration::from_millis(10)), 0);
+        assert_eq!(clock.advance(Duration::from_millis(7)), 1);
+        assert_eq!(clock.advance(Duration::from_secs(1)), FRAME_RATE);
+    }
+
+    fn calibrate(pacer: &mut VsyncPacer, start: Instant, interval: Duration) -> Instant {
+        let mut now = start;
+        for _ in 0..=CALIBRATION_UPDATES {
+            assert_eq!(pacer.pacing(), Pacing::Calibrating);
+            now += interval;
+            pacer.update(now);
+        }
+        now
+    }
+
+    #[test]
+    fn locks_to_measured_refresh() {
+        let mut pacer = VsyncPacer::default();
+        let now = calibrate(&mut pacer, Instant::now(), Duration::from_micros(6944));
+        assert_eq!(pacer.pacing(), Pacing::Locked(144));
+        // A late update still only advances one refresh
+        let dt = pacer.update(now + Duration::from_millis(30));
+        assert_eq!(dt, Duration::from_secs(1) / 144);
+        assert_eq!(pacer.wait(now), Duration::ZERO);
+    }
+
+    #[test]
+    fn sleeps_when_updates_dont_wait() {
+        let mut pacer = VsyncPacer::default();
+        let now = calibrate(&mut pacer, Instant::now(), Duration::from_micros(100));
+        assert_eq!(pacer.pacing(), Pacing::Unsynced);
+        assert_eq!(pacer.update(now), FRAME_DURATION);
+        let later = now + Duration::from_millis(10);
+        assert_eq!(
+            pacer.wait(later),
+            FRAME_DURATION - Duration::from_millis(10)
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! An emulator view for egui tools (`--features egui`). The widget runs the machine in real
+//! time while it's shown and playing, takes keypad input from the keyboard while it has
+//! focus (click it to focus), and scales the display to the width it's given:
+//!
+//! ```ignore
+//! let mut emulator = Chip8Widget::new(&rom)?;
+//! // Each frame
+//! emulator.controls(ui);
+//! ui.add(&mut emulator);
+//! ```
+use crate::chip8::{Chip8, Fault, SCREEN_HEIGHT, SCREEN_WIDTH};
+use egui::{
+    pos2, vec2, Color32, ColorImage, Key, Rect, Response, Sense, TextureHandle, TextureOptions, Ui,
+    Widget,
+};
+use std::error::Error;
+use std::time::Duration;
+
+/// The keys a new widget reads, for each keypad key from 0 to F. They sit in the block of
+/// keys from 1 down to V in the same spots as on the VIP keypad (see `KEYPAD_LAYOUT`).
+#[rustfmt::skip]
+pub const DEFAULT_KEYS: [Key; 16] = [Key::X, Key::Num1, Key::Num2, Key::Num3,
+    Key::Q, Key::W, Key::E,
+    Key::A, Key::S, Key::D,
+    Key::Z, Key::C,
+    Key::Num4, Key::R, Key::F, Key::V];
+
+pub struct Chip8Widget {
+    pub chip8: Chip8,
+    /// Keyboard keys for the keypad, from 0 to F.
+    pub keys: [Key; 16],
+    pub paused: bool,
+    /// The fault `controls` shows. The widget won't run again until it's cleared.
+    pub fault: Option<Fault>,
+    /// The screen, uploaded on the first frame shown.
+    texture: Option<TextureHandle>,
+}
+
+impl Chip8Widget {
+    /// Powers on a machine with a ROM loaded, ready to play.
+    pub fn new(rom: &[u8]) -> Result<Chip8Widget, Box<dyn Error>> {
+        let mut chip8 = Chip8::default();
+        chip8.try_load_program(rom)?;
+        Ok(Chip8Widget {
+            chip8,
+            keys: DEFAULT_KEYS,
+            paused: false,
+            fault: None,
+            texture: None,
+        })
+    }
+
+    /// A play/pause button, and what went wrong if the machine crashed.
+    pub fn controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let label = if self.paused { "Play" } else { "Pause" };
+            if ui.button(label).clicked() {
+                self.paused = !self.paused;
+            }
+            if let Some(fault) = self.fault {
+                ui.colored_label(ui.visuals().error_fg_color, fault.to_string());
+            }
+        });
+    }
+
+    fn running(&self) -> bool {
+        !self.paused && self.fault.is_none()
+    }
+
+    fn press_keys(&mut self, ui: &Ui, focused: bool) {
+        for (key, code) in (0..16).zip(self.keys.iter()) {
+            if focused && ui.input(|i| i.key_down(*code)) {
+                self.chip8.set_key_down(key);
+            } else {
+                self.chip8.set_key_up(key);
+            }
+        }
+    }
+
+    fn screen_image(&self) -> ColorImage {
+        let rgba = self.chip8.render_to_image(1, self.chip8.palette());
+        ColorImage::from_rgba_unmultiplied([SCREEN_WIDTH, SCREEN_HEIGHT], &rgba)
+    }
+}
+
+impl Widget for &mut Chip8Widget {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let width = ui.available_width();
+        let size = vec2(width, width * SCREEN_HEIGHT as f32 / SCREEN_WIDTH as f32);
+        let (rect, response) = ui.allocate_exact_size(size, Sense::click());
+        if response.clicked() {
+            response.request_focus();
+        }
+
+        if self.running() {
+            self.press_keys(ui, response.has_focus());
+            let dt = Duration::from_secs_f32(ui.input(|i| i.stable_dt));
+            if let Err(fault) = self.chip8.advance(dt) {
+                self.fault = Some(fault);
+            }
+            ui.ctx().request_repaint();
+        }
+
+        if self.chip8.take_display_dirty() || self.texture.is_none() {
+            let image = self.screen_image();
+            match self.texture {
+                Some(ref mut texture) => texture.set(image, TextureOptions::NEAREST),
+                None => {
+                    let texture = ui
+                        .ctx()
+                        .load_texture("chip8", image, TextureOptions::NEAREST);
+                    self.texture = Some(texture);
+                }
+            }
+        }
+        let texture = self.texture.as_ref().unwrap();
+        let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+        ui.painter().image(texture.id(), rect, uv, Color32::WHITE);
+        if response.has_focus() {
+            ui.painter()
+                .rect_stroke(rect, 0.0, ui.visuals().selection.stroke);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{CentralPanel, Context, RawInput};
+
+    fn show(ctx: &Context, widget: &mut Chip8Widget) {
+        let input = RawInput {
+            predicted_dt: 0.1,
+            ..RawInput::default()
+        };
+        let _ = ctx.run(input, |ctx| {
+            CentralPanel::default().show(ctx, |ui| {
+                ui.add(&mut *widget);
+            });
+        });
+    }
+
+    #[test]
+    fn runs_only_while_playing() {
+        // LD V0, 1; ADD V0, 1; JP 202
+        let mut widget = Chip8Widget::new(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x02]).unwrap();
+        let ctx = Context::default();
+        widget.paused = true;
+        show(&ctx, &mut widget);
+        assert_eq!(widget.chip8.pc(), 0x200);
+        assert!(widget.texture.is_some());
+
+        widget.paused = false;
+        show(&ctx, &mut widget);
+        assert!(widget.chip8.registers()[0] > 1);
+    }
+}
@@ -0,0 +1,136 @@
+//! Memory access heatmaps, for seeing which parts of memory a ROM actually uses. The
+//! debugger counts every data read and write, and the monitor's `heat` command exports the
+//! counts as a PNG: one 4x4 cell per address, 64 addresses to a row, with reads in green and
+//! writes in red, so stray writes into code stand out. Executed instructions are counted too,
+//! for the monitor's disassembly (`d`) to color hot loops.
+use crate::png;
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// Pixels per side of the cell drawn for each address.
+const CELL_SIZE: usize = 4;
+const ROW_ADDRESSES: usize = 64;
+
+/// Read, write, and execution counts for every address in memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub reads: Vec<u32>,
+    pub writes: Vec<u32>,
+    /// Times an instruction starting at each address was executed.
+    pub executions: Vec<u32>,
+}
+
+impl MemoryAccess {
+    pub fn new(memory_size: usize) -> MemoryAccess {
+        MemoryAccess {
+            reads: vec![0; memory_size],
+            writes: vec![0; memory_size],
+            executions: vec![0; memory_size],
+        }
+    }
+
+    pub fn read(&mut self, addrs: Range<usize>) {
+        count(&mut self.reads, addrs);
+    }
+
+    pub fn write(&mut self, addrs: Range<usize>) {
+        count(&mut self.writes, addrs);
+    }
+
+    pub fn execute(&mut self, addr: usize) {
+        count(&mut self.executions, addr..addr + 1);
+    }
+
+    /// How hot an address is on a logarithmic scale from 0 (never executed) to `levels - 1`
+    /// (as often as the hottest instruction).
+    pub fn execution_heat(&self, addr: usize, levels: u32) -> u32 {
+        let max = self.executions.iter().copied().max().unwrap_or(0);
+        match self.executions.get(addr).copied().unwrap_or(0) {
+            0 => 0,
+            n => {
+                let scaled = f64::from(n).ln_1p() / f64::from(max).ln_1p();
+                1 + (scaled * f64::from(levels - 2)).round() as u32
+            }
+        }
+    }
+
+    /// Renders the heatmap as RGB pixels, returning them with the image width and height.
+    /// Counts are scaled logarithmically, so a handful of accesses still shows up next to a
+    /// hot loop's thousands.
+    pub fn render(&self) -> (Vec<u8>, usize, usize) {
+        let width = ROW_ADDRESSES * CELL_SIZE;
+        let height = self.reads.len().div_ceil(ROW_ADDRESSES) * CELL_SIZE;
+        let scale = |counts: &[u32]| {
+            let max = f64::from(counts.iter().copied().max().unwrap_or(0)).ln_1p();
+            move |n: u32| match n {
+                0 => 0,
+                n => (64.0 + 191.0 * f64::from(n).ln_1p() / max) as u8,
+            }
+        };
+        let (red, green) = (scale(&self.writes), scale(&self.reads));
+        let mut pixels = vec![0; width * height * 3];
+        for addr in 0..self.reads.len() {
+            let color = [red(self.writes[addr]), green(self.reads[addr]), 0];
+            let (x, y) = (addr % ROW_ADDRESSES, addr / ROW_ADDRESSES);
+            for row in y * CELL_SIZE..(y + 1) * CELL_SIZE {
+                let start = (row * width + x * CELL_SIZE) * 3;
+                for pixel in pixels[start..start + CELL_SIZE * 3].chunks_mut(3) {
+                    pixel.copy_from_slice(&color);
+                }
+            }
+        }
+        (pixels, width, height)
+    }
+
+    pub fn write_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let (pixels, width, height) = self.render();
+        png::write(out, &pixels, width, height, &[])
+    }
+}
+
+fn count(counts: &mut [u32], addrs: Range<usize>) {
+    let end = addrs.end.min(counts.len());
+    for n in counts[addrs.start.min(end)..end].iter_mut() {
+        *n = n.saturating_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_and_renders_accesses() {
+        let mut access = MemoryAccess::new(4096);
+        access.read(0x200..0x204);
+        access.read(0x200..0x201);
+        access.write(0x300..0x302);
+        // Accesses running off the end of memory are cut short
+        access.write(0xFFF..0x1001);
+        assert_eq!(&access.reads[0x1FF..0x205], &[0, 2, 1, 1, 1, 0]);
+        assert_eq!(access.writes[0xFFF], 1);
+
+        let (pixels, width, height) = access.render();
+        assert_eq!((width, height), (256, 256));
+        let pixel = |addr: usize| {
+            let (x, y) = (addr % 64 * CELL_SIZE, addr / 64 * CELL_SIZE);
+            let start = (y * width + x) * 3;
+            &pixels[start..start + 3]
+        };
+        assert_eq!(pixel(0x1FF), &[0, 0, 0]);
+        assert_eq!(pixel(0x200), &[0, 255, 0]);
+        assert!(pixel(0x201)[1] > 64 && pixel(0x201)[1] < 255);
+        assert_eq!(pixel(0x300), &[255, 0, 0]);
+    }
+
+    #[test]
+    fn writes_png() {
+        let mut out = Vec::new();
+        MemoryAccess::new(4096).write_png(&mut out).unwrap();
+        assert_eq!(&out[1..4], b"PNG");
+        assert_eq!(
+            &out[out.len() - 12..],
+            &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+    }
+}
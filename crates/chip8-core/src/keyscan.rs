@@ -0,0 +1,56 @@
+//! Working out which keypad keys a ROM reads, by looking at its code, so touch frontends can
+//! leave the others off the screen. Games nearly always load a key into a register right
+//! before testing it (`LD V1, 5; SKNP V1`), which is what this looks for.
+use crate::opcode::Opcode;
+
+/// How many instructions back from a key test to look for the load of its key.
+const LOOKBACK: usize = 4;
+
+/// The keys `program` tests, from 0 to F, or None if any key could matter: it waits for a
+/// key press, which any key answers, or it tests a key it didn't load as a constant.
+pub fn keys_used(program: &[u8]) -> Option<[bool; 16]> {
+    // Code isn't always aligned, but games' input loops nearly always are
+    let code: Vec<Option<Opcode>> = program
+        .chunks_exact(2)
+        .map(|word| Opcode::decode(u16::from_be_bytes([word[0], word[1]])))
+        .collect();
+    let mut keys = [false; 16];
+    for (i, opcode) in code.iter().enumerate() {
+        let register = match opcode {
+            Some(Opcode::WaitForPress(_)) => return None,
+            Some(Opcode::SkipIfPressed(x)) | Some(Opcode::SkipIfNotPressed(x)) => *x,
+            _ => continue,
+        };
+        let key =
+            code[i.saturating_sub(LOOKBACK)..i]
+                .iter()
+                .rev()
+                .find_map(|opcode| match opcode {
+                    Some(Opcode::LoadConstant(x, kk)) if *x == register => Some(kk & 0xF),
+                    _ => None,
+                })?;
+        keys[key as usize] = true;
+    }
+    Some(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_keys_loaded_before_tests() {
+        // LD V1, 5; SKNP V1; LD V2, 1; LD V1, 8; SKP V1; JP 200
+        let program = [
+            0x61, 0x05, 0xE1, 0xA1, 0x62, 0x01, 0x61, 0x08, 0xE1, 0x9E, 0x12, 0x00,
+        ];
+        let keys = keys_used(&program).unwrap();
+        let used: Vec<usize> = (0..16).filter(|&k| keys[k]).collect();
+        assert_eq!(used, vec![5, 8]);
+
+        // A key read into a register could be any of them: LD V0, K
+        assert_eq!(keys_used(&[0xF0, 0x0A]), None);
+        // So could a key from somewhere else: LD V1, [I]; SKP V1
+        assert_eq!(keys_used(&[0xF1, 0x65, 0xE1, 0x9E]), None);
+    }
+}
@@ -0,0 +1,24 @@
+//! The emulator core: the machine and what describes it, with no windowing or audio
+//! dependencies, for embedding in any frontend. The desktop emulator is `chip8-cli` and the
+//! browser one is `chip8-wasm`. Bevy games can use `Chip8Plugin` (see `bevy_plugin`) and
+//! egui tools `Chip8Widget` (see `egui_widget`), behind the features of the same names, and
+//! Bevy games can draw screens through their own shaders with `screen_shader`.
+#[macro_use]
+extern crate enum_primitive_derive;
+
+#[cfg(feature = "bevy")]
+pub mod bevy_plugin;
+pub mod chip8;
+pub mod clock;
+#[cfg(feature = "egui")]
+pub mod egui_widget;
+pub mod hash;
+pub mod heatmap;
+pub mod keyscan;
+pub mod opcode;
+pub mod palette;
+pub mod png;
+pub mod quirks;
+#[cfg(feature = "bevy")]
+pub mod screen_shader;
+pub mod tone;
@@ -0,0 +1,451 @@
+use crate::chip8::Register;
+use num_traits::FromPrimitive;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// A raw two-byte instruction word, with accessors for the fields packed into it.
+pub struct Instruction(u16);
+
+impl Instruction {
+    /// We can use the top 4-bits of the opcode as a switch into the type of opcode for easier parsing
+    pub fn op(&self) -> u8 {
+        (self.0 >> 12) as u8
+    }
+
+    pub fn x(&self) -> u8 {
+        ((self.0 >> 8) & 0x0F) as u8
+    }
+
+    pub fn y(&self) -> u8 {
+        ((self.0 >> 4) & 0x0F) as u8
+    }
+
+    /// The register named by the x field.
+    pub fn vx(&self) -> Register {
+        Register::from_u8(self.x()).unwrap()
+    }
+
+    /// The register named by the y field.
+    pub fn vy(&self) -> Register {
+        Register::from_u8(self.y()).unwrap()
+    }
+
+    pub fn kk(&self) -> u8 {
+        (self.0 & 0xFF) as u8
+    }
+
+    pub fn nnn(&self) -> usize {
+        (self.0 & 0xFFF) as usize
+    }
+
+    pub fn n(&self) -> u8 {
+        (self.0 & 0x0F) as u8
+    }
+}
+
+/// Builds the opcode for an instruction word that matched a decoder entry.
+pub type DecodeFn = fn(&Instruction) -> Opcode;
+
+/// Matches instruction words whose bits under `mask` equal `bits`.
+#[derive(Copy, Clone)]
+struct Entry {
+    mask: u16,
+    bits: u16,
+    decode: DecodeFn,
+}
+
+/// Table-driven instruction decoder. Entries are bucketed by the top nibble of the word, and
+/// within a bucket the most specific mask is tried first, so variant machines (SCHIP, XO-CHIP)
+/// can register instructions that carve pieces out of broader patterns such as 0nnn.
+pub struct Decoder {
+    buckets: [Vec<Entry>; 16],
+}
+
+impl Decoder {
+    /// A decoder that recognizes nothing, for building up a custom instruction set.
+    pub fn empty() -> Decoder {
+        Decoder {
+            buckets: Default::default(),
+        }
+    }
+
+    /// The shared decoder for the standard instruction set, built on first use.
+    pub fn standard() -> &'static Decoder {
+        static STANDARD: OnceLock<Decoder> = OnceLock::new();
+        STANDARD.get_or_init(Decoder::chip8)
+    }
+
+    /// The original CHIP-8 instruction set, plus the SCHIP exit and flag register instructions
+    /// and the XO-CHIP audio instructions.
+    pub fn chip8() -> Decoder {
+        let mut d = Decoder::empty();
+        // Other commands that are now noops like 0nnn (SYS addr)
+        d.register(0xF000, 0x0000, |_| Opcode::Noop);
+        d.register(0xF0FF, 0x00E0, |_| Opcode::ClearDisplay);
+        d.register(0xF0FF, 0x00EE, |_| Opcode::Return);
+        d.register(0xF0FF, 0x00FD, |_| Opcode::Exit);
+        d.register(0xF000, 0x1000, |i| Opcode::Jump(i.nnn()));
+        d.register(0xF000, 0x2000, |i| Opcode::CallSubroutine(i.nnn()));
+        d.register(0xF000, 0x3000, |i| {
+            Opcode::SkipIfConstantEqual(i.vx(), i.kk())
+        });
+        d.register(0xF000, 0x4000, |i| {
+            Opcode::SkipIfConstantNotEqual(i.vx(), i.kk())
+        });
+        d.register(0xF000, 0x5000, |i| {
+            Opcode::SkipIfRegistersEqual(i.vx(), i.vy())
+        });
+        d.register(0xF000, 0x6000, |i| Opcode::LoadConstant(i.vx(), i.kk()));
+        d.register(0xF000, 0x7000, |i| Opcode::AddConstant(i.vx(), i.kk()));
+        d.register(0xF00F, 0x8000, |i| Opcode::LoadRegister(i.vx(), i.vy()));
+        d.register(0xF00F, 0x8001, |i| Opcode::Or(i.vx(), i.vy()));
+        d.register(0xF00F, 0x8002, |i| Opcode::And(i.vx(), i.vy()));
+        d.register(0xF00F, 0x8003, |i| Opcode::Xor(i.vx(), i.vy()));
+        d.register(0xF00F, 0x8004, |i| Opcode::AddRegister(i.vx(), i.vy()));
+        d.register(0xF00F, 0x8005, |i| {
+            Opcode::SubtractRightRegister(i.vx(), i.vy())
+        });
+        d.register(0xF00F, 0x8006, |i| Opcode::ShiftRight(i.vx(), i.vy()));
+        d.register(0xF00F, 0x8007, |i| {
+            Opcode::SubtractLeftRegister(i.vx(), i.vy())
+        });
+        d.register(0xF00F, 0x800E, |i| Opcode::ShiftLeft(i.vx(), i.vy()));
+        d.register(0xF000, 0x9000, |i| {
+            Opcode::SkipIfRegistersNotEqual(i.vx(), i.vy())
+        });
+        d.register(0xF000, 0xA000, |i| Opcode::LoadAddress(i.nnn()));
+        d.register(0xF000, 0xB000, |i| Opcode::JumpPlus(i.nnn()));
+        d.register(0xF000, 0xC000, |i| Opcode::Random(i.vx(), i.kk()));
+        d.register(0xF000, 0xD000, |i| {
+            Opcode::DisplaySprite(i.vx(), i.vy(), i.n())
+        });
+        d.register(0xF0FF, 0xE09E, |i| Opcode::SkipIfPressed(i.vx()));
+        d.register(0xF0FF, 0xE0A1, |i| Opcode::SkipIfNotPressed(i.vx()));
+        d.register(0xF0FF, 0xF007, |i| Opcode::LoadDelayTimer(i.vx()));
+        d.register(0xF0FF, 0xF00A, |i| Opcode::WaitForPress(i.vx()));
+        d.register(0xF0FF, 0xF015, |i| Opcode::SetDelayTimer(i.vx()));
+        d.register(0xF0FF, 0xF018, |i| Opcode::SetSoundTimer(i.vx()));
+        d.register(0xF0FF, 0xF01E, |i| Opcode::AddAddress(i.vx()));
+        d.register(0xF0FF, 0xF029, |i| Opcode::LoadAddressOfSprite(i.vx()));
+        d.register(0xF0FF, 0xF033, |i| Opcode::LoadDigits(i.vx()));
+        d.register(0xF0FF, 0xF055, |i| Opcode::StoreRegisters(i.vx()));
+        d.register(0xF0FF, 0xF065, |i| Opcode::LoadRegisters(i.vx()));
+        d.register(0xF0FF, 0xF075, |i| Opcode::SaveFlags(i.vx()));
+        d.register(0xF0FF, 0xF085, |i| Opcode::LoadFlags(i.vx()));
+        d.register(0xFFFF, 0xF002, |_| Opcode::LoadAudioPattern);
+        d.register(0xF0FF, 0xF03A, |i| Opcode::SetPitch(i.vx()));
+        d
+    }
+
+    /// Adds an instruction for the words whose bits under `mask` equal `bits`. The mask must
+    /// cover the top nibble. An entry registered later takes precedence over an earlier one
+    /// with an equally specific mask.
+    pub fn register(&mut self, mask: u16, bits: u16, decode: DecodeFn) {
+        assert_eq!(
+            mask & 0xF000,
+            0xF000,
+            "Decoder masks must cover the top nibble"
+        );
+        let bucket = &mut self.buckets[(bits >> 12) as usize];
+        let specificity = mask.count_ones();
+        let pos = bucket
+            .iter()
+            .position(|e| e.mask.count_ones() <= specificity)
+            .unwrap_or(bucket.len());
+        bucket.insert(pos, Entry { mask, bits, decode });
+    }
+
+    /// Decodes an instruction, or None if no entry matches it.
+    pub fn decode(&self, val: u16) -> Option<Opcode> {
+        let inst = Instruction(val);
+        self.buckets[inst.op() as usize]
+            .iter()
+            .find(|e| val & e.mask == e.bits)
+            .map(|e| (e.decode)(&inst))
+    }
+}
+
+/// Defines `Opcode` from a table of its variants and what each does, so the variants' doc
+/// comments and `description` share one text.
+macro_rules! opcodes {
+    ($($variant:ident $(($($field:ty),+))? => $description:literal,)+) => {
+        /// Represents different opcodes that the Chip-8 can execute.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub enum Opcode {
+            $(#[doc = $description] $variant $(($($field),+))?,)+
+        }
+
+        impl Opcode {
+            /// What the instruction does, as its doc comment puts it, for `chip8 explain`.
+            pub fn description(&self) -> &'static str {
+                match self {
+                    $(Opcode::$variant { .. } => $description,)+
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    ClearDisplay => "*00E0 - CLS*. Clear the display.",
+    Return => "*00EE - RET*. Return from a subroutine.",
+    Exit => "*00FD - EXIT*. Stop running the program (SCHIP).",
+    Noop => "*0nnn - SYS addr*. While a valid instruction, this is typically a noop in modern \
+        interpreters.",
+    Jump(usize) => "*1nnn - JP addr*. Jump to location nnn.",
+    CallSubroutine(usize) => "*2nnn - CALL addr*. Calls subroutine at nnn.",
+    SkipIfConstantEqual(Register, u8) => "*3xkk - SE Vx, byte*. Skip next instruction if Vx = kk.",
+    SkipIfConstantNotEqual(Register, u8) => "*4xkk - SNE Vx, byte*. Skip next instruction if Vx \
+        != kk.",
+    SkipIfRegistersEqual(Register, Register) => "*5xy0 - SE Vx, Vy*. Skip next instruction if Vx \
+        = Vy.",
+    LoadConstant(Register, u8) => "*6xkk - LD Vx, byte*. Puts the value kk into register Vx.",
+    AddConstant(Register, u8) => "*7xkk - ADD Vx, byte*. Adds kk to register Vx.",
+    LoadRegister(Register, Register) => "*8xy0 - LD Vx, Vy*. Sets register Vx to value in \
+        register Vy.",
+    Or(Register, Register) => "*8xy1 - OR Vx, Vy*. Performs bitwise OR between Vx and Vy, then \
+        stores result in Vx.",
+    And(Register, Register) => "*8xy2 - AND Vx, Vy*. Performs bitwise AND between Vx and Vy, then \
+        stores result in Vx.",
+    Xor(Register, Register) => "*8xy3 - XOR Vx, Vy*. Performs bitwise XOR between Vx and Vy, then \
+        stores result in Vx.",
+    AddRegister(Register, Register) => "*8xy4 - ADD Vx, Vy*. Adds the values of register Vx and \
+        Vy together.",
+    SubtractRightRegister(Register, Register) => "*8xy5 - SUB Vx, Vy*. Subtracts the value of \
+        register Vy from register Vx, then stores result in Vx.",
+    ShiftRight(Register, Register) => "*8xy6 - SHR Vx {, Vy}*. Shifts Vx (or Vy, depending on the \
+        interpreter) to the right by 1, then stores the result in Vx.",
+    SubtractLeftRegister(Register, Register) => "*8xy7 - SUBN Vx, Vy*. Subtracts the value of \
+        register Vx from register Vy, then stores result in Vx.",
+    ShiftLeft(Register, Register) => "*8xyE - SHL Vx {, Vy}*. Shifts Vx (or Vy, depending on the \
+        interpreter) to the left by 1, then stores the result in Vx.",
+    SkipIfRegistersNotEqual(Register, Register) => "*9xy0 - SNE Vx, Vy*. Skip next instruction if \
+        registers Vx and Vy are not equal.",
+    LoadAddress(usize) => "*Annn - LD I, addr*. Sets the value of I register to nnn.",
+    JumpPlus(usize) => "*Bnnn - JP V0, addr*. Jump to location nnn + V0.",
+    Random(Register, u8) => "*Cxkk - RND Vx, byte*. Generates a random number between 0 and 255, \
+        AND it with the value kk, then stores result in Vx.",
+    DisplaySprite(Register, Register, u8) => "*Dxyn - DRW Vx, Vy, nibble*. Displays n-byte sprite \
+        starting at memory location I at (Vx, Vy).",
+    SkipIfPressed(Register) => "*Ex9E - SKP Vx*. Skip next instruction if key with value Vx is \
+        pressed.",
+    SkipIfNotPressed(Register) => "*ExA1 - SKNP Vx*. Skip next instruction if key with value Vx \
+        is not pressed.",
+    LoadDelayTimer(Register) => "*Fx07 - LD Vx, DT*. Places the value of delay timer into \
+        register Vx.",
+    WaitForPress(Register) => "*Fx0A - LD Vx, K*. Wait for a key press, store the value in \
+        register Vx.",
+    SetDelayTimer(Register) => "*Fx15 - LD DT, Vx*. Set delay timer = Vx.",
+    SetSoundTimer(Register) => "*Fx18 - LD ST, Vx*. Set sound timer = Vx.",
+    AddAddress(Register) => "*Fx1E - ADD I, Vx*. The values of I and register Vx are added, then \
+        stores result in I.",
+    LoadAddressOfSprite(Register) => "*Fx29 - LD F, Vx*. The value of I is set to the location of \
+        sprite for digit Vx.",
+    LoadDigits(Register) => "*Fx33 - LD B, Vx*. Store BCD representation of Vx in addresses I, \
+        I+1, and I+2.",
+    StoreRegisters(Register) => "*Fx55 - LD [I], Vx*. Store registers V0 through Vx in memory \
+        starting at location I.",
+    LoadRegisters(Register) => "*Fx65 - LD Vx, [I]*. Load registers V0 through Vx from memory \
+        starting at location I.",
+    SaveFlags(Register) => "*Fx75 - LD R, Vx* (SCHIP). Store registers V0 through Vx in the flag \
+        registers.",
+    LoadFlags(Register) => "*Fx85 - LD Vx, R* (SCHIP). Load registers V0 through Vx from the flag \
+        registers.",
+    LoadAudioPattern => "*F002 - audio* (XO-CHIP). Load the 16-byte audio pattern buffer from \
+        memory at I.",
+    SetPitch(Register) => "*Fx3A - pitch := Vx* (XO-CHIP). Set the playback rate of the audio \
+        pattern from Vx.",
+    Custom(u16) => "An instruction word run by a handler from `Chip8::register_opcode`.",
+}
+
+impl Opcode {
+    /// Decodes an instruction, returning None for words that aren't valid instructions (which
+    /// is expected when looking at sprite data rather than code).
+    pub fn decode(val: u16) -> Option<Opcode> {
+        Decoder::standard().decode(val)
+    }
+}
+
+impl fmt::Display for Opcode {
+    /// Formats the opcode as assembly, using the mnemonics from Cowgod's technical reference.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Opcode::ClearDisplay => write!(f, "CLS"),
+            Opcode::Return => write!(f, "RET"),
+            Opcode::Exit => write!(f, "EXIT"),
+            Opcode::Noop => write!(f, "SYS"),
+            Opcode::Jump(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Opcode::CallSubroutine(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            Opcode::SkipIfConstantEqual(vx, kk) => write!(f, "SE {}, 0x{:02X}", vx, kk),
+            Opcode::SkipIfConstantNotEqual(vx, kk) => write!(f, "SNE {}, 0x{:02X}", vx, kk),
+            Opcode::SkipIfRegistersEqual(vx, vy) => write!(f, "SE {}, {}", vx, vy),
+            Opcode::LoadConstant(vx, kk) => write!(f, "LD {}, 0x{:02X}", vx, kk),
+            Opcode::AddConstant(vx, kk) => write!(f, "ADD {}, 0x{:02X}", vx, kk),
+            Opcode::LoadRegister(vx, vy) => write!(f, "LD {}, {}", vx, vy),
+            Opcode::Or(vx, vy) => write!(f, "OR {}, {}", vx, vy),
+            Opcode::And(vx, vy) => write!(f, "AND {}, {}", vx, vy),
+            Opcode::Xor(vx, vy) => write!(f, "XOR {}, {}", vx, vy),
+            Opcode::AddRegister(vx, vy) => write!(f, "ADD {}, {}", vx, vy),
+            Opcode::SubtractRightRegister(vx, vy) => write!(f, "SUB {}, {}", vx, vy),
+            Opcode::ShiftRight(vx, vy) if vx == vy => write!(f, "SHR {}", vx),
+            Opcode::ShiftRight(vx, vy) => write!(f, "SHR {}, {}", vx, vy),
+            Opcode::SubtractLeftRegister(vx, vy) => write!(f, "SUBN {}, {}", vx, vy),
+            Opcode::ShiftLeft(vx, vy) if vx == vy => write!(f, "SHL {}", vx),
+            Opcode::ShiftLeft(vx, vy) => write!(f, "SHL {}, {}", vx, vy),
+            Opcode::SkipIfRegistersNotEqual(vx, vy) => write!(f, "SNE {}, {}", vx, vy),
+            Opcode::LoadAddress(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            Opcode::JumpPlus(nnn) => write!(f, "JP V0, 0x{:03X}", nnn),
+            Opcode::Random(vx, kk) => write!(f, "RND {}, 0x{:02X}", vx, kk),
+            Opcode::DisplaySprite(vx, vy, n) => write!(f, "DRW {}, {}, {}", vx, vy, n),
+            Opcode::SkipIfPressed(vx) => write!(f, "SKP {}", vx),
+            Opcode::SkipIfNotPressed(vx) => write!(f, "SKNP {}", vx),
+            Opcode::LoadDelayTimer(vx) => write!(f, "LD {}, DT", vx),
+            Opcode::WaitForPress(vx) => write!(f, "LD {}, K", vx),
+            Opcode::SetDelayTimer(vx) => write!(f, "LD DT, {}", vx),
+            Opcode::SetSoundTimer(vx) => write!(f, "LD ST, {}", vx),
+            Opcode::AddAddress(vx) => write!(f, "ADD I, {}", vx),
+            Opcode::LoadAddressOfSprite(vx) => write!(f, "LD F, {}", vx),
+            Opcode::LoadDigits(vx) => write!(f, "LD B, {}", vx),
+            Opcode::StoreRegisters(vx) => write!(f, "LD [I], {}", vx),
+            Opcode::LoadRegisters(vx) => write!(f, "LD {}, [I]", vx),
+            Opcode::SaveFlags(vx) => write!(f, "LD R, {}", vx),
+            Opcode::LoadFlags(vx) => write!(f, "LD {}, R", vx),
+            // XO-CHIP has no Cowgod mnemonics, so these follow Octo
+            Opcode::LoadAudioPattern => write!(f, "AUDIO"),
+            Opcode::SetPitch(vx) => write!(f, "PITCH {}", vx),
+            Opcode::Custom(word) => write!(f, "CUSTOM 0x{:04X}", word),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(val: u16) -> Opcode {
+        Opcode::decode(val).unwrap()
+    }
+
+    #[test]
+    fn describes_every_opcode() {
+        let descriptions: std::collections::BTreeSet<&str> = (0..=u16::MAX)
+            .filter_map(Opcode::decode)
+            .map(|op| op.description())
+            .collect();
+        assert_eq!(descriptions.len(), 40);
+        assert_eq!(
+            op(0x8AC5).description(),
+            "*8xy5 - SUB Vx, Vy*. Subtracts the value of register Vy from register Vx, then \
+             stores result in Vx."
+        );
+    }
+
+    #[test]
+    fn parses_draw_opcodes() {
+        assert_eq!(Opcode::ClearDisplay, op(0x00E0));
+        assert_eq!(
+            Opcode::DisplaySprite(Register::VA, Register::VB, 0x6),
+            op(0xDAB6)
+        );
+        assert_eq!(Opcode::LoadAddressOfSprite(Register::V4), op(0xF429));
+    }
+
+    #[test]
+    fn parses_flow_opcodes() {
+        assert_eq!(Opcode::CallSubroutine(0x2D4), op(0x22D4));
+        assert_eq!(Opcode::Jump(0x53A), op(0x153A));
+        assert_eq!(Opcode::Noop, op(0x0123));
+        assert_eq!(Opcode::Return, op(0x00EE));
+        assert_eq!(Opcode::Exit, op(0x00FD));
+        assert_eq!(Opcode::SkipIfConstantEqual(Register::V7, 0x14), op(0x3714));
+        assert_eq!(
+            Opcode::SkipIfConstantNotEqual(Register::VA, 0xAE),
+            op(0x4AAE)
+        );
+        assert_eq!(
+            Opcode::SkipIfRegistersEqual(Register::VA, Register::VD),
+            op(0x5AD0)
+        );
+        assert_eq!(
+            Opcode::SkipIfRegistersNotEqual(Register::V1, Register::V4),
+            op(0x9140)
+        );
+        assert_eq!(Opcode::JumpPlus(0x17A), op(0xB17A));
+        assert_eq!(Opcode::SkipIfPressed(Register::V9), op(0xE99E));
+        assert_eq!(Opcode::SkipIfNotPressed(Register::VE), op(0xEEA1));
+        assert_eq!(Opcode::WaitForPress(Register::VA), op(0xFA0A));
+    }
+
+    #[test]
+    fn parses_memory_opcodes() {
+        assert_eq!(Opcode::LoadConstant(Register::VA, 0x02), op(0x6A02));
+        assert_eq!(Opcode::LoadConstant(Register::V0, 0xFF), op(0x60FF));
+        assert_eq!(Opcode::LoadAddress(0x2EA), op(0xA2EA));
+        assert_eq!(Opcode::LoadRegister(Register::V1, Register::V2), op(0x8120));
+        assert_eq!(Opcode::LoadDigits(Register::VA), op(0xFA33));
+        assert_eq!(Opcode::StoreRegisters(Register::V9), op(0xF955));
+        assert_eq!(Opcode::LoadRegisters(Register::VD), op(0xFD65));
+        assert_eq!(Opcode::SaveFlags(Register::V7), op(0xF775));
+        assert_eq!(Opcode::LoadFlags(Register::VF), op(0xFF85));
+    }
+
+    #[test]
+    fn parses_math_opcodes() {
+        assert_eq!(Opcode::AddConstant(Register::V2, 0x3B), op(0x723B));
+        assert_eq!(Opcode::Or(Register::V8, Register::VA), op(0x88A1));
+        assert_eq!(Opcode::And(Register::V1, Register::V3), op(0x8132));
+        assert_eq!(Opcode::Xor(Register::V5, Register::VC), op(0x85C3));
+        assert_eq!(Opcode::AddRegister(Register::V4, Register::V5), op(0x8454));
+        assert_eq!(
+            Opcode::SubtractRightRegister(Register::V2, Register::VA),
+            op(0x82A5)
+        );
+        assert_eq!(Opcode::ShiftRight(Register::V7, Register::V1), op(0x8716));
+        assert_eq!(
+            Opcode::SubtractLeftRegister(Register::VA, Register::VC),
+            op(0x8AC7)
+        );
+        assert_eq!(Opcode::ShiftLeft(Register::V7, Register::VA), op(0x87AE));
+        assert_eq!(Opcode::Random(Register::V4, 0x14), op(0xC414));
+        assert_eq!(Opcode::AddAddress(Register::V8), op(0xF81E));
+    }
+
+    #[test]
+    fn parses_timer_opcodes() {
+        assert_eq!(Opcode::SetDelayTimer(Register::V0), op(0xF015));
+        assert_eq!(Opcode::LoadDelayTimer(Register::V0), op(0xF007));
+        assert_eq!(Opcode::SetSoundTimer(Register::V3), op(0xF318));
+        assert_eq!(Opcode::LoadAudioPattern, op(0xF002));
+        assert_eq!(Opcode::SetPitch(Register::V5), op(0xF53A));
+        assert_eq!(Opcode::decode(0xF102), None);
+    }
+
+    #[test]
+    fn registered_entries_take_precedence() {
+        let mut decoder = Decoder::chip8();
+        // SCHIP's 00FF (high resolution) carved out of 0nnn
+        decoder.register(0xFFFF, 0x00FF, |_| Opcode::ClearDisplay);
+        assert_eq!(decoder.decode(0x00FF), Some(Opcode::ClearDisplay));
+        assert_eq!(decoder.decode(0x0123), Some(Opcode::Noop));
+        decoder.register(0xF000, 0x1000, |i| Opcode::CallSubroutine(i.nnn()));
+        assert_eq!(decoder.decode(0x1234), Some(Opcode::CallSubroutine(0x234)));
+        assert_eq!(Decoder::empty().decode(0x00E0), None);
+        assert_eq!(
+            Decoder::standard().decode(0x1234),
+            Some(Opcode::Jump(0x234))
+        );
+    }
+
+    #[test]
+    fn formats_as_assembly() {
+        assert_eq!(op(0x00E0).to_string(), "CLS");
+        assert_eq!(op(0x22D4).to_string(), "CALL 0x2D4");
+        assert_eq!(op(0x4AAE).to_string(), "SNE VA, 0xAE");
+        assert_eq!(op(0x8AC7).to_string(), "SUBN VA, VC");
+        assert_eq!(op(0x8776).to_string(), "SHR V7");
+        assert_eq!(op(0x87AE).to_string(), "SHL V7, VA");
+        assert_eq!(op(0xDAB6).to_string(), "DRW VA, VB, 6");
+        assert_eq!(op(0xFD65).to_string(), "LD VD, [I]");
+        assert_eq!(op(0xF375).to_string(), "LD R, V3");
+    }
+}
@@ -0,0 +1,88 @@
+//! Display colors. A palette holds four colors so it can cover XO-CHIP's two bit planes: the
+//! background, each plane on its own, and both planes overlapping. Plain CHIP-8 only ever
+//! shows the first two.
+
+/// Named palettes selectable with `--palette`.
+pub const PRESETS: [(&str, Palette); 3] = [
+    (
+        "classic",
+        Palette {
+            colors: [0x00_00_00, 0xFF_FF_FF, 0xAA_AA_AA, 0x55_55_55],
+        },
+    ),
+    // Bright primaries on black, for low vision
+    (
+        "high-contrast",
+        Palette {
+            colors: [0x00_00_00, 0xFF_FF_00, 0x00_FF_FF, 0xFF_FF_FF],
+        },
+    ),
+    // From the Okabe-Ito set, which stays distinguishable with deuteranopia and protanopia
+    (
+        "colorblind",
+        Palette {
+            colors: [0x00_00_00, 0xE6_9F_00, 0x56_B4_E9, 0xF0_E4_42],
+        },
+    ),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [u32; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        PRESETS[0].1
+    }
+}
+
+impl Palette {
+    /// Parses a preset name, or a custom palette of two or four comma-separated hex colors
+    /// (e.g. `000000,FFB000`).
+    pub fn parse(text: &str) -> Result<Palette, String> {
+        if let Some((_, palette)) = PRESETS.iter().find(|(name, _)| *name == text) {
+            return Ok(*palette);
+        }
+        let colors = text
+            .split(',')
+            .map(|c| u32::from_str_radix(c.trim_start_matches('#'), 16).ok())
+            .collect::<Option<Vec<u32>>>()
+            .filter(|colors| colors.iter().all(|c| *c <= 0xFF_FF_FF));
+        let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+        match colors.as_deref() {
+            Some(&[off, on]) => Ok(Palette {
+                colors: [off, on, on, on],
+            }),
+            Some(&[a, b, c, d]) => Ok(Palette {
+                colors: [a, b, c, d],
+            }),
+            _ => Err(format!(
+                "Unknown palette: {} (expected {} or 2 or 4 hex colors)",
+                text,
+                names.join(", ")
+            )),
+        }
+    }
+
+    pub fn color(&self, pixel: u8) -> u32 {
+        self.colors[pixel as usize & 0x3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_presets_and_custom_colors() {
+        assert_eq!(Palette::parse("classic"), Ok(Palette::default()));
+        assert_eq!(Palette::parse("colorblind").unwrap().color(1), 0xE6_9F_00);
+        let custom = Palette::parse("101010,#FFB000").unwrap();
+        assert_eq!((custom.color(0), custom.color(1)), (0x10_10_10, 0xFF_B0_00));
+        assert_eq!(Palette::parse("0,1,2,3").unwrap().colors, [0, 1, 2, 3]);
+        assert!(Palette::parse("sepia").is_err());
+        assert!(Palette::parse("000000,FFFFFF,000000").is_err());
+        assert!(Palette::parse("000000,1000000").is_err());
+    }
+}
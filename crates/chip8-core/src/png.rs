@@ -0,0 +1,148 @@
+//! Just enough of PNG to write uncompressed 8-bit RGB images, with text chunks for metadata,
+//! and to read that text back.
+use std::error::Error;
+use std::io::{self, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Deflate's stored blocks hold at most this many bytes.
+const MAX_BLOCK: usize = 0xFFFF;
+
+/// Writes an image, with each of `text` as a keyword and its value in a tEXt chunk.
+pub fn write<W: Write>(
+    out: &mut W,
+    rgb: &[u8],
+    width: usize,
+    height: usize,
+    text: &[(&str, String)],
+) -> io::Result<()> {
+    out.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, and the only compression, filter and interlace methods
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    chunk(out, b"IHDR", &header)?;
+
+    for (keyword, value) in text {
+        chunk(
+            out,
+            b"tEXt",
+            &[keyword.as_bytes(), &[0], value.as_bytes()].concat(),
+        )?;
+    }
+
+    // Every scanline starts with its filter type, which is always none here
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for line in rgb.chunks(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+    // A zlib stream made of stored deflate blocks
+    let mut data = vec![0x78, 0x01];
+    let blocks = raw.chunks(MAX_BLOCK).count();
+    for (i, block) in raw.chunks(MAX_BLOCK).enumerate() {
+        data.push((i + 1 == blocks) as u8);
+        let len = block.len() as u16;
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&(!len).to_le_bytes());
+        data.extend_from_slice(block);
+    }
+    data.extend_from_slice(&adler32(&raw).to_be_bytes());
+    chunk(out, b"IDAT", &data)?;
+
+    chunk(out, b"IEND", &[])
+}
+
+/// The keywords and values of a PNG's tEXt chunks, in order.
+pub fn read_text(png: &[u8]) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    if !png.starts_with(&SIGNATURE) {
+        return Err("Not a PNG".into());
+    }
+    let mut text = Vec::new();
+    let mut rest = &png[SIGNATURE.len()..];
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        if rest.len() < 12 + len {
+            break;
+        }
+        let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+        let crc = &rest[8 + len..12 + len];
+        if crc32(&rest[4..8 + len]).to_be_bytes() != crc {
+            return Err(format!("Corrupt {} chunk", String::from_utf8_lossy(kind)).into());
+        }
+        if kind == b"tEXt" {
+            let split = data
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or("tEXt chunk has no keyword")?;
+            // tEXt is Latin-1, where every byte is the character with that code
+            let latin1 = |bytes: &[u8]| bytes.iter().map(|b| char::from(*b)).collect::<String>();
+            text.push((latin1(&data[..split]), latin1(&data[split + 1..])));
+        }
+        if kind == b"IEND" {
+            return Ok(text);
+        }
+        rest = &rest[12 + len..];
+    }
+    Err("PNG ends early".into())
+}
+
+fn chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(&[&kind[..], data].concat());
+    out.write_all(&crc.to_be_bytes())
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_valid_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn reads_back_text() {
+        let mut out = Vec::new();
+        let text = [("Title", "PONG".to_string()), ("Frame", "42".to_string())];
+        write(&mut out, &[0; 2 * 2 * 3], 2, 2, &text).unwrap();
+        let read = read_text(&out).unwrap();
+        assert_eq!(read[0], ("Title".to_string(), "PONG".to_string()));
+        assert_eq!(read[1], ("Frame".to_string(), "42".to_string()));
+
+        // Changing a byte breaks its chunk's checksum
+        let last = out.len() - 13;
+        out[last] ^= 1;
+        assert!(read_text(&out).is_err());
+        assert!(read_text(b"GIF89a").is_err());
+    }
+}
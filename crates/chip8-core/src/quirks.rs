@@ -0,0 +1,97 @@
+//! Behaviors that differ between CHIP-8 interpreters. ROMs written for one interpreter can
+//! break on another, so each difference can be toggled on its own with `--quirks`.
+use std::fmt;
+
+/// Gets at one of the quirk toggles.
+type Toggle = fn(&mut Quirks) -> &mut bool;
+
+/// Every quirk by name, as used by `--quirks` and movie files.
+const NAMES: [(&str, Toggle); 8] = [
+    ("legacy-flags", |q| &mut q.legacy_flags),
+    ("memory-increment", |q| &mut q.memory_increment),
+    ("exclusive-range", |q| &mut q.exclusive_range),
+    ("shift-vy", |q| &mut q.shift_vy),
+    ("jump-vx", |q| &mut q.jump_vx),
+    ("wrap-sprites", |q| &mut q.wrap_sprites),
+    ("vf-reset", |q| &mut q.vf_reset),
+    ("memory-stack", |q| &mut q.memory_stack),
+];
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// This emulator's original flag handling: the 8xyN ALU instructions and sprite
+    /// collisions only ever set VF to 1, never clearing it, and subtracting equal values
+    /// counts as a borrow.
+    pub legacy_flags: bool,
+    /// Fx55/Fx65 leave I pointing just past the registers they transferred, as on the COSMAC
+    /// VIP. Later interpreters leave I alone.
+    pub memory_increment: bool,
+    /// This emulator's original Fx55/Fx65, which transfer V0 up to but not including Vx.
+    pub exclusive_range: bool,
+    /// 8xy6/8xyE shift Vy and store the result in Vx, as on the COSMAC VIP. CHIP-48 and SCHIP
+    /// shift Vx in place and ignore Vy.
+    pub shift_vy: bool,
+    /// Bnnn is read as BXNN, jumping to XNN plus Vx rather than nnn plus V0, as on CHIP-48
+    /// and SCHIP.
+    pub jump_vx: bool,
+    /// Sprites wrap around the edges of the screen, as this emulator originally drew them.
+    /// The COSMAC VIP clips them, wrapping only the starting position.
+    pub wrap_sprites: bool,
+    /// OR, AND and XOR clear VF, as on the COSMAC VIP.
+    pub vf_reset: bool,
+    /// Return addresses are kept in memory at 0xEA0, as on the COSMAC VIP, where programs can
+    /// read and overwrite them. Otherwise they are kept apart from memory.
+    pub memory_stack: bool,
+}
+
+impl Quirks {
+    /// Parses a comma-separated list of quirk names to turn on (e.g. `legacy-flags`).
+    pub fn parse(text: &str) -> Result<Quirks, String> {
+        let mut quirks = Quirks::default();
+        for name in text.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match NAMES.iter().find(|(n, _)| *n == name) {
+                Some((_, flag)) => *flag(&mut quirks) = true,
+                None => {
+                    let names: Vec<&str> = NAMES.iter().map(|(n, _)| *n).collect();
+                    return Err(format!(
+                        "Unknown quirk: {} (expected {})",
+                        name,
+                        names.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(quirks)
+    }
+}
+
+impl fmt::Display for Quirks {
+    /// Lists the quirks that are on, in the form `parse` accepts.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut quirks = *self;
+        let names: Vec<&str> = NAMES
+            .iter()
+            .filter(|(_, flag)| *flag(&mut quirks))
+            .map(|(n, _)| *n)
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quirk_lists() {
+        assert_eq!(Quirks::parse(""), Ok(Quirks::default()));
+        let quirks = Quirks::parse("legacy-flags").unwrap();
+        assert!(quirks.legacy_flags);
+        assert_eq!(Quirks::parse(&quirks.to_string()), Ok(quirks));
+        let quirks = Quirks::parse("memory-increment, exclusive-range").unwrap();
+        assert!(quirks.memory_increment && quirks.exclusive_range && !quirks.legacy_flags);
+        assert_eq!(quirks.to_string(), "memory-increment,exclusive-range");
+        assert_eq!(Quirks::default().to_string(), "");
+        assert!(Quirks::parse("legacy-flags,bogus").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Post-processing for cabinet screens (`--features bevy`): a `ScreenMaterial` draws a
+//! cabinet's screen through a WGSL fragment shader, so a look like a curved tube, phosphor glow
+//! or an LCD grid is a file that can be shared rather than a code change. The shaders in
+//! `shaders/` are the looks that come with the crate, and `screen.wgsl` the one to start from.
+//!
+//! ```ignore
+//! App::new()
+//!     .add_plugins((DefaultPlugins, Chip8Plugin, ScreenShaderPlugin::load("crt.wgsl")?))
+//!     .add_systems(Startup, |mut commands: Commands, mut images: ResMut<Assets<Image>>,
+//!             mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<ScreenMaterial>>| {
+//!         let cabinet = Cabinet::new(include_bytes!("PONG"), &mut images).unwrap();
+//!         commands.spawn(MaterialMesh2dBundle {
+//!             mesh: meshes.add(Rectangle::new(512.0, 256.0)).into(),
+//!             material: materials.add(ScreenMaterial::new(cabinet.screen.clone())),
+//!             ..default()
+//!         });
+//!         commands.spawn(cabinet);
+//!     })
+//!     .run();
+//! ```
+use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef};
+use bevy::sprite::{Material2d, Material2dPlugin};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// The screen as it is.
+pub const PLAIN: &str = include_str!("../shaders/screen.wgsl");
+/// A curved tube with scanlines.
+pub const CURVATURE: &str = include_str!("../shaders/curvature.wgsl");
+/// Lit pixels bleeding onto their neighbours.
+pub const GLOW: &str = include_str!("../shaders/glow.wgsl");
+/// Dark gaps between the pixels.
+pub const LCD_GRID: &str = include_str!("../shaders/lcd.wgsl");
+
+/// Where the plugin puts its shader. Material shaders are chosen per type rather than per
+/// material, so every screen in the app has the same look.
+pub const SCREEN_SHADER: Handle<Shader> = Handle::weak_from_u128(0x6368_6970_385f_7363_7265_656e);
+
+/// A cabinet's screen, for a mesh. Shaders get it as a `texture_2d<f32>` at binding 0 of group
+/// 2 and its sampler at binding 1, as in `screen.wgsl`.
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
+pub struct ScreenMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub screen: Handle<Image>,
+}
+
+impl ScreenMaterial {
+    pub fn new(screen: Handle<Image>) -> ScreenMaterial {
+        ScreenMaterial { screen }
+    }
+}
+
+impl Material2d for ScreenMaterial {
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Handle(SCREEN_SHADER)
+    }
+}
+
+/// Adds `ScreenMaterial`, drawing with the given WGSL source.
+pub struct ScreenShaderPlugin {
+    source: String,
+    path: String,
+}
+
+impl ScreenShaderPlugin {
+    pub fn new(source: &str) -> ScreenShaderPlugin {
+        ScreenShaderPlugin {
+            source: source.to_string(),
+            path: "chip8/screen.wgsl".to_string(),
+        }
+    }
+
+    /// Reads a shader from a file. Compile errors are reported by Bevy when the screen is
+    /// first drawn, naming the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<ScreenShaderPlugin, Box<dyn Error>> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read shader {}: {}", path.display(), e))?;
+        Ok(ScreenShaderPlugin {
+            source,
+            path: path.display().to_string(),
+        })
+    }
+}
+
+impl Default for ScreenShaderPlugin {
+    fn default() -> ScreenShaderPlugin {
+        ScreenShaderPlugin::new(PLAIN)
+    }
+}
+
+impl Plugin for ScreenShaderPlugin {
+    fn build(&self, app: &mut App) {
+        app.world_mut().resource_mut::<Assets<Shader>>().insert(
+            &SCREEN_SHADER,
+            Shader::from_wgsl(self.source.clone(), self.path.clone()),
+        );
+        app.add_plugins(Material2dPlugin::<ScreenMaterial>::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::asset::AssetPlugin;
+
+    #[test]
+    fn loads_shaders_from_files() {
+        let path = std::env::temp_dir().join("chip8-screen-shader-test.wgsl");
+        fs::write(&path, LCD_GRID).unwrap();
+        let plugin = ScreenShaderPlugin::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(ScreenShaderPlugin::load(&path).is_err());
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Shader>()
+            .add_plugins(plugin);
+        let shaders = app.world().resource::<Assets<Shader>>();
+        let shader = shaders.get(&SCREEN_SHADER).unwrap();
+        assert_eq!(shader.path, path.display().to_string());
+        assert!(app.world().contains_resource::<Assets<ScreenMaterial>>());
+    }
+}
@@ -0,0 +1,173 @@
+//! The buzzer's sound, as samples. XO-CHIP programs can load their own 1-bit audio pattern
+//! and pitch, and everything else plays the beep, a 250Hz square wave unless configured
+//! otherwise.
+use std::f64::consts::TAU;
+
+/// The shape of the beep played before a program loads an audio pattern of its own.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl Waveform {
+    pub fn parse(text: &str) -> Result<Waveform, String> {
+        match text {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!(
+                "Unknown waveform: {} (expected square, triangle, or sine)",
+                text
+            )),
+        }
+    }
+
+    /// The wave's level from -1 to 1, `phase` of the way through a cycle.
+    fn level(self, phase: f64) -> f64 {
+        match self {
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => -1.0,
+            Waveform::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+            Waveform::Sine => (TAU * phase).sin(),
+        }
+    }
+}
+
+/// How the buzzer sounds. Volume applies to everything played, while frequency and waveform
+/// only shape the beep, since XO-CHIP patterns bring their own.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Beep {
+    /// Loudness from 0 (silent) to 1 (full scale).
+    pub volume: f32,
+    /// Hz at the default pitch. XO-CHIP's pitch register still scales it.
+    pub frequency: f64,
+    pub waveform: Waveform,
+}
+
+impl Default for Beep {
+    fn default() -> Beep {
+        Beep {
+            volume: 0.2,
+            frequency: 250.0,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// What the buzzer should be playing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tone {
+    /// XO-CHIP audio pattern, 128 bits played in order, or all zeros for the plain buzzer.
+    pub pattern: [u8; 16],
+    pub pitch: u8,
+    /// Whether the sound timer is running.
+    pub on: bool,
+}
+
+impl Tone {
+    pub const SILENT: Tone = Tone {
+        pattern: [0; 16],
+        pitch: 64,
+        on: false,
+    };
+
+    /// Pattern bits played per second. XO-CHIP's pitch register is on a logarithmic scale,
+    /// with 64 playing 4000 bits per second and each 48 steps an octave.
+    fn bit_rate(&self) -> f64 {
+        4000.0 * self.pitch_scale()
+    }
+
+    /// How much the pitch register speeds playback up from the default pitch.
+    fn pitch_scale(&self) -> f64 {
+        2f64.powf((f64::from(self.pitch) - 64.0) / 48.0)
+    }
+}
+
+/// Turns tones into samples, keeping its place in the pattern from one sample to the next.
+pub struct Synth {
+    pub tone: Tone,
+    beep: Beep,
+    sample_rate: u32,
+    /// Position in the pattern, in bits, or in the beep, in cycles.
+    phase: f64,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32, beep: Beep) -> Synth {
+        Synth {
+            tone: Tone::SILENT,
+            beep,
+            sample_rate,
+            phase: 0.0,
+        }
+    }
+
+    pub fn sample(&mut self) -> f32 {
+        if !self.tone.on {
+            self.phase = 0.0;
+            return 0.0;
+        }
+        let sample_rate = f64::from(self.sample_rate);
+        let level = if self.tone.pattern == [0; 16] {
+            let phase = self.phase.fract();
+            let frequency = self.beep.frequency * self.tone.pitch_scale();
+            self.phase = (phase + frequency / sample_rate) % 1.0;
+            self.beep.waveform.level(phase)
+        } else {
+            let bit = self.phase as usize % 128;
+            self.phase = (self.phase + self.tone.bit_rate() / sample_rate) % 128.0;
+            if self.tone.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                1.0
+            } else {
+                -1.0
+            }
+        };
+        self.beep.volume * level as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_pattern_at_pitch() {
+        // At 8000 samples per second, the default pitch plays a bit every other sample
+        let mut synth = Synth::new(8000, Beep::default());
+        assert_eq!(synth.sample(), 0.0);
+        let mut pattern = [0; 16];
+        pattern[0] = 0b1010_0000;
+        synth.tone = Tone {
+            pattern,
+            pitch: 64,
+            on: true,
+        };
+        let samples: Vec<f32> = (0..8).map(|_| synth.sample()).collect();
+        let (high, low) = (0.2, -0.2);
+        assert_eq!(samples, vec![high, high, low, low, high, high, low, low]);
+        // An octave up plays a bit per sample
+        synth.tone.pitch = 64 + 48;
+        synth.phase = 0.0;
+        assert_eq!(synth.sample(), high);
+        assert_eq!(synth.sample(), low);
+    }
+
+    #[test]
+    fn shapes_the_beep() {
+        let beep = Beep {
+            volume: 0.5,
+            frequency: 1000.0,
+            waveform: Waveform::Triangle,
+        };
+        // Four samples per cycle at 4000 samples per second
+        let mut synth = Synth::new(4000, beep);
+        synth.tone.on = true;
+        let samples: Vec<f32> = (0..5).map(|_| synth.sample()).collect();
+        assert_eq!(samples, vec![0.5, 0.0, -0.5, 0.0, 0.5]);
+        assert_eq!(Waveform::parse("sine"), Ok(Waveform::Sine));
+        assert!(Waveform::parse("sawtooth").is_err());
+    }
+}
@@ -0,0 +1,399 @@
+//! The emulator as a JavaScript class, for embedding games in web pages. Build it with
+//! `wasm-pack build --target web` in this crate's directory, then drive it from the page:
+//!
+//! ```js
+//! import init, { Chip8Emu } from "./pkg/chip8_wasm.js";
+//! await init();
+//! const emu = new Chip8Emu(document.querySelector("canvas"));
+//! emu.loadRom(new Uint8Array(await (await fetch("PONG")).arrayBuffer()));
+//! emu.setQuirks({ "vf-reset": true, "shift-vy": true });
+//! emu.onBeep(on => canvas.classList.toggle("beeping", on));
+//! addEventListener("keydown", e => e.key in KEYS && emu.keyDown(KEYS[e.key]));
+//! addEventListener("keyup", e => e.key in KEYS && emu.keyUp(KEYS[e.key]));
+//! let last = performance.now();
+//! requestAnimationFrame(function frame(now) {
+//!     emu.advance(now - last);
+//!     last = now;
+//!     requestAnimationFrame(frame);
+//! });
+//! ```
+//!
+//! The buzzer plays through Web Audio, including XO-CHIP audio patterns. Browsers keep pages
+//! quiet until they're interacted with, so it's heard from the first click, touch, or key
+//! press on the page. `onBeep` is for showing the beep as well.
+//!
+//! The canvas is drawn at 64x32, one canvas pixel per CHIP-8 pixel. Size it up with CSS, and
+//! `image-rendering: pixelated` keeps the pixels sharp.
+//!
+//! On phones and tablets, `emu.attachTouchKeypad(element)` fills an element with a keypad to
+//! play with. Its keys are `.chip8-key` elements, with `.held` added while they're touched,
+//! for the page to style.
+#![cfg(target_arch = "wasm32")]
+use chip8_core::chip8::{self, Chip8, KEYPAD_LAYOUT, SCREEN_HEIGHT, SCREEN_WIDTH};
+use chip8_core::keyscan;
+use chip8_core::quirks::Quirks;
+use chip8_core::tone::{Beep, Synth, Tone};
+use js_sys::{Function, Math, Object};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use web_sys::{
+    AudioContext, AudioProcessingEvent, CanvasRenderingContext2d, Document, HtmlCanvasElement,
+    HtmlElement, ImageData, ScriptProcessorNode, TouchEvent,
+};
+
+const TOUCH_EVENTS: [&str; 4] = ["touchstart", "touchmove", "touchend", "touchcancel"];
+/// Interactions that let a page make sound. Safari only counts a touch once it ends.
+const GESTURE_EVENTS: [&str; 3] = ["pointerdown", "touchend", "keydown"];
+/// Samples generated each time the browser asks for more: about 20ms, so a new tone is heard
+/// within a frame or two.
+const AUDIO_BUFFER_SIZE: u32 = 1024;
+
+#[wasm_bindgen]
+pub struct Chip8Emu {
+    chip8: Chip8,
+    rom: Vec<u8>,
+    context: CanvasRenderingContext2d,
+    on_beep: Option<Function>,
+    beeping: bool,
+    /// None where the browser has no Web Audio, which leaves the game silent.
+    buzzer: Option<Buzzer>,
+    /// Keys held through `keyDown`, from 0 to F.
+    keys: [bool; 16],
+    /// Keys held on the touch keypad, kept up to date by its touch handler.
+    touch_keys: Rc<Cell<[bool; 16]>>,
+    /// The touch keypad's keys, in `KEYPAD_LAYOUT` order.
+    touch_buttons: Vec<HtmlElement>,
+    /// The element the touch keypad was attached to, which `on_touch` listens on.
+    touch_container: Option<HtmlElement>,
+    /// Kept alive for as long as the keypad can be touched.
+    on_touch: Option<Closure<dyn FnMut(TouchEvent)>>,
+}
+
+#[wasm_bindgen]
+impl Chip8Emu {
+    /// Takes over a canvas to draw the screen on. Nothing runs until a ROM is loaded.
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: HtmlCanvasElement) -> Result<Chip8Emu, JsValue> {
+        canvas.set_width(SCREEN_WIDTH as u32);
+        canvas.set_height(SCREEN_HEIGHT as u32);
+        let context = canvas
+            .get_context("2d")?
+            .ok_or("The canvas has no 2D context")?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        let buzzer = match canvas.owner_document() {
+            Some(document) => Buzzer::start(&document).ok(),
+            None => None,
+        };
+        Ok(Chip8Emu {
+            chip8: power_on(Quirks::default()),
+            rom: Vec::new(),
+            context,
+            on_beep: None,
+            beeping: false,
+            buzzer,
+            keys: [false; 16],
+            touch_keys: Rc::new(Cell::new([false; 16])),
+            touch_buttons: Vec::new(),
+            touch_container: None,
+            on_touch: None,
+        })
+    }
+
+    /// Loads a ROM and starts it from power-on.
+    #[wasm_bindgen(js_name = loadRom)]
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        chip8::check_program_size(rom)?;
+        self.rom = rom.to_vec();
+        self.reset();
+        self.show_keys_used()
+    }
+
+    /// Restarts the loaded ROM from power-on.
+    pub fn reset(&mut self) {
+        self.chip8 = power_on(self.chip8.quirks());
+        self.chip8.load_program(&self.rom);
+        self.set_beeping(false);
+    }
+
+    /// Turns on the quirks named as keys of an object, using the names `--quirks` takes,
+    /// e.g. `{ "vf-reset": true }`. Quirks left out or set to a falsy value are turned off.
+    #[wasm_bindgen(js_name = setQuirks)]
+    pub fn set_quirks(&mut self, quirks: &Object) -> Result<(), JsValue> {
+        let names: Vec<String> = Object::entries(quirks)
+            .iter()
+            .map(|entry| entry.unchecked_into::<js_sys::Array>())
+            .filter(|entry| entry.get(1).is_truthy())
+            .filter_map(|entry| entry.get(0).as_string())
+            .collect();
+        self.chip8.set_quirks(Quirks::parse(&names.join(","))?);
+        Ok(())
+    }
+
+    /// Registers a function to call with `true` when the buzzer starts and `false` when it
+    /// stops, so the page can show it.
+    #[wasm_bindgen(js_name = onBeep)]
+    pub fn on_beep(&mut self, callback: Function) {
+        self.on_beep = Some(callback);
+    }
+
+    /// Presses a key on the hex keypad, 0 to 15.
+    #[wasm_bindgen(js_name = keyDown)]
+    pub fn key_down(&mut self, key: u8) -> Result<(), JsValue> {
+        check_key(key)?;
+        self.keys[key as usize] = true;
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = keyUp)]
+    pub fn key_up(&mut self, key: u8) -> Result<(), JsValue> {
+        check_key(key)?;
+        self.keys[key as usize] = false;
+        Ok(())
+    }
+
+    /// Runs the machine for `ms` milliseconds of real time, then draws the screen if it
+    /// changed. Meant to be called from `requestAnimationFrame`.
+    pub fn advance(&mut self, ms: f64) -> Result<(), JsValue> {
+        if self.rom.is_empty() {
+            return Ok(());
+        }
+        let touched = self.touch_keys.get();
+        for key in 0..16 {
+            if self.keys[key as usize] || touched[key as usize] {
+                self.chip8.set_key_down(key);
+            } else {
+                self.chip8.set_key_up(key);
+            }
+        }
+        let dt = Duration::from_secs_f64(ms.max(0.0) / 1000.0);
+        self.chip8
+            .advance(dt)
+            .map_err(|fault| JsValue::from(fault.to_string()))?;
+        self.set_beeping(self.chip8.sound_timer() > 0);
+        if self.chip8.take_display_dirty() {
+            self.draw()?;
+        }
+        Ok(())
+    }
+
+    /// Fills `container` with a 4x4 touch keypad, laid out like the COSMAC VIP's. Any number
+    /// of keys can be held at once, and sliding a finger moves it from key to key. Keys the
+    /// loaded ROM never reads are hidden, when they can be told from its code.
+    #[wasm_bindgen(js_name = attachTouchKeypad)]
+    pub fn attach_touch_keypad(&mut self, container: HtmlElement) -> Result<(), JsValue> {
+        let document = container
+            .owner_document()
+            .ok_or("The keypad element isn't in a document")?;
+        self.detach_touch_keypad();
+        container.set_inner_html("");
+        let style = container.style();
+        style.set_property("display", "grid")?;
+        style.set_property("grid-template-columns", "repeat(4, 1fr)")?;
+        // Touches play the keypad rather than scrolling or zooming the page
+        style.set_property("touch-action", "none")?;
+        style.set_property("user-select", "none")?;
+        self.touch_buttons.clear();
+        for key in KEYPAD_LAYOUT.iter().flatten() {
+            let button = document.create_element("div")?.dyn_into::<HtmlElement>()?;
+            button.set_class_name("chip8-key");
+            button.set_attribute("data-key", &key.to_string())?;
+            button.set_text_content(Some(&format!("{:X}", key)));
+            container.append_child(&button)?;
+            self.touch_buttons.push(button);
+        }
+
+        let touch_keys = self.touch_keys.clone();
+        let buttons = self.touch_buttons.clone();
+        let on_touch = Closure::<dyn FnMut(TouchEvent)>::new(move |event: TouchEvent| {
+            event.prevent_default();
+            // Work out every key under a finger, rather than tracking each touch's target,
+            // since a touch's target stays the key it started on
+            let touches = event.touches();
+            let mut held = [false; 16];
+            for touch in (0..touches.length()).filter_map(|i| touches.item(i)) {
+                let key = document
+                    .element_from_point(touch.client_x() as f32, touch.client_y() as f32)
+                    .and_then(|element| element.get_attribute("data-key"))
+                    .and_then(|key| key.parse::<usize>().ok());
+                if let Some(key) = key.filter(|&key| key < 16) {
+                    held[key] = true;
+                }
+            }
+            touch_keys.set(held);
+            for (button, key) in buttons.iter().zip(KEYPAD_LAYOUT.iter().flatten()) {
+                let _ = button
+                    .class_list()
+                    .toggle_with_force("held", held[*key as usize]);
+            }
+        });
+        for event in TOUCH_EVENTS.iter() {
+            container.add_event_listener_with_callback(event, on_touch.as_ref().unchecked_ref())?;
+        }
+        self.touch_container = Some(container);
+        self.on_touch = Some(on_touch);
+        self.show_keys_used()
+    }
+}
+
+impl Chip8Emu {
+    /// Stops listening for touches on the keypad's element, so the handler can be freed, and
+    /// lets go of any keys it held.
+    fn detach_touch_keypad(&mut self) {
+        if let (Some(container), Some(on_touch)) =
+            (self.touch_container.take(), self.on_touch.take())
+        {
+            for event in TOUCH_EVENTS.iter() {
+                let _ = container
+                    .remove_event_listener_with_callback(event, on_touch.as_ref().unchecked_ref());
+            }
+        }
+        self.touch_keys.set([false; 16]);
+    }
+
+    /// Hides the touch keys the ROM doesn't read.
+    fn show_keys_used(&self) -> Result<(), JsValue> {
+        let used = keyscan::keys_used(&self.rom).unwrap_or([true; 16]);
+        for (button, key) in self
+            .touch_buttons
+            .iter()
+            .zip(KEYPAD_LAYOUT.iter().flatten())
+        {
+            let visibility = if used[*key as usize] {
+                "visible"
+            } else {
+                "hidden"
+            };
+            button.style().set_property("visibility", visibility)?;
+        }
+        Ok(())
+    }
+
+    fn set_beeping(&mut self, beeping: bool) {
+        if let Some(ref buzzer) = self.buzzer {
+            let (pattern, pitch) = self.chip8.audio_pattern();
+            buzzer.synth.borrow_mut().tone = Tone {
+                pattern,
+                pitch,
+                on: beeping,
+            };
+        }
+        if beeping == self.beeping {
+            return;
+        }
+        self.beeping = beeping;
+        if let Some(ref callback) = self.on_beep {
+            // A failing callback is the page's problem, and shouldn't stop the game
+            let _ = callback.call1(&JsValue::NULL, &JsValue::from(beeping));
+        }
+    }
+
+    fn draw(&self) -> Result<(), JsValue> {
+        let pixels = self.chip8.render_to_image(1, self.chip8.palette());
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&pixels),
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        )?;
+        self.context.put_image_data(&image, 0.0, 0.0)
+    }
+}
+
+impl Drop for Chip8Emu {
+    fn drop(&mut self) {
+        self.detach_touch_keypad();
+    }
+}
+
+/// The buzzer, played through Web Audio. A script processor fills the browser's buffers from
+/// the same generator the desktop build uses, with whatever tone the emulator last set.
+struct Buzzer {
+    synth: Rc<RefCell<Synth>>,
+    context: AudioContext,
+    processor: ScriptProcessorNode,
+    /// Where the listeners that resume the audio are registered.
+    document: Document,
+    /// Kept alive for as long as audio plays.
+    _on_process: Closure<dyn FnMut(AudioProcessingEvent)>,
+    on_gesture: Closure<dyn FnMut()>,
+}
+
+impl Buzzer {
+    /// Starts generating samples, and resumes the audio once the page is interacted with,
+    /// since it starts out suspended until then.
+    fn start(document: &Document) -> Result<Buzzer, JsValue> {
+        let context = AudioContext::new()?;
+        let synth = Rc::new(RefCell::new(Synth::new(
+            context.sample_rate() as u32,
+            Beep::default(),
+        )));
+        let processor = context
+            .create_script_processor_with_buffer_size_and_number_of_input_channels_and_number_of_output_channels(
+                AUDIO_BUFFER_SIZE,
+                0,
+                1,
+            )?;
+        let generator = synth.clone();
+        let on_process =
+            Closure::<dyn FnMut(AudioProcessingEvent)>::new(move |event: AudioProcessingEvent| {
+                let buffer = match event.output_buffer() {
+                    Ok(buffer) => buffer,
+                    Err(_) => return,
+                };
+                let mut synth = generator.borrow_mut();
+                let samples: Vec<f32> = (0..buffer.length()).map(|_| synth.sample()).collect();
+                let _ = buffer.copy_to_channel(&samples, 0);
+            });
+        processor.set_onaudioprocess(Some(on_process.as_ref().unchecked_ref()));
+        processor.connect_with_audio_node(&context.destination())?;
+
+        let resumed = context.clone();
+        let on_gesture = Closure::<dyn FnMut()>::new(move || {
+            let _ = resumed.resume();
+        });
+        for event in GESTURE_EVENTS.iter() {
+            document
+                .add_event_listener_with_callback(event, on_gesture.as_ref().unchecked_ref())?;
+        }
+        Ok(Buzzer {
+            synth,
+            context,
+            processor,
+            document: document.clone(),
+            _on_process: on_process,
+            on_gesture,
+        })
+    }
+}
+
+/// Unhooks the closures before they're freed, since the browser would otherwise keep calling
+/// them, and stops the audio.
+impl Drop for Buzzer {
+    fn drop(&mut self) {
+        for event in GESTURE_EVENTS.iter() {
+            let _ = self.document.remove_event_listener_with_callback(
+                event,
+                self.on_gesture.as_ref().unchecked_ref(),
+            );
+        }
+        self.processor.set_onaudioprocess(None);
+        let _ = self.processor.disconnect();
+        let _ = self.context.close();
+    }
+}
+
+/// A machine with its RNG seeded from the browser's, since there's no OS to ask.
+fn power_on(quirks: Quirks) -> Chip8 {
+    let mut chip8 = Chip8::with_seed((Math::random() * u64::MAX as f64) as u64);
+    chip8.set_quirks(quirks);
+    chip8
+}
+
+fn check_key(key: u8) -> Result<(), JsValue> {
+    if key > 0xF {
+        return Err(format!("Key is not between 0 and 15: {}", key).into());
+    }
+    Ok(())
+}